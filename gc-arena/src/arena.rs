@@ -2,25 +2,46 @@ use std::{f64, usize};
 
 use crate::context::{Context, MutationContext};
 
+/// The strategy the garbage collector uses to decide which objects to trace in each collection.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum CollectorMode {
+    /// Every collection traces the whole heap, interleaved with mutation.
+    Incremental,
+    /// Objects which survive a collection become "old", and most collections are "minor"
+    /// collections which only trace and free the "young" objects allocated since the last one.
+    /// Old objects are only traced during a minor collection if they have been mutated since,
+    /// which is tracked by the write barrier.  Every collection runs to completion at once.
+    Generational,
+}
+
 #[derive(Debug, Clone)]
 pub struct ArenaParameters {
     pub(crate) pause_factor: f64,
     pub(crate) timing_factor: f64,
     pub(crate) min_sleep: usize,
+    pub(crate) mode: CollectorMode,
+    pub(crate) minor_factor: f64,
+    pub(crate) major_factor: f64,
 }
 
 /// Creates a default ArenaParameters with `pause_factor` set to 0.5, `timing_factor` set to 1.5,
-/// and `min_sleep` set to 4096.
+/// `min_sleep` set to 4096, `mode` set to `CollectorMode::Incremental`, `minor_factor` set to 0.2
+/// and `major_factor` set to 1.0.
 impl Default for ArenaParameters {
     fn default() -> ArenaParameters {
         const PAUSE_FACTOR: f64 = 0.5;
         const TIMING_FACTOR: f64 = 1.5;
         const MIN_SLEEP: usize = 4096;
+        const MINOR_FACTOR: f64 = 0.2;
+        const MAJOR_FACTOR: f64 = 1.0;
 
         ArenaParameters {
             pause_factor: PAUSE_FACTOR,
             timing_factor: TIMING_FACTOR,
             min_sleep: MIN_SLEEP,
+            mode: CollectorMode::Incremental,
+            minor_factor: MINOR_FACTOR,
+            major_factor: MAJOR_FACTOR,
         }
     }
 }
@@ -53,6 +74,31 @@ impl ArenaParameters {
         self.min_sleep = min_sleep;
         self
     }
+
+    /// The mode the garbage collector starts in, which may later be changed with
+    /// `MutationContext::set_collector_mode`.
+    pub fn set_mode(mut self, mode: CollectorMode) -> ArenaParameters {
+        self.mode = mode;
+        self
+    }
+
+    /// In generational mode, the garbage collector will wait until <current heap size> *
+    /// `minor_factor` bytes have been allocated (but at least `min_sleep`) before running a minor
+    /// collection.  Must be >= 0.0.
+    pub fn set_minor_factor(mut self, minor_factor: f64) -> ArenaParameters {
+        assert!(minor_factor >= 0.0);
+        self.minor_factor = minor_factor;
+        self
+    }
+
+    /// In generational mode, once the heap has grown by more than `major_factor` times its size
+    /// after the last major collection, the next collection is a major collection which traces
+    /// and frees old objects as well.  Must be >= 0.0.
+    pub fn set_major_factor(mut self, major_factor: f64) -> ArenaParameters {
+        assert!(major_factor >= 0.0);
+        self.major_factor = major_factor;
+        self
+    }
 }
 
 /// Creates a new "garbage collected arena" type.  The macro takes two parameters, the name you
@@ -75,10 +121,10 @@ impl ArenaParameters {
 /// ```
 ///
 /// Garbage collected arenas allow for isolated sets of garbage collected objects with zero-overhead
-/// garbage collected pointers.  It provides incremental mark and sweep garbage collection which
-/// must be manually triggered outside the `mutate` method, and works best when units of work inside
-/// `mutate` can be kept relatively small.  It is designed primarily to be a garbage collector for
-/// scripting language runtimes.
+/// garbage collected pointers.  It provides incremental (or optionally generational) mark and sweep
+/// garbage collection which must be manually triggered outside the `mutate` method, and works best
+/// when units of work inside `mutate` can be kept relatively small.  It is designed primarily to
+/// be a garbage collector for scripting language runtimes.
 ///
 /// The arena API is able to provide extremely cheap Gc pointers because it is based around
 /// "generativity".  During construction and access, the root type is branded by a unique, invariant
@@ -172,6 +218,22 @@ macro_rules! make_arena {
                 self.context.total_allocated()
             }
 
+            /// Returns statistics about the state of the garbage collector and its last finished
+            /// cycle.
+            #[allow(unused)]
+            #[inline]
+            pub fn gc_stats(&self) -> $crate::GcStats {
+                self.context.gc_stats()
+            }
+
+            /// Walks every allocated object and returns the number of objects and bytes allocated
+            /// for each type, largest first.  This takes time proportional to the number of
+            /// allocated objects, and is meant for finding leaks.
+            #[allow(unused)]
+            pub fn heap_profile(&self) -> Vec<$crate::TypeAllocations> {
+                self.context.heap_profile()
+            }
+
             /// When the garbage collector is not sleeping, all allocated objects cause the arena to
             /// accumulate "allocation debt".  This debt is then be used to time incremental garbage
             /// collection based on the tuning parameters set in `ArenaParameters`.  The allocation
@@ -199,7 +261,8 @@ macro_rules! make_arena {
 
             /// Run the current garbage collection cycle to completion, stopping once the garbage
             /// collector has entered the sleeping phase.  If the garbage collector is currently
-            /// sleeping, starts a new cycle and runs that cycle to completion.
+            /// sleeping, starts a new cycle and runs that cycle to completion.  In generational
+            /// mode, this runs a minor collection unless a major collection is due.
             #[allow(unused)]
             pub fn collect_all(&mut self) {
                 self.context.wake();
@@ -5,7 +5,10 @@ use crate::context::CollectionContext;
 /// ensure this certain rules must be followed:
 ///
 ///   1. `Collect::trace` *must* trace over *every* `Gc` pointer held inside this type, and cannot
-///      fail.
+///      fail.  The only exceptions are pointers which are removed by `Collect::clear_weak` if their
+///      target is not reached, after calling `CollectionContext::defer_weak`, and pointers which
+///      are traced by `Collect::resurrect` if their target is not reached, after calling
+///      `CollectionContext::defer_resurrect`.
 ///   2. Held `Gc` pointers must not be accessed inside `Drop::drop` since during drop any such
 ///      pointer may be dangling.
 ///   3. Internal mutability *must* not be used to adopt new `Gc` pointers without calling
@@ -35,4 +38,32 @@ pub unsafe trait Collect {
     /// held values to ensure this.
     #[inline]
     fn trace(&self, _cc: CollectionContext) {}
+
+    /// Called on objects which registered themselves with `CollectionContext::defer_weak` once
+    /// every other reachable object has been traced.  Should trace the values of ephemeron entries
+    /// whose keys have since been reached, as checked by `Gc::is_reached`.  This is called
+    /// repeatedly for as long as it reaches new objects.
+    #[inline]
+    fn trace_ephemerons(&self, _cc: CollectionContext) {}
+
+    /// Called on objects which registered themselves with `CollectionContext::defer_resurrect` once
+    /// `Collect::trace_ephemerons` no longer reaches any new objects.  May resurrect objects which
+    /// were not reached by tracing them, which keeps them and every object they reach alive for
+    /// another cycle.
+    #[inline]
+    fn resurrect(&mut self, _cc: CollectionContext) {}
+
+    /// Called on objects which registered themselves with `CollectionContext::defer_weak` after
+    /// every object which will be kept alive has been reached, right before sweeping.  *Must*
+    /// remove every held `Gc` pointer which `Collect::trace` skipped and whose target was not
+    /// reached, because such targets are about to be freed.
+    #[inline]
+    fn clear_weak(&mut self, _cc: CollectionContext) {}
+
+    /// The name of this type, which heap profiles group allocations by.  There is normally no need
+    /// to implement this method.
+    #[inline]
+    fn type_name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
 }
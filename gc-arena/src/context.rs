@@ -1,10 +1,14 @@
 use std::cell::{Cell, RefCell, UnsafeCell};
+use std::collections::HashMap;
 use std::marker::PhantomData;
+use std::mem::ManuallyDrop;
 use std::ptr::NonNull;
+use std::time::Instant;
 use std::{f64, mem, usize};
 
-use crate::arena::ArenaParameters;
+use crate::arena::{ArenaParameters, CollectorMode};
 use crate::collect::Collect;
+use crate::stats::{CycleStats, GcStats, TypeAllocations};
 use crate::types::{GcBox, GcColor, GcFlags, Invariant};
 
 /// Handle value given by arena callbacks during construction and mutation.  Allows allocating new
@@ -23,6 +27,63 @@ impl<'gc, 'context> MutationContext<'gc, 'context> {
     pub(crate) unsafe fn write_barrier<T: 'gc + Collect>(self, ptr: NonNull<GcBox<T>>) {
         self.context.write_barrier(ptr)
     }
+
+    pub(crate) unsafe fn upgrade<T: 'gc + Collect>(self, ptr: NonNull<GcBox<T>>) -> bool {
+        self.context.upgrade(ptr)
+    }
+
+    pub(crate) unsafe fn set_external_size<T: 'gc + Collect>(
+        self,
+        ptr: NonNull<GcBox<T>>,
+        size: usize,
+    ) {
+        self.context.set_external_size(ptr, size)
+    }
+
+    // Whether the collector is in the middle of tracing reachable objects
+    pub(crate) fn is_propagating(self) -> bool {
+        self.context.phase.get() == Phase::Propagate
+    }
+
+    /// Return total currently used memory
+    #[inline]
+    pub fn total_allocated(self) -> usize {
+        self.context.total_allocated()
+    }
+
+    /// Returns statistics about the state of the garbage collector and its last finished cycle.
+    #[inline]
+    pub fn gc_stats(self) -> GcStats {
+        self.context.gc_stats()
+    }
+
+    /// Collection cannot happen during mutation, so this requests that the next collection of the
+    /// arena finishes any cycle in progress and then runs a complete new cycle.  Until then, the
+    /// allocation debt of the arena is infinite.
+    #[inline]
+    pub fn request_full_collection(self) {
+        self.context.full_collection_requested.set(true);
+    }
+
+    /// The mode of the collector, or the mode it will switch to if a switch has been requested
+    /// with `set_collector_mode`.
+    #[inline]
+    pub fn collector_mode(self) -> CollectorMode {
+        self.context
+            .requested_mode
+            .get()
+            .unwrap_or_else(|| self.context.mode.get())
+    }
+
+    /// Requests that the collector switch to the given mode.  Collection cannot happen during
+    /// mutation, so the switch happens at the start of the next collection, which finishes any
+    /// cycle in progress and then runs a full collection in the new mode.
+    pub fn set_collector_mode(self, mode: CollectorMode) {
+        if mode != self.collector_mode() {
+            self.context.requested_mode.set(Some(mode));
+            self.request_full_collection();
+        }
+    }
 }
 
 /// Handle value given by arena callbacks during garbage collection, which must be passed through
@@ -36,6 +97,33 @@ impl<'context> CollectionContext<'context> {
     pub(crate) unsafe fn trace<T: Collect>(self, ptr: NonNull<GcBox<T>>) {
         self.context.trace(ptr)
     }
+
+    pub(crate) unsafe fn trace_weak<T: Collect>(self, ptr: NonNull<GcBox<T>>) {
+        self.context.trace_weak(ptr)
+    }
+
+    pub(crate) unsafe fn is_reached<T: Collect>(self, ptr: NonNull<GcBox<T>>) -> bool {
+        self.context.is_reached(ptr)
+    }
+
+    /// Registers the object whose value is currently being traced to have
+    /// `Collect::trace_ephemerons` and `Collect::clear_weak` called on its value later in this
+    /// collection cycle, so that it can hold weak or ephemeron entries.  Does nothing while tracing
+    /// the arena root.
+    pub fn defer_weak(self) {
+        if let Some(ptr) = self.context.tracing.get() {
+            self.context.weak.borrow_mut().push(ptr);
+        }
+    }
+
+    /// Registers the object whose value is currently being traced to have `Collect::resurrect`
+    /// called on its value later in this collection cycle.  Does nothing while tracing the arena
+    /// root.
+    pub fn defer_resurrect(self) {
+        if let Some(ptr) = self.context.tracing.get() {
+            self.context.resurrect.borrow_mut().push(ptr);
+        }
+    }
 }
 
 // Main gc context type, public because it must be accessible from the `make_arena!` macro.
@@ -48,6 +136,21 @@ pub struct Context {
     remembered_size: Cell<usize>,
     wakeup_total: Cell<usize>,
     allocation_debt: Cell<f64>,
+    full_collection_requested: Cell<bool>,
+
+    mode: Cell<CollectorMode>,
+    // The mode to switch to at the start of the next collection
+    requested_mode: Cell<Option<CollectorMode>>,
+    // Whether the current collection only traces and frees young objects
+    minor: Cell<bool>,
+    // In generational mode, the total allocation above which the next collection is major
+    major_threshold: Cell<usize>,
+
+    cycles: Cell<u64>,
+    live_objects: Cell<usize>,
+    // Statistics for the cycle in progress
+    cycle_stats: Cell<CycleStats>,
+    last_cycle: Cell<Option<CycleStats>>,
 
     all: Cell<Option<NonNull<GcBox<Collect>>>>,
     sweep: Cell<Option<NonNull<GcBox<Collect>>>>,
@@ -55,6 +158,16 @@ pub struct Context {
 
     gray: RefCell<Vec<NonNull<GcBox<Collect>>>>,
     gray_again: RefCell<Vec<NonNull<GcBox<Collect>>>>,
+
+    // The object whose value is currently being traced, if any
+    tracing: Cell<Option<NonNull<GcBox<dyn Collect>>>>,
+    // Objects which must have their weak entries processed once propagation is finished
+    weak: RefCell<Vec<NonNull<GcBox<dyn Collect>>>>,
+    // Objects which may resurrect unreached objects once propagation is finished
+    resurrect: RefCell<Vec<NonNull<GcBox<dyn Collect>>>>,
+    // The remembered set of old objects which have been mutated since the last collection in
+    // generational mode, and so may point to young objects
+    touched: RefCell<Vec<NonNull<GcBox<dyn Collect>>>>,
 }
 
 impl Drop for Context {
@@ -69,7 +182,7 @@ impl Drop for Context {
                         while let Some(ptr) = drop_resume.0.take() {
                             let gc_box = ptr.as_ref();
                             drop_resume.0 = gc_box.next.get();
-                            Box::from_raw(ptr.as_ptr());
+                            free_gc_box(ptr);
                         }
                     }
                 }
@@ -83,17 +196,30 @@ impl Drop for Context {
 impl Context {
     pub unsafe fn new(parameters: ArenaParameters) -> Context {
         Context {
-            parameters,
             phase: Cell::new(Phase::Wake),
             total_allocated: Cell::new(0),
             remembered_size: Cell::new(0),
             wakeup_total: Cell::new(0),
             allocation_debt: Cell::new(0.0),
+            full_collection_requested: Cell::new(false),
+            mode: Cell::new(parameters.mode),
+            requested_mode: Cell::new(None),
+            minor: Cell::new(false),
+            major_threshold: Cell::new(0),
+            cycles: Cell::new(0),
+            live_objects: Cell::new(0),
+            cycle_stats: Cell::new(CycleStats::default()),
+            last_cycle: Cell::new(None),
             all: Cell::new(None),
             sweep: Cell::new(None),
             sweep_prev: Cell::new(None),
             gray: RefCell::new(Vec::new()),
             gray_again: RefCell::new(Vec::new()),
+            tracing: Cell::new(None),
+            weak: RefCell::new(Vec::new()),
+            resurrect: RefCell::new(Vec::new()),
+            touched: RefCell::new(Vec::new()),
+            parameters,
         }
     }
 
@@ -110,7 +236,11 @@ impl Context {
 
     #[inline]
     pub fn allocation_debt(&self) -> f64 {
-        self.allocation_debt.get()
+        if self.full_collection_requested.get() {
+            f64::INFINITY
+        } else {
+            self.allocation_debt.get()
+        }
     }
 
     #[inline]
@@ -118,6 +248,48 @@ impl Context {
         self.total_allocated.get()
     }
 
+    pub fn gc_stats(&self) -> GcStats {
+        GcStats {
+            cycles: self.cycles.get(),
+            phase: self.phase.get(),
+            mode: self.mode.get(),
+            total_allocated: self.total_allocated.get(),
+            live_objects: self.live_objects.get(),
+            last_cycle: self.last_cycle.get(),
+        }
+    }
+
+    // Walks every allocated object, and returns the number of objects and bytes allocated for each
+    // type, largest first.
+    pub fn heap_profile(&self) -> Vec<TypeAllocations> {
+        let mut types = HashMap::new();
+        let mut next = self.all.get();
+        while let Some(ptr) = next {
+            let gc_box = unsafe { ptr.as_ref() };
+            let type_name = if gc_box.flags.is_dead() {
+                "(dropped)"
+            } else {
+                unsafe { (*gc_box.value.get()).type_name() }
+            };
+            let allocations = types.entry(type_name).or_insert(TypeAllocations {
+                type_name,
+                objects: 0,
+                bytes: 0,
+            });
+            allocations.objects += 1;
+            allocations.bytes += box_size(gc_box);
+            next = gc_box.next.get();
+        }
+
+        let mut types: Vec<TypeAllocations> = types.into_values().collect();
+        types.sort_by(|a, b| {
+            b.bytes
+                .cmp(&a.bytes)
+                .then_with(|| a.type_name.cmp(b.type_name))
+        });
+        types
+    }
+
     // If the garbage collector is currently in the sleep phase, transition to the wake phase.
     pub fn wake(&self) {
         if self.phase.get() == Phase::Sleep {
@@ -133,7 +305,32 @@ impl Context {
     //
     // In order for this to be safe, at the time of call no `Gc` pointers can be live that are not
     // reachable from the given root object.
-    pub unsafe fn do_collection<R: Collect>(&self, root: &R, work: f64) -> f64 {
+    pub unsafe fn do_collection<R: Collect>(&self, root: &R, mut work: f64) -> f64 {
+        let mut full = false;
+        if self.full_collection_requested.replace(false) {
+            // If a full collection has been requested, any cycle in progress may have already
+            // marked objects that have since become unreachable, so we finish it before running a
+            // complete new cycle.  The collector may only switch modes between cycles.
+            if self.phase.get() != Phase::Sleep && self.phase.get() != Phase::Wake {
+                self.do_collection(root, f64::INFINITY);
+            }
+            if let Some(mode) = self.requested_mode.take() {
+                self.mode.set(mode);
+            }
+            self.wake();
+            work = f64::INFINITY;
+            full = true;
+        }
+
+        if self.mode.get() == CollectorMode::Generational {
+            // Generational collections are not incremental, since the mutator could otherwise
+            // make young objects reachable only through old objects which have already been
+            // passed over.
+            work = f64::INFINITY;
+        }
+
+        let call_start = Instant::now();
+        let mut timer = call_start;
         let mut work_done = 0.0;
         let cc = CollectionContext { context: self };
 
@@ -142,14 +339,29 @@ impl Context {
                 Phase::Wake => {
                     // In the Wake phase, we trace the root object and add its children to the gray
                     // queue, and transition to the propagate phase.
+                    self.minor.set(
+                        self.mode.get() == CollectorMode::Generational
+                            && !full
+                            && self.total_allocated.get() <= self.major_threshold.get(),
+                    );
                     root.trace(cc);
 
+                    if self.minor.get() {
+                        // A minor collection does not trace old objects, except for those in the
+                        // remembered set which may now point to young objects.
+                        for &ptr in self.touched.borrow().iter() {
+                            self.tracing.set(Some(ptr));
+                            (*ptr.as_ref().value.get()).trace(cc);
+                            self.tracing.set(None);
+                        }
+                    }
+
                     let root_size = mem::size_of::<R>() as f64;
                     work_done += root_size;
                     self.allocation_debt
                         .set((self.allocation_debt.get() - root_size).max(0.0));
 
-                    self.phase.set(Phase::Propagate);
+                    self.set_phase(Phase::Propagate, &mut timer);
                 }
                 Phase::Propagate => {
                     // We look for an object first in the normal gray queue, then the "gray again"
@@ -158,7 +370,7 @@ impl Context {
                     // double count them.  Processing "gray again" objects later also gives them
                     // more time to be mutated again without triggering another write barrier.
                     let next_gray = if let Some(ptr) = self.gray.borrow_mut().pop() {
-                        let gray_size = mem::size_of_val(ptr.as_ref()) as f64;
+                        let gray_size = box_size(ptr.as_ref()) as f64;
                         work_done += gray_size;
                         self.allocation_debt
                             .set((self.allocation_debt.get() - gray_size).max(0.0));
@@ -173,27 +385,53 @@ impl Context {
                         // If we have an object in the gray queue, take one, trace it, and turn it
                         // black.
                         let gc_box = ptr.as_ref();
+                        self.tracing.set(Some(ptr));
                         (*gc_box.value.get()).trace(cc);
+                        self.tracing.set(None);
                         gc_box.flags.set_color(GcColor::Black);
+                    } else if self.trace_ephemerons(cc) {
+                        // Ephemeron entries reached new objects, which must be propagated before
+                        // checking the ephemerons again.
+                    } else if self.resurrect(cc) {
+                        // Resurrected objects must be propagated, and may reach more ephemeron
+                        // entries.
                     } else {
-                        // If we have no objects left in the normal gray queue, we enter the sweep
-                        // phase.
-                        self.phase.set(Phase::Sweep);
+                        // If we have no objects left in the normal gray queue, every reachable
+                        // object has been reached, so we can clear weak entries and enter the
+                        // sweep phase.
+                        for ptr in self.weak.borrow_mut().drain(..) {
+                            (**ptr.as_ref().value.get()).clear_weak(cc);
+                        }
+                        // Every object which survives the sweep becomes old, so none of them can
+                        // point to young objects.  The remembered set must be cleared before
+                        // sweeping, which may free objects in it.
+                        for ptr in self.touched.borrow_mut().drain(..) {
+                            ptr.as_ref().flags.set_touched(false);
+                        }
+                        self.set_phase(Phase::Sweep, &mut timer);
                         self.sweep.set(self.all.get());
                     }
                 }
                 Phase::Sweep => {
-                    if let Some(sweep_ptr) = self.sweep.get() {
+                    // Young objects are always at the beginning of the main list, and a minor
+                    // collection stops sweeping at the first old object.
+                    let sweep_ptr = self
+                        .sweep
+                        .get()
+                        .filter(|ptr| !(self.minor.get() && ptr.as_ref().flags.is_old()));
+                    if let Some(sweep_ptr) = sweep_ptr {
                         let sweep = sweep_ptr.as_ref();
-                        let sweep_size = mem::size_of_val(sweep);
+                        let sweep_size = box_size(sweep);
 
                         let next_ptr = sweep.next.get();
                         self.sweep.set(next_ptr);
 
                         // If the next object in the sweep list is white, we need to remove it from
-                        // the main list and destruct it, otherwise it should be black, and we
-                        // simply turn it white again.
-                        if sweep.flags.color() == GcColor::White {
+                        // the main list and destruct it.  If it is only reachable through weak
+                        // pointers we destruct its value but keep it in the main list, and
+                        // otherwise it should be black, and we simply turn it white again.
+                        let color = sweep.flags.color();
+                        if color == GcColor::White {
                             // If the next object in the sweep portion of the main list is white, we
                             // need to remove it from the main object list and destruct it.
                             if let Some(sweep_prev) = self.sweep_prev.get() {
@@ -206,10 +444,31 @@ impl Context {
                             }
                             self.total_allocated
                                 .set(self.total_allocated.get() - sweep_size);
+                            self.live_objects.set(self.live_objects.get() - 1);
+                            let mut stats = self.cycle_stats.get();
+                            stats.objects_freed += 1;
+                            stats.bytes_freed += sweep_size;
+                            self.cycle_stats.set(stats);
                             work_done += sweep_size as f64;
                             self.allocation_debt
                                 .set((self.allocation_debt.get() - sweep_size as f64).max(0.0));
-                            Box::from_raw(sweep_ptr.as_ptr());
+                            free_gc_box(sweep_ptr);
+                        } else if color == GcColor::WhiteWeak {
+                            // The allocation is freed in a later cycle where no weak pointers
+                            // reach it.
+                            if !sweep.flags.is_dead() {
+                                sweep.flags.set_dead();
+                                ManuallyDrop::drop(&mut *sweep.value.get());
+                                let external_size = sweep.external_size.replace(0);
+                                self.total_allocated
+                                    .set(self.total_allocated.get() - external_size);
+                                let mut stats = self.cycle_stats.get();
+                                stats.bytes_freed += external_size;
+                                self.cycle_stats.set(stats);
+                            }
+                            self.sweep_prev.set(Some(sweep_ptr));
+                            sweep.flags.set_color(GcColor::White);
+                            self.promote(sweep);
                         } else {
                             // If the next object in the sweep portion of the main list is black, we
                             // need to keep it but turn it back white.  No gray objects should be in
@@ -221,35 +480,109 @@ impl Context {
                             self.remembered_size
                                 .set(self.remembered_size.get() + sweep_size);
                             sweep.flags.set_color(GcColor::White);
+                            self.promote(sweep);
                         }
                     } else {
                         // We are done sweeping, so enter the sleeping phase.
+                        self.sweep.set(None);
                         self.sweep_prev.set(None);
-                        self.phase.set(Phase::Sleep);
+                        self.set_phase(Phase::Sleep, &mut timer);
+                        self.record_pause(call_start);
 
                         // Do not let debt accumulate across cycles, when we enter sleep, zero the debt out.
                         self.allocation_debt.set(0.0);
 
-                        self.wakeup_total.set(
-                            self.total_allocated.get()
-                                + ((self.remembered_size.get() as f64
-                                    * self.parameters.pause_factor)
-                                    .round()
-                                    .min(usize::MAX as f64)
-                                    as usize)
+                        let total_allocated = self.total_allocated.get();
+                        if self.mode.get() == CollectorMode::Generational {
+                            if !self.minor.get() {
+                                self.major_threshold.set(
+                                    total_allocated
+                                        + scale_size(total_allocated, self.parameters.major_factor),
+                                );
+                            }
+                            self.wakeup_total.set(
+                                total_allocated
+                                    + scale_size(total_allocated, self.parameters.minor_factor)
+                                        .max(self.parameters.min_sleep),
+                            );
+                        } else {
+                            self.wakeup_total.set(
+                                total_allocated
+                                    + scale_size(
+                                        self.remembered_size.get(),
+                                        self.parameters.pause_factor,
+                                    )
                                     .max(self.parameters.min_sleep),
-                        );
+                            );
+                        }
+
+                        let mut stats = self.cycle_stats.replace(CycleStats::default());
+                        stats.minor = self.minor.replace(false);
+                        stats.live_objects = self.live_objects.get();
+                        self.last_cycle.set(Some(stats));
+                        self.cycles.set(self.cycles.get() + 1);
                     }
                 }
                 Phase::Sleep => break,
             }
         }
 
+        if self.phase.get() != Phase::Sleep {
+            self.record_time(&mut timer);
+            self.record_pause(call_start);
+        }
+
         work_done
     }
 
+    // Switches to the given phase, first adding the time spent in the current phase to the stats
+    // of the cycle in progress.
+    fn set_phase(&self, phase: Phase, timer: &mut Instant) {
+        self.record_time(timer);
+        self.phase.set(phase);
+    }
+
+    fn record_time(&self, timer: &mut Instant) {
+        let now = Instant::now();
+        let mut stats = self.cycle_stats.get();
+        match self.phase.get() {
+            Phase::Wake | Phase::Propagate => stats.propagate += now - *timer,
+            Phase::Sweep => stats.sweep += now - *timer,
+            Phase::Sleep => {}
+        }
+        self.cycle_stats.set(stats);
+        *timer = now;
+    }
+
+    fn record_pause(&self, call_start: Instant) {
+        let mut stats = self.cycle_stats.get();
+        stats.max_pause = stats.max_pause.max(call_start.elapsed());
+        self.cycle_stats.set(stats);
+    }
+
     unsafe fn allocate<T: Collect>(&self, t: T) -> NonNull<GcBox<T>> {
-        let alloc_size = mem::size_of::<GcBox<T>>();
+        self.add_allocated(mem::size_of::<GcBox<T>>());
+        self.live_objects.set(self.live_objects.get() + 1);
+
+        let gc_box = GcBox {
+            flags: GcFlags::new(),
+            next: Cell::new(self.all.get()),
+            external_size: Cell::new(0),
+            value: UnsafeCell::new(ManuallyDrop::new(t)),
+        };
+        gc_box.flags.set_needs_trace(T::needs_trace());
+        let ptr = NonNull::new_unchecked(Box::into_raw(Box::new(gc_box)));
+        self.all.set(Some(static_gc_box(ptr)));
+        if self.phase.get() == Phase::Sweep && self.sweep_prev.get().is_none() {
+            self.sweep_prev.set(self.all.get());
+        }
+
+        ptr
+    }
+
+    // Counts newly allocated memory, which wakes the collector and adds to the allocation debt just
+    // like allocating new objects.
+    fn add_allocated(&self, alloc_size: usize) {
         self.total_allocated
             .set(self.total_allocated.get() + alloc_size);
         if self.phase.get() == Phase::Sleep && self.total_allocated.get() > self.wakeup_total.get()
@@ -264,20 +597,17 @@ impl Context {
                     + alloc_size as f64 / self.parameters.timing_factor,
             );
         }
+    }
 
-        let gc_box = GcBox {
-            flags: GcFlags::new(),
-            next: Cell::new(self.all.get()),
-            value: UnsafeCell::new(t),
-        };
-        gc_box.flags.set_needs_trace(T::needs_trace());
-        let ptr = NonNull::new_unchecked(Box::into_raw(Box::new(gc_box)));
-        self.all.set(Some(static_gc_box(ptr)));
-        if self.phase.get() == Phase::Sweep && self.sweep_prev.get().is_none() {
-            self.sweep_prev.set(self.all.get());
+    unsafe fn set_external_size<T: Collect>(&self, ptr: NonNull<GcBox<T>>, size: usize) {
+        let gc_box = ptr.as_ref();
+        let old_size = gc_box.external_size.replace(size);
+        if size > old_size {
+            self.add_allocated(size - old_size);
+        } else {
+            self.total_allocated
+                .set(self.total_allocated.get() - (old_size - size));
         }
-
-        ptr
     }
 
     unsafe fn write_barrier<T: Collect>(&self, ptr: NonNull<GcBox<T>>) {
@@ -289,13 +619,50 @@ impl Context {
             gc_box.flags.set_color(GcColor::Gray);
             self.gray_again.borrow_mut().push(static_gc_box(ptr));
         }
+
+        // In generational mode, a mutated old object may now point to young objects, so it must be
+        // traced by the next minor collection.
+        if self.mode.get() == CollectorMode::Generational
+            && gc_box.flags.is_old()
+            && !gc_box.flags.is_touched()
+            && gc_box.flags.needs_trace()
+        {
+            gc_box.flags.set_touched(true);
+            self.touched.borrow_mut().push(static_gc_box(ptr));
+        }
+    }
+
+    // Returns whether a weak pointer to the given object may be upgraded to a `Gc` pointer.
+    unsafe fn upgrade<T: Collect>(&self, ptr: NonNull<GcBox<T>>) -> bool {
+        let gc_box = ptr.as_ref();
+        if gc_box.flags.is_dead() {
+            return false;
+        }
+
+        match self.phase.get() {
+            // An object that was only reachable through weak pointers when propagation finished
+            // has not been swept yet, but it will be, so it must stay unreachable.
+            Phase::Sweep => gc_box.flags.color() != GcColor::WhiteWeak,
+            // The object may already have been passed over by weak pointers, and must now be kept
+            // alive through the rest of the cycle.
+            Phase::Propagate => {
+                self.trace(ptr);
+                true
+            }
+            Phase::Wake | Phase::Sleep => true,
+        }
     }
 
     unsafe fn trace<T: Collect>(&self, ptr: NonNull<GcBox<T>>) {
         let gc_box = ptr.as_ref();
+        if self.minor.get() && gc_box.flags.is_old() {
+            // Old objects are considered reached throughout a minor collection.
+            return;
+        }
+
         match gc_box.flags.color() {
             GcColor::Black | GcColor::Gray => {}
-            GcColor::White => {
+            GcColor::White | GcColor::WhiteWeak => {
                 if gc_box.flags.needs_trace() {
                     // A white traceable object is not in the gray queue, becomes gray and enters
                     // the normal gray queue.
@@ -308,13 +675,80 @@ impl Context {
             }
         }
     }
+
+    unsafe fn is_reached<T: Collect>(&self, ptr: NonNull<GcBox<T>>) -> bool {
+        let gc_box = ptr.as_ref();
+        match gc_box.flags.color() {
+            GcColor::Black | GcColor::Gray => true,
+            GcColor::White | GcColor::WhiteWeak => self.minor.get() && gc_box.flags.is_old(),
+        }
+    }
+
+    // Traces the ephemeron entries of every object which deferred its weak entries, and returns
+    // whether any new objects were reached.
+    unsafe fn trace_ephemerons(&self, cc: CollectionContext) -> bool {
+        let weak = self.weak.borrow();
+        for &ptr in weak.iter() {
+            (*ptr.as_ref().value.get()).trace_ephemerons(cc);
+        }
+        !self.gray.borrow().is_empty()
+    }
+
+    // Lets every object which deferred resurrection resurrect unreached objects, and returns
+    // whether any were.
+    unsafe fn resurrect(&self, cc: CollectionContext) -> bool {
+        let resurrect = mem::take(&mut *self.resurrect.borrow_mut());
+        for ptr in resurrect {
+            (**ptr.as_ref().value.get()).resurrect(cc);
+        }
+        !self.gray.borrow().is_empty()
+    }
+
+    // Weak pointers do not keep an object alive, but mark that the object's allocation must be kept
+    // if it is swept.
+    unsafe fn trace_weak<T: Collect>(&self, ptr: NonNull<GcBox<T>>) {
+        let gc_box = ptr.as_ref();
+        if gc_box.flags.color() == GcColor::White && !(self.minor.get() && gc_box.flags.is_old()) {
+            gc_box.flags.set_color(GcColor::WhiteWeak);
+        }
+    }
+
+    // In generational mode, objects which survive a collection become old.
+    fn promote(&self, gc_box: &GcBox<dyn Collect>) {
+        if self.mode.get() == CollectorMode::Generational {
+            gc_box.flags.set_old();
+        }
+    }
+}
+
+fn scale_size(size: usize, factor: f64) -> usize {
+    (size as f64 * factor).round().min(usize::MAX as f64) as usize
+}
+
+// The memory used by an object, including the memory its value owns outside of the allocation.
+fn box_size(gc_box: &GcBox<dyn Collect>) -> usize {
+    mem::size_of_val(gc_box) + gc_box.external_size.get()
+}
+
+// Drops the value of the given object if it has not already been dropped, and frees it.
+unsafe fn free_gc_box(ptr: NonNull<GcBox<dyn Collect>>) {
+    let gc_box = ptr.as_ref();
+    if !gc_box.flags.is_dead() {
+        ManuallyDrop::drop(&mut *gc_box.value.get());
+    }
+    drop(Box::from_raw(ptr.as_ptr()));
 }
 
+/// The phases of a garbage collection cycle.
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
-enum Phase {
+pub enum Phase {
+    /// A new cycle is due, and will start by tracing the arena root.
     Wake,
+    /// Reachable objects are being traced.
     Propagate,
+    /// Unreachable objects are being freed.
     Sweep,
+    /// The collector is waiting for enough allocation to start a new cycle.
     Sleep,
 }
 
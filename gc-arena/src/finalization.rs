@@ -0,0 +1,141 @@
+use std::collections::{HashSet, VecDeque};
+
+use crate::collect::Collect;
+use crate::context::{CollectionContext, MutationContext};
+use crate::gc::Gc;
+use crate::gc_cell::GcCell;
+
+/// Values pointing to a garbage collected object, which may be registered with a
+/// `FinalizationQueue`.
+pub trait Finalizable<'gc>: Collect + Copy {
+    /// The address of the object pointed to, which identifies it.
+    fn as_ptr(&self) -> *const ();
+
+    /// Whether the object pointed to has been reached in the current collection cycle, see
+    /// `Gc::is_reached`.
+    fn is_reached(&self, cc: CollectionContext) -> bool;
+}
+
+impl<'gc, T: 'gc + Collect> Finalizable<'gc> for Gc<'gc, T> {
+    fn as_ptr(&self) -> *const () {
+        Gc::as_ptr(*self) as *const ()
+    }
+
+    fn is_reached(&self, cc: CollectionContext) -> bool {
+        Gc::is_reached(cc, *self)
+    }
+}
+
+impl<'gc, T: 'gc + Collect> Finalizable<'gc> for GcCell<'gc, T> {
+    fn as_ptr(&self) -> *const () {
+        GcCell::as_ptr(*self) as *const ()
+    }
+
+    fn is_reached(&self, cc: CollectionContext) -> bool {
+        GcCell::is_reached(cc, *self)
+    }
+}
+
+/// A set of objects which are not freed when they become unreachable, but instead are resurrected
+/// once and handed back to the mutator through `FinalizationQueue::take`, so that they may be
+/// finalized.
+///
+/// Objects reachable from a queued object are also kept alive.  Once taken from the queue, an
+/// object is no longer registered and is freed as normal, unless it is registered again.  The
+/// queue itself must stay reachable, objects registered with an unreachable queue are freed
+/// without being queued.
+pub struct FinalizationQueue<'gc, T: 'gc + Finalizable<'gc>>(GcCell<'gc, QueueState<T>>);
+
+impl<'gc, T: 'gc + Finalizable<'gc>> Copy for FinalizationQueue<'gc, T> {}
+
+impl<'gc, T: 'gc + Finalizable<'gc>> Clone for FinalizationQueue<'gc, T> {
+    fn clone(&self) -> FinalizationQueue<'gc, T> {
+        *self
+    }
+}
+
+unsafe impl<'gc, T: 'gc + Finalizable<'gc>> Collect for FinalizationQueue<'gc, T> {
+    fn trace(&self, cc: CollectionContext) {
+        self.0.trace(cc)
+    }
+}
+
+impl<'gc, T: 'gc + Finalizable<'gc>> FinalizationQueue<'gc, T> {
+    pub fn new(mc: MutationContext<'gc, '_>) -> FinalizationQueue<'gc, T> {
+        FinalizationQueue(GcCell::allocate(
+            mc,
+            QueueState {
+                registered: Vec::new(),
+                new: Vec::new(),
+                pending: VecDeque::new(),
+                objects: HashSet::new(),
+            },
+        ))
+    }
+
+    /// Registers the given object to be queued once it becomes unreachable.  Returns false if the
+    /// object is already registered or queued, in which case this does nothing.
+    pub fn register(&self, mc: MutationContext<'gc, '_>, object: T) -> bool {
+        let mut state = self.0.write(mc);
+        if state.objects.insert(object.as_ptr()) {
+            if mc.is_propagating() {
+                state.new.push(object);
+            } else {
+                state.registered.push(object);
+            }
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Takes the object which was queued first, if any.
+    pub fn take(&self, mc: MutationContext<'gc, '_>) -> Option<T> {
+        let mut state = self.0.write(mc);
+        let object = state.pending.pop_front()?;
+        state.objects.remove(&object.as_ptr());
+        Some(object)
+    }
+
+    /// The number of objects waiting to be taken.
+    pub fn pending(&self) -> usize {
+        self.0.read().pending.len()
+    }
+}
+
+struct QueueState<T> {
+    // Objects which are not traced, and are queued once they are not reached
+    registered: Vec<T>,
+    // Objects registered while the collector was propagating, which are traced until the end of
+    // propagation, because they may not have been reached when they were registered
+    new: Vec<T>,
+    pending: VecDeque<T>,
+    // The addresses of every registered or queued object
+    objects: HashSet<*const ()>,
+}
+
+// Safe, does not implement drop and only skips tracing objects which are resurrected if they are
+// not reached
+unsafe impl<'gc, T: Finalizable<'gc>> Collect for QueueState<T> {
+    fn trace(&self, cc: CollectionContext) {
+        cc.defer_resurrect();
+        self.new.trace(cc);
+        for object in &self.pending {
+            object.trace(cc);
+        }
+    }
+
+    fn resurrect(&mut self, cc: CollectionContext) {
+        let pending = &mut self.pending;
+        self.registered.retain(|object| {
+            if object.is_reached(cc) {
+                true
+            } else {
+                object.trace(cc);
+                pending.push_back(*object);
+                false
+            }
+        });
+        self.registered.append(&mut self.new);
+    }
+}
@@ -5,6 +5,7 @@ use std::ptr::NonNull;
 
 use crate::collect::Collect;
 use crate::context::{CollectionContext, MutationContext};
+use crate::gc_weak::GcWeak;
 use crate::types::{GcBox, Invariant};
 
 /// A garbage collected pointer to a type T.  Implements Copy, and is implemented as a plain machine
@@ -14,20 +15,18 @@ use crate::types::{GcBox, Invariant};
 /// pointers will never be dangling and are always safe to access.
 pub struct Gc<'gc, T: 'gc + Collect> {
     pub(crate) ptr: NonNull<GcBox<T>>,
-    _invariant: Invariant<'gc>,
+    pub(crate) _invariant: Invariant<'gc>,
 }
 
 impl<'gc, T: 'gc + Collect + Debug> Debug for Gc<'gc, T> {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
-        fmt.debug_struct("Gc")
-            .field("ptr", unsafe { &*self.ptr.as_ref().value.get() })
-            .finish()
+        fmt.debug_struct("Gc").field("ptr", &**self).finish()
     }
 }
 
 impl<'gc, T: 'gc + Collect + Display> Display for Gc<'gc, T> {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
-        write!(fmt, "{}", unsafe { &*self.ptr.as_ref().value.get() })
+        write!(fmt, "{}", &**self)
     }
 }
 
@@ -72,11 +71,31 @@ impl<'gc, T: 'gc + Collect> Gc<'gc, T> {
         }
     }
 
+    /// Records the size of the memory owned by the object outside of its allocation, such as the
+    /// buffer of a `Vec`, replacing any previously recorded size.  This memory is counted by
+    /// `total_allocated` and the collector pacing until the object is freed.
+    pub fn set_external_size(mc: MutationContext<'gc, '_>, gc: Self, size: usize) {
+        unsafe {
+            mc.set_external_size(gc.ptr, size);
+        }
+    }
+
+    /// Creates a weak pointer to the same object, which does not keep it alive.
+    pub fn downgrade(this: Gc<'gc, T>) -> GcWeak<'gc, T> {
+        GcWeak { inner: this }
+    }
+
+    /// Whether the object has been reached in the current collection cycle, and so will not be
+    /// freed by it.  Meant to be called from `Collect::trace_ephemerons` and `Collect::clear_weak`.
+    pub fn is_reached(cc: CollectionContext, gc: Self) -> bool {
+        unsafe { cc.is_reached(gc.ptr) }
+    }
+
     pub fn ptr_eq(this: Gc<'gc, T>, other: Gc<'gc, T>) -> bool {
         Gc::as_ptr(this) == Gc::as_ptr(other)
     }
 
     pub fn as_ptr(gc: Gc<'gc, T>) -> *const T {
-        unsafe { gc.ptr.as_ref().value.get() }
+        unsafe { &**gc.ptr.as_ref().value.get() as *const T }
     }
 }
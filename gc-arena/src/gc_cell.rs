@@ -4,13 +4,14 @@ use std::fmt::{self, Debug};
 use crate::collect::Collect;
 use crate::context::{CollectionContext, MutationContext};
 use crate::gc::Gc;
+use crate::gc_weak_cell::GcWeakCell;
 
 /// A garbage collected pointer to a type T that may be safely mutated.  When a type that may hold
 /// `Gc` pointers is mutated, it may adopt new `Gc` pointers, and in order for this to be safe this
 /// must be accompanied by a call to `Gc::write_barrier`.  This type wraps the given `T` in a
 /// `RefCell` in such a way that writing to the `RefCell` is always accompanied by a call to
 /// `Gc::write_barrier`.
-pub struct GcCell<'gc, T: 'gc + Collect>(Gc<'gc, GcRefCell<T>>);
+pub struct GcCell<'gc, T: 'gc + Collect>(pub(crate) Gc<'gc, GcRefCell<T>>);
 
 impl<'gc, T: Collect + 'gc> Copy for GcCell<'gc, T> {}
 
@@ -44,6 +45,21 @@ impl<'gc, T: 'gc + Collect> GcCell<'gc, T> {
         ))
     }
 
+    pub fn downgrade(this: GcCell<'gc, T>) -> GcWeakCell<'gc, T> {
+        GcWeakCell(Gc::downgrade(this.0))
+    }
+
+    /// Whether the object has been reached in the current collection cycle, see `Gc::is_reached`.
+    pub fn is_reached(cc: CollectionContext, this: GcCell<'gc, T>) -> bool {
+        Gc::is_reached(cc, this.0)
+    }
+
+    /// Records the size of the memory owned by the object outside of its allocation, see
+    /// `Gc::set_external_size`.
+    pub fn set_external_size(mc: MutationContext<'gc, '_>, this: GcCell<'gc, T>, size: usize) {
+        Gc::set_external_size(mc, this.0, size)
+    }
+
     pub fn ptr_eq(this: GcCell<'gc, T>, other: GcCell<'gc, T>) -> bool {
         this.as_ptr() == other.as_ptr()
     }
@@ -76,12 +92,30 @@ impl<'gc, T: 'gc + Collect> GcCell<'gc, T> {
     }
 }
 
-struct GcRefCell<T: Collect> {
-    cell: RefCell<T>,
+pub(crate) struct GcRefCell<T: Collect> {
+    pub(crate) cell: RefCell<T>,
 }
 
 unsafe impl<'gc, T: Collect + 'gc> Collect for GcRefCell<T> {
     fn trace(&self, cc: CollectionContext) {
         self.cell.borrow().trace(cc);
     }
+
+    fn trace_ephemerons(&self, cc: CollectionContext) {
+        self.cell.borrow().trace_ephemerons(cc);
+    }
+
+    fn resurrect(&mut self, cc: CollectionContext) {
+        self.cell.get_mut().resurrect(cc);
+    }
+
+    fn clear_weak(&mut self, cc: CollectionContext) {
+        self.cell.get_mut().clear_weak(cc);
+    }
+
+    // `GcCell` allocations are reported as the inner type, which the cell may be mutably borrowed
+    // for.
+    fn type_name(&self) -> &'static str {
+        std::any::type_name::<T>()
+    }
 }
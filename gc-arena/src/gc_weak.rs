@@ -0,0 +1,49 @@
+use std::fmt::{self, Debug};
+
+use crate::collect::Collect;
+use crate::context::{CollectionContext, MutationContext};
+use crate::gc::Gc;
+
+/// A weak pointer to a garbage collected object of type T, created with `Gc::downgrade`.  Tracing a
+/// `GcWeak` does not keep its target alive, once the target is no longer reachable through strong
+/// pointers it is dropped during the next collection and `GcWeak::upgrade` returns `None`.
+pub struct GcWeak<'gc, T: 'gc + Collect> {
+    pub(crate) inner: Gc<'gc, T>,
+}
+
+impl<'gc, T: Collect + 'gc> Copy for GcWeak<'gc, T> {}
+
+impl<'gc, T: Collect + 'gc> Clone for GcWeak<'gc, T> {
+    fn clone(&self) -> GcWeak<'gc, T> {
+        *self
+    }
+}
+
+impl<'gc, T: 'gc + Collect> Debug for GcWeak<'gc, T> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "(GcWeak)")
+    }
+}
+
+unsafe impl<'gc, T: 'gc + Collect> Collect for GcWeak<'gc, T> {
+    fn trace(&self, cc: CollectionContext) {
+        unsafe {
+            cc.trace_weak(self.inner.ptr);
+        }
+    }
+}
+
+impl<'gc, T: 'gc + Collect> GcWeak<'gc, T> {
+    /// Returns a strong pointer to the target, or `None` if it has been collected.
+    pub fn upgrade(&self, mc: MutationContext<'gc, '_>) -> Option<Gc<'gc, T>> {
+        if unsafe { mc.upgrade(self.inner.ptr) } {
+            Some(self.inner)
+        } else {
+            None
+        }
+    }
+
+    pub fn ptr_eq(this: GcWeak<'gc, T>, other: GcWeak<'gc, T>) -> bool {
+        this.inner.ptr == other.inner.ptr
+    }
+}
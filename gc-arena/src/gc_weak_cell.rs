@@ -0,0 +1,41 @@
+use std::fmt::{self, Debug};
+
+use crate::collect::Collect;
+use crate::context::{CollectionContext, MutationContext};
+use crate::gc_cell::{GcCell, GcRefCell};
+use crate::gc_weak::GcWeak;
+
+/// A weak pointer to a `GcCell`, created with `GcCell::downgrade`.  Like `GcWeak`, it does not keep
+/// its target alive.
+pub struct GcWeakCell<'gc, T: 'gc + Collect>(pub(crate) GcWeak<'gc, GcRefCell<T>>);
+
+impl<'gc, T: Collect + 'gc> Copy for GcWeakCell<'gc, T> {}
+
+impl<'gc, T: Collect + 'gc> Clone for GcWeakCell<'gc, T> {
+    fn clone(&self) -> GcWeakCell<'gc, T> {
+        *self
+    }
+}
+
+impl<'gc, T: 'gc + Collect> Debug for GcWeakCell<'gc, T> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "(GcWeakCell)")
+    }
+}
+
+unsafe impl<'gc, T: 'gc + Collect> Collect for GcWeakCell<'gc, T> {
+    fn trace(&self, cc: CollectionContext) {
+        self.0.trace(cc)
+    }
+}
+
+impl<'gc, T: 'gc + Collect> GcWeakCell<'gc, T> {
+    /// Returns the target `GcCell`, or `None` if it has been collected.
+    pub fn upgrade(&self, mc: MutationContext<'gc, '_>) -> Option<GcCell<'gc, T>> {
+        self.0.upgrade(mc).map(GcCell)
+    }
+
+    pub fn ptr_eq(this: GcWeakCell<'gc, T>, other: GcWeakCell<'gc, T>) -> bool {
+        GcWeak::ptr_eq(this.0, other.0)
+    }
+}
@@ -5,14 +5,22 @@ mod arena;
 mod collect;
 mod collect_impl;
 mod context;
+mod finalization;
 mod gc;
 mod gc_cell;
+mod gc_weak;
+mod gc_weak_cell;
 mod static_collect;
+mod stats;
 mod types;
 
 pub use self::arena::*;
 pub use self::collect::*;
 pub use self::context::*;
+pub use self::finalization::*;
 pub use self::gc::*;
 pub use self::gc_cell::*;
+pub use self::gc_weak::*;
+pub use self::gc_weak_cell::*;
 pub use self::static_collect::*;
+pub use self::stats::*;
@@ -0,0 +1,52 @@
+use std::time::Duration;
+
+use crate::arena::CollectorMode;
+use crate::context::Phase;
+
+/// A snapshot of the state of the garbage collector, returned by `MutationContext::gc_stats` and
+/// the `gc_stats` method of arenas.
+#[derive(Debug, Copy, Clone)]
+pub struct GcStats {
+    /// The number of collection cycles which have finished.
+    pub cycles: u64,
+    /// The phase the collector is currently in.
+    pub phase: Phase,
+    pub mode: CollectorMode,
+    /// The memory currently used by allocated objects, in bytes.  This counts the allocations made
+    /// for `Gc` pointers, and the memory owned by objects as recorded by `Gc::set_external_size`.
+    pub total_allocated: usize,
+    /// The number of currently allocated objects.
+    pub live_objects: usize,
+    /// Statistics for the most recently finished cycle, if any.
+    pub last_cycle: Option<CycleStats>,
+}
+
+/// Statistics for a single collection cycle.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct CycleStats {
+    /// Whether this was a minor collection in generational mode.
+    pub minor: bool,
+    /// The number of objects freed by this cycle.
+    pub objects_freed: usize,
+    /// The memory freed by this cycle, in bytes.
+    pub bytes_freed: usize,
+    /// The number of objects left allocated once this cycle finished.
+    pub live_objects: usize,
+    /// The longest single call to the collector during this cycle.
+    pub max_pause: Duration,
+    /// The time spent tracing the root and propagating through reachable objects.
+    pub propagate: Duration,
+    /// The time spent sweeping unreachable objects.
+    pub sweep: Duration,
+}
+
+/// The allocations of a single type, as reported by a heap profile.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct TypeAllocations {
+    /// The name of the allocated type, as given by `Collect::type_name`.  Objects whose value has
+    /// already been dropped because they are only reachable through weak pointers are reported
+    /// under the name "(dropped)".
+    pub type_name: &'static str,
+    pub objects: usize,
+    pub bytes: usize,
+}
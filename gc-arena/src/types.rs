@@ -1,5 +1,6 @@
 use std::cell::{Cell, UnsafeCell};
 use std::marker::PhantomData;
+use std::mem::ManuallyDrop;
 use std::ptr::NonNull;
 
 use crate::collect::Collect;
@@ -7,6 +8,9 @@ use crate::collect::Collect;
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
 pub(crate) enum GcColor {
     White,
+    // A white object which has been reached through a weak pointer, so that when it is swept its
+    // value is dropped but its allocation is kept for the weak pointers to check.
+    WhiteWeak,
     Gray,
     Black,
 }
@@ -14,7 +18,12 @@ pub(crate) enum GcColor {
 pub(crate) struct GcBox<T: Collect + ?Sized> {
     pub(crate) flags: GcFlags,
     pub(crate) next: Cell<Option<NonNull<GcBox<Collect>>>>,
-    pub(crate) value: UnsafeCell<T>,
+    // The size of the memory owned by the value outside of this allocation, as last given to
+    // `MutationContext::set_external_size`
+    pub(crate) external_size: Cell<usize>,
+    // Dropped manually, either when the box is freed or earlier if the value is swept while weak
+    // pointers to it remain.
+    pub(crate) value: UnsafeCell<ManuallyDrop<T>>,
 }
 
 pub(crate) struct GcFlags(Cell<u8>);
@@ -29,7 +38,7 @@ impl GcFlags {
             0x0 => GcColor::White,
             0x1 => GcColor::Gray,
             0x2 => GcColor::Black,
-            _ => unreachable!(),
+            _ => GcColor::WhiteWeak,
         }
     }
 
@@ -40,6 +49,7 @@ impl GcFlags {
                     GcColor::White => 0x0,
                     GcColor::Gray => 0x1,
                     GcColor::Black => 0x2,
+                    GcColor::WhiteWeak => 0x3,
                 },
         )
     }
@@ -52,6 +62,34 @@ impl GcFlags {
         self.0
             .set((self.0.get() & !0x4) | if needs_trace { 0x4 } else { 0x0 });
     }
+
+    // Whether the value has been dropped, leaving only the allocation for weak pointers
+    pub(crate) fn is_dead(&self) -> bool {
+        self.0.get() & 0x8 != 0x0
+    }
+
+    pub(crate) fn set_dead(&self) {
+        self.0.set(self.0.get() | 0x8);
+    }
+
+    // Whether the object has survived a collection in generational mode
+    pub(crate) fn is_old(&self) -> bool {
+        self.0.get() & 0x10 != 0x0
+    }
+
+    pub(crate) fn set_old(&self) {
+        self.0.set(self.0.get() | 0x10);
+    }
+
+    // Whether an old object has been mutated since the last collection and is in the remembered set
+    pub(crate) fn is_touched(&self) -> bool {
+        self.0.get() & 0x20 != 0x0
+    }
+
+    pub(crate) fn set_touched(&self, touched: bool) {
+        self.0
+            .set((self.0.get() & !0x20) | if touched { 0x20 } else { 0x0 });
+    }
 }
 
 // Phantom type that holds a lifetime and ensures that it is invariant.
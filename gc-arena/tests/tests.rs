@@ -3,7 +3,10 @@ use std::rc::Rc;
 
 use rand::distributions::Distribution;
 
-use gc_arena::{make_arena, unsafe_empty_collect, ArenaParameters, Collect, Gc, GcCell};
+use gc_arena::{
+    make_arena, unsafe_empty_collect, ArenaParameters, Collect, CollectionContext, CollectorMode,
+    FinalizationQueue, Gc, GcCell, GcWeak, GcWeakCell,
+};
 
 #[test]
 fn simple_allocation() {
@@ -72,6 +75,290 @@ fn repeated_allocation_deallocation() {
     assert_eq!(Rc::strong_count(&r.0), live_size + 1);
 }
 
+#[test]
+fn repeated_allocation_generational() {
+    #[derive(Clone)]
+    struct RefCounter(Rc<()>);
+    unsafe_empty_collect!(RefCounter);
+
+    type Bucket<'gc> = GcCell<'gc, Vec<Gc<'gc, (i32, RefCounter)>>>;
+
+    #[derive(Collect)]
+    #[collect(empty_drop)]
+    struct TestRoot<'gc>(GcCell<'gc, Vec<Bucket<'gc>>>);
+    make_arena!(TestArena, TestRoot);
+
+    let r = RefCounter(Rc::new(()));
+
+    let mut arena = TestArena::new(
+        ArenaParameters::default()
+            .set_mode(CollectorMode::Generational)
+            .set_min_sleep(1024),
+        |mc| {
+            let buckets = (0..10).map(|_| GcCell::allocate(mc, Vec::new())).collect();
+            TestRoot(GcCell::allocate(mc, buckets))
+        },
+    );
+
+    let bucket_range = rand::distributions::Uniform::from(0..10);
+    let mut rng = rand::thread_rng();
+
+    for n in 0..200 {
+        arena.mutate(|mc, root| {
+            // Young objects are mostly only reachable through old buckets, which are only traced
+            // by minor collections when they are in the remembered set.
+            for _ in 0..20 {
+                let i = bucket_range.sample(&mut rng);
+                let bucket = root.0.read()[i as usize];
+                bucket.write(mc).push(Gc::allocate(mc, (i, r.clone())));
+            }
+
+            let i = bucket_range.sample(&mut rng);
+            let bucket = root.0.read()[i as usize];
+            let mut bucket = bucket.write(mc);
+            let len = bucket.len();
+            bucket.truncate(len / 2);
+
+            if n % 50 == 0 {
+                let i = bucket_range.sample(&mut rng);
+                root.0.write(mc)[i as usize] = GcCell::allocate(mc, Vec::new());
+            }
+        });
+
+        arena.collect_debt();
+
+        arena.mutate(|_, root| {
+            for (i, bucket) in root.0.read().iter().enumerate() {
+                for value in bucket.read().iter() {
+                    assert_eq!(value.0, i as i32);
+                }
+            }
+        });
+    }
+
+    arena.mutate(|mc, _| mc.request_full_collection());
+    arena.collect_debt();
+
+    let live_size: usize =
+        arena.mutate(|_, root| root.0.read().iter().map(|b| b.read().len()).sum());
+    assert_eq!(Rc::strong_count(&r.0), live_size + 1);
+}
+
+#[test]
+fn generational() {
+    #[derive(Clone)]
+    struct RefCounter(Rc<()>);
+    unsafe_empty_collect!(RefCounter);
+
+    #[derive(Collect)]
+    #[collect(empty_drop)]
+    struct TestRoot<'gc> {
+        old: GcCell<'gc, Vec<Gc<'gc, RefCounter>>>,
+        weak: GcCell<'gc, Option<GcWeak<'gc, RefCounter>>>,
+    }
+    make_arena!(TestArena, TestRoot);
+
+    let r = RefCounter(Rc::new(()));
+
+    let mut arena = TestArena::new(
+        ArenaParameters::default().set_mode(CollectorMode::Generational),
+        |mc| TestRoot {
+            old: GcCell::allocate(mc, Vec::new()),
+            weak: GcCell::allocate(mc, None),
+        },
+    );
+
+    // The first collection is a major collection, after which every surviving object is old.
+    arena.mutate(|mc, root| {
+        root.old.write(mc).push(Gc::allocate(mc, r.clone()));
+        Gc::allocate(mc, r.clone());
+    });
+    arena.collect_all();
+    assert_eq!(Rc::strong_count(&r.0), 2);
+
+    // Young objects reachable only through a mutated old object survive a minor collection, and
+    // unreachable young objects do not.
+    arena.mutate(|mc, root| {
+        let young = Gc::allocate(mc, r.clone());
+        root.old.write(mc).push(young);
+        *root.weak.write(mc) = Some(Gc::downgrade(Gc::allocate(mc, r.clone())));
+        Gc::allocate(mc, r.clone());
+    });
+    assert_eq!(Rc::strong_count(&r.0), 5);
+    arena.collect_all();
+    assert_eq!(Rc::strong_count(&r.0), 3);
+    arena.mutate(|mc, root| {
+        assert!(root.weak.read().unwrap().upgrade(mc).is_none());
+    });
+
+    // Unreachable old objects are only freed by a major collection.
+    arena.mutate(|mc, root| {
+        root.old.write(mc).clear();
+    });
+    arena.collect_all();
+    assert_eq!(Rc::strong_count(&r.0), 3);
+    arena.mutate(|mc, _| mc.request_full_collection());
+    arena.collect_debt();
+    assert_eq!(Rc::strong_count(&r.0), 1);
+
+    // Switching modes takes effect at the next collection.
+    arena.mutate(|mc, root| {
+        assert_eq!(mc.collector_mode(), CollectorMode::Generational);
+        mc.set_collector_mode(CollectorMode::Incremental);
+        assert_eq!(mc.collector_mode(), CollectorMode::Incremental);
+        root.old.write(mc).push(Gc::allocate(mc, r.clone()));
+    });
+    assert_eq!(arena.allocation_debt(), f64::INFINITY);
+    arena.collect_debt();
+    arena.mutate(|mc, root| {
+        root.old.write(mc).clear();
+        mc.set_collector_mode(CollectorMode::Generational);
+    });
+    arena.collect_debt();
+    assert_eq!(Rc::strong_count(&r.0), 1);
+}
+
+#[test]
+fn gc_stats() {
+    #[derive(Collect)]
+    #[collect(empty_drop)]
+    struct TestRoot<'gc> {
+        numbers: GcCell<'gc, Vec<Gc<'gc, i32>>>,
+        strings: GcCell<'gc, Vec<Gc<'gc, String>>>,
+    }
+    make_arena!(TestArena, TestRoot);
+
+    let mut arena = TestArena::new(ArenaParameters::default(), |mc| TestRoot {
+        numbers: GcCell::allocate(mc, Vec::new()),
+        strings: GcCell::allocate(mc, Vec::new()),
+    });
+    arena.collect_all();
+    let stats = arena.gc_stats();
+    assert_eq!(stats.cycles, 1);
+    assert_eq!(stats.live_objects, 2);
+    assert_eq!(stats.last_cycle.unwrap().live_objects, 2);
+
+    arena.mutate(|mc, root| {
+        for i in 0..10 {
+            root.numbers.write(mc).push(Gc::allocate(mc, i));
+        }
+        for _ in 0..5 {
+            root.strings.write(mc).push(Gc::allocate(mc, String::new()));
+        }
+    });
+    assert_eq!(arena.gc_stats().live_objects, 17);
+
+    let profile = arena.heap_profile();
+    let find = |type_name| {
+        profile
+            .iter()
+            .find(|allocations| allocations.type_name == type_name)
+            .unwrap()
+            .clone()
+    };
+    assert_eq!(find(std::any::type_name::<i32>()).objects, 10);
+    assert_eq!(find(std::any::type_name::<String>()).objects, 5);
+    // `GcCell` allocations are reported as their inner type
+    assert_eq!(find(std::any::type_name::<Vec<Gc<i32>>>()).objects, 1);
+    assert_eq!(
+        profile
+            .iter()
+            .map(|allocations| allocations.bytes)
+            .sum::<usize>(),
+        arena.total_allocated()
+    );
+
+    arena.mutate(|mc, root| {
+        root.numbers.write(mc).truncate(4);
+    });
+    arena.collect_all();
+    let stats = arena.gc_stats();
+    assert_eq!(stats.cycles, 2);
+    assert_eq!(stats.live_objects, 11);
+    let cycle = stats.last_cycle.unwrap();
+    assert_eq!(cycle.objects_freed, 6);
+    assert_eq!(cycle.live_objects, 11);
+    assert!(cycle.max_pause >= cycle.sweep);
+}
+
+#[test]
+fn external_size() {
+    #[derive(Collect)]
+    #[collect(empty_drop)]
+    struct TestRoot<'gc>(GcCell<'gc, Option<Gc<'gc, Vec<u8>>>>);
+    make_arena!(TestArena, TestRoot);
+
+    let mut arena = TestArena::new(ArenaParameters::default(), |mc| {
+        TestRoot(GcCell::allocate(mc, None))
+    });
+    arena.collect_all();
+    let before = arena.total_allocated();
+
+    arena.mutate(|mc, root| {
+        let buffer = Gc::allocate(mc, vec![0; 1 << 20]);
+        Gc::set_external_size(mc, buffer, buffer.capacity());
+        *root.0.write(mc) = Some(buffer);
+    });
+    assert!(arena.total_allocated() >= before + (1 << 20));
+    assert_eq!(
+        arena
+            .heap_profile()
+            .iter()
+            .map(|allocations| allocations.bytes)
+            .sum::<usize>(),
+        arena.total_allocated()
+    );
+
+    // Shrinking the recorded size is counted immediately, and the rest is counted as freed with
+    // the object
+    arena.mutate(|mc, root| {
+        Gc::set_external_size(mc, root.0.read().unwrap(), 1 << 10);
+    });
+    assert!(arena.total_allocated() < before + (1 << 20));
+    arena.mutate(|mc, root| {
+        *root.0.write(mc) = None;
+    });
+    arena.collect_all();
+    assert_eq!(arena.total_allocated(), before);
+    assert!(arena.gc_stats().last_cycle.unwrap().bytes_freed >= 1 << 10);
+}
+
+#[test]
+fn generational_free_touched() {
+    #[derive(Collect)]
+    #[collect(empty_drop)]
+    struct TestRoot<'gc>(GcCell<'gc, Option<GcCell<'gc, Vec<Gc<'gc, i32>>>>>);
+    make_arena!(TestArena, TestRoot);
+
+    for &mode in &[CollectorMode::Generational, CollectorMode::Incremental] {
+        let mut arena = TestArena::new(
+            ArenaParameters::default().set_mode(CollectorMode::Generational),
+            |mc| TestRoot(GcCell::allocate(mc, Some(GcCell::allocate(mc, Vec::new())))),
+        );
+        arena.collect_all();
+
+        // Mutating the old cell adds it to the remembered set, and it is then freed by the next
+        // full collection, whether or not it switches modes.
+        arena.mutate(|mc, root| {
+            let cell = root.0.read().unwrap();
+            cell.write(mc).push(Gc::allocate(mc, 1));
+            *root.0.write(mc) = None;
+            mc.set_collector_mode(mode);
+            mc.request_full_collection();
+        });
+        arena.collect_debt();
+        assert_eq!(arena.gc_stats().live_objects, 1);
+
+        arena.mutate(|mc, root| {
+            *root.0.write(mc) = Some(GcCell::allocate(mc, vec![Gc::allocate(mc, 2)]));
+        });
+        arena.collect_all();
+        arena.mutate(|_, root| {
+            assert_eq!(*root.0.read().unwrap().read()[0], 2);
+        });
+    }
+}
+
 #[test]
 fn all_dropped() {
     #[derive(Clone)]
@@ -130,6 +417,222 @@ fn all_garbage_collected() {
     assert_eq!(Rc::strong_count(&r.0), 1);
 }
 
+#[test]
+fn weak_pointers() {
+    #[derive(Clone)]
+    struct RefCounter(Rc<()>);
+    unsafe_empty_collect!(RefCounter);
+
+    #[derive(Collect)]
+    #[collect(empty_drop)]
+    struct TestRoot<'gc> {
+        strong: GcCell<'gc, Option<Gc<'gc, RefCounter>>>,
+        weak: GcWeak<'gc, RefCounter>,
+    }
+    make_arena!(TestArena, TestRoot);
+
+    let r = RefCounter(Rc::new(()));
+
+    let mut arena = TestArena::new(ArenaParameters::default(), |mc| {
+        let strong = Gc::allocate(mc, r.clone());
+        TestRoot {
+            strong: GcCell::allocate(mc, Some(strong)),
+            weak: Gc::downgrade(strong),
+        }
+    });
+
+    arena.collect_all();
+    arena.mutate(|mc, root| {
+        let strong = root.weak.upgrade(mc).unwrap();
+        assert!(Gc::ptr_eq(strong, root.strong.read().unwrap()));
+        *root.strong.write(mc) = None;
+    });
+    assert_eq!(Rc::strong_count(&r.0), 2);
+
+    // The weak pointer alone does not keep the value alive
+    arena.collect_all();
+    assert_eq!(Rc::strong_count(&r.0), 1);
+    arena.mutate(|mc, root| {
+        assert!(root.weak.upgrade(mc).is_none());
+    });
+
+    arena.collect_all();
+    arena.mutate(|mc, root| {
+        assert!(root.weak.upgrade(mc).is_none());
+    });
+}
+
+#[test]
+fn weak_cells() {
+    #[derive(Collect)]
+    #[collect(empty_drop)]
+    struct TestRoot<'gc> {
+        strong: GcCell<'gc, Vec<GcCell<'gc, i32>>>,
+        weak: GcCell<'gc, Vec<GcWeakCell<'gc, i32>>>,
+    }
+    make_arena!(TestArena, TestRoot);
+
+    let mut arena = TestArena::new(ArenaParameters::default(), |mc| TestRoot {
+        strong: GcCell::allocate(mc, Vec::new()),
+        weak: GcCell::allocate(mc, Vec::new()),
+    });
+
+    arena.mutate(|mc, root| {
+        for i in 0..100 {
+            let cell = GcCell::allocate(mc, i);
+            if i % 2 == 0 {
+                root.strong.write(mc).push(cell);
+            }
+            root.weak.write(mc).push(GcCell::downgrade(cell));
+        }
+    });
+
+    arena.collect_all();
+    arena.mutate(|mc, root| {
+        for (i, weak) in root.weak.read().iter().enumerate() {
+            match weak.upgrade(mc) {
+                Some(cell) => {
+                    assert_eq!(i % 2, 0);
+                    *cell.write(mc) += 1;
+                    assert_eq!(*cell.read(), i as i32 + 1);
+                }
+                None => assert_eq!(i % 2, 1),
+            }
+        }
+    });
+}
+
+#[test]
+fn ephemerons() {
+    // Values are kept alive only while their keys are reachable
+    struct Ephemerons<'gc>(Vec<(Gc<'gc, i32>, Gc<'gc, i32>)>);
+
+    unsafe impl<'gc> Collect for Ephemerons<'gc> {
+        fn trace(&self, cc: CollectionContext) {
+            cc.defer_weak();
+        }
+
+        fn trace_ephemerons(&self, cc: CollectionContext) {
+            for (key, value) in &self.0 {
+                if Gc::is_reached(cc, *key) {
+                    value.trace(cc);
+                }
+            }
+        }
+
+        fn clear_weak(&mut self, cc: CollectionContext) {
+            self.0.retain(|&(key, _)| Gc::is_reached(cc, key));
+        }
+    }
+
+    #[derive(Collect)]
+    #[collect(empty_drop)]
+    struct TestRoot<'gc> {
+        keys: GcCell<'gc, Vec<Gc<'gc, i32>>>,
+        ephemerons: GcCell<'gc, Ephemerons<'gc>>,
+    }
+    make_arena!(TestArena, TestRoot);
+
+    let mut arena = TestArena::new(ArenaParameters::default(), |mc| TestRoot {
+        keys: GcCell::allocate(mc, Vec::new()),
+        ephemerons: GcCell::allocate(mc, Ephemerons(Vec::new())),
+    });
+
+    arena.mutate(|mc, root| {
+        let mut ephemerons = root.ephemerons.write(mc);
+        let mut prev = Gc::allocate(mc, 0);
+        root.keys.write(mc).push(prev);
+        // Each value is the key of the next entry, so the whole chain is reachable from the first
+        // key
+        for i in 1..10 {
+            let next = Gc::allocate(mc, i);
+            ephemerons.0.push((prev, next));
+            prev = next;
+        }
+        ephemerons
+            .0
+            .push((Gc::allocate(mc, -1), Gc::allocate(mc, -1)));
+    });
+
+    arena.collect_all();
+    arena.mutate(|mc, root| {
+        let ephemerons = root.ephemerons.read();
+        assert_eq!(ephemerons.0.len(), 9);
+        for (i, (key, value)) in ephemerons.0.iter().enumerate() {
+            assert_eq!((**key, **value), (i as i32, i as i32 + 1));
+        }
+        root.keys.write(mc).clear();
+    });
+
+    arena.collect_all();
+    arena.mutate(|_, root| {
+        assert!(root.ephemerons.read().0.is_empty());
+    });
+}
+
+#[test]
+fn finalization() {
+    #[derive(Clone)]
+    struct RefCounter(Rc<()>);
+    unsafe_empty_collect!(RefCounter);
+
+    #[derive(Collect)]
+    #[collect(empty_drop)]
+    struct TestRoot<'gc> {
+        kept: GcCell<'gc, Vec<Gc<'gc, (i32, RefCounter)>>>,
+        finalizers: FinalizationQueue<'gc, Gc<'gc, (i32, RefCounter)>>,
+    }
+    make_arena!(TestArena, TestRoot);
+
+    let r = RefCounter(Rc::new(()));
+
+    let mut arena = TestArena::new(ArenaParameters::default(), |mc| TestRoot {
+        kept: GcCell::allocate(mc, Vec::new()),
+        finalizers: FinalizationQueue::new(mc),
+    });
+
+    arena.mutate(|mc, root| {
+        for i in 0..10 {
+            let object = Gc::allocate(mc, (i, r.clone()));
+            assert!(root.finalizers.register(mc, object));
+            assert!(!root.finalizers.register(mc, object));
+            if i % 2 == 0 {
+                root.kept.write(mc).push(object);
+            }
+        }
+    });
+
+    // Unreachable objects are resurrected and queued instead of being freed
+    arena.collect_all();
+    assert_eq!(Rc::strong_count(&r.0), 11);
+    arena.mutate(|mc, root| {
+        assert_eq!(root.finalizers.pending(), 5);
+        let mut finalized = Vec::new();
+        while let Some(object) = root.finalizers.take(mc) {
+            finalized.push(object.0);
+        }
+        finalized.sort();
+        assert_eq!(finalized, vec![1, 3, 5, 7, 9]);
+    });
+
+    // Once taken, they are freed as normal
+    arena.collect_all();
+    assert_eq!(Rc::strong_count(&r.0), 6);
+    arena.mutate(|mc, root| {
+        assert_eq!(root.finalizers.pending(), 0);
+        root.kept.write(mc).clear();
+    });
+
+    arena.collect_all();
+    assert_eq!(Rc::strong_count(&r.0), 6);
+    arena.mutate(|mc, root| {
+        assert_eq!(root.finalizers.pending(), 5);
+        while root.finalizers.take(mc).is_some() {}
+    });
+    arena.collect_all();
+    assert_eq!(Rc::strong_count(&r.0), 1);
+}
+
 #[test]
 fn derive_collect() {
     #[allow(unused)]
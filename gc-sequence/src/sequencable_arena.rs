@@ -62,7 +62,10 @@ macro_rules! make_sequencable_arena {
             use std::any::Any;
             use std::marker::PhantomData;
 
-            use gc_arena::{make_arena, ArenaParameters, Collect, GcCell, MutationContext};
+            use gc_arena::{
+                make_arena, ArenaParameters, Collect, GcCell, GcStats, MutationContext,
+                TypeAllocations,
+            };
             use gc_sequence::{Sequence, SequenceExt};
 
             use super::$root;
@@ -143,6 +146,21 @@ macro_rules! make_sequencable_arena {
                     self.0.total_allocated()
                 }
 
+                /// Returns statistics about the state of the garbage collector and its last
+                /// finished cycle.
+                #[allow(unused)]
+                #[inline]
+                $innervis fn gc_stats(&self) -> GcStats {
+                    self.0.gc_stats()
+                }
+
+                /// Walks every allocated object and returns the number of objects and bytes
+                /// allocated for each type, largest first.
+                #[allow(unused)]
+                $innervis fn heap_profile(&self) -> Vec<TypeAllocations> {
+                    self.0.heap_profile()
+                }
+
                 /// Returns the current "allocation debt", measured in bytes.  Allocation debt rises
                 /// as allocation takes place based on the `ArenaParameters` set for this arena.
                 #[allow(unused)]
@@ -215,6 +233,17 @@ macro_rules! make_sequencable_arena {
                     self.0.allocation_debt()
                 }
 
+                #[allow(unused)]
+                #[inline]
+                $innervis fn gc_stats(&self) -> GcStats {
+                    self.0.gc_stats()
+                }
+
+                #[allow(unused)]
+                $innervis fn heap_profile(&self) -> Vec<TypeAllocations> {
+                    self.0.heap_profile()
+                }
+
                 #[allow(unused)]
                 #[inline]
                 $innervis fn collect_debt(&mut self) {
@@ -0,0 +1,1019 @@
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet};
+use std::error::Error as StdError;
+use std::fs;
+use std::io::{self, BufRead, Read, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+use gc_arena::{Collect, MutationContext};
+use gc_sequence::Sequence;
+use luster::{
+    compile_named, Callback, CallbackResult, Closure, Error, ExecutionBudget, Function,
+    FunctionName, FunctionProto, Hook, HookMask, IoFile, IoHost, Limits, Lua, LuaHost, OpenMode,
+    StdIoHost, StdOsHost, Table, Thread, ThreadMode, Value,
+};
+
+use crate::json::{self, Json};
+
+// The only thread reported to the client, the main thread of the `Lua` instance
+const THREAD_ID: i64 = 1;
+
+// The largest message body that will be read, larger messages end the session since their body
+// cannot be skipped without reading it
+const MAX_MESSAGE_SIZE: usize = 16 * 1024 * 1024;
+
+// The most instructions an `evaluate` request may run, so that evaluating an infinite loop does
+// not hang the debugger
+const EVALUATE_INSTRUCTIONS: u64 = 1_000_000;
+
+/// Runs a Debug Adapter Protocol server over stdin and stdout until the client disconnects.
+///
+/// The program to debug is given by the `program` argument of the "launch" request, or if that is
+/// missing, by `program`.
+pub fn run(program: Option<&str>) -> Result<(), Box<dyn StdError>> {
+    let (sender, messages) = mpsc::channel();
+    thread::spawn(move || read_messages(sender));
+
+    let connection = Rc::new(Connection::default());
+    let lua = Lua::new_with_host(LuaHost {
+        os: Rc::new(StdOsHost::new()),
+        io: Rc::new(AdapterIoHost(connection.clone())),
+    });
+
+    DebugAdapter {
+        lua,
+        connection,
+        messages,
+        hook: Rc::new(HookShared::default()),
+        default_program: program.map(PathBuf::from),
+        state: State::Initializing {
+            launched: false,
+            configured: false,
+        },
+        mode: RunMode::Continue,
+        hooked: false,
+        breakpoints: HashMap::new(),
+        chunk_paths: HashMap::new(),
+        variables: Vec::new(),
+    }
+    .run();
+
+    Ok(())
+}
+
+// Reads protocol messages from stdin on a separate thread, so that requests such as "pause" can be
+// received while the VM is running.
+fn read_messages(sender: mpsc::Sender<Json>) {
+    let stdin = io::stdin();
+    let mut stdin = stdin.lock();
+    loop {
+        let mut length = None;
+        loop {
+            let mut line = String::new();
+            match stdin.read_line(&mut line) {
+                Ok(0) | Err(_) => return,
+                Ok(_) => {}
+            }
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            if let Some(value) = line.strip_prefix("Content-Length:") {
+                length = value.trim().parse().ok();
+            }
+        }
+
+        let mut body = match length {
+            Some(length) if length <= MAX_MESSAGE_SIZE => vec![0; length],
+            Some(_) => return,
+            None => continue,
+        };
+        if stdin.read_exact(&mut body).is_err() {
+            return;
+        }
+        if let Ok(message) = json::parse(&String::from_utf8_lossy(&body)) {
+            if sender.send(message).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+// Writes numbered protocol messages to stdout
+#[derive(Default)]
+struct Connection {
+    seq: Cell<i64>,
+}
+
+impl Connection {
+    fn send(&self, mut fields: Vec<(&str, Json)>) {
+        let seq = self.seq.get() + 1;
+        self.seq.set(seq);
+        fields.insert(0, ("seq", seq.into()));
+        let body = Json::object(fields).to_string();
+
+        let stdout = io::stdout();
+        let mut stdout = stdout.lock();
+        let _ = write!(stdout, "Content-Length: {}\r\n\r\n{}", body.len(), body);
+        let _ = stdout.flush();
+    }
+
+    fn event(&self, event: &str, body: Json) {
+        self.send(vec![
+            ("type", "event".into()),
+            ("event", event.into()),
+            ("body", body),
+        ]);
+    }
+
+    fn respond(&self, request: &Json, result: Result<Json, String>) {
+        let mut fields = vec![
+            ("type", "response".into()),
+            ("request_seq", request.get("seq").clone()),
+            ("command", request.get("command").clone()),
+        ];
+        match result {
+            Ok(body) => {
+                fields.push(("success", true.into()));
+                fields.push(("body", body));
+            }
+            Err(message) => {
+                fields.push(("success", false.into()));
+                fields.push(("message", message.into()));
+            }
+        }
+        self.send(fields);
+    }
+
+    fn output(&self, category: &str, output: &str) {
+        self.event(
+            "output",
+            Json::object(vec![
+                ("category", category.into()),
+                ("output", output.into()),
+            ]),
+        );
+    }
+}
+
+// Standard input and output carry the protocol, so anything the program writes to them is sent to
+// the client as output events instead, and standard input is always empty.
+struct AdapterIoHost(Rc<Connection>);
+
+impl IoHost for AdapterIoHost {
+    fn open(&self, path: &[u8], mode: OpenMode) -> Result<Box<dyn IoFile>, io::Error> {
+        StdIoHost.open(path, mode)
+    }
+
+    fn stdin(&self) -> Box<dyn IoFile> {
+        Box::new(EmptyInput)
+    }
+
+    fn stdout(&self) -> Box<dyn IoFile> {
+        Box::new(OutputEvents(self.0.clone(), "stdout"))
+    }
+
+    fn stderr(&self) -> Box<dyn IoFile> {
+        Box::new(OutputEvents(self.0.clone(), "stderr"))
+    }
+}
+
+struct EmptyInput;
+
+impl IoFile for EmptyInput {
+    fn read(&mut self, _buf: &mut [u8]) -> Result<usize, io::Error> {
+        Ok(0)
+    }
+}
+
+struct OutputEvents(Rc<Connection>, &'static str);
+
+impl IoFile for OutputEvents {
+    fn write(&mut self, buf: &[u8]) -> Result<(), io::Error> {
+        self.0.output(self.1, &String::from_utf8_lossy(buf));
+        Ok(())
+    }
+}
+
+// Shared between the debugger and the line hook it installs on the main thread.  The hook records
+// each new line, then waits until the debugger decides whether to stop there and later resumes.
+#[derive(Default)]
+struct HookShared {
+    line: Cell<Option<i64>>,
+    waiting: Cell<bool>,
+}
+
+#[derive(Collect)]
+#[collect(require_static)]
+struct WaitForResume(Rc<HookShared>);
+
+impl<'gc> Sequence<'gc> for WaitForResume {
+    type Output = Result<CallbackResult<'gc>, Error<'gc>>;
+
+    fn step(&mut self, _: MutationContext<'gc, '_>) -> Option<Self::Output> {
+        if self.0.waiting.get() {
+            None
+        } else {
+            Some(Ok(CallbackResult::Return(Vec::new())))
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    // The program runs once it has been both launched and configured
+    Initializing { launched: bool, configured: bool },
+    Running,
+    // Stopped inside the line hook, so the level of the innermost paused function is 1
+    Paused,
+    Finished,
+}
+
+// When to stop at the next new line, other than at breakpoints.  Stepping depths count the frames
+// on the call stack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RunMode {
+    Continue,
+    Entry,
+    Pause,
+    StepIn,
+    StepOver(usize),
+    StepOut(usize),
+}
+
+// Values cannot be held outside of the arena, so variable references record where to find their
+// values again, relative to the paused call stack.
+#[derive(Debug, Clone, PartialEq)]
+enum Variables {
+    Locals(usize),
+    UpValues(usize),
+    // A table found in another variable reference: a local or upvalue by number if the parent is
+    // `Locals` or `UpValues`, otherwise a field of the parent table
+    Field(usize, Key),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Key {
+    Boolean(bool),
+    Integer(i64),
+    Number(f64),
+    String(Vec<u8>),
+}
+
+impl Key {
+    fn from_value(value: Value) -> Option<Key> {
+        match value {
+            Value::Boolean(b) => Some(Key::Boolean(b)),
+            Value::Integer(i) => Some(Key::Integer(i)),
+            Value::Number(n) => Some(Key::Number(n)),
+            Value::String(s) => Some(Key::String(s.as_bytes().to_vec())),
+            _ => None,
+        }
+    }
+
+    fn to_value<'gc>(&self, mc: MutationContext<'gc, '_>) -> Value<'gc> {
+        match self {
+            Key::Boolean(b) => Value::Boolean(*b),
+            Key::Integer(i) => Value::Integer(*i),
+            Key::Number(n) => Value::Number(*n),
+            Key::String(s) => Value::String(luster::String::new(mc, s)),
+        }
+    }
+}
+
+// A variable listed by a "variables" request, and the key to find it by if it can be expanded
+struct Variable {
+    name: String,
+    value: String,
+    type_name: &'static str,
+    child: Option<Key>,
+}
+
+struct DebugAdapter {
+    lua: Lua,
+    connection: Rc<Connection>,
+    messages: Receiver<Json>,
+    hook: Rc<HookShared>,
+    default_program: Option<PathBuf>,
+    state: State,
+    mode: RunMode,
+    hooked: bool,
+    // Breakpoint lines by canonical source path
+    breakpoints: HashMap<PathBuf, Vec<i64>>,
+    // The canonical paths of the chunk names seen so far
+    chunk_paths: HashMap<Vec<u8>, Option<PathBuf>>,
+    // Variable references handed out since the program last paused, numbered from 1
+    variables: Vec<Variables>,
+}
+
+impl DebugAdapter {
+    fn run(&mut self) {
+        loop {
+            if self.state == State::Running {
+                loop {
+                    match self.messages.try_recv() {
+                        Ok(message) => {
+                            if !self.handle(message) {
+                                return;
+                            }
+                        }
+                        Err(TryRecvError::Empty) => break,
+                        Err(TryRecvError::Disconnected) => return,
+                    }
+                }
+                if self.state == State::Running {
+                    self.step();
+                }
+            } else {
+                match self.messages.recv() {
+                    Ok(message) => {
+                        if !self.handle(message) {
+                            return;
+                        }
+                    }
+                    Err(_) => return,
+                }
+            }
+        }
+    }
+
+    // Handles a single request, returning false if the debugger should exit
+    fn handle(&mut self, request: Json) -> bool {
+        if request.get("type").as_str() != Some("request") {
+            return true;
+        }
+        let args = request.get("arguments").clone();
+        let result = match request.get("command").as_str().unwrap_or("") {
+            "initialize" => {
+                self.connection.respond(
+                    &request,
+                    Ok(Json::object(vec![
+                        ("supportsConfigurationDoneRequest", true.into()),
+                        ("supportsEvaluateForHovers", true.into()),
+                    ])),
+                );
+                self.connection.event("initialized", Json::object(vec![]));
+                return true;
+            }
+            "launch" => self.launch(&args),
+            "setBreakpoints" => Ok(self.set_breakpoints(&args)),
+            "configurationDone" => {
+                if let State::Initializing { launched, .. } = self.state {
+                    self.state = State::Initializing {
+                        launched,
+                        configured: true,
+                    };
+                }
+                self.start();
+                Ok(Json::Null)
+            }
+            "threads" => Ok(Json::object(vec![(
+                "threads",
+                vec![Json::object(vec![
+                    ("id", THREAD_ID.into()),
+                    ("name", "main".into()),
+                ])]
+                .into(),
+            )])),
+            "stackTrace" => self.paused().map(|_| self.stack_trace(&args)),
+            "scopes" => self.paused().and_then(|_| self.scopes(&args)),
+            "variables" => self.paused().and_then(|_| self.list_variables(&args)),
+            "continue" => self
+                .resume(RunMode::Continue)
+                .map(|_| Json::object(vec![("allThreadsContinued", true.into())])),
+            "next" => {
+                let depth = self.depth();
+                self.resume(RunMode::StepOver(depth)).map(|_| Json::Null)
+            }
+            "stepIn" => self.resume(RunMode::StepIn).map(|_| Json::Null),
+            "stepOut" => {
+                let depth = self.depth();
+                self.resume(RunMode::StepOut(depth)).map(|_| Json::Null)
+            }
+            "pause" => {
+                if self.state == State::Running {
+                    self.mode = RunMode::Pause;
+                    self.update_hook();
+                }
+                Ok(Json::Null)
+            }
+            "evaluate" => self.evaluate(&args),
+            "disconnect" => {
+                self.connection.respond(&request, Ok(Json::Null));
+                return false;
+            }
+            "terminate" => {
+                self.connection.respond(&request, Ok(Json::Null));
+                self.connection.event("terminated", Json::object(vec![]));
+                return false;
+            }
+            command => Err(format!("unsupported request '{}'", command)),
+        };
+        self.connection.respond(&request, result);
+        true
+    }
+
+    fn launch(&mut self, args: &Json) -> Result<Json, String> {
+        let configured = match self.state {
+            State::Initializing {
+                launched: false,
+                configured,
+            } => configured,
+            _ => return Err("program already launched".to_owned()),
+        };
+
+        let program = match args.get("program").as_str() {
+            Some(program) => PathBuf::from(program),
+            None => self
+                .default_program
+                .clone()
+                .ok_or_else(|| "no program to debug".to_owned())?,
+        };
+        let source = fs::read(&program)
+            .map_err(|err| format!("cannot open {}: {}", program.display(), err))?;
+        let chunk_name = format!("@{}", program.display());
+
+        self.lua.mutate(move |mc, root| {
+            let proto = compile_named(
+                mc,
+                root.interned_strings,
+                chunk_name.as_bytes(),
+                &source[..],
+            )
+            .map_err(|err| err.to_string())?;
+            let closure =
+                Closure::new(mc, proto, Some(root.globals)).map_err(|err| err.to_string())?;
+            root.main_thread
+                .start(mc, Function::Closure(closure), &[])
+                .map_err(|err| err.to_string())
+        })?;
+
+        if args.get("stopOnEntry").as_bool() == Some(true) {
+            self.mode = RunMode::Entry;
+        }
+        self.state = State::Initializing {
+            launched: true,
+            configured,
+        };
+        self.start();
+        Ok(Json::Null)
+    }
+
+    // Starts running the program if it has been launched and configured
+    fn start(&mut self) {
+        if self.state
+            == (State::Initializing {
+                launched: true,
+                configured: true,
+            })
+        {
+            self.state = State::Running;
+            self.update_hook();
+        }
+    }
+
+    fn set_breakpoints(&mut self, args: &Json) -> Json {
+        let code_lines = args
+            .get("source")
+            .get("path")
+            .as_str()
+            .and_then(|path| self.code_lines(Path::new(path)))
+            .unwrap_or_default();
+
+        // Breakpoints on lines that no code was compiled from could never be hit, so they are
+        // reported as unverified and not set
+        let mut lines = Vec::new();
+        let response = args
+            .get("breakpoints")
+            .as_array()
+            .iter()
+            .map(|breakpoint| match breakpoint.get("line").as_i64() {
+                Some(line) if code_lines.contains(&line) => {
+                    lines.push(line);
+                    Json::object(vec![("verified", true.into()), ("line", line.into())])
+                }
+                Some(line) if line > 0 => {
+                    Json::object(vec![("verified", false.into()), ("line", line.into())])
+                }
+                _ => Json::object(vec![("verified", false.into())]),
+            })
+            .collect::<Vec<_>>();
+
+        if let Some(path) = args.get("source").get("path").as_str() {
+            let path = canonical_path(Path::new(path));
+            if lines.is_empty() {
+                self.breakpoints.remove(&path);
+            } else {
+                self.breakpoints.insert(path, lines);
+            }
+        }
+        self.update_hook();
+
+        Json::object(vec![("breakpoints", response.into())])
+    }
+
+    // Compiles the source file at the given path and returns every line that code was compiled
+    // from, or None if it cannot be read or compiled
+    fn code_lines(&mut self, path: &Path) -> Option<HashSet<i64>> {
+        let source = fs::read(path).ok()?;
+        self.lua.mutate(|mc, root| {
+            let proto = compile_named(mc, root.interned_strings, b"=?", &source[..]).ok()?;
+            let mut lines = HashSet::new();
+            proto_lines(&proto, &mut lines);
+            Some(lines)
+        })
+    }
+
+    // Runs the main thread for a single step, then checks whether it has reached a new line where
+    // it should stop
+    fn step(&mut self) {
+        let finished = self.lua.mutate(|mc, root| {
+            let _ = root.main_thread.step(mc);
+            match root.main_thread.mode() {
+                ThreadMode::Running => None,
+                _ => Some(match root.main_thread.take_results(mc) {
+                    Some(Err(err)) => Err(err.to_static().to_string()),
+                    _ => Ok(()),
+                }),
+            }
+        });
+
+        if let Some(result) = finished {
+            let exit_code = match result {
+                Ok(()) => 0,
+                Err(message) => {
+                    let traceback = self
+                        .lua
+                        .mutate(|_, root| root.main_thread.error_traceback());
+                    let mut output = format!("error: {}\n", message);
+                    if let Some(traceback) = traceback {
+                        output.push_str(&format!("{}\n", traceback));
+                    }
+                    self.connection.output("stderr", &output);
+                    1
+                }
+            };
+            self.state = State::Finished;
+            self.connection.event(
+                "exited",
+                Json::object(vec![("exitCode", (exit_code as i64).into())]),
+            );
+            self.connection.event("terminated", Json::object(vec![]));
+            return;
+        }
+
+        if let Some(line) = self.hook.line.take() {
+            match self.stop_reason(line) {
+                Some(reason) => {
+                    self.state = State::Paused;
+                    self.connection.event(
+                        "stopped",
+                        Json::object(vec![
+                            ("reason", reason.into()),
+                            ("threadId", THREAD_ID.into()),
+                            ("allThreadsStopped", true.into()),
+                        ]),
+                    );
+                }
+                None => self.hook.waiting.set(false),
+            }
+        }
+    }
+
+    fn stop_reason(&mut self, line: i64) -> Option<&'static str> {
+        let chunk_name =
+            self.lua
+                .mutate(|_, root| match root.main_thread.frame_info(1)?.function? {
+                    Function::Closure(closure) => {
+                        Some(closure.0.proto.chunk_name.as_bytes().to_vec())
+                    }
+                    Function::Callback(_) => None,
+                });
+        if let Some(path) = chunk_name.and_then(|chunk_name| self.chunk_path(chunk_name)) {
+            if let Some(lines) = self.breakpoints.get(&path) {
+                if lines.contains(&line) {
+                    return Some("breakpoint");
+                }
+            }
+        }
+
+        match self.mode {
+            RunMode::Continue => None,
+            RunMode::Entry => Some("entry"),
+            RunMode::Pause => Some("pause"),
+            RunMode::StepIn => Some("step"),
+            RunMode::StepOver(depth) => Some("step").filter(|_| self.depth() <= depth),
+            RunMode::StepOut(depth) => Some("step").filter(|_| self.depth() < depth),
+        }
+    }
+
+    fn chunk_path(&mut self, chunk_name: Vec<u8>) -> Option<PathBuf> {
+        self.chunk_paths
+            .entry(chunk_name)
+            .or_insert_with_key(|chunk_name| match chunk_name.split_first() {
+                Some((b'@', path)) => {
+                    Some(canonical_path(Path::new(&*String::from_utf8_lossy(path))))
+                }
+                _ => None,
+            })
+            .clone()
+    }
+
+    // The number of frames on the call stack below the hook
+    fn depth(&mut self) -> usize {
+        self.lua.mutate(|_, root| {
+            let mut depth = 0;
+            while root.main_thread.frame_info(depth + 1).is_some() {
+                depth += 1;
+            }
+            depth
+        })
+    }
+
+    fn paused(&self) -> Result<(), String> {
+        if self.state == State::Paused {
+            Ok(())
+        } else {
+            Err("program is not paused".to_owned())
+        }
+    }
+
+    fn resume(&mut self, mode: RunMode) -> Result<(), String> {
+        self.paused()?;
+        self.mode = mode;
+        self.variables.clear();
+        self.state = State::Running;
+        self.hook.waiting.set(false);
+        self.update_hook();
+        Ok(())
+    }
+
+    // Installs the line hook only while it is needed, since it makes the VM run a single
+    // instruction at a time
+    fn update_hook(&mut self) {
+        let needed = match self.state {
+            State::Running | State::Paused => {
+                !self.breakpoints.is_empty() || self.mode != RunMode::Continue
+            }
+            _ => false,
+        };
+        if needed == self.hooked {
+            return;
+        }
+        self.hooked = needed;
+
+        let shared = self.hook.clone();
+        self.lua.mutate(move |mc, root| {
+            let hook = if needed {
+                let callback = Callback::new_sequence(mc, move |args| {
+                    let line = match args.get(1) {
+                        Some(&Value::Integer(line)) => line,
+                        _ => 0,
+                    };
+                    shared.line.set(Some(line));
+                    shared.waiting.set(true);
+                    Ok(WaitForResume(shared.clone()))
+                });
+                Some(Hook {
+                    function: Function::Callback(callback),
+                    mask: HookMask {
+                        line: true,
+                        ..HookMask::default()
+                    },
+                    count: 0,
+                })
+            } else {
+                None
+            };
+            root.main_thread.set_hook(mc, hook);
+        });
+    }
+
+    fn stack_trace(&mut self, args: &Json) -> Json {
+        let frames = self.lua.mutate(|_, root| {
+            let mut frames = Vec::new();
+            let mut level = 1;
+            while let Some(info) = root.main_thread.frame_info(level) {
+                let mut frame = vec![("id", level.into())];
+                match info.function {
+                    Some(Function::Closure(closure)) => {
+                        let proto = &closure.0.proto;
+                        let chunk_name = String::from_utf8_lossy(proto.chunk_name.as_bytes());
+                        frame.push((
+                            "name",
+                            function_name(&info.name, &chunk_name, proto.line_defined.0).into(),
+                        ));
+                        if let Some(path) = chunk_name.strip_prefix('@') {
+                            let name = Path::new(path)
+                                .file_name()
+                                .map(|name| name.to_string_lossy().into_owned())
+                                .unwrap_or_else(|| path.to_owned());
+                            frame.push((
+                                "source",
+                                Json::object(vec![("name", name.into()), ("path", path.into())]),
+                            ));
+                        }
+                        let line = info.current_line.map(|line| line.0 as i64).unwrap_or(0);
+                        frame.push(("line", line.into()));
+                    }
+                    _ => {
+                        frame.push(("name", function_name(&info.name, "[C]", 0).into()));
+                        frame.push(("line", 0i64.into()));
+                        frame.push(("presentationHint", "subtle".into()));
+                    }
+                }
+                frame.push(("column", 1i64.into()));
+                frames.push(Json::object(frame));
+                level += 1;
+            }
+            frames
+        });
+
+        // A missing or zero number of levels requests every frame from `startFrame` on
+        let start = args.get("startFrame").as_i64().unwrap_or(0).max(0) as usize;
+        let levels = match args.get("levels").as_i64() {
+            Some(levels) if levels > 0 => levels as usize,
+            _ => usize::MAX,
+        };
+        let total = frames.len();
+        let frames = frames
+            .into_iter()
+            .skip(start)
+            .take(levels)
+            .collect::<Vec<_>>();
+
+        Json::object(vec![
+            ("totalFrames", total.into()),
+            ("stackFrames", frames.into()),
+        ])
+    }
+
+    fn scopes(&mut self, args: &Json) -> Result<Json, String> {
+        let level = frame_level(args)?;
+        let locals = self.variables_reference(Variables::Locals(level));
+        let upvalues = self.variables_reference(Variables::UpValues(level));
+        let scope = |name: &str, reference: usize| {
+            Json::object(vec![
+                ("name", name.into()),
+                ("variablesReference", reference.into()),
+                ("expensive", false.into()),
+            ])
+        };
+        Ok(Json::object(vec![(
+            "scopes",
+            vec![scope("Locals", locals), scope("Upvalues", upvalues)].into(),
+        )]))
+    }
+
+    fn variables_reference(&mut self, variables: Variables) -> usize {
+        match self.variables.iter().position(|v| *v == variables) {
+            Some(index) => index + 1,
+            None => {
+                self.variables.push(variables);
+                self.variables.len()
+            }
+        }
+    }
+
+    fn list_variables(&mut self, args: &Json) -> Result<Json, String> {
+        let reference =
+            args.get("variablesReference")
+                .as_i64()
+                .filter(|&reference| reference > 0 && reference as usize <= self.variables.len())
+                .ok_or_else(|| "invalid variables reference".to_owned())? as usize;
+
+        let variables = &self.variables;
+        let listed = self.lua.mutate(|mc, root| {
+            let thread = root.main_thread;
+            let mut listed = Vec::new();
+            match &variables[reference - 1] {
+                &Variables::Locals(level) => {
+                    for &step in &[1, -1] {
+                        let mut n = step;
+                        while let Some((name, value)) = thread.local(level, n) {
+                            let name = String::from_utf8_lossy(name.as_bytes()).into_owned();
+                            listed.push(variable(name, value, Some(Key::Integer(n))));
+                            n += step;
+                        }
+                    }
+                }
+                &Variables::UpValues(level) => {
+                    if let Some(Function::Closure(closure)) =
+                        thread.frame_info(level).and_then(|info| info.function)
+                    {
+                        for i in 0..closure.upvalue_count() {
+                            let name = closure.0.proto.upvalue_names[i].as_bytes();
+                            let name = String::from_utf8_lossy(name).into_owned();
+                            let value = closure.upvalue(i).unwrap().get();
+                            listed.push(variable(name, value, Some(Key::Integer(i as i64 + 1))));
+                        }
+                    }
+                }
+                Variables::Field(..) => {
+                    if let Value::Table(table) = resolve(mc, thread, variables, reference) {
+                        for (key, value) in table.iter() {
+                            let name = match key {
+                                Value::String(s) => {
+                                    String::from_utf8_lossy(s.as_bytes()).into_owned()
+                                }
+                                key => format!("[{}]", display_value(key)),
+                            };
+                            listed.push(variable(name, value, Key::from_value(key)));
+                        }
+                    }
+                }
+            }
+            listed
+        });
+
+        let mut result = Vec::new();
+        for variable in listed {
+            let child = match variable.child {
+                Some(key) => self.variables_reference(Variables::Field(reference, key)),
+                None => 0,
+            };
+            result.push(Json::object(vec![
+                ("name", variable.name.into()),
+                ("value", variable.value.into()),
+                ("type", variable.type_name.into()),
+                ("variablesReference", child.into()),
+            ]));
+        }
+        Ok(Json::object(vec![("variables", result.into())]))
+    }
+
+    // Evaluates an expression or statement with the locals and upvalues of the given frame in
+    // scope, on a separate thread with a limited budget.  Assignments to locals and upvalues are
+    // not written back to the paused frame.
+    fn evaluate(&mut self, args: &Json) -> Result<Json, String> {
+        let expression = args
+            .get("expression")
+            .as_str()
+            .ok_or_else(|| "missing expression".to_owned())?
+            .to_owned();
+        let level = match args.get("frameId") {
+            Json::Null => None,
+            _ => Some(frame_level(args)?),
+        };
+        if level.is_some() {
+            self.paused()?;
+        }
+
+        let result = self.lua.mutate(move |mc, root| {
+            let env = Table::new(mc);
+            let metatable = Table::new(mc);
+            metatable
+                .set(mc, luster::String::new_static(b"__index"), root.globals)
+                .unwrap();
+            metatable
+                .set(mc, luster::String::new_static(b"__newindex"), root.globals)
+                .unwrap();
+            env.set_metatable(mc, Some(metatable));
+            if let Some(level) = level {
+                let thread = root.main_thread;
+                if let Some(Function::Closure(closure)) =
+                    thread.frame_info(level).and_then(|info| info.function)
+                {
+                    for i in 0..closure.upvalue_count() {
+                        let value = closure.upvalue(i).unwrap().get();
+                        let _ = env.set(mc, closure.0.proto.upvalue_names[i], value);
+                    }
+                }
+                let mut n = 1;
+                while let Some((name, value)) = thread.local(level, n) {
+                    let _ = env.set(mc, name, value);
+                    n += 1;
+                }
+            }
+
+            let source = format!("return {}", expression);
+            let proto = compile_named(mc, root.interned_strings, b"=eval", source.as_bytes())
+                .or_else(|_| {
+                    compile_named(mc, root.interned_strings, b"=eval", expression.as_bytes())
+                })
+                .map_err(|err| err.to_string())?;
+            let closure = Closure::new(mc, proto, Some(env)).map_err(|err| err.to_string())?;
+
+            let thread = Thread::new(mc, Some(root.string_metatable), false);
+            thread.set_budget(
+                mc,
+                Some(Rc::new(ExecutionBudget::new(Limits {
+                    instructions: Some(EVALUATE_INSTRUCTIONS),
+                    ..Limits::default()
+                }))),
+            );
+            thread
+                .start(mc, Function::Closure(closure), &[])
+                .map_err(|err| err.to_string())?;
+            while thread.mode() == ThreadMode::Running {
+                thread.step(mc).map_err(|err| err.to_string())?;
+            }
+            match thread.take_results(mc) {
+                Some(Ok(values)) => Ok(values
+                    .into_iter()
+                    .map(display_value)
+                    .collect::<Vec<_>>()
+                    .join(", ")),
+                Some(Err(err)) => Err(err.to_static().to_string()),
+                None => Err("evaluation did not finish".to_owned()),
+            }
+        })?;
+
+        Ok(Json::object(vec![
+            ("result", result.into()),
+            ("variablesReference", 0i64.into()),
+        ]))
+    }
+}
+
+fn frame_level(args: &Json) -> Result<usize, String> {
+    args.get("frameId")
+        .as_i64()
+        .filter(|&level| level > 0)
+        .map(|level| level as usize)
+        .ok_or_else(|| "invalid frame id".to_owned())
+}
+
+// Finds the value of a `Variables::Field` reference
+fn resolve<'gc>(
+    mc: MutationContext<'gc, '_>,
+    thread: Thread<'gc>,
+    variables: &[Variables],
+    reference: usize,
+) -> Value<'gc> {
+    let (parent, key) = match &variables[reference - 1] {
+        Variables::Field(parent, key) => (*parent, key),
+        _ => return Value::Nil,
+    };
+    match (&variables[parent - 1], key) {
+        (&Variables::Locals(level), &Key::Integer(n)) => thread
+            .local(level, n)
+            .map(|(_, value)| value)
+            .unwrap_or(Value::Nil),
+        (&Variables::UpValues(level), &Key::Integer(n)) => {
+            match thread.frame_info(level).and_then(|info| info.function) {
+                Some(Function::Closure(closure)) => closure
+                    .upvalue(n as usize - 1)
+                    .map(|upvalue| upvalue.get())
+                    .unwrap_or(Value::Nil),
+                _ => Value::Nil,
+            }
+        }
+        (Variables::Field(..), key) => match resolve(mc, thread, variables, parent) {
+            Value::Table(table) => table.get(key.to_value(mc)),
+            _ => Value::Nil,
+        },
+        _ => Value::Nil,
+    }
+}
+
+fn variable(name: String, value: Value, key: Option<Key>) -> Variable {
+    Variable {
+        name,
+        value: display_value(value),
+        type_name: value.type_name(),
+        child: match value {
+            Value::Table(_) => key,
+            _ => None,
+        },
+    }
+}
+
+fn display_value(value: Value) -> String {
+    match value {
+        Value::String(s) => format!("{:?}", String::from_utf8_lossy(s.as_bytes())),
+        value => {
+            let mut buf = Vec::new();
+            value.display(&mut buf).unwrap();
+            String::from_utf8_lossy(&buf).into_owned()
+        }
+    }
+}
+
+fn function_name(name: &FunctionName, chunk_name: &str, line_defined: u64) -> String {
+    match name {
+        FunctionName::MainChunk => "main chunk".to_owned(),
+        FunctionName::Global(name)
+        | FunctionName::Local(name)
+        | FunctionName::Field(name)
+        | FunctionName::Method(name)
+        | FunctionName::UpValue(name) => name.clone(),
+        FunctionName::Unknown => format!("function <{}:{}>", chunk_name, line_defined),
+        name => name.to_string(),
+    }
+}
+
+// Adds the lines that the given function and the functions nested in it were compiled from
+fn proto_lines(proto: &FunctionProto, lines: &mut HashSet<i64>) {
+    lines.extend(proto.opcode_lines.iter().map(|&(_, line)| line.0 as i64));
+    for proto in &proto.prototypes {
+        proto_lines(proto, lines);
+    }
+}
+
+fn canonical_path(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_owned())
+}
@@ -0,0 +1,307 @@
+use std::error::Error as StdError;
+use std::fmt::{self, Write};
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// A parsed JSON value, just enough to speak the Debug Adapter Protocol.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    // Keeps the order that fields were written in
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn object(fields: Vec<(&str, Json)>) -> Json {
+        Json::Object(
+            fields
+                .into_iter()
+                .map(|(name, value)| (name.to_owned(), value))
+                .collect(),
+        )
+    }
+
+    /// Returns the field with the given name, or `Json::Null` if this is not an object or there
+    /// is no such field.
+    pub fn get(&self, name: &str) -> &Json {
+        match self {
+            Json::Object(fields) => fields
+                .iter()
+                .find(|(field, _)| field == name)
+                .map(|(_, value)| value)
+                .unwrap_or(&Json::Null),
+            _ => &Json::Null,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            Json::Number(n)
+                if n.fract() == 0.0 && n >= i64::MIN as f64 && n < -(i64::MIN as f64) =>
+            {
+                Some(n as i64)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            Json::Bool(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> &[Json] {
+        match self {
+            Json::Array(array) => array,
+            _ => &[],
+        }
+    }
+}
+
+impl From<bool> for Json {
+    fn from(b: bool) -> Json {
+        Json::Bool(b)
+    }
+}
+
+impl From<i64> for Json {
+    fn from(n: i64) -> Json {
+        Json::Number(n as f64)
+    }
+}
+
+impl From<usize> for Json {
+    fn from(n: usize) -> Json {
+        Json::Number(n as f64)
+    }
+}
+
+impl From<&str> for Json {
+    fn from(s: &str) -> Json {
+        Json::String(s.to_owned())
+    }
+}
+
+impl From<String> for Json {
+    fn from(s: String) -> Json {
+        Json::String(s)
+    }
+}
+
+impl From<Vec<Json>> for Json {
+    fn from(array: Vec<Json>) -> Json {
+        Json::Array(array)
+    }
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Json::Null => write!(fmt, "null"),
+            Json::Bool(b) => write!(fmt, "{}", b),
+            Json::Number(n) if n.fract() == 0.0 && n.abs() < 1e15 => write!(fmt, "{}", *n as i64),
+            Json::Number(n) if n.is_finite() => write!(fmt, "{}", n),
+            Json::Number(_) => write!(fmt, "null"),
+            Json::String(s) => write_string(fmt, s),
+            Json::Array(array) => {
+                fmt.write_char('[')?;
+                for (i, value) in array.iter().enumerate() {
+                    if i != 0 {
+                        fmt.write_char(',')?;
+                    }
+                    write!(fmt, "{}", value)?;
+                }
+                fmt.write_char(']')
+            }
+            Json::Object(fields) => {
+                fmt.write_char('{')?;
+                for (i, (name, value)) in fields.iter().enumerate() {
+                    if i != 0 {
+                        fmt.write_char(',')?;
+                    }
+                    write_string(fmt, name)?;
+                    write!(fmt, ":{}", value)?;
+                }
+                fmt.write_char('}')
+            }
+        }
+    }
+}
+
+fn write_string(fmt: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    fmt.write_char('"')?;
+    for c in s.chars() {
+        match c {
+            '"' => fmt.write_str("\\\"")?,
+            '\\' => fmt.write_str("\\\\")?,
+            '\n' => fmt.write_str("\\n")?,
+            '\r' => fmt.write_str("\\r")?,
+            '\t' => fmt.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(fmt, "\\u{:04x}", c as u32)?,
+            c => fmt.write_char(c)?,
+        }
+    }
+    fmt.write_char('"')
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JsonError;
+
+impl StdError for JsonError {}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "invalid JSON")
+    }
+}
+
+// The deepest nesting of arrays and objects that will be parsed, so that malicious input cannot
+// overflow the stack
+const MAX_DEPTH: usize = 128;
+
+pub fn parse(source: &str) -> Result<Json, JsonError> {
+    let mut chars = source.chars().peekable();
+    let value = parse_value(&mut chars, 0)?;
+    skip_whitespace(&mut chars);
+    match chars.next() {
+        None => Ok(value),
+        Some(_) => Err(JsonError),
+    }
+}
+
+fn skip_whitespace(chars: &mut Peekable<Chars>) {
+    while let Some(c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else {
+            break;
+        }
+    }
+}
+
+fn expect(chars: &mut Peekable<Chars>, expected: &str) -> Result<(), JsonError> {
+    for e in expected.chars() {
+        if chars.next() != Some(e) {
+            return Err(JsonError);
+        }
+    }
+    Ok(())
+}
+
+fn parse_value(chars: &mut Peekable<Chars>, depth: usize) -> Result<Json, JsonError> {
+    skip_whitespace(chars);
+    match chars.peek().cloned().ok_or(JsonError)? {
+        'n' => expect(chars, "null").map(|_| Json::Null),
+        't' => expect(chars, "true").map(|_| Json::Bool(true)),
+        'f' => expect(chars, "false").map(|_| Json::Bool(false)),
+        '"' => parse_string(chars).map(Json::String),
+        '[' | '{' if depth >= MAX_DEPTH => Err(JsonError),
+        '[' => {
+            chars.next();
+            let mut array = Vec::new();
+            skip_whitespace(chars);
+            if chars.peek() == Some(&']') {
+                chars.next();
+                return Ok(Json::Array(array));
+            }
+            loop {
+                array.push(parse_value(chars, depth + 1)?);
+                skip_whitespace(chars);
+                match chars.next() {
+                    Some(',') => {}
+                    Some(']') => return Ok(Json::Array(array)),
+                    _ => return Err(JsonError),
+                }
+            }
+        }
+        '{' => {
+            chars.next();
+            let mut fields = Vec::new();
+            skip_whitespace(chars);
+            if chars.peek() == Some(&'}') {
+                chars.next();
+                return Ok(Json::Object(fields));
+            }
+            loop {
+                skip_whitespace(chars);
+                let name = parse_string(chars)?;
+                skip_whitespace(chars);
+                expect(chars, ":")?;
+                fields.push((name, parse_value(chars, depth + 1)?));
+                skip_whitespace(chars);
+                match chars.next() {
+                    Some(',') => {}
+                    Some('}') => return Ok(Json::Object(fields)),
+                    _ => return Err(JsonError),
+                }
+            }
+        }
+        _ => {
+            let mut number = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_ascii_digit() || c == '-' || c == '+' || c == '.' || c == 'e' || c == 'E' {
+                    number.push(c);
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            number.parse().map(Json::Number).map_err(|_| JsonError)
+        }
+    }
+}
+
+fn parse_string(chars: &mut Peekable<Chars>) -> Result<String, JsonError> {
+    expect(chars, "\"")?;
+    let mut s = String::new();
+    loop {
+        match chars.next().ok_or(JsonError)? {
+            '"' => return Ok(s),
+            '\\' => match chars.next().ok_or(JsonError)? {
+                '"' => s.push('"'),
+                '\\' => s.push('\\'),
+                '/' => s.push('/'),
+                'b' => s.push('\u{8}'),
+                'f' => s.push('\u{c}'),
+                'n' => s.push('\n'),
+                'r' => s.push('\r'),
+                't' => s.push('\t'),
+                'u' => {
+                    let mut code = parse_hex4(chars)?;
+                    // Combine UTF-16 surrogate pairs
+                    if (0xd800..0xdc00).contains(&code) {
+                        expect(chars, "\\u")?;
+                        let low = parse_hex4(chars)?;
+                        code =
+                            0x10000 + ((code - 0xd800) << 10) + (low.wrapping_sub(0xdc00) & 0x3ff);
+                    }
+                    s.push(std::char::from_u32(code).unwrap_or('\u{fffd}'));
+                }
+                _ => return Err(JsonError),
+            },
+            c => s.push(c),
+        }
+    }
+}
+
+fn parse_hex4(chars: &mut Peekable<Chars>) -> Result<u32, JsonError> {
+    let mut code = 0;
+    for _ in 0..4 {
+        let digit = chars.next().and_then(|c| c.to_digit(16)).ok_or(JsonError)?;
+        code = code * 16 + digit;
+    }
+    Ok(code)
+}
@@ -1,5 +1,9 @@
+mod debug_adapter;
+mod json;
+
 use std::error::Error as StdError;
 use std::fs::File;
+use std::process;
 use std::vec::Vec;
 
 use clap::{crate_authors, crate_description, crate_name, crate_version, App, Arg};
@@ -7,9 +11,44 @@ use rustyline::Editor;
 
 use gc_sequence::{self as sequence, SequenceExt, SequenceResultExt};
 use luster::{
-    compile, io, Closure, Error, Function, Lua, ParserError, StaticError, ThreadSequence,
+    compile_named, io, Closure, Error, Function, Lua, ParserError, ParserErrorKind, StaticError,
+    ThreadSequence,
 };
 
+// Prints the traceback of the last error to escape the main thread, if there is one
+fn print_traceback(lua: &mut Lua) {
+    if let Some(traceback) = lua.mutate(|_, root| root.main_thread.error_traceback()) {
+        eprintln!("{}", traceback);
+    }
+}
+
+// Prints the collector statistics and a heap profile of the arena
+fn print_gc_stats(lua: &Lua) {
+    let stats = lua.gc_stats();
+    eprintln!(
+        "gc: {:?} mode, {} cycles, {} objects in {} bytes",
+        stats.mode, stats.cycles, stats.live_objects, stats.total_allocated
+    );
+    if let Some(cycle) = stats.last_cycle {
+        eprintln!(
+            "gc: last {} cycle freed {} objects ({} bytes), max pause {:?}, propagate {:?}, sweep {:?}",
+            if cycle.minor { "minor" } else { "full" },
+            cycle.objects_freed,
+            cycle.bytes_freed,
+            cycle.max_pause,
+            cycle.propagate,
+            cycle.sweep,
+        );
+    }
+    eprintln!("{:>12} {:>10}  type", "bytes", "objects");
+    for allocations in lua.heap_profile() {
+        eprintln!(
+            "{:>12} {:>10}  {}",
+            allocations.bytes, allocations.objects, allocations.type_name
+        );
+    }
+}
+
 fn run_repl(lua: &mut Lua) {
     let mut editor = Editor::<()>::new();
 
@@ -27,15 +66,18 @@ fn run_repl(lua: &mut Lua) {
 
             match lua.sequence(move |root| {
                 sequence::from_fn_with(root, move |mc, root| {
-                    let result = compile(mc, root.interned_strings, line_clone.as_bytes());
+                    let result =
+                        compile_named(mc, root.interned_strings, b"=stdin", line_clone.as_bytes());
                     let result = match result {
                         Ok(res) => Ok(res),
-                        err @ Err(Error::ParserError(ParserError::EndOfStream { expected: _ })) => {
-                            err
-                        }
-                        Err(_) => compile(
+                        err @ Err(Error::ParserError(ParserError {
+                            kind: ParserErrorKind::EndOfStream { .. },
+                            ..
+                        })) => err,
+                        Err(_) => compile_named(
                             mc,
                             root.interned_strings,
+                            b"=stdin",
                             (String::new() + "return " + &line_clone).as_bytes(),
                         ),
                     };
@@ -62,7 +104,10 @@ fn run_repl(lua: &mut Lua) {
                 })
                 .boxed()
             }) {
-                err @ Err(StaticError::ParserError(ParserError::EndOfStream { expected: _ })) => {
+                err @ Err(StaticError::ParserError(ParserError {
+                    kind: ParserErrorKind::EndOfStream { .. },
+                    ..
+                })) => {
                     match line.chars().last() {
                         Some(c) => {
                             if c == '\n' {
@@ -84,6 +129,7 @@ fn run_repl(lua: &mut Lua) {
                 Err(e) => {
                     editor.add_history_entry(line);
                     eprintln!("error: {}", e);
+                    print_traceback(lua);
                     break;
                 }
             }
@@ -102,23 +148,44 @@ fn main() -> Result<(), Box<StdError>> {
                 .long("repl")
                 .help("Load into REPL after loading file, if any"),
         )
+        .arg(
+            Arg::with_name("debug-adapter")
+                .long("debug-adapter")
+                .help("Debug the file with the Debug Adapter Protocol over stdin and stdout"),
+        )
+        .arg(
+            Arg::with_name("gc-stats")
+                .long("gc-stats")
+                .help("Print garbage collector statistics and a heap profile on exit"),
+        )
         .arg(Arg::with_name("file").help("File to interpret").index(1))
         .get_matches();
 
+    if matches.is_present("debug-adapter") {
+        return debug_adapter::run(matches.value_of("file"));
+    }
+
     let mut lua = Lua::new();
 
+    let gc_stats = matches.is_present("gc-stats");
+
     if !matches.is_present("file") {
         run_repl(&mut lua);
+        if gc_stats {
+            print_gc_stats(&lua);
+        }
         return Ok(());
     }
 
-    let file = io::buffered_read(File::open(matches.value_of("file").unwrap())?)?;
+    let file_name = matches.value_of("file").unwrap();
+    let chunk_name = format!("@{}", file_name);
+    let file = io::buffered_read(File::open(file_name)?)?;
 
-    lua.sequence(|root| {
-        sequence::from_fn_with(root, |mc, root| {
+    let result = lua.sequence(move |root| {
+        sequence::from_fn_with(root, move |mc, root| {
             Ok(Closure::new(
                 mc,
-                compile(mc, root.interned_strings, file)?,
+                compile_named(mc, root.interned_strings, chunk_name.as_bytes(), file)?,
                 Some(root.globals),
             )?)
         })
@@ -133,11 +200,24 @@ fn main() -> Result<(), Box<StdError>> {
         .map_ok(|_| ())
         .map_err(|e| e.to_static())
         .boxed()
-    })?;
+    });
+
+    if let Err(err) = result {
+        eprintln!("error: {}", err);
+        print_traceback(&mut lua);
+        if gc_stats {
+            print_gc_stats(&lua);
+        }
+        process::exit(1);
+    }
 
     if matches.is_present("repl") {
         run_repl(&mut lua);
     }
 
+    if gc_stats {
+        print_gc_stats(&lua);
+    }
+
     Ok(())
 }
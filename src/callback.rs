@@ -4,7 +4,7 @@ use std::hash::{Hash, Hasher};
 use gc_arena::{Collect, Gc, MutationContext, StaticCollect};
 use gc_sequence::{Sequence, SequenceExt};
 
-use crate::{Error, Function, Value};
+use crate::{Error, Function, Thread, Value};
 
 // Safe, does not implement drop
 #[derive(Collect)]
@@ -31,7 +31,10 @@ pub trait ContinuationFn<'gc>: Collect {
 // Safe, does not implement drop
 #[derive(Collect)]
 #[collect(unsafe_drop)]
-pub struct Continuation<'gc>(Box<dyn ContinuationFn<'gc> + 'gc>);
+pub struct Continuation<'gc> {
+    function: Box<dyn ContinuationFn<'gc> + 'gc>,
+    error_handler: Option<Function<'gc>>,
+}
 
 impl<'gc> Continuation<'gc> {
     pub fn new<F>(cont: F) -> Continuation<'gc>
@@ -54,7 +57,10 @@ impl<'gc> Continuation<'gc> {
             }
         }
 
-        Continuation(Box::new(StaticContinuationFn(cont)))
+        Continuation {
+            function: Box::new(StaticContinuationFn(cont)),
+            error_handler: None,
+        }
     }
 
     pub fn new_with<C, F>(context: C, continuation: F) -> Continuation<'gc>
@@ -80,10 +86,10 @@ impl<'gc> Continuation<'gc> {
             }
         }
 
-        Continuation(Box::new(ContextContinuationFn(
-            context,
-            StaticCollect(continuation),
-        )))
+        Continuation {
+            function: Box::new(ContextContinuationFn(context, StaticCollect(continuation))),
+            error_handler: None,
+        }
     }
 
     pub fn new_immediate<F>(cont: F) -> Continuation<'gc>
@@ -133,13 +139,30 @@ impl<'gc> Continuation<'gc> {
         })
     }
 
+    /// Sets a message handler for errors raised below this continuation.
+    ///
+    /// When an error unwinds to this continuation, the handler is first called with the error value
+    /// *before* any frames are removed from the stack, so that it may inspect the state of the thread
+    /// at the point of the error.  The result of the handler becomes the error value that is passed
+    /// to the continuation.
+    pub fn with_error_handler(mut self, handler: Function<'gc>) -> Continuation<'gc> {
+        self.error_handler = Some(handler);
+        self
+    }
+
     pub fn call(self, res: Result<Vec<Value<'gc>>, Error<'gc>>) -> CallbackReturn<'gc> {
-        self.0.call(res)
+        self.function.call(res)
+    }
+
+    pub(crate) fn take_error_handler(&mut self) -> Option<Function<'gc>> {
+        self.error_handler.take()
     }
 }
 
 pub trait CallbackFn<'gc>: Collect {
-    fn call(&self, res: Vec<Value<'gc>>) -> CallbackReturn<'gc>;
+    /// Calls the callback on the given thread.  The thread is running the callback, so it may not
+    /// be inspected until the callback returns a sequence and that sequence is stepped.
+    fn call(&self, thread: Thread<'gc>, args: Vec<Value<'gc>>) -> CallbackReturn<'gc>;
 }
 
 #[derive(Clone, Copy, Collect)]
@@ -159,7 +182,7 @@ impl<'gc> Callback<'gc> {
         where
             F: 'static + Fn(Vec<Value<'gc>>) -> CallbackReturn<'gc>,
         {
-            fn call(&self, res: Vec<Value<'gc>>) -> CallbackReturn<'gc> {
+            fn call(&self, _: Thread<'gc>, res: Vec<Value<'gc>>) -> CallbackReturn<'gc> {
                 self.0(res)
             }
         }
@@ -181,7 +204,7 @@ impl<'gc> Callback<'gc> {
             C: 'gc + Collect,
             F: 'static + Fn(&C, Vec<Value<'gc>>) -> CallbackReturn<'gc>,
         {
-            fn call(&self, args: Vec<Value<'gc>>) -> CallbackReturn<'gc> {
+            fn call(&self, _: Thread<'gc>, args: Vec<Value<'gc>>) -> CallbackReturn<'gc> {
                 (self.1).0(&self.0, args)
             }
         }
@@ -230,8 +253,36 @@ impl<'gc> Callback<'gc> {
         })
     }
 
-    pub fn call(&self, args: Vec<Value<'gc>>) -> CallbackReturn<'gc> {
-        self.0.call(args)
+    /// Creates a callback which is given the thread it is running on.  The returned sequence is
+    /// stepped with the thread's call stack intact, with the callback itself as the top frame, so
+    /// it may inspect the thread (for example with `Thread::location`).
+    pub fn new_sequence_with_thread<S, F>(mc: MutationContext<'gc, '_>, f: F) -> Callback<'gc>
+    where
+        S: 'gc + Sequence<'gc, Output = Result<CallbackResult<'gc>, Error<'gc>>>,
+        F: 'static + Fn(Thread<'gc>, Vec<Value<'gc>>) -> Result<S, Error<'gc>>,
+    {
+        #[derive(Collect)]
+        #[collect(require_static)]
+        struct ThreadCallbackFn<F>(F);
+
+        impl<'gc, S, F> CallbackFn<'gc> for ThreadCallbackFn<F>
+        where
+            S: 'gc + Sequence<'gc, Output = Result<CallbackResult<'gc>, Error<'gc>>>,
+            F: 'static + Fn(Thread<'gc>, Vec<Value<'gc>>) -> Result<S, Error<'gc>>,
+        {
+            fn call(&self, thread: Thread<'gc>, args: Vec<Value<'gc>>) -> CallbackReturn<'gc> {
+                match self.0(thread, args) {
+                    Ok(seq) => CallbackReturn::Sequence(seq.boxed()),
+                    Err(err) => CallbackReturn::Immediate(Err(err)),
+                }
+            }
+        }
+
+        Callback(Gc::allocate(mc, Box::new(ThreadCallbackFn(f))))
+    }
+
+    pub fn call(&self, thread: Thread<'gc>, args: Vec<Value<'gc>>) -> CallbackReturn<'gc> {
+        self.0.call(thread, args)
     }
 }
 
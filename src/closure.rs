@@ -1,10 +1,17 @@
+use std::cell::Cell;
 use std::error::Error as StdError;
 use std::fmt;
 use std::hash::{Hash, Hasher};
+use std::mem;
 
-use gc_arena::{Collect, Gc, GcCell, MutationContext};
+use gc_arena::{Collect, CollectionContext, Gc, GcCell, MutationContext};
 
-use crate::{Constant, OpCode, RegisterIndex, Table, Thread, UpValueIndex, Value};
+use crate::{
+    Constant, LineNumber, OpCode, RegisterIndex, String, Table, Thread, UpValueIndex, Value,
+};
+
+// The maximum length of a chunk name in error messages, like `LUA_IDSIZE` in PUC-Rio Lua.
+pub(crate) const ID_SIZE: usize = 60;
 
 #[derive(Debug, Collect, Clone, Copy, PartialEq, Eq)]
 #[collect(require_static)]
@@ -14,23 +21,93 @@ pub enum UpValueDescriptor {
     Outer(UpValueIndex),
 }
 
+/// A named local variable of a function, along with the register it is stored in and the range of
+/// opcodes in which it is in scope.
+#[derive(Debug, Collect, Clone, Copy)]
+#[collect(require_copy)]
+pub struct LocalVariable<'gc> {
+    pub name: String<'gc>,
+    pub register: RegisterIndex,
+    /// The index of the first opcode where the variable is in scope
+    pub start_pc: usize,
+    /// The index of the first opcode after the variable goes out of scope
+    pub end_pc: usize,
+}
+
 #[derive(Debug, Collect)]
 #[collect(empty_drop)]
 pub struct FunctionProto<'gc> {
+    /// The name of the chunk this function was compiled from, as given to `load`
+    pub chunk_name: String<'gc>,
+    /// The line the function was defined on, or 0 for the main function of a chunk
+    pub line_defined: LineNumber,
     pub fixed_params: u8,
     pub has_varargs: bool,
     pub stack_size: u16,
     pub constants: Vec<Constant<'gc>>,
     pub opcodes: Vec<OpCode>,
+    /// Pairs of the index of an opcode and the source line it and every following opcode (up to
+    /// the next entry) were compiled from, in order of opcode index
+    pub opcode_lines: Vec<(usize, LineNumber)>,
     pub upvalues: Vec<UpValueDescriptor>,
+    /// The names of the upvalues in `upvalues`, in the same order
+    pub upvalue_names: Vec<String<'gc>>,
+    /// Every local variable of the function including its parameters, in order of declaration
+    pub local_variables: Vec<LocalVariable<'gc>>,
     pub prototypes: Vec<Gc<'gc, FunctionProto<'gc>>>,
 }
 
+impl<'gc> FunctionProto<'gc> {
+    // Allocates the prototype, recording the memory owned by its buffers so that it counts towards
+    // the memory limit.
+    pub(crate) fn allocate(self, mc: MutationContext<'gc, '_>) -> Gc<'gc, FunctionProto<'gc>> {
+        let size = self.constants.capacity() * mem::size_of::<Constant>()
+            + self.opcodes.capacity() * mem::size_of::<OpCode>()
+            + self.opcode_lines.capacity() * mem::size_of::<(usize, LineNumber)>()
+            + self.upvalues.capacity() * mem::size_of::<UpValueDescriptor>()
+            + self.upvalue_names.capacity() * mem::size_of::<String>()
+            + self.local_variables.capacity() * mem::size_of::<LocalVariable>()
+            + self.prototypes.capacity() * mem::size_of::<Gc<FunctionProto>>();
+        let proto = Gc::allocate(mc, self);
+        Gc::set_external_size(mc, proto, size);
+        proto
+    }
+
+    /// Returns the source line that the opcode at the given index was compiled from, if known.
+    pub fn opcode_line(&self, pc: usize) -> Option<LineNumber> {
+        let i = match self
+            .opcode_lines
+            .binary_search_by_key(&pc, |&(start, _)| start)
+        {
+            Ok(i) => i,
+            Err(0) => return None,
+            Err(i) => i - 1,
+        };
+        Some(self.opcode_lines[i].1)
+    }
+
+    /// Returns the name of the `n`th local variable (starting from 1) which is in scope at the
+    /// opcode with the given index, along with the register it is stored in, like
+    /// `luaF_getlocalname` in PUC-Rio Lua.
+    pub fn local_variable(&self, n: usize, pc: usize) -> Option<(String<'gc>, RegisterIndex)> {
+        self.local_variables
+            .iter()
+            .filter(|local| local.start_pc <= pc && pc < local.end_pc)
+            .nth(n.checked_sub(1)?)
+            .map(|local| (local.name, local.register))
+    }
+}
+
 // Pretty-print a `FunctionProto` with minimal formatting
 impl<'gc> fmt::Display for FunctionProto<'gc> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         writeln!(f, "=============")?;
-        writeln!(f, "FunctionProto({:p})", self)?;
+        writeln!(
+            f,
+            "FunctionProto({:p}) {}",
+            self,
+            std::string::String::from_utf8_lossy(self.chunk_name.as_bytes())
+        )?;
         writeln!(f, "=============")?;
         writeln!(
             f,
@@ -46,7 +123,10 @@ impl<'gc> fmt::Display for FunctionProto<'gc> {
         if self.opcodes.len() > 0 {
             writeln!(f, "opcodes:")?;
             for (i, c) in self.opcodes.iter().enumerate() {
-                writeln!(f, "{}: {:?}", i, c)?;
+                match self.opcode_line(i) {
+                    Some(line) => writeln!(f, "{}: [{}] {:?}", i, line, c)?,
+                    None => writeln!(f, "{}: {:?}", i, c)?,
+                }
             }
         }
         if self.upvalues.len() > 0 {
@@ -76,11 +156,42 @@ pub enum UpValueState<'gc> {
 #[collect(require_copy)]
 pub struct UpValue<'gc>(pub GcCell<'gc, UpValueState<'gc>>);
 
-#[derive(Debug, Collect)]
-#[collect(empty_drop)]
+impl<'gc> UpValue<'gc> {
+    /// Returns the current value of this upvalue.
+    ///
+    /// If the upvalue is still open, this reads the stack of the thread that owns it, so it must not
+    /// be called while that thread is borrowed (for example, while the thread is running the VM).
+    pub fn get(self) -> Value<'gc> {
+        match *self.0.read() {
+            UpValueState::Open(thread, ind) => thread.stack_value(ind),
+            UpValueState::Closed(v) => v,
+        }
+    }
+
+    /// Sets the value of this upvalue, with the same restrictions as `UpValue::get`.
+    pub fn set(self, mc: MutationContext<'gc, '_>, value: Value<'gc>) {
+        let mut state = self.0.write(mc);
+        match &mut *state {
+            UpValueState::Open(thread, ind) => thread.set_stack_value(mc, *ind, value),
+            UpValueState::Closed(v) => *v = value,
+        }
+    }
+}
+
+#[derive(Debug)]
 pub struct ClosureState<'gc> {
     pub proto: Gc<'gc, FunctionProto<'gc>>,
-    pub upvalues: Vec<UpValue<'gc>>,
+    // Upvalues may be replaced after the closure is created by `Closure::set_upvalue`.
+    pub(crate) upvalues: Vec<Cell<UpValue<'gc>>>,
+}
+
+unsafe impl<'gc> Collect for ClosureState<'gc> {
+    fn trace(&self, cc: CollectionContext) {
+        self.proto.trace(cc);
+        for upvalue in &self.upvalues {
+            upvalue.get().trace(cc);
+        }
+    }
 }
 
 #[derive(Debug, Copy, Clone, Collect)]
@@ -132,17 +243,17 @@ impl<'gc> Closure<'gc> {
         proto: FunctionProto<'gc>,
         environment: Option<Table<'gc>>,
     ) -> Result<Closure<'gc>, ClosureError> {
-        let proto = Gc::allocate(mc, proto);
+        let proto = proto.allocate(mc);
         let mut upvalues = Vec::new();
 
         if !proto.upvalues.is_empty() {
             if proto.upvalues.len() > 1 || proto.upvalues[0] != UpValueDescriptor::Environment {
                 return Err(ClosureError::HasUpValues);
             } else if let Some(environment) = environment {
-                upvalues.push(UpValue(GcCell::allocate(
+                upvalues.push(Cell::new(UpValue(GcCell::allocate(
                     mc,
                     UpValueState::Closed(Value::Table(environment)),
-                )));
+                ))));
             } else {
                 return Err(ClosureError::RequiresEnv);
             }
@@ -150,4 +261,70 @@ impl<'gc> Closure<'gc> {
 
         Ok(Closure(Gc::allocate(mc, ClosureState { proto, upvalues })))
     }
+
+    /// Create a closure from a prototype and a list of upvalues, one for each upvalue descriptor of
+    /// the prototype.
+    pub(crate) fn with_upvalues(
+        mc: MutationContext<'gc, '_>,
+        proto: Gc<'gc, FunctionProto<'gc>>,
+        upvalues: Vec<UpValue<'gc>>,
+    ) -> Closure<'gc> {
+        let upvalues = upvalues.into_iter().map(Cell::new).collect();
+        Closure(Gc::allocate(mc, ClosureState { proto, upvalues }))
+    }
+
+    pub fn upvalue_count(self) -> usize {
+        self.0.upvalues.len()
+    }
+
+    pub fn upvalue(self, index: usize) -> Option<UpValue<'gc>> {
+        self.0.upvalues.get(index).map(|upvalue| upvalue.get())
+    }
+
+    /// Replaces the upvalue at the given index, so that this closure shares it with any other
+    /// closures that refer to it.  Returns false if there is no upvalue at that index.
+    pub fn set_upvalue(
+        self,
+        mc: MutationContext<'gc, '_>,
+        index: usize,
+        upvalue: UpValue<'gc>,
+    ) -> bool {
+        match self.0.upvalues.get(index) {
+            Some(cell) => {
+                Gc::write_barrier(mc, self.0);
+                cell.set(upvalue);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+// Formats a chunk name for use in error messages, like `luaO_chunkid` in PUC-Rio Lua.  Names
+// starting with '=' are used as-is, names starting with '@' are file names, and anything else is
+// the source of the chunk itself.
+pub(crate) fn chunk_id(name: &[u8]) -> Vec<u8> {
+    match name.split_first() {
+        Some((b'=', rest)) => rest[0..rest.len().min(ID_SIZE - 1)].to_vec(),
+        Some((b'@', rest)) => {
+            if rest.len() < ID_SIZE {
+                rest.to_vec()
+            } else {
+                [&b"..."[..], &rest[rest.len() - (ID_SIZE - 4)..]].concat()
+            }
+        }
+        _ => {
+            // Room for the source itself after `[string "`, `..."]` and the terminator
+            const MAX_LEN: usize = ID_SIZE - 15;
+            let line_end = name.iter().position(|&c| c == b'\n');
+            let len = line_end.unwrap_or(name.len()).min(MAX_LEN);
+            let mut id = b"[string \"".to_vec();
+            id.extend_from_slice(&name[0..len]);
+            if line_end.is_some() || name.len() > MAX_LEN {
+                id.extend_from_slice(b"...");
+            }
+            id.extend_from_slice(b"\"]");
+            id
+        }
+    }
 }
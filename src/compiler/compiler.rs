@@ -4,19 +4,19 @@ use std::{fmt, iter, mem};
 
 use num_traits::cast;
 
-use gc_arena::{Collect, Gc, MutationContext};
+use gc_arena::{Collect, MutationContext};
 
 use crate::parser::{
     AssignmentStatement, AssignmentTarget, BinaryOperator, Block, CallSuffix, Chunk,
     ConstructorField, Expression, FieldSuffix, ForStatement, FunctionCallStatement,
-    FunctionDefinition, FunctionStatement, HeadExpression, IfStatement, LocalFunctionStatement,
-    LocalStatement, PrimaryExpression, RecordKey, RepeatStatement, ReturnStatement,
-    SimpleExpression, Statement, SuffixPart, SuffixedExpression, TableConstructor, UnaryOperator,
-    WhileStatement,
+    FunctionDefinition, FunctionStatement, HeadExpression, IfStatement, LineAnnotated,
+    LocalFunctionStatement, LocalStatement, PrimaryExpression, RecordKey, RepeatStatement,
+    ReturnStatement, SimpleExpression, Statement, SuffixPart, SuffixedExpression, TableConstructor,
+    UnaryOperator, WhileStatement,
 };
 use crate::{
-    Constant, ConstantIndex16, ConstantIndex8, FunctionProto, OpCode, Opt254, PrototypeIndex,
-    RegisterIndex, String, UpValueDescriptor, UpValueIndex, VarCount,
+    Constant, ConstantIndex16, ConstantIndex8, FunctionProto, LineNumber, LocalVariable, OpCode,
+    Opt254, PrototypeIndex, RegisterIndex, String, UpValueDescriptor, UpValueIndex, VarCount,
 };
 
 use super::operators::{
@@ -62,19 +62,22 @@ impl fmt::Display for CompilerError {
 
 pub fn compile_chunk<'gc>(
     mc: MutationContext<'gc, '_>,
+    chunk_name: String<'gc>,
     chunk: &Chunk<String<'gc>>,
 ) -> Result<FunctionProto<'gc>, CompilerError> {
     let mut compiler = Compiler {
         mutation_context: mc,
+        chunk_name,
         current_function: CompilerFunction::start(&[], true)?,
         upper_functions: Vec::new(),
     };
     compiler.block(&chunk.block)?;
-    compiler.current_function.finish(mc)
+    compiler.current_function.finish(mc, chunk_name)
 }
 
 struct Compiler<'gc, 'a> {
     mutation_context: MutationContext<'gc, 'a>,
+    chunk_name: String<'gc>,
     current_function: CompilerFunction<'gc>,
     upper_functions: Vec<CompilerFunction<'gc>>,
 }
@@ -92,6 +95,10 @@ struct CompilerFunction<'gc> {
     has_varargs: bool,
     fixed_params: u8,
     locals: Vec<(String<'gc>, RegisterIndex)>,
+    // Every local variable declared so far, along with the index into `local_variables` of each
+    // entry in `locals`
+    local_variables: Vec<LocalVariable<'gc>>,
+    local_variable_indices: Vec<usize>,
 
     blocks: Vec<BlockDescriptor>,
     unique_jump_id: u64,
@@ -99,6 +106,11 @@ struct CompilerFunction<'gc> {
     pending_jumps: Vec<PendingJump<'gc>>,
 
     opcodes: Vec<OpCode>,
+    // The source line of each run of opcodes, as pairs of the index of the first opcode in the run
+    // and its line number.
+    opcode_lines: Vec<(usize, LineNumber)>,
+    // The line this function is defined on, or 0 for the main chunk
+    line_defined: LineNumber,
 }
 
 #[derive(Debug)]
@@ -224,8 +236,9 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
 
         while let Some((_, last)) = self.current_function.locals.last() {
             if last.0 as u16 >= last_block.stack_bottom {
-                self.current_function.register_allocator.free(*last);
-                self.current_function.locals.pop();
+                let last = *last;
+                self.current_function.register_allocator.free(last);
+                self.current_function.pop_local();
             } else {
                 break;
             }
@@ -270,13 +283,13 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
     fn block_statements(&mut self, block: &Block<String<'gc>>) -> Result<(), CompilerError> {
         if let Some(return_statement) = &block.return_statement {
             for statement in &block.statements {
-                self.statement(statement)?;
+                self.line_statement(statement)?;
             }
-            self.return_statement(return_statement)?;
+            self.line_return_statement(return_statement)?;
         } else {
             let mut last = block.statements.len();
             for i in (0..block.statements.len()).rev() {
-                match &block.statements[i] {
+                match &block.statements[i].inner {
                     Statement::Label(_) => {}
                     _ => break,
                 }
@@ -286,17 +299,33 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
 
             self.enter_block();
             for i in 0..block.statements.len() - trailing_labels.len() {
-                self.statement(&block.statements[i])?;
+                self.line_statement(&block.statements[i])?;
             }
             self.exit_block()?;
 
             for label_statement in trailing_labels {
-                self.statement(&label_statement)?;
+                self.line_statement(&label_statement)?;
             }
         }
         Ok(())
     }
 
+    fn line_statement(
+        &mut self,
+        statement: &LineAnnotated<Statement<String<'gc>>>,
+    ) -> Result<(), CompilerError> {
+        self.current_function.set_line(statement.line_number);
+        self.statement(&statement.inner)
+    }
+
+    fn line_return_statement(
+        &mut self,
+        return_statement: &LineAnnotated<ReturnStatement<String<'gc>>>,
+    ) -> Result<(), CompilerError> {
+        self.current_function.set_line(return_statement.line_number);
+        self.return_statement(&return_statement.inner)
+    }
+
     fn statement(&mut self, statement: &Statement<String<'gc>>) -> Result<(), CompilerError> {
         match statement {
             Statement::If(if_statement) => self.if_statement(if_statement),
@@ -434,7 +463,7 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
                     .register_allocator
                     .push(1)
                     .ok_or(CompilerError::Registers)?;
-                self.current_function.locals.push((*name, loop_var));
+                self.current_function.push_local(*name, loop_var);
 
                 self.block_statements(body)?;
                 self.exit_block()?;
@@ -511,8 +540,7 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
                     .ok_or(CompilerError::Registers)?;
                 for i in 0..name_count {
                     self.current_function
-                        .locals
-                        .push((names[i as usize], RegisterIndex(names_reg.0 + i)));
+                        .push_local(names[i as usize], RegisterIndex(names_reg.0 + i));
                 }
 
                 self.jump(loop_label)?;
@@ -581,10 +609,10 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
         // `repeat` statements do not follow the trailing label rule, because the variables inside
         // the block are in scope for the `until` condition at the end.
         for statement in &repeat_statement.body.statements {
-            self.statement(statement)?;
+            self.line_statement(statement)?;
         }
         if let Some(return_statement) = &repeat_statement.body.return_statement {
-            self.return_statement(return_statement)?;
+            self.line_return_statement(return_statement)?;
         }
 
         let condition = self.expression(&repeat_statement.until)?;
@@ -672,8 +700,7 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
                 .push(OpCode::LoadNil { dest, count });
             for i in 0..name_len {
                 self.current_function
-                    .locals
-                    .push((local_statement.names[i], RegisterIndex(dest.0 + i as u8)));
+                    .push_local(local_statement.names[i], RegisterIndex(dest.0 + i as u8));
             }
         } else {
             for i in 0..val_len {
@@ -688,16 +715,15 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
                     let dest = self.expr_push_count(expr, names_left)?;
 
                     for j in 0..names_left {
-                        self.current_function.locals.push((
+                        self.current_function.push_local(
                             local_statement.names[val_len - 1 + j as usize],
                             RegisterIndex(dest.0 + j),
-                        ));
+                        );
                     }
                 } else {
                     let reg = self.expr_discharge(expr, ExprDestination::PushNew)?;
                     self.current_function
-                        .locals
-                        .push((local_statement.names[i], reg));
+                        .push_local(local_statement.names[i], reg);
                 }
             }
         }
@@ -800,9 +826,7 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
         self.current_function
             .opcodes
             .push(OpCode::Closure { proto, dest });
-        self.current_function
-            .locals
-            .push((local_function.name, dest));
+        self.current_function.push_local(local_function.name, dest);
 
         Ok(())
     }
@@ -1036,17 +1060,21 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
         has_varargs: bool,
         body: &Block<String<'gc>>,
     ) -> Result<PrototypeIndex, CompilerError> {
-        let old_current = mem::replace(
-            &mut self.current_function,
-            CompilerFunction::start(parameters, has_varargs)?,
-        );
+        let mut new_function = CompilerFunction::start(parameters, has_varargs)?;
+        // Until the first statement of the body, opcodes belong to the line the function is
+        // defined on.
+        if let Some(&(_, line_number)) = self.current_function.opcode_lines.last() {
+            new_function.line_defined = line_number;
+            new_function.set_line(line_number);
+        }
+        let old_current = mem::replace(&mut self.current_function, new_function);
         self.upper_functions.push(old_current);
         self.block(body)?;
         let proto = mem::replace(
             &mut self.current_function,
             self.upper_functions.pop().unwrap(),
         )
-        .finish(self.mutation_context)?;
+        .finish(self.mutation_context, self.chunk_name)?;
         self.current_function.prototypes.push(proto);
         Ok(PrototypeIndex(
             cast(self.current_function.prototypes.len() - 1).ok_or(CompilerError::Functions)?,
@@ -1465,6 +1493,14 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
                     self.call_function(*func, args, VarCount::variable())?;
                     VarCount::variable()
                 }
+                ExprDescriptor::MethodCall {
+                    table,
+                    method,
+                    args,
+                } => {
+                    self.call_method(*table, *method, args, VarCount::variable())?;
+                    VarCount::variable()
+                }
                 ExprDescriptor::VarArgs => {
                     self.current_function.opcodes.push(OpCode::VarArgs {
                         dest: RegisterIndex(
@@ -1884,6 +1920,23 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
                     .ok_or(CompilerError::Registers)?;
                 dest
             }
+            ExprDescriptor::MethodCall {
+                table,
+                method,
+                args,
+            } => {
+                let dest = self.call_method(
+                    *table,
+                    *method,
+                    args,
+                    VarCount::try_constant(count).ok_or(CompilerError::Registers)?,
+                )?;
+                self.current_function
+                    .register_allocator
+                    .push(count)
+                    .ok_or(CompilerError::Registers)?;
+                dest
+            }
             ExprDescriptor::VarArgs => {
                 let dest = self
                     .current_function
@@ -2009,20 +2062,53 @@ impl<'gc> CompilerFunction<'gc> {
         function.has_varargs = has_varargs;
         function.fixed_params = fixed_params;
         for i in 0..fixed_params {
-            function
-                .locals
-                .push((parameters[i as usize], RegisterIndex(i)));
+            function.push_local(parameters[i as usize], RegisterIndex(i));
         }
         Ok(function)
     }
 
-    fn finish(mut self, mc: MutationContext<'gc, '_>) -> Result<FunctionProto<'gc>, CompilerError> {
+    // Sets the source line number of all opcodes pushed after this call.
+    fn set_line(&mut self, line_number: LineNumber) {
+        match self.opcode_lines.last_mut() {
+            Some((_, last)) if *last == line_number => {}
+            Some((start, last)) if *start == self.opcodes.len() => *last = line_number,
+            _ => self.opcode_lines.push((self.opcodes.len(), line_number)),
+        }
+    }
+
+    // Declares a local variable stored in the given register, which is in scope starting from the
+    // next opcode pushed.
+    fn push_local(&mut self, name: String<'gc>, register: RegisterIndex) {
+        self.locals.push((name, register));
+        self.local_variable_indices.push(self.local_variables.len());
+        self.local_variables.push(LocalVariable {
+            name,
+            register,
+            start_pc: self.opcodes.len(),
+            end_pc: self.opcodes.len(),
+        });
+    }
+
+    // Removes the most recently declared local variable, which goes out of scope after the last
+    // opcode pushed.
+    fn pop_local(&mut self) -> Option<(String<'gc>, RegisterIndex)> {
+        let local = self.locals.pop()?;
+        let index = self.local_variable_indices.pop().unwrap();
+        self.local_variables[index].end_pc = self.opcodes.len();
+        Some(local)
+    }
+
+    fn finish(
+        mut self,
+        mc: MutationContext<'gc, '_>,
+        chunk_name: String<'gc>,
+    ) -> Result<FunctionProto<'gc>, CompilerError> {
         self.opcodes.push(OpCode::Return {
             start: RegisterIndex(0),
             count: VarCount::constant(0),
         });
         assert!(self.locals.len() == self.fixed_params as usize);
-        for (_, r) in self.locals.drain(..) {
+        while let Some((_, r)) = self.pop_local() {
             self.register_allocator.free(r);
         }
         assert_eq!(
@@ -2036,16 +2122,21 @@ impl<'gc> CompilerFunction<'gc> {
         }
 
         Ok(FunctionProto {
+            chunk_name,
+            line_defined: self.line_defined,
             fixed_params: self.fixed_params,
             has_varargs: self.has_varargs,
             stack_size: self.register_allocator.stack_size(),
             constants: self.constants,
             opcodes: self.opcodes,
+            opcode_lines: self.opcode_lines,
             upvalues: self.upvalues.iter().map(|(_, d)| *d).collect(),
+            upvalue_names: self.upvalues.iter().map(|(n, _)| *n).collect(),
+            local_variables: self.local_variables,
             prototypes: self
                 .prototypes
                 .into_iter()
-                .map(|f| Gc::allocate(mc, f))
+                .map(|f| f.allocate(mc))
                 .collect(),
         })
     }
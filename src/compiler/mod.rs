@@ -14,9 +14,21 @@ pub fn compile<'gc, R: Read>(
     mc: MutationContext<'gc, '_>,
     interned_strings: InternedStringSet<'gc>,
     source: R,
+) -> Result<FunctionProto<'gc>, Error<'gc>> {
+    compile_named(mc, interned_strings, b"=?", source)
+}
+
+/// Compiles a chunk with the given chunk name, which follows the same conventions as the name given
+/// to `load`: "=name" for a literal name, "@name" for a file name, or otherwise the chunk source.
+pub fn compile_named<'gc, R: Read>(
+    mc: MutationContext<'gc, '_>,
+    interned_strings: InternedStringSet<'gc>,
+    chunk_name: &[u8],
+    source: R,
 ) -> Result<FunctionProto<'gc>, Error<'gc>> {
     Ok(compile_chunk(
         mc,
+        interned_strings.new_string(mc, chunk_name),
         &parse_chunk(source, |s| interned_strings.new_string(mc, s))?,
     )?)
 }
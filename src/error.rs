@@ -6,7 +6,8 @@ use gc_arena::{Collect, MutationContext, StaticCollect};
 
 use crate::{
     BadThreadMode, BinaryOperatorError, ClosureError, CompilerError, InternedStringSet,
-    InvalidTableKey, ParserError, StringError, ThreadError, Value,
+    InvalidNextKey, InvalidTableKey, LimitError, MetaOperatorError, PackError, ParserError,
+    StringError, ThreadError, Value,
 };
 
 #[derive(Debug, Clone, Copy, Collect)]
@@ -52,12 +53,16 @@ pub enum Error<'gc> {
     CompilerError(CompilerError),
     ClosureError(ClosureError),
     InvalidTableKey(InvalidTableKey),
+    InvalidNextKey(InvalidNextKey),
     StringError(StringError),
+    PackError(PackError),
     ThreadError(ThreadError),
     BadThreadMode(BadThreadMode),
     TypeError(TypeError),
     BinaryOperatorError(BinaryOperatorError),
+    MetaOperatorError(MetaOperatorError),
     RuntimeError(RuntimeError<'gc>),
+    LimitError(LimitError),
 }
 
 impl<'gc> StdError for Error<'gc> {}
@@ -70,12 +75,16 @@ impl<'gc> fmt::Display for Error<'gc> {
             Error::CompilerError(error) => write!(fmt, "compiler error: {}", error),
             Error::ClosureError(error) => write!(fmt, "closure error: {}", error),
             Error::InvalidTableKey(error) => write!(fmt, "invalid table key: {}", error),
+            Error::InvalidNextKey(error) => write!(fmt, "table error: {}", error),
             Error::StringError(error) => write!(fmt, "string error: {}", error),
+            Error::PackError(error) => write!(fmt, "pack error: {}", error),
             Error::ThreadError(error) => write!(fmt, "thread error: {}", error),
             Error::BadThreadMode(error) => write!(fmt, "bad thread mode: {}", error),
             Error::TypeError(error) => write!(fmt, "type error: {}", error),
             Error::BinaryOperatorError(error) => write!(fmt, "operator error: {}", error),
+            Error::MetaOperatorError(error) => write!(fmt, "metamethod error: {}", error),
             Error::RuntimeError(error) => write!(fmt, "runtime error: {}", error),
+            Error::LimitError(error) => write!(fmt, "limit error: {}", error),
         }
     }
 }
@@ -110,12 +119,24 @@ impl<'gc> From<InvalidTableKey> for Error<'gc> {
     }
 }
 
+impl<'gc> From<InvalidNextKey> for Error<'gc> {
+    fn from(error: InvalidNextKey) -> Error<'gc> {
+        Error::InvalidNextKey(error)
+    }
+}
+
 impl<'gc> From<StringError> for Error<'gc> {
     fn from(error: StringError) -> Error<'gc> {
         Error::StringError(error)
     }
 }
 
+impl<'gc> From<PackError> for Error<'gc> {
+    fn from(error: PackError) -> Error<'gc> {
+        Error::PackError(error)
+    }
+}
+
 impl<'gc> From<ThreadError> for Error<'gc> {
     fn from(error: ThreadError) -> Error<'gc> {
         Error::ThreadError(error)
@@ -140,12 +161,24 @@ impl<'gc> From<BinaryOperatorError> for Error<'gc> {
     }
 }
 
+impl<'gc> From<MetaOperatorError> for Error<'gc> {
+    fn from(error: MetaOperatorError) -> Error<'gc> {
+        Error::MetaOperatorError(error)
+    }
+}
+
 impl<'gc> From<RuntimeError<'gc>> for Error<'gc> {
     fn from(error: RuntimeError<'gc>) -> Error<'gc> {
         Error::RuntimeError(error)
     }
 }
 
+impl<'gc> From<LimitError> for Error<'gc> {
+    fn from(error: LimitError) -> Error<'gc> {
+        Error::LimitError(error)
+    }
+}
+
 impl<'gc> Error<'gc> {
     pub fn to_static(self) -> StaticError {
         match self {
@@ -154,16 +187,20 @@ impl<'gc> Error<'gc> {
             Error::CompilerError(error) => StaticError::CompilerError(error),
             Error::ClosureError(error) => StaticError::ClosureError(error),
             Error::InvalidTableKey(error) => StaticError::InvalidTableKey(error),
+            Error::InvalidNextKey(error) => StaticError::InvalidNextKey(error),
             Error::StringError(error) => StaticError::StringError(error),
+            Error::PackError(error) => StaticError::PackError(error),
             Error::ThreadError(error) => StaticError::ThreadError(error),
             Error::BadThreadMode(error) => StaticError::BadThreadMode(error),
             Error::TypeError(error) => StaticError::TypeError(error),
             Error::BinaryOperatorError(error) => StaticError::BinaryOperatorError(error),
+            Error::MetaOperatorError(error) => StaticError::MetaOperatorError(error),
             Error::RuntimeError(error) => {
                 let mut buf = Vec::new();
                 error.0.display(&mut buf).unwrap();
                 StaticError::RuntimeError(StdString::from_utf8_lossy(&buf).to_owned().to_string())
             }
+            Error::LimitError(error) => StaticError::LimitError(error),
         }
     }
 
@@ -190,12 +227,16 @@ pub enum StaticError {
     CompilerError(CompilerError),
     ClosureError(ClosureError),
     InvalidTableKey(InvalidTableKey),
+    InvalidNextKey(InvalidNextKey),
     StringError(StringError),
+    PackError(PackError),
     ThreadError(ThreadError),
     BadThreadMode(BadThreadMode),
     TypeError(TypeError),
     BinaryOperatorError(BinaryOperatorError),
+    MetaOperatorError(MetaOperatorError),
     RuntimeError(String),
+    LimitError(LimitError),
 }
 
 impl StdError for StaticError {}
@@ -208,12 +249,16 @@ impl fmt::Display for StaticError {
             StaticError::CompilerError(error) => write!(fmt, "compiler error: {}", error),
             StaticError::ClosureError(error) => write!(fmt, "closure error: {}", error),
             StaticError::InvalidTableKey(error) => write!(fmt, "invalid table key: {}", error),
+            StaticError::InvalidNextKey(error) => write!(fmt, "table error: {}", error),
             StaticError::StringError(error) => write!(fmt, "string error: {}", error),
+            StaticError::PackError(error) => write!(fmt, "pack error: {}", error),
             StaticError::ThreadError(error) => write!(fmt, "thread error: {}", error),
             StaticError::BadThreadMode(error) => write!(fmt, "bad thread mode: {}", error),
             StaticError::TypeError(error) => write!(fmt, "type error: {}", error),
             StaticError::BinaryOperatorError(error) => write!(fmt, "operator error: {}", error),
+            StaticError::MetaOperatorError(error) => write!(fmt, "metamethod error: {}", error),
             StaticError::RuntimeError(error) => write!(fmt, "runtime error: {}", error),
+            StaticError::LimitError(error) => write!(fmt, "limit error: {}", error),
         }
     }
 }
@@ -510,11 +510,10 @@ where
                             }
                         }
 
-                        let c = char::from_u32(u).ok_or(LexerError::EscapeUnicodeInvalid)?;
-                        let mut buf = [0; 4];
-                        for &b in c.encode_utf8(&mut buf).as_bytes() {
-                            self.string_buffer.push(b);
+                        if char::from_u32(u).is_none() {
+                            return Err(LexerError::EscapeUnicodeInvalid);
                         }
+                        encode_utf8(u, &mut self.string_buffer);
                     }
 
                     b'z' => {
@@ -771,7 +770,7 @@ pub fn read_integer(s: &[u8]) -> Option<i64> {
 pub fn read_hex_integer(s: &[u8]) -> Option<i64> {
     let (is_neg, s) = read_neg(s);
 
-    if s[0] != b'0' || (s[1] != b'x' && s[1] != b'X') {
+    if s.len() < 3 || s[0] != b'0' || (s[1] != b'x' && s[1] != b'X') {
         return None;
     }
 
@@ -870,6 +869,35 @@ pub fn read_hex_float(s: &[u8]) -> Option<f64> {
     Some(base * (exp as f64).exp2())
 }
 
+/// Appends the UTF-8 encoding of `c` to `buf`.
+///
+/// Like PUC-Rio Lua, this uses the original UTF-8 scheme of up to 6 bytes, so any value up to
+/// `0x7FFFFFFF` can be encoded, including surrogates.  Callers must check that `c` is in range.
+pub fn encode_utf8(mut c: u32, buf: &mut Vec<u8>) {
+    debug_assert!(c <= 0x7FFF_FFFF);
+    if c < 0x80 {
+        buf.push(c as u8);
+        return;
+    }
+
+    let mut bytes = [0; 6];
+    let mut n = bytes.len();
+    // The maximum value that fits in the first byte
+    let mut max_first: u32 = 0x3f;
+    loop {
+        n -= 1;
+        bytes[n] = 0x80 | (c & 0x3f) as u8;
+        c >>= 6;
+        max_first >>= 1;
+        if c <= max_first {
+            break;
+        }
+    }
+    n -= 1;
+    bytes[n] = ((!max_first << 1) | c) as u8;
+    buf.extend_from_slice(&bytes[n..]);
+}
+
 fn read_neg(s: &[u8]) -> (bool, &[u8]) {
     if s.len() > 0 {
         if s[0] == b'-' {
@@ -8,33 +8,43 @@ pub mod io;
 mod lexer;
 #[macro_use]
 mod lua;
+mod meta_ops;
 mod opcode;
 pub mod parser;
 mod string;
 mod table;
 mod thread;
 mod types;
+mod userdata;
 mod value;
 
 mod stdlib;
 
 pub use callback::{Callback, CallbackResult, CallbackReturn, Continuation};
 pub use closure::{
-    Closure, ClosureError, ClosureState, FunctionProto, UpValue, UpValueDescriptor, UpValueState,
+    Closure, ClosureError, ClosureState, FunctionProto, LocalVariable, UpValue, UpValueDescriptor,
+    UpValueState,
 };
-pub use compiler::{compile, compile_chunk, CompilerError};
+pub use compiler::{compile, compile_chunk, compile_named, CompilerError};
 pub use constant::Constant;
 pub use error::{Error, RuntimeError, StaticError, TypeError};
 pub use lexer::{Lexer, LexerError, Token};
-pub use lua::{Lua, Root};
+pub use lua::{Lua, LuaHost, Root};
+pub use meta_ops::{MetaMethod, MetaOperatorError};
 pub use opcode::OpCode;
-pub use parser::{parse_chunk, ParserError};
+pub use parser::{parse_chunk, ParserError, ParserErrorKind};
+pub use stdlib::{
+    IoFile, IoHost, MemoryIoHost, OpenMode, OsHost, PackError, SandboxOsHost, StdIoHost, StdOsHost,
+};
 pub use string::{InternedStringSet, String, StringError};
-pub use table::{InvalidTableKey, Table, TableState};
+pub use table::{InvalidNextKey, InvalidTableKey, Table, TableIter, TableState};
 pub use thread::{
-    BadThreadMode, BinaryOperatorError, Thread, ThreadError, ThreadMode, ThreadSequence,
+    BadThreadMode, BinaryOperatorError, ExecutionBudget, FrameInfo, FunctionName, Hook, HookMask,
+    LimitError, Limits, Thread, ThreadError, ThreadMode, ThreadSequence, Traceback, TracebackFrame,
 };
 pub use types::{
-    ConstantIndex16, ConstantIndex8, Opt254, PrototypeIndex, RegisterIndex, UpValueIndex, VarCount,
+    ConstantIndex16, ConstantIndex8, LineNumber, Opt254, PrototypeIndex, RegisterIndex,
+    UpValueIndex, VarCount,
 };
+pub use userdata::{StaticRoot, UserData, UserDataRoot, UserDataState};
 pub use value::{Function, Value};
@@ -1,30 +1,96 @@
-use gc_arena::{ArenaParameters, Collect, MutationContext};
+use std::rc::Rc;
+
+use gc_arena::{
+    ArenaParameters, Collect, FinalizationQueue, GcStats, MutationContext, TypeAllocations,
+};
 use gc_sequence::{make_sequencable_arena, Sequence};
 
 use crate::{
-    stdlib::{load_base, load_coroutine, load_math},
-    InternedStringSet, Table, Thread,
+    stdlib::{
+        load_base, load_coroutine, load_debug, load_io, load_math, load_os, load_package,
+        load_string, load_table, load_utf8, lua_searcher,
+    },
+    Callback, ExecutionBudget, InternedStringSet, IoHost, Limits, MemoryIoHost, OsHost,
+    SandboxOsHost, StdIoHost, StdOsHost, String, Table, Thread, Value,
 };
 
+/// The interfaces through which the standard library accesses the host system.
+#[derive(Clone)]
+pub struct LuaHost {
+    pub os: Rc<dyn OsHost>,
+    pub io: Rc<dyn IoHost>,
+}
+
+impl LuaHost {
+    /// Uses the real system clock, environment and filesystem.
+    pub fn std() -> LuaHost {
+        LuaHost {
+            os: Rc::new(StdOsHost::new()),
+            io: Rc::new(StdIoHost),
+        }
+    }
+
+    /// Denies access to the environment and the real filesystem, any files opened through the `io`
+    /// library are kept in memory.
+    pub fn sandboxed() -> LuaHost {
+        LuaHost {
+            os: Rc::new(SandboxOsHost::new()),
+            io: Rc::new(MemoryIoHost::new()),
+        }
+    }
+}
+
+impl Default for LuaHost {
+    fn default() -> LuaHost {
+        LuaHost::std()
+    }
+}
+
 #[derive(Collect, Clone, Copy)]
 #[collect(require_copy)]
 pub struct Root<'gc> {
     pub main_thread: Thread<'gc>,
     pub globals: Table<'gc>,
     pub interned_strings: InternedStringSet<'gc>,
+    /// The metatable shared by all string values.
+    pub string_metatable: Table<'gc>,
+    /// The `package` table used by `require`, even if the global `package` is replaced.
+    pub package: Table<'gc>,
+    /// The tables and userdata with a `__gc` metamethod, whose finalizers are called by the main
+    /// thread once they become unreachable.
+    pub finalizers: FinalizationQueue<'gc, Value<'gc>>,
 }
 
 impl<'gc> Root<'gc> {
     pub fn new(mc: MutationContext<'gc, '_>) -> Root<'gc> {
+        Root::new_with_host(mc, LuaHost::std())
+    }
+
+    /// Creates a new `Root` whose standard library accesses the host system through the given
+    /// `LuaHost`.
+    pub fn new_with_host(mc: MutationContext<'gc, '_>, host: LuaHost) -> Root<'gc> {
+        let string_metatable = Table::new(mc);
         let root = Root {
-            main_thread: Thread::new(mc, false),
+            main_thread: Thread::new(mc, Some(string_metatable), false),
             globals: Table::new(mc),
             interned_strings: InternedStringSet::new(mc),
+            string_metatable,
+            package: Table::new(mc),
+            finalizers: FinalizationQueue::new(mc),
         };
+        root.main_thread.set_finalizers(mc, Some(root.finalizers));
 
-        load_base(mc, root, root.globals);
+        load_base(mc, root, root.globals, host.io.clone());
         load_coroutine(mc, root, root.globals);
+        load_debug(mc, root, root.globals);
+        load_io(mc, root, root.globals, host.io.clone());
         load_math(mc, root, root.globals);
+        load_os(mc, root, root.globals, host.os);
+        load_string(mc, root, root.globals);
+        load_table(mc, root, root.globals);
+        load_utf8(mc, root, root.globals);
+        // Must be loaded last, so that the other libraries are added to `package.loaded`
+        load_package(mc, root, root.globals, host.io);
 
         root
     }
@@ -36,15 +102,100 @@ pub use lua_arena::Arena;
 pub use lua_arena::Sequencer;
 
 /// Simpler wrapper for `Arena` that automatically garbage collects at reasonable intervals.
-pub struct Lua(Option<lua_arena::Arena>);
+///
+/// The main thread, and every coroutine created from it, share an `ExecutionBudget` whose limits
+/// can be set with `Lua::set_limits`.  No limits are set by default.
+pub struct Lua {
+    arena: Option<lua_arena::Arena>,
+    budget: Rc<ExecutionBudget>,
+}
 
 const COLLECTOR_GRANULARITY: f64 = 1024.0;
 
 impl Lua {
     pub fn new() -> Lua {
-        Lua(Some(Arena::new(ArenaParameters::default(), |mc| {
-            Root::new(mc)
-        })))
+        Lua::new_with_host(LuaHost::std())
+    }
+
+    /// Creates a new `Lua` whose standard library accesses the host system through the given
+    /// `LuaHost`, for example to provide a deterministic clock or to sandbox the filesystem.
+    pub fn new_with_host(host: LuaHost) -> Lua {
+        Lua::from_arena(Arena::new(ArenaParameters::default(), move |mc| {
+            Root::new_with_host(mc, host)
+        }))
+    }
+
+    fn from_arena(mut arena: Arena) -> Lua {
+        let budget = Rc::new(ExecutionBudget::default());
+        let main_budget = budget.clone();
+        arena.mutate(move |mc, root| root.main_thread.set_budget(mc, Some(main_budget)));
+        Lua {
+            arena: Some(arena),
+            budget,
+        }
+    }
+
+    /// Sets the limits on the resources used by scripts, and resets the count of instructions run.
+    /// Scripts that exceed a limit are stopped with `Error::LimitError`.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.budget.set_limits(limits);
+    }
+
+    /// The budget shared by the main thread and its coroutines.
+    pub fn budget(&self) -> &ExecutionBudget {
+        &self.budget
+    }
+
+    /// The number of bytes currently allocated in the arena, which is what `Limits::memory` is
+    /// compared against.
+    pub fn total_allocated(&self) -> usize {
+        self.arena.as_ref().unwrap().total_allocated()
+    }
+
+    /// Statistics about the garbage collector and its last finished cycle.
+    pub fn gc_stats(&self) -> GcStats {
+        self.arena.as_ref().unwrap().gc_stats()
+    }
+
+    /// Walks every object in the arena and returns the objects and bytes allocated for each type,
+    /// largest first.  Useful for finding what is keeping memory alive in long running sessions.
+    pub fn heap_profile(&self) -> Vec<TypeAllocations> {
+        self.arena.as_ref().unwrap().heap_profile()
+    }
+
+    /// Registers a native module, so that `require(name)` calls the loader returned by `loader` and
+    /// caches its result in `package.loaded`.
+    pub fn register_module<F>(&mut self, name: &str, loader: F)
+    where
+        F: for<'gc> FnOnce(MutationContext<'gc, '_>, Root<'gc>) -> Callback<'gc>,
+    {
+        self.mutate(move |mc, root| {
+            let key = String::new(mc, name.as_bytes());
+            let preload = match root.package.get(String::new_static(b"preload")) {
+                Value::Table(preload) => preload,
+                _ => {
+                    let preload = Table::new(mc);
+                    root.package
+                        .set(mc, String::new_static(b"preload"), preload)
+                        .unwrap();
+                    preload
+                }
+            };
+            preload.set(mc, key, loader(mc, root)).unwrap();
+        })
+    }
+
+    /// Adds a searcher to the end of `package.searchers` which finds Lua modules using
+    /// `package.path` in the given `IoHost` rather than the one the `Lua` was created with.  A
+    /// `MemoryIoHost` can be used this way to `require` embedded resources without touching the
+    /// disk.
+    pub fn add_vfs_searcher(&mut self, vfs: Rc<dyn IoHost>) {
+        self.mutate(move |mc, root| {
+            if let Value::Table(searchers) = root.package.get(String::new_static(b"searchers")) {
+                let searcher = lua_searcher(mc, root, root.globals, vfs);
+                searchers.set(mc, searchers.length() + 1, searcher).unwrap();
+            }
+        })
     }
 
     /// Runs a single action inside the Lua arena, during which no garbage collection may take place.
@@ -53,7 +204,7 @@ impl Lua {
         R: 'static,
         F: for<'gc> FnOnce(MutationContext<'gc, '_>, Root<'gc>) -> R,
     {
-        let arena = self.0.as_mut().unwrap();
+        let arena = self.arena.as_mut().unwrap();
         let r = arena.mutate(move |mc, root| f(mc, *root));
         if arena.allocation_debt() > COLLECTOR_GRANULARITY {
             arena.collect_debt();
@@ -68,11 +219,11 @@ impl Lua {
         R: 'static,
         F: for<'gc> FnOnce(Root<'gc>) -> Box<dyn Sequence<'gc, Output = R> + 'gc>,
     {
-        let mut sequencer = self.0.take().unwrap().sequence(move |root| f(*root));
+        let mut sequencer = self.arena.take().unwrap().sequence(move |root| f(*root));
         loop {
             match sequencer.step() {
                 Ok((arena, output)) => {
-                    self.0 = Some(arena);
+                    self.arena = Some(arena);
                     return output;
                 }
                 Err(s) => {
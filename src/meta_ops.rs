@@ -0,0 +1,550 @@
+use std::error::Error as StdError;
+use std::fmt;
+use std::io::Write;
+
+use gc_arena::{Collect, MutationContext};
+use gc_sequence as sequence;
+
+use crate::{
+    BinaryOperatorError, Callback, CallbackResult, Continuation, Error, Function, RuntimeError,
+    String, StringError, Table, ThreadError, TypeError, Value,
+};
+
+/// The maximum length of a chain of `__index` or `__newindex` tables that will be followed before
+/// an error is raised, the same limit that PUC-Rio Lua uses.
+const MAX_META_CHAIN: usize = 2000;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum MetaMethod {
+    Index,
+    NewIndex,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Pow,
+    Unm,
+    IDiv,
+    BAnd,
+    BOr,
+    BXor,
+    Shl,
+    Shr,
+    BNot,
+    Concat,
+    Len,
+    Eq,
+    Lt,
+    Le,
+    Call,
+    ToString,
+    Name,
+}
+
+impl MetaMethod {
+    pub fn name(self) -> &'static str {
+        match self {
+            MetaMethod::Index => "__index",
+            MetaMethod::NewIndex => "__newindex",
+            MetaMethod::Add => "__add",
+            MetaMethod::Sub => "__sub",
+            MetaMethod::Mul => "__mul",
+            MetaMethod::Div => "__div",
+            MetaMethod::Mod => "__mod",
+            MetaMethod::Pow => "__pow",
+            MetaMethod::Unm => "__unm",
+            MetaMethod::IDiv => "__idiv",
+            MetaMethod::BAnd => "__band",
+            MetaMethod::BOr => "__bor",
+            MetaMethod::BXor => "__bxor",
+            MetaMethod::Shl => "__shl",
+            MetaMethod::Shr => "__shr",
+            MetaMethod::BNot => "__bnot",
+            MetaMethod::Concat => "__concat",
+            MetaMethod::Len => "__len",
+            MetaMethod::Eq => "__eq",
+            MetaMethod::Lt => "__lt",
+            MetaMethod::Le => "__le",
+            MetaMethod::Call => "__call",
+            MetaMethod::ToString => "__tostring",
+            MetaMethod::Name => "__name",
+        }
+    }
+}
+
+impl<'gc> From<MetaMethod> for Value<'gc> {
+    fn from(method: MetaMethod) -> Value<'gc> {
+        Value::String(String::new_static(method.name().as_bytes()))
+    }
+}
+
+#[derive(Debug, Clone, Copy, Collect)]
+#[collect(require_static)]
+pub enum MetaOperatorError {
+    ChainTooLong(MetaMethod),
+}
+
+impl StdError for MetaOperatorError {}
+
+impl fmt::Display for MetaOperatorError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MetaOperatorError::ChainTooLong(method) => {
+                write!(fmt, "'{}' chain too long; possible loop", method.name())
+            }
+        }
+    }
+}
+
+/// A call to a metamethod function that must be performed to finish a meta operation.
+pub struct MetaCall<'gc> {
+    pub function: Function<'gc>,
+    pub args: Vec<Value<'gc>>,
+}
+
+pub enum MetaResult<'gc> {
+    Value(Value<'gc>),
+    Call(MetaCall<'gc>),
+}
+
+/// Returns the metatable associated with the given value, if any.
+pub fn get_metatable<'gc>(value: Value<'gc>) -> Option<Table<'gc>> {
+    match value {
+        Value::Table(table) => table.metatable(),
+        Value::UserData(userdata) => userdata.metatable(),
+        _ => None,
+    }
+}
+
+/// Returns the given metamethod from the metatable of the given value, or `Value::Nil` if there is
+/// none.
+pub fn get_metamethod<'gc>(value: Value<'gc>, method: MetaMethod) -> Value<'gc> {
+    get_metatable(value)
+        .map(|mt| mt.get(method))
+        .unwrap_or(Value::Nil)
+}
+
+/// Performs `indexed[key]`, following any `__index` metamethods.  String values use the given
+/// string metatable.
+pub fn index<'gc>(
+    string_metatable: Option<Table<'gc>>,
+    mut indexed: Value<'gc>,
+    key: Value<'gc>,
+) -> Result<MetaResult<'gc>, Error<'gc>> {
+    for _ in 0..MAX_META_CHAIN {
+        let idx = match indexed {
+            Value::Table(table) => {
+                let value = table.get(key);
+                if value != Value::Nil {
+                    return Ok(MetaResult::Value(value));
+                }
+
+                let idx = get_metamethod(indexed, MetaMethod::Index);
+                if idx == Value::Nil {
+                    return Ok(MetaResult::Value(Value::Nil));
+                }
+                idx
+            }
+            _ => {
+                let idx = match indexed {
+                    Value::String(_) => string_metatable
+                        .map(|mt| mt.get(MetaMethod::Index))
+                        .unwrap_or(Value::Nil),
+                    _ => get_metamethod(indexed, MetaMethod::Index),
+                };
+                if idx == Value::Nil {
+                    return Err(TypeError {
+                        expected: "table",
+                        found: indexed.type_name(),
+                    }
+                    .into());
+                }
+                idx
+            }
+        };
+
+        match idx {
+            Value::Function(function) => {
+                return Ok(MetaResult::Call(MetaCall {
+                    function,
+                    args: vec![indexed, key],
+                }));
+            }
+            idx => indexed = idx,
+        }
+    }
+
+    Err(MetaOperatorError::ChainTooLong(MetaMethod::Index).into())
+}
+
+/// Performs `indexed[key] = value`, following any `__newindex` metamethods.  Returns a `MetaCall`
+/// if a metamethod function must be called to finish the assignment.
+pub fn new_index<'gc>(
+    mc: MutationContext<'gc, '_>,
+    mut indexed: Value<'gc>,
+    key: Value<'gc>,
+    value: Value<'gc>,
+) -> Result<Option<MetaCall<'gc>>, Error<'gc>> {
+    for _ in 0..MAX_META_CHAIN {
+        let idx = match indexed {
+            Value::Table(table) => {
+                let idx = get_metamethod(indexed, MetaMethod::NewIndex);
+                if idx == Value::Nil || table.get(key) != Value::Nil {
+                    table.set(mc, key, value)?;
+                    return Ok(None);
+                }
+                idx
+            }
+            _ => {
+                let idx = get_metamethod(indexed, MetaMethod::NewIndex);
+                if idx == Value::Nil {
+                    return Err(TypeError {
+                        expected: "table",
+                        found: indexed.type_name(),
+                    }
+                    .into());
+                }
+                idx
+            }
+        };
+
+        match idx {
+            Value::Function(function) => {
+                return Ok(Some(MetaCall {
+                    function,
+                    args: vec![indexed, key, value],
+                }));
+            }
+            idx => indexed = idx,
+        }
+    }
+
+    Err(MetaOperatorError::ChainTooLong(MetaMethod::NewIndex).into())
+}
+
+/// Performs a binary arithmetic or bitwise operation, falling back to the associated metamethod of
+/// either operand if the operation cannot be performed on the raw values.
+///
+/// Panics if the given `MetaMethod` is not a binary arithmetic or bitwise metamethod.
+pub fn arithmetic<'gc>(
+    method: MetaMethod,
+    lhs: Value<'gc>,
+    rhs: Value<'gc>,
+) -> Result<MetaResult<'gc>, Error<'gc>> {
+    let (res, error) = match method {
+        MetaMethod::Add => (lhs.add(rhs), BinaryOperatorError::Add),
+        MetaMethod::Sub => (lhs.subtract(rhs), BinaryOperatorError::Subtract),
+        MetaMethod::Mul => (lhs.multiply(rhs), BinaryOperatorError::Multiply),
+        MetaMethod::Div => (lhs.float_divide(rhs), BinaryOperatorError::FloatDivide),
+        MetaMethod::Mod => (lhs.modulo(rhs), BinaryOperatorError::Modulo),
+        MetaMethod::Pow => (lhs.exponentiate(rhs), BinaryOperatorError::Exponentiate),
+        MetaMethod::IDiv => (lhs.floor_divide(rhs), BinaryOperatorError::FloorDivide),
+        MetaMethod::BAnd => (lhs.bitwise_and(rhs), BinaryOperatorError::BitAnd),
+        MetaMethod::BOr => (lhs.bitwise_or(rhs), BinaryOperatorError::BitOr),
+        MetaMethod::BXor => (lhs.bitwise_xor(rhs), BinaryOperatorError::BitXor),
+        MetaMethod::Shl => (lhs.shift_left(rhs), BinaryOperatorError::ShiftLeft),
+        MetaMethod::Shr => (lhs.shift_right(rhs), BinaryOperatorError::ShiftRight),
+        method => panic!("{} is not a binary arithmetic metamethod", method.name()),
+    };
+
+    if let Some(res) = res {
+        return Ok(MetaResult::Value(res));
+    }
+
+    match binary_metamethod(lhs, rhs, method) {
+        Value::Nil => Err(error.into()),
+        metamethod => Ok(MetaResult::Call(meta_call(metamethod, vec![lhs, rhs])?)),
+    }
+}
+
+/// Performs a unary arithmetic or bitwise operation, falling back to the associated metamethod if
+/// the operation cannot be performed on the raw value.
+///
+/// Panics if the given `MetaMethod` is not `MetaMethod::Unm` or `MetaMethod::BNot`.
+pub fn unary<'gc>(method: MetaMethod, value: Value<'gc>) -> Result<MetaResult<'gc>, Error<'gc>> {
+    let (res, error) = match method {
+        MetaMethod::Unm => (value.negate(), BinaryOperatorError::UnaryNegate),
+        MetaMethod::BNot => (value.bitwise_not(), BinaryOperatorError::BitNot),
+        method => panic!("{} is not a unary arithmetic metamethod", method.name()),
+    };
+
+    if let Some(res) = res {
+        return Ok(MetaResult::Value(res));
+    }
+
+    match get_metamethod(value, method) {
+        Value::Nil => Err(error.into()),
+        metamethod => Ok(MetaResult::Call(meta_call(metamethod, vec![value, value])?)),
+    }
+}
+
+/// Performs the length operator `#value`, using the `__len` metamethod if it is present.
+pub fn length<'gc>(value: Value<'gc>) -> Result<MetaResult<'gc>, Error<'gc>> {
+    if let Value::String(s) = value {
+        return Ok(MetaResult::Value(Value::Integer(s.len() as i64)));
+    }
+
+    match get_metamethod(value, MetaMethod::Len) {
+        Value::Nil => match value {
+            Value::Table(table) => Ok(MetaResult::Value(Value::Integer(table.length()))),
+            value => Err(TypeError {
+                expected: "table or string",
+                found: value.type_name(),
+            }
+            .into()),
+        },
+        metamethod => Ok(MetaResult::Call(meta_call(metamethod, vec![value, value])?)),
+    }
+}
+
+/// Performs the equality operator `lhs == rhs`, using the `__eq` metamethod when comparing two
+/// distinct tables or two distinct userdata.  Non-`MetaCall` results are always `Value::Boolean`.
+pub fn equal<'gc>(lhs: Value<'gc>, rhs: Value<'gc>) -> Result<MetaResult<'gc>, Error<'gc>> {
+    if lhs == rhs {
+        return Ok(MetaResult::Value(Value::Boolean(true)));
+    }
+
+    match (lhs, rhs) {
+        (Value::Table(_), Value::Table(_)) | (Value::UserData(_), Value::UserData(_)) => {
+            match binary_metamethod(lhs, rhs, MetaMethod::Eq) {
+                Value::Nil => Ok(MetaResult::Value(Value::Boolean(false))),
+                metamethod => Ok(MetaResult::Call(meta_call(metamethod, vec![lhs, rhs])?)),
+            }
+        }
+        _ => Ok(MetaResult::Value(Value::Boolean(false))),
+    }
+}
+
+/// Performs the comparison `lhs < rhs`, using the `__lt` metamethod if the values cannot be
+/// compared directly.  Non-`MetaCall` results are always `Value::Boolean`.
+pub fn less_than<'gc>(lhs: Value<'gc>, rhs: Value<'gc>) -> Result<MetaResult<'gc>, Error<'gc>> {
+    if let Some(res) = lhs.less_than(rhs) {
+        return Ok(MetaResult::Value(Value::Boolean(res)));
+    }
+
+    match binary_metamethod(lhs, rhs, MetaMethod::Lt) {
+        Value::Nil => Err(BinaryOperatorError::LessThan.into()),
+        metamethod => Ok(MetaResult::Call(meta_call(metamethod, vec![lhs, rhs])?)),
+    }
+}
+
+/// Performs the comparison `lhs <= rhs`, using the `__le` metamethod if the values cannot be
+/// compared directly.  As in Lua 5.3, if there is no `__le` metamethod this falls back to computing
+/// `not (rhs < lhs)` with the `__lt` metamethod.  Non-`MetaCall` results are always
+/// `Value::Boolean`.
+pub fn less_equal<'gc>(
+    mc: MutationContext<'gc, '_>,
+    lhs: Value<'gc>,
+    rhs: Value<'gc>,
+) -> Result<MetaResult<'gc>, Error<'gc>> {
+    if let Some(res) = lhs.less_equal(rhs) {
+        return Ok(MetaResult::Value(Value::Boolean(res)));
+    }
+
+    match binary_metamethod(lhs, rhs, MetaMethod::Le) {
+        Value::Nil => {}
+        metamethod => return Ok(MetaResult::Call(meta_call(metamethod, vec![lhs, rhs])?)),
+    }
+
+    match binary_metamethod(rhs, lhs, MetaMethod::Lt) {
+        Value::Nil => Err(BinaryOperatorError::LessEqual.into()),
+        metamethod => {
+            let lt_call = meta_call(metamethod, vec![rhs, lhs])?;
+            Ok(MetaResult::Call(MetaCall {
+                function: Function::Callback(Callback::new_immediate_with(
+                    mc,
+                    lt_call.function,
+                    |&function, args| {
+                        Ok(CallbackResult::TailCall {
+                            function,
+                            args,
+                            continuation: Continuation::new_immediate(|res| {
+                                let res = res?.get(0).cloned().unwrap_or(Value::Nil);
+                                Ok(CallbackResult::Return(vec![res.not()]))
+                            }),
+                        })
+                    },
+                )),
+                args: lt_call.args,
+            }))
+        }
+    }
+}
+
+/// Concatenates the given values as by the Lua `..` operator.  Adjacent strings and numbers are
+/// concatenated directly, any other values are concatenated right to left by calling their
+/// `__concat` metamethods.
+pub fn concat<'gc>(
+    mc: MutationContext<'gc, '_>,
+    values: &[Value<'gc>],
+) -> Result<MetaResult<'gc>, Error<'gc>> {
+    if values.iter().all(|&v| is_concatable(v)) {
+        return Ok(MetaResult::Value(Value::String(String::concat(
+            mc, values,
+        )?)));
+    }
+
+    Ok(MetaResult::Call(MetaCall {
+        function: Function::Callback(Callback::new_sequence(mc, |args| {
+            Ok(sequence::from_fn_with(args, |mc, args| {
+                concat_remaining(mc, args)
+            }))
+        })),
+        args: values.to_vec(),
+    }))
+}
+
+// Concatenates as many of the trailing values as possible directly, then either returns the single
+// remaining value or tail calls the `__concat` metamethod of the last two values, continuing with
+// the remaining values once it returns.
+fn concat_remaining<'gc>(
+    mc: MutationContext<'gc, '_>,
+    mut values: Vec<Value<'gc>>,
+) -> Result<CallbackResult<'gc>, Error<'gc>> {
+    let suffix_start = values
+        .iter()
+        .rposition(|&v| !is_concatable(v))
+        .map(|i| i + 1)
+        .unwrap_or(0);
+    if values.len() - suffix_start > 1 {
+        let suffix = String::concat(mc, &values[suffix_start..])?;
+        values.truncate(suffix_start);
+        values.push(Value::String(suffix));
+    }
+
+    if values.len() <= 1 {
+        return Ok(CallbackResult::Return(values));
+    }
+
+    let rhs = values.pop().unwrap();
+    let lhs = values.pop().unwrap();
+    let metamethod = match binary_metamethod(lhs, rhs, MetaMethod::Concat) {
+        Value::Nil => {
+            let bad = if is_concatable(lhs) { rhs } else { lhs };
+            return Err(StringError::Concat {
+                bad_type: bad.type_name(),
+            }
+            .into());
+        }
+        metamethod => metamethod,
+    };
+    let call = meta_call(metamethod, vec![lhs, rhs])?;
+
+    Ok(CallbackResult::TailCall {
+        function: call.function,
+        args: call.args,
+        continuation: Continuation::new_sequence_with(values, |mut values, res| {
+            values.push(res?.get(0).cloned().unwrap_or(Value::Nil));
+            Ok(sequence::from_fn_with(values, |mc, values| {
+                concat_remaining(mc, values)
+            }))
+        }),
+    })
+}
+
+/// Converts a value to a string like `tostring`, calling its `__tostring` metamethod if it has one.
+/// Otherwise, tables and userdata with a string `__name` metafield are named by it.
+///
+/// The result of a metamethod call should be checked with `tostring_result`.
+pub fn tostring<'gc>(
+    mc: MutationContext<'gc, '_>,
+    value: Value<'gc>,
+) -> Result<MetaResult<'gc>, Error<'gc>> {
+    if let Value::String(_) = value {
+        return Ok(MetaResult::Value(value));
+    }
+
+    match get_metamethod(value, MetaMethod::ToString) {
+        Value::Nil => {}
+        metamethod => return Ok(MetaResult::Call(meta_call(metamethod, vec![value])?)),
+    }
+
+    let mut buf = Vec::new();
+    match (get_metamethod(value, MetaMethod::Name), value) {
+        (Value::String(name), Value::Table(table)) => {
+            buf.extend_from_slice(name.as_bytes());
+            write!(buf, ": {:p}", table.0.as_ptr())?;
+        }
+        (Value::String(name), Value::UserData(userdata)) => {
+            buf.extend_from_slice(name.as_bytes());
+            write!(buf, ": {:p}", userdata.0.as_ptr())?;
+        }
+        _ => value.display(&mut buf)?,
+    }
+    Ok(MetaResult::Value(Value::String(String::new(mc, &buf))))
+}
+
+/// Checks the results of a `__tostring` metamethod call, which must return a string.
+pub fn tostring_result<'gc>(results: &[Value<'gc>]) -> Result<String<'gc>, Error<'gc>> {
+    match results.get(0).cloned().unwrap_or(Value::Nil) {
+        Value::String(s) => Ok(s),
+        _ => Err(RuntimeError(Value::String(String::new_static(
+            b"'__tostring' must return a string",
+        )))
+        .into()),
+    }
+}
+
+/// Resolves a value that is being called into the function that should be called.  Functions
+/// resolve to themselves, any other value resolves through its `__call` metamethod, which may in
+/// turn be any callable value.  Along with the function, returns the values which should be
+/// prepended to its arguments: every value in the chain of `__call` metamethods, with the original
+/// value last.
+pub fn call<'gc>(callee: Value<'gc>) -> Result<(Function<'gc>, Vec<Value<'gc>>), ThreadError> {
+    let mut prefix = Vec::new();
+    let mut callee = callee;
+    for _ in 0..MAX_META_CHAIN {
+        match callee {
+            Value::Function(function) => {
+                prefix.reverse();
+                return Ok((function, prefix));
+            }
+            value => match get_metamethod(value, MetaMethod::Call) {
+                Value::Nil => {
+                    return Err(ThreadError::BadCall(TypeError {
+                        expected: "function",
+                        found: value.type_name(),
+                    }));
+                }
+                metamethod => {
+                    prefix.push(value);
+                    callee = metamethod;
+                }
+            },
+        }
+    }
+    Err(ThreadError::CallChainTooLong)
+}
+
+fn is_concatable<'gc>(value: Value<'gc>) -> bool {
+    match value {
+        Value::String(_) | Value::Integer(_) | Value::Number(_) => true,
+        _ => false,
+    }
+}
+
+// Returns the given metamethod of the first operand, or if it does not exist, the second.
+fn binary_metamethod<'gc>(lhs: Value<'gc>, rhs: Value<'gc>, method: MetaMethod) -> Value<'gc> {
+    match get_metamethod(lhs, method) {
+        Value::Nil => get_metamethod(rhs, method),
+        metamethod => metamethod,
+    }
+}
+
+fn meta_call<'gc>(
+    metamethod: Value<'gc>,
+    args: Vec<Value<'gc>>,
+) -> Result<MetaCall<'gc>, ThreadError> {
+    match metamethod {
+        Value::Function(function) => Ok(MetaCall { function, args }),
+        metamethod => {
+            let (function, mut prefix) = call(metamethod)?;
+            prefix.extend(args);
+            Ok(MetaCall {
+                function,
+                args: prefix,
+            })
+        }
+    }
+}
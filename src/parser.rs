@@ -5,7 +5,7 @@ use std::rc::Rc;
 
 use gc_arena::Collect;
 
-use crate::{Lexer, LexerError, Token};
+use crate::{Lexer, LexerError, LineNumber, Token};
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct Chunk<S> {
@@ -14,8 +14,15 @@ pub struct Chunk<S> {
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct Block<S> {
-    pub statements: Vec<Statement<S>>,
-    pub return_statement: Option<ReturnStatement<S>>,
+    pub statements: Vec<LineAnnotated<Statement<S>>>,
+    pub return_statement: Option<LineAnnotated<ReturnStatement<S>>>,
+}
+
+/// An AST node along with the line number of its first token.
+#[derive(Debug, PartialEq, Clone)]
+pub struct LineAnnotated<T> {
+    pub line_number: LineNumber,
+    pub inner: T,
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -238,7 +245,7 @@ pub enum RecordKey<S> {
 
 #[derive(Debug, Collect)]
 #[collect(require_static)]
-pub enum ParserError {
+pub enum ParserErrorKind {
     Unexpected {
         unexpected: String,
         expected: Option<String>,
@@ -252,9 +259,9 @@ pub enum ParserError {
     LexerError(LexerError),
 }
 
-impl StdError for ParserError {}
+impl StdError for ParserErrorKind {}
 
-impl fmt::Display for ParserError {
+impl fmt::Display for ParserErrorKind {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let write_expected = |f: &mut fmt::Formatter, expected: &Option<String>| {
             match expected {
@@ -267,42 +274,62 @@ impl fmt::Display for ParserError {
         };
 
         match self {
-            ParserError::Unexpected {
+            ParserErrorKind::Unexpected {
                 unexpected,
                 expected,
             } => {
                 write!(f, "found {:?}", unexpected)?;
                 write_expected(f, expected)
             }
-            ParserError::EndOfStream { expected } => {
+            ParserErrorKind::EndOfStream { expected } => {
                 write!(f, "unexpected end of token stream")?;
                 write_expected(f, expected)
             }
-            ParserError::AssignToExpression => write!(f, "cannot assign to expression"),
-            ParserError::ExpressionNotStatement => write!(f, "expression is not a statement"),
-            ParserError::RecursionLimit => write!(f, "recursion limit reached"),
-            ParserError::LexerError(lexer_error) => write!(f, "{}", lexer_error),
+            ParserErrorKind::AssignToExpression => write!(f, "cannot assign to expression"),
+            ParserErrorKind::ExpressionNotStatement => write!(f, "expression is not a statement"),
+            ParserErrorKind::RecursionLimit => write!(f, "recursion limit reached"),
+            ParserErrorKind::LexerError(lexer_error) => write!(f, "{}", lexer_error),
         }
     }
 }
 
+#[derive(Debug, Collect)]
+#[collect(require_static)]
+pub struct ParserError {
+    pub kind: ParserErrorKind,
+    /// The line number of the most recently read token when the error occurred
+    pub line_number: LineNumber,
+}
+
+impl StdError for ParserError {}
+
+impl fmt::Display for ParserError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line_number, self.kind)
+    }
+}
+
 pub fn parse_chunk<R, S, CS>(source: R, create_string: CS) -> Result<Chunk<S>, ParserError>
 where
     R: Read,
     S: fmt::Debug + PartialEq,
     CS: FnMut(&[u8]) -> S,
 {
-    Parser {
+    let mut parser = Parser {
         lexer: Lexer::new(source, create_string),
         read_buffer: Vec::new(),
         recursion_guard: Rc::new(()),
-    }
-    .parse_chunk()
+    };
+    parser.parse_chunk().map_err(|kind| ParserError {
+        kind,
+        line_number: LineNumber(parser.lexer.line_number() + 1),
+    })
 }
 
 struct Parser<R, S, CS> {
     lexer: Lexer<R, CS>,
-    read_buffer: Vec<Token<S>>,
+    // Tokens which have been read ahead, along with the line number each token starts on
+    read_buffer: Vec<(Token<S>, LineNumber)>,
     recursion_guard: Rc<()>,
 }
 
@@ -312,16 +339,16 @@ where
     S: fmt::Debug + PartialEq,
     CS: FnMut(&[u8]) -> S,
 {
-    fn parse_chunk(&mut self) -> Result<Chunk<S>, ParserError> {
+    fn parse_chunk(&mut self) -> Result<Chunk<S>, ParserErrorKind> {
         let block = self.parse_block()?;
         if self.look_ahead(0)? != None {
-            Err(ParserError::EndOfStream { expected: None })
+            Err(ParserErrorKind::EndOfStream { expected: None })
         } else {
             Ok(Chunk { block })
         }
     }
 
-    fn parse_block(&mut self) -> Result<Block<S>, ParserError> {
+    fn parse_block(&mut self) -> Result<Block<S>, ParserErrorKind> {
         let mut statements = Vec::new();
         let mut return_statement = None;
 
@@ -333,12 +360,20 @@ where
                     self.take_next()?;
                 }
                 Some(&Token::Return) => {
-                    return_statement = Some(self.parse_return_statement()?);
+                    let line_number = self.line_ahead()?;
+                    return_statement = Some(LineAnnotated {
+                        line_number,
+                        inner: self.parse_return_statement()?,
+                    });
                     break;
                 }
                 None => break,
                 _ => {
-                    statements.push(self.parse_statement()?);
+                    let line_number = self.line_ahead()?;
+                    statements.push(LineAnnotated {
+                        line_number,
+                        inner: self.parse_statement()?,
+                    });
                 }
             }
         }
@@ -349,7 +384,7 @@ where
         })
     }
 
-    fn parse_statement(&mut self) -> Result<Statement<S>, ParserError> {
+    fn parse_statement(&mut self) -> Result<Statement<S>, ParserErrorKind> {
         let _recursion_guard = self.recursion_guard()?;
 
         Ok(match *self.get_next()? {
@@ -382,7 +417,7 @@ where
         })
     }
 
-    fn parse_return_statement(&mut self) -> Result<ReturnStatement<S>, ParserError> {
+    fn parse_return_statement(&mut self) -> Result<ReturnStatement<S>, ParserErrorKind> {
         self.expect_next(Token::Return)?;
         let returns = match self.look_ahead(0)? {
             None
@@ -399,7 +434,7 @@ where
         Ok(ReturnStatement { returns })
     }
 
-    fn parse_if_statement(&mut self) -> Result<IfStatement<S>, ParserError> {
+    fn parse_if_statement(&mut self) -> Result<IfStatement<S>, ParserErrorKind> {
         self.expect_next(Token::If)?;
         let if_cond = self.parse_expression()?;
         self.expect_next(Token::Then)?;
@@ -430,7 +465,7 @@ where
         })
     }
 
-    fn parse_while_statement(&mut self) -> Result<WhileStatement<S>, ParserError> {
+    fn parse_while_statement(&mut self) -> Result<WhileStatement<S>, ParserErrorKind> {
         self.expect_next(Token::While)?;
         let condition = self.parse_expression()?;
         self.expect_next(Token::Do)?;
@@ -440,7 +475,7 @@ where
         Ok(WhileStatement { condition, block })
     }
 
-    fn parse_for_statement(&mut self) -> Result<ForStatement<S>, ParserError> {
+    fn parse_for_statement(&mut self) -> Result<ForStatement<S>, ParserErrorKind> {
         self.expect_next(Token::For)?;
         let name = self.expect_name()?;
 
@@ -491,14 +526,14 @@ where
                 })
             }
 
-            token => Err(ParserError::Unexpected {
+            token => Err(ParserErrorKind::Unexpected {
                 unexpected: format!("{:?}", token),
                 expected: Some("'=' or 'in'".to_owned()),
             }),
         }
     }
 
-    fn parse_repeat_statement(&mut self) -> Result<RepeatStatement<S>, ParserError> {
+    fn parse_repeat_statement(&mut self) -> Result<RepeatStatement<S>, ParserErrorKind> {
         self.expect_next(Token::Repeat)?;
         let body = self.parse_block()?;
         self.expect_next(Token::Until)?;
@@ -506,7 +541,7 @@ where
         Ok(RepeatStatement { body, until })
     }
 
-    fn parse_function_statement(&mut self) -> Result<FunctionStatement<S>, ParserError> {
+    fn parse_function_statement(&mut self) -> Result<FunctionStatement<S>, ParserErrorKind> {
         self.expect_next(Token::Function)?;
 
         let name = self.expect_name()?;
@@ -537,7 +572,9 @@ where
         })
     }
 
-    fn parse_local_function_statement(&mut self) -> Result<LocalFunctionStatement<S>, ParserError> {
+    fn parse_local_function_statement(
+        &mut self,
+    ) -> Result<LocalFunctionStatement<S>, ParserErrorKind> {
         self.expect_next(Token::Function)?;
 
         let name = self.expect_name()?;
@@ -546,7 +583,7 @@ where
         Ok(LocalFunctionStatement { name, definition })
     }
 
-    fn parse_local_statement(&mut self) -> Result<LocalStatement<S>, ParserError> {
+    fn parse_local_statement(&mut self) -> Result<LocalStatement<S>, ParserErrorKind> {
         self.expect_next(Token::Local)?;
         let mut names = Vec::new();
         names.push(self.expect_name()?);
@@ -565,20 +602,20 @@ where
         Ok(LocalStatement { names, values })
     }
 
-    fn parse_label_statement(&mut self) -> Result<LabelStatement<S>, ParserError> {
+    fn parse_label_statement(&mut self) -> Result<LabelStatement<S>, ParserErrorKind> {
         self.expect_next(Token::DoubleColon)?;
         let name = self.expect_name()?;
         self.expect_next(Token::DoubleColon)?;
         Ok(LabelStatement { name })
     }
 
-    fn parse_goto_statement(&mut self) -> Result<GotoStatement<S>, ParserError> {
+    fn parse_goto_statement(&mut self) -> Result<GotoStatement<S>, ParserErrorKind> {
         self.expect_next(Token::Goto)?;
         let name = self.expect_name()?;
         Ok(GotoStatement { name })
     }
 
-    fn parse_expression_statement(&mut self) -> Result<Statement<S>, ParserError> {
+    fn parse_expression_statement(&mut self) -> Result<Statement<S>, ParserErrorKind> {
         let mut suffixed_expression = self.parse_suffixed_expression()?;
         if self.check_ahead(0, Token::Assign)? || self.check_ahead(0, Token::Comma)? {
             let mut targets = Vec::new();
@@ -589,13 +626,13 @@ where
                             AssignmentTarget::Field(suffixed_expression, field_suffix)
                         }
                         SuffixPart::Call(_) => {
-                            return Err(ParserError::AssignToExpression);
+                            return Err(ParserErrorKind::AssignToExpression);
                         }
                     }
                 } else {
                     match suffixed_expression.primary {
                         PrimaryExpression::Name(name) => AssignmentTarget::Name(name),
-                        _ => return Err(ParserError::AssignToExpression),
+                        _ => return Err(ParserErrorKind::AssignToExpression),
                     }
                 };
                 targets.push(assignment_target);
@@ -623,18 +660,18 @@ where
                         call: call_suffix,
                     }))
                 }
-                SuffixPart::Field(_) => Err(ParserError::ExpressionNotStatement),
+                SuffixPart::Field(_) => Err(ParserErrorKind::ExpressionNotStatement),
             }
         } else {
-            Err(ParserError::ExpressionNotStatement)
+            Err(ParserErrorKind::ExpressionNotStatement)
         }
     }
 
-    fn parse_expression(&mut self) -> Result<Expression<S>, ParserError> {
+    fn parse_expression(&mut self) -> Result<Expression<S>, ParserErrorKind> {
         self.parse_sub_expression(MIN_PRIORITY)
     }
 
-    fn parse_expression_list(&mut self) -> Result<Vec<Expression<S>>, ParserError> {
+    fn parse_expression_list(&mut self) -> Result<Vec<Expression<S>>, ParserErrorKind> {
         let mut expressions = Vec::new();
         expressions.push(self.parse_expression()?);
         while self.check_ahead(0, Token::Comma)? {
@@ -644,7 +681,10 @@ where
         Ok(expressions)
     }
 
-    fn parse_sub_expression(&mut self, priority_limit: u8) -> Result<Expression<S>, ParserError> {
+    fn parse_sub_expression(
+        &mut self,
+        priority_limit: u8,
+    ) -> Result<Expression<S>, ParserErrorKind> {
         let _recursion_guard = self.recursion_guard()?;
 
         let head = if let Some(unary_op) = get_unary_operator(self.get_next()?) {
@@ -672,7 +712,7 @@ where
         })
     }
 
-    fn parse_simple_expression(&mut self) -> Result<SimpleExpression<S>, ParserError> {
+    fn parse_simple_expression(&mut self) -> Result<SimpleExpression<S>, ParserErrorKind> {
         Ok(match *self.get_next()? {
             Token::Float(f) => {
                 self.take_next()?;
@@ -708,7 +748,7 @@ where
         })
     }
 
-    fn parse_primary_expression(&mut self) -> Result<PrimaryExpression<S>, ParserError> {
+    fn parse_primary_expression(&mut self) -> Result<PrimaryExpression<S>, ParserErrorKind> {
         match self.take_next()? {
             Token::LeftParen => {
                 let expr = self.parse_expression()?;
@@ -716,14 +756,14 @@ where
                 Ok(PrimaryExpression::GroupedExpression(expr))
             }
             Token::Name(n) => Ok(PrimaryExpression::Name(n)),
-            token => Err(ParserError::Unexpected {
+            token => Err(ParserErrorKind::Unexpected {
                 unexpected: format!("{:?}", token),
                 expected: Some("grouped expression or name".to_owned()),
             }),
         }
     }
 
-    fn parse_field_suffix(&mut self) -> Result<FieldSuffix<S>, ParserError> {
+    fn parse_field_suffix(&mut self) -> Result<FieldSuffix<S>, ParserErrorKind> {
         match self.get_next()? {
             Token::Dot => {
                 self.take_next()?;
@@ -735,14 +775,14 @@ where
                 self.expect_next(Token::RightBracket)?;
                 Ok(FieldSuffix::Indexed(expr))
             }
-            token => Err(ParserError::Unexpected {
+            token => Err(ParserErrorKind::Unexpected {
                 unexpected: format!("{:?}", token),
                 expected: Some("field or suffix".to_owned()),
             }),
         }
     }
 
-    fn parse_call_suffix(&mut self) -> Result<CallSuffix<S>, ParserError> {
+    fn parse_call_suffix(&mut self) -> Result<CallSuffix<S>, ParserErrorKind> {
         let method_name = match *self.get_next()? {
             Token::Colon => {
                 self.take_next()?;
@@ -775,7 +815,7 @@ where
                 tail: vec![],
             }],
             token => {
-                return Err(ParserError::Unexpected {
+                return Err(ParserErrorKind::Unexpected {
                     unexpected: format!("{:?}", token),
                     expected: Some("function arguments".to_owned()),
                 });
@@ -789,20 +829,20 @@ where
         })
     }
 
-    fn parse_suffix_part(&mut self) -> Result<SuffixPart<S>, ParserError> {
+    fn parse_suffix_part(&mut self) -> Result<SuffixPart<S>, ParserErrorKind> {
         match self.get_next()? {
             Token::Dot | Token::LeftBracket => Ok(SuffixPart::Field(self.parse_field_suffix()?)),
             Token::Colon | Token::LeftParen | Token::LeftBrace | Token::String(_) => {
                 Ok(SuffixPart::Call(self.parse_call_suffix()?))
             }
-            token => Err(ParserError::Unexpected {
+            token => Err(ParserErrorKind::Unexpected {
                 unexpected: format!("{:?}", token),
                 expected: Some("expression suffix".to_owned()),
             }),
         }
     }
 
-    fn parse_suffixed_expression(&mut self) -> Result<SuffixedExpression<S>, ParserError> {
+    fn parse_suffixed_expression(&mut self) -> Result<SuffixedExpression<S>, ParserErrorKind> {
         let primary = self.parse_primary_expression()?;
         let mut suffixes = Vec::new();
         loop {
@@ -822,7 +862,7 @@ where
         Ok(SuffixedExpression { primary, suffixes })
     }
 
-    fn parse_function_definition(&mut self) -> Result<FunctionDefinition<S>, ParserError> {
+    fn parse_function_definition(&mut self) -> Result<FunctionDefinition<S>, ParserErrorKind> {
         self.expect_next(Token::LeftParen)?;
 
         let mut parameters = Vec::new();
@@ -836,7 +876,7 @@ where
                         break;
                     }
                     token => {
-                        return Err(ParserError::Unexpected {
+                        return Err(ParserErrorKind::Unexpected {
                             unexpected: format!("{:?}", token),
                             expected: Some("parameter name or '...'".to_owned()),
                         });
@@ -861,7 +901,7 @@ where
         })
     }
 
-    fn parse_table_constructor(&mut self) -> Result<TableConstructor<S>, ParserError> {
+    fn parse_table_constructor(&mut self) -> Result<TableConstructor<S>, ParserErrorKind> {
         self.expect_next(Token::LeftBrace)?;
         let mut fields = Vec::new();
         loop {
@@ -880,7 +920,7 @@ where
         Ok(TableConstructor { fields })
     }
 
-    fn parse_constructor_field(&mut self) -> Result<ConstructorField<S>, ParserError> {
+    fn parse_constructor_field(&mut self) -> Result<ConstructorField<S>, ParserErrorKind> {
         Ok(match *self.get_next()? {
             Token::Name(_) => {
                 if self.check_ahead(1, Token::Assign)? {
@@ -906,37 +946,37 @@ where
 
     // Error if we have more than MAX_RECURSION guards live, otherwise return a new recursion guard
     // (a recursion guard is just an Rc used solely for its live count).
-    fn recursion_guard(&self) -> Result<Rc<()>, ParserError> {
+    fn recursion_guard(&self) -> Result<Rc<()>, ParserErrorKind> {
         if Rc::strong_count(&self.recursion_guard) < MAX_RECURSION {
             Ok(self.recursion_guard.clone())
         } else {
-            Err(ParserError::RecursionLimit)
+            Err(ParserErrorKind::RecursionLimit)
         }
     }
 
     // Return a reference to the next token in the stream, erroring if we are at the end.
-    fn get_next(&mut self) -> Result<&Token<S>, ParserError> {
+    fn get_next(&mut self) -> Result<&Token<S>, ParserErrorKind> {
         self.read_ahead(1)?;
-        if let Some(token) = self.read_buffer.get(0) {
+        if let Some((token, _)) = self.read_buffer.get(0) {
             Ok(token)
         } else {
-            Err(ParserError::EndOfStream { expected: None })
+            Err(ParserErrorKind::EndOfStream { expected: None })
         }
     }
 
     // Consumes the next token, returning an error if it does not match the given token.
-    fn expect_next(&mut self, token: Token<S>) -> Result<(), ParserError> {
+    fn expect_next(&mut self, token: Token<S>) -> Result<(), ParserErrorKind> {
         self.read_ahead(1)?;
         if self.read_buffer.is_empty() {
-            Err(ParserError::EndOfStream {
+            Err(ParserErrorKind::EndOfStream {
                 expected: Some(format!("{:?}", token)),
             })
         } else {
-            let next_token = self.read_buffer.remove(0);
+            let (next_token, _) = self.read_buffer.remove(0);
             if next_token == token {
                 Ok(())
             } else {
-                Err(ParserError::Unexpected {
+                Err(ParserErrorKind::Unexpected {
                     unexpected: format!("{:?}", next_token),
                     expected: Some(format!("{:?}", token)),
                 })
@@ -945,16 +985,16 @@ where
     }
 
     // Consume the next token which should be a name, and return it, otherwise error.
-    fn expect_name(&mut self) -> Result<S, ParserError> {
+    fn expect_name(&mut self) -> Result<S, ParserErrorKind> {
         self.read_ahead(1)?;
         if self.read_buffer.is_empty() {
-            Err(ParserError::EndOfStream {
+            Err(ParserErrorKind::EndOfStream {
                 expected: Some("name".to_owned()),
             })
         } else {
-            match self.read_buffer.remove(0) {
+            match self.read_buffer.remove(0).0 {
                 Token::Name(name) => Ok(name),
-                token => Err(ParserError::Unexpected {
+                token => Err(ParserErrorKind::Unexpected {
                     unexpected: format!("{:?}", token),
                     expected: Some("name".to_owned()),
                 }),
@@ -963,16 +1003,16 @@ where
     }
 
     // Consume the next token which should be a string, and return it, otherwise error.
-    fn expect_string(&mut self) -> Result<S, ParserError> {
+    fn expect_string(&mut self) -> Result<S, ParserErrorKind> {
         self.read_ahead(1)?;
         if self.read_buffer.is_empty() {
-            Err(ParserError::EndOfStream {
+            Err(ParserErrorKind::EndOfStream {
                 expected: Some("string".to_owned()),
             })
         } else {
-            match self.read_buffer.remove(0) {
+            match self.read_buffer.remove(0).0 {
                 Token::String(string) => Ok(string),
-                token => Err(ParserError::Unexpected {
+                token => Err(ParserErrorKind::Unexpected {
                     unexpected: format!("{:?}", token),
                     expected: Some("string".to_owned()),
                 }),
@@ -981,26 +1021,36 @@ where
     }
 
     // Take the next token in the stream by value, erroring if we are at the end.
-    fn take_next(&mut self) -> Result<Token<S>, ParserError> {
+    fn take_next(&mut self) -> Result<Token<S>, ParserErrorKind> {
         self.read_ahead(1)?;
         if self.read_buffer.is_empty() {
-            Err(ParserError::EndOfStream { expected: None })
+            Err(ParserErrorKind::EndOfStream { expected: None })
         } else {
-            Ok(self.read_buffer.remove(0))
+            Ok(self.read_buffer.remove(0).0)
         }
     }
 
     // Return the nth token ahead in the stream, if it is not past the end.
-    fn look_ahead(&mut self, n: usize) -> Result<Option<&Token<S>>, ParserError> {
+    fn look_ahead(&mut self, n: usize) -> Result<Option<&Token<S>>, ParserErrorKind> {
         self.read_ahead(n + 1)?;
-        Ok(self.read_buffer.get(n))
+        Ok(self.read_buffer.get(n).map(|(token, _)| token))
+    }
+
+    // Return the line number that the next token in the stream starts on, or the current line
+    // number if we are at the end.
+    fn line_ahead(&mut self) -> Result<LineNumber, ParserErrorKind> {
+        self.read_ahead(1)?;
+        Ok(match self.read_buffer.get(0) {
+            Some(&(_, line_number)) => line_number,
+            None => LineNumber(self.lexer.line_number() + 1),
+        })
     }
 
     // Return true if the nth token ahead in the stream matches the given token.  If this would read
     // past the end of the stream, this will simply return false.
-    fn check_ahead(&mut self, n: usize, token: Token<S>) -> Result<bool, ParserError> {
+    fn check_ahead(&mut self, n: usize, token: Token<S>) -> Result<bool, ParserErrorKind> {
         self.read_ahead(n)?;
-        Ok(if let Some(t) = self.read_buffer.get(n) {
+        Ok(if let Some((t, _)) = self.read_buffer.get(n) {
             *t == token
         } else {
             false
@@ -1009,10 +1059,18 @@ where
 
     // Read at least `n` tokens ahead in the stream, filling the read buffer up to size `n` (if
     // possible).
-    fn read_ahead(&mut self, n: usize) -> Result<(), ParserError> {
+    fn read_ahead(&mut self, n: usize) -> Result<(), ParserErrorKind> {
         while self.read_buffer.len() <= n {
-            if let Some(token) = self.lexer.read_token().map_err(ParserError::LexerError)? {
-                self.read_buffer.push(token);
+            self.lexer
+                .skip_whitespace()
+                .map_err(ParserErrorKind::LexerError)?;
+            let line_number = LineNumber(self.lexer.line_number() + 1);
+            if let Some(token) = self
+                .lexer
+                .read_token()
+                .map_err(ParserErrorKind::LexerError)?
+            {
+                self.read_buffer.push((token, line_number));
             } else {
                 break;
             }
@@ -1,27 +1,53 @@
-use std::io::{self, Write};
+use std::cell::Cell;
+use std::rc::Rc;
 
-use gc_arena::MutationContext;
+use gc_arena::{Collect, CollectorMode, Gc, MutationContext, StaticCollect};
 use gc_sequence as sequence;
 
 use crate::{
-    Callback, CallbackResult, Continuation, Root, RuntimeError, String, Table, TypeError, Value,
+    closure::chunk_id,
+    compile_named,
+    io::skip_prefix,
+    lexer::{read_float, read_hex_float, read_hex_integer, read_integer},
+    meta_ops::{self, MetaResult},
+    thread::location_message,
+    Callback, CallbackResult, Closure, Continuation, Error, Function, InternedStringSet, IoHost,
+    OpenMode, Root, RuntimeError, String, Table, TypeError, Value,
 };
 
-pub fn load_base<'gc>(mc: MutationContext<'gc, '_>, root: Root<'gc>, env: Table<'gc>) {
+use super::string::{integer_arg, new_callback, runtime_error, string_arg};
+
+// The signature at the start of a precompiled chunk.
+const BINARY_SIGNATURE: &[u8] = b"\x1bLua";
+
+fn table_arg<'gc>(value: Value<'gc>) -> Result<Table<'gc>, TypeError> {
+    match value {
+        Value::Table(table) => Ok(table),
+        value => Err(TypeError {
+            expected: "table",
+            found: value.type_name(),
+        }),
+    }
+}
+
+pub fn load_base<'gc>(
+    mc: MutationContext<'gc, '_>,
+    root: Root<'gc>,
+    env: Table<'gc>,
+    io: Rc<dyn IoHost>,
+) {
+    let load_state = LoadState::new(env, root.interned_strings, io.clone());
     env.set(
         mc,
         String::new_static(b"print"),
-        Callback::new_immediate(mc, |args| {
-            let mut stdout = io::stdout();
-            for i in 0..args.len() {
-                args[i].display(&mut stdout)?;
-                if i != args.len() - 1 {
-                    stdout.write_all(&b"\t"[..])?;
-                }
-            }
-            stdout.write_all(&b"\n"[..])?;
-            stdout.flush()?;
-            Ok(CallbackResult::Return(vec![]))
+        Callback::new_sequence_with(mc, StaticCollect(io), |io, args| {
+            let print = Print {
+                io: StaticCollect(io.0.clone()),
+                args,
+                line: Vec::new(),
+                next: 0,
+            };
+            Ok(sequence::from_fn_with(print, print_continue))
         }),
     )
     .unwrap();
@@ -29,9 +55,27 @@ pub fn load_base<'gc>(mc: MutationContext<'gc, '_>, root: Root<'gc>, env: Table<
     env.set(
         mc,
         String::new_static(b"error"),
-        Callback::new_immediate(mc, |args| {
+        Callback::new_sequence_with_thread(mc, |thread, args| {
             let err = args.get(0).cloned().unwrap_or(Value::Nil);
-            Err(RuntimeError(err).into())
+            let level = integer_arg(args.get(1).cloned().unwrap_or(Value::Nil), Some(1))?;
+            // The location of the error is only available once the callback is running on the
+            // thread, where level 0 is `error` itself and level 1 is the function that called it.
+            Ok(sequence::from_fn_with(
+                (thread, err, level),
+                |mc, (thread, err, level)| {
+                    let err = match err {
+                        Value::String(msg) if level > 0 => match thread.location(level as usize) {
+                            Some((chunk_name, line)) => Value::String(String::new(
+                                mc,
+                                &location_message(chunk_name, line, msg.as_bytes()),
+                            )),
+                            None => err,
+                        },
+                        err => err,
+                    };
+                    Err(RuntimeError(err).into())
+                },
+            ))
         }),
     )
     .unwrap();
@@ -101,6 +145,13 @@ pub fn load_base<'gc>(mc: MutationContext<'gc, '_>, root: Root<'gc>, env: Table<
         mc,
         String::new_static(b"select"),
         Callback::new_immediate(mc, |args| {
+            if let Some(Value::String(s)) = args.get(0) {
+                if s.as_bytes() == b"#" {
+                    return Ok(CallbackResult::Return(vec![Value::Integer(
+                        args.len() as i64 - 1,
+                    )]));
+                }
+            }
             match args.get(0).cloned().unwrap_or(Value::Nil).to_integer() {
                 Some(n) if n >= 1 && (n as usize) <= args.len() => Ok(CallbackResult::Return(
                     args[n as usize..args.len()].to_vec(),
@@ -116,4 +167,882 @@ pub fn load_base<'gc>(mc: MutationContext<'gc, '_>, root: Root<'gc>, env: Table<
         }),
     )
     .unwrap();
+
+    env.set(
+        mc,
+        String::new_static(b"getmetatable"),
+        Callback::new_immediate_with(mc, root.string_metatable, |string_metatable, args| {
+            let metatable = match args.get(0).cloned().unwrap_or(Value::Nil) {
+                Value::String(_) => Some(*string_metatable),
+                value => meta_ops::get_metatable(value),
+            };
+            Ok(CallbackResult::Return(vec![match metatable {
+                Some(metatable) => match metatable.get(String::new_static(b"__metatable")) {
+                    Value::Nil => Value::Table(metatable),
+                    protected => protected,
+                },
+                None => Value::Nil,
+            }]))
+        }),
+    )
+    .unwrap();
+
+    env.set(
+        mc,
+        String::new_static(b"setmetatable"),
+        Callback::new_sequence_with(mc, root.finalizers, |finalizers, args| {
+            let table = table_arg(args.get(0).cloned().unwrap_or(Value::Nil))?;
+            let metatable = match args.get(1).cloned().unwrap_or(Value::Nil) {
+                Value::Nil => None,
+                Value::Table(metatable) => Some(metatable),
+                value => {
+                    return Err(TypeError {
+                        expected: "nil or table",
+                        found: value.type_name(),
+                    }
+                    .into());
+                }
+            };
+
+            if let Some(current) = table.metatable() {
+                if current.get(String::new_static(b"__metatable")) != Value::Nil {
+                    return Err(RuntimeError(Value::String(String::new_static(
+                        b"cannot change a protected metatable",
+                    )))
+                    .into());
+                }
+            }
+
+            Ok(sequence::from_fn_with(
+                (table, metatable, *finalizers),
+                |mc, (table, metatable, finalizers)| {
+                    table.set_metatable(mc, metatable);
+                    if let Some(metatable) = metatable {
+                        if metatable.get(String::new_static(b"__gc")) != Value::Nil {
+                            finalizers.register(mc, Value::Table(table));
+                        }
+                    }
+                    Ok(CallbackResult::Return(vec![Value::Table(table)]))
+                },
+            ))
+        }),
+    )
+    .unwrap();
+
+    env.set(
+        mc,
+        String::new_static(b"rawequal"),
+        Callback::new_immediate(mc, |args| {
+            let a = args.get(0).cloned().unwrap_or(Value::Nil);
+            let b = args.get(1).cloned().unwrap_or(Value::Nil);
+            Ok(CallbackResult::Return(vec![Value::Boolean(a == b)]))
+        }),
+    )
+    .unwrap();
+
+    env.set(
+        mc,
+        String::new_static(b"rawlen"),
+        Callback::new_immediate(mc, |args| {
+            match args.get(0).cloned().unwrap_or(Value::Nil) {
+                Value::Table(table) => {
+                    Ok(CallbackResult::Return(vec![Value::Integer(table.length())]))
+                }
+                Value::String(string) => Ok(CallbackResult::Return(vec![Value::Integer(
+                    string.len() as i64,
+                )])),
+                value => Err(TypeError {
+                    expected: "table or string",
+                    found: value.type_name(),
+                }
+                .into()),
+            }
+        }),
+    )
+    .unwrap();
+
+    env.set(
+        mc,
+        String::new_static(b"rawget"),
+        Callback::new_immediate(mc, |args| {
+            let table = table_arg(args.get(0).cloned().unwrap_or(Value::Nil))?;
+            let key = args.get(1).cloned().unwrap_or(Value::Nil);
+            Ok(CallbackResult::Return(vec![table.get(key)]))
+        }),
+    )
+    .unwrap();
+
+    env.set(
+        mc,
+        String::new_static(b"rawset"),
+        Callback::new_sequence(mc, |args| {
+            let table = table_arg(args.get(0).cloned().unwrap_or(Value::Nil))?;
+            let key = args.get(1).cloned().unwrap_or(Value::Nil);
+            let value = args.get(2).cloned().unwrap_or(Value::Nil);
+            Ok(sequence::from_fn_with(
+                (table, key, value),
+                |mc, (table, key, value)| {
+                    table.set(mc, key, value)?;
+                    Ok(CallbackResult::Return(vec![Value::Table(table)]))
+                },
+            ))
+        }),
+    )
+    .unwrap();
+    env.set(mc, String::new_static(b"_G"), env).unwrap();
+
+    let next = Callback::new_immediate(mc, |args| {
+        let table = table_arg(args.get(0).cloned().unwrap_or(Value::Nil))?;
+        let key = args.get(1).cloned().unwrap_or(Value::Nil);
+        Ok(CallbackResult::Return(match table.next(key)? {
+            Some((key, value)) => vec![key, value],
+            None => vec![Value::Nil],
+        }))
+    });
+    env.set(mc, String::new_static(b"next"), next).unwrap();
+
+    env.set(
+        mc,
+        String::new_static(b"pairs"),
+        Callback::new_immediate_with(mc, next, |next, args| {
+            let value = args.get(0).cloned().unwrap_or(Value::Nil);
+            let metamethod = meta_ops::get_metatable(value)
+                .map(|mt| mt.get(String::new_static(b"__pairs")))
+                .unwrap_or(Value::Nil);
+            match (value, metamethod) {
+                (_, Value::Function(function)) => Ok(CallbackResult::TailCall {
+                    function,
+                    args: vec![value],
+                    continuation: Continuation::new_immediate(|res| {
+                        let mut res = res?;
+                        res.resize(3, Value::Nil);
+                        Ok(CallbackResult::Return(res))
+                    }),
+                }),
+                (Value::Table(_), Value::Nil) => Ok(CallbackResult::Return(vec![
+                    Value::Function(Function::Callback(*next)),
+                    value,
+                    Value::Nil,
+                ])),
+                (value, _) => Err(TypeError {
+                    expected: "table",
+                    found: value.type_name(),
+                }
+                .into()),
+            }
+        }),
+    )
+    .unwrap();
+
+    let ipairs_next =
+        Callback::new_immediate_with(mc, root.string_metatable, |string_metatable, args| {
+            let value = args.get(0).cloned().unwrap_or(Value::Nil);
+            let index =
+                integer_arg(args.get(1).cloned().unwrap_or(Value::Nil), None)?.wrapping_add(1);
+            let result = move |value| {
+                Ok(CallbackResult::Return(match value {
+                    Value::Nil => vec![Value::Nil],
+                    value => vec![Value::Integer(index), value],
+                }))
+            };
+            match meta_ops::index(Some(*string_metatable), value, Value::Integer(index))? {
+                MetaResult::Value(value) => result(value),
+                MetaResult::Call(call) => Ok(CallbackResult::TailCall {
+                    function: call.function,
+                    args: call.args,
+                    continuation: Continuation::new_immediate(move |res| {
+                        result(res?.get(0).cloned().unwrap_or(Value::Nil))
+                    }),
+                }),
+            }
+        });
+    env.set(
+        mc,
+        String::new_static(b"ipairs"),
+        Callback::new_immediate_with(mc, ipairs_next, |ipairs_next, args| {
+            if args.is_empty() {
+                return Err(TypeError {
+                    expected: "value",
+                    found: "no value",
+                }
+                .into());
+            }
+            Ok(CallbackResult::Return(vec![
+                Value::Function(Function::Callback(*ipairs_next)),
+                args[0],
+                Value::Integer(0),
+            ]))
+        }),
+    )
+    .unwrap();
+
+    env.set(
+        mc,
+        String::new_static(b"tostring"),
+        new_callback(mc, |mc, args| {
+            let value = match args.get(0) {
+                Some(&value) => value,
+                None => {
+                    return Err(TypeError {
+                        expected: "value",
+                        found: "no value",
+                    }
+                    .into());
+                }
+            };
+            match meta_ops::tostring(mc, value)? {
+                MetaResult::Value(v) => Ok(CallbackResult::Return(vec![v])),
+                MetaResult::Call(call) => Ok(CallbackResult::TailCall {
+                    function: call.function,
+                    args: call.args,
+                    continuation: Continuation::new_immediate(|res| {
+                        let s = meta_ops::tostring_result(&res?)?;
+                        Ok(CallbackResult::Return(vec![Value::String(s)]))
+                    }),
+                }),
+            }
+        }),
+    )
+    .unwrap();
+
+    env.set(
+        mc,
+        String::new_static(b"tonumber"),
+        Callback::new_immediate(mc, |args| {
+            let value = args.get(0).cloned().unwrap_or(Value::Nil);
+            let number = match args.get(1).cloned().unwrap_or(Value::Nil) {
+                Value::Nil => match value {
+                    Value::Integer(_) | Value::Number(_) => value,
+                    Value::String(s) => string_to_number(s.as_bytes()),
+                    _ => {
+                        if args.is_empty() {
+                            return Err(TypeError {
+                                expected: "value",
+                                found: "no value",
+                            }
+                            .into());
+                        }
+                        Value::Nil
+                    }
+                },
+                base => {
+                    let base = integer_arg(base, None)?;
+                    if base < 2 || base > 36 {
+                        return Err(RuntimeError(Value::String(String::new_static(
+                            b"bad argument #2 to 'tonumber' (base out of range)",
+                        )))
+                        .into());
+                    }
+                    let s = match value {
+                        Value::String(s) => s,
+                        value => {
+                            return Err(TypeError {
+                                expected: "string",
+                                found: value.type_name(),
+                            }
+                            .into());
+                        }
+                    };
+                    string_to_integer_base(s.as_bytes(), base as u32)
+                        .map(Value::Integer)
+                        .unwrap_or(Value::Nil)
+                }
+            };
+            Ok(CallbackResult::Return(vec![number]))
+        }),
+    )
+    .unwrap();
+
+    env.set(
+        mc,
+        String::new_static(b"assert"),
+        Callback::new_immediate(mc, |args| match args.get(0) {
+            None => Err(RuntimeError(Value::String(String::new_static(
+                b"bad argument #1 to 'assert' (value expected)",
+            )))
+            .into()),
+            Some(v) if v.to_bool() => Ok(CallbackResult::Return(args)),
+            Some(_) => Err(RuntimeError(
+                args.get(1)
+                    .cloned()
+                    .unwrap_or(Value::String(String::new_static(b"assertion failed!"))),
+            )
+            .into()),
+        }),
+    )
+    .unwrap();
+
+    env.set(
+        mc,
+        String::new_static(b"xpcall"),
+        Callback::new_immediate_with(mc, root.interned_strings, |interned_strings, mut args| {
+            let function = match args.get(0).cloned().unwrap_or(Value::Nil) {
+                Value::Function(function) => function,
+                value => {
+                    return Err(TypeError {
+                        expected: "function",
+                        found: value.type_name(),
+                    }
+                    .into());
+                }
+            };
+            let handler = match args.get(1).cloned().unwrap_or(Value::Nil) {
+                Value::Function(handler) => handler,
+                value => {
+                    return Err(TypeError {
+                        expected: "function",
+                        found: value.type_name(),
+                    }
+                    .into());
+                }
+            };
+
+            args.drain(0..2);
+            Ok(CallbackResult::TailCall {
+                function,
+                args,
+                continuation: Continuation::new_sequence_with(
+                    *interned_strings,
+                    move |interned_strings, res| {
+                        Ok(sequence::from_fn_with(
+                            (res, interned_strings),
+                            |mc, (res, interned_strings)| {
+                                Ok(CallbackResult::Return(match res {
+                                    Ok(mut res) => {
+                                        res.insert(0, Value::Boolean(true));
+                                        res
+                                    }
+                                    Err(err) => vec![
+                                        Value::Boolean(false),
+                                        err.to_value(mc, interned_strings),
+                                    ],
+                                }))
+                            },
+                        ))
+                    },
+                )
+                .with_error_handler(handler),
+            })
+        }),
+    )
+    .unwrap();
+
+    env.set(
+        mc,
+        String::new_static(b"collectgarbage"),
+        Callback::new_sequence_with(mc, Gc::allocate(mc, GcTuning::new()), |&tuning, args| {
+            Ok(sequence::from_fn_with((tuning, args), collect_garbage))
+        }),
+    )
+    .unwrap();
+
+    let load = Callback::new_sequence_with(mc, load_state.clone(), |state, args| {
+        let chunk = args.get(0).cloned().unwrap_or(Value::Nil);
+        let chunk_name = match args.get(1).cloned().unwrap_or(Value::Nil) {
+            Value::Nil => match chunk {
+                Value::String(s) => s.as_bytes().to_vec(),
+                _ => b"=(load)".to_vec(),
+            },
+            name => string_arg(name)?,
+        };
+        let mode = match args.get(2).cloned().unwrap_or(Value::Nil) {
+            Value::Nil => b"bt".to_vec(),
+            mode => string_arg(mode)?,
+        };
+        let env = match args.get(3).cloned().unwrap_or(Value::Nil) {
+            Value::Nil => state.globals,
+            Value::Table(env) => env,
+            value => {
+                return Err(TypeError {
+                    expected: "table",
+                    found: value.type_name(),
+                }
+                .into());
+            }
+        };
+
+        let load = Load {
+            state: state.clone(),
+            chunk_name,
+            mode,
+            env,
+            source: Vec::new(),
+        };
+        match chunk {
+            Value::String(_) | Value::Function(_) => {}
+            value => {
+                return Err(TypeError {
+                    expected: "string or function",
+                    found: value.type_name(),
+                }
+                .into());
+            }
+        }
+        Ok(sequence::from_fn_with(
+            (load, chunk),
+            |mc, (load, chunk)| match chunk {
+                Value::Function(reader) => load_reader(load, reader),
+                Value::String(source) => {
+                    Ok(CallbackResult::Return(load.load(mc, source.as_bytes())))
+                }
+                _ => unreachable!(),
+            },
+        ))
+    });
+    env.set(mc, String::new_static(b"load"), load).unwrap();
+    env.set(mc, String::new_static(b"loadstring"), load)
+        .unwrap();
+
+    env.set(
+        mc,
+        String::new_static(b"loadfile"),
+        Callback::new_sequence_with(mc, load_state.clone(), |state, args| {
+            let file_name = match args.get(0).cloned().unwrap_or(Value::Nil) {
+                Value::Nil => None,
+                name => Some(string_arg(name)?),
+            };
+            let mode = match args.get(1).cloned().unwrap_or(Value::Nil) {
+                Value::Nil => b"bt".to_vec(),
+                mode => string_arg(mode)?,
+            };
+            let env = match args.get(2).cloned().unwrap_or(Value::Nil) {
+                Value::Nil => state.globals,
+                Value::Table(env) => env,
+                value => {
+                    return Err(TypeError {
+                        expected: "table",
+                        found: value.type_name(),
+                    }
+                    .into());
+                }
+            };
+
+            Ok(sequence::from_fn_with(
+                (state.clone(), file_name, mode, env),
+                |mc, (state, file_name, mode, env)| {
+                    Ok(CallbackResult::Return(
+                        match state.load_file(mc, file_name.as_ref().map(|n| &n[..]), mode, env) {
+                            Ok(closure) => vec![Value::Function(Function::Closure(closure))],
+                            Err(msg) => vec![Value::Nil, msg],
+                        },
+                    ))
+                },
+            ))
+        }),
+    )
+    .unwrap();
+
+    env.set(
+        mc,
+        String::new_static(b"dofile"),
+        Callback::new_sequence_with(mc, load_state, |state, args| {
+            let file_name = match args.get(0).cloned().unwrap_or(Value::Nil) {
+                Value::Nil => None,
+                name => Some(string_arg(name)?),
+            };
+
+            Ok(sequence::from_fn_with(
+                (state.clone(), file_name),
+                |mc, (state, file_name)| {
+                    let closure = state
+                        .load_file(
+                            mc,
+                            file_name.as_ref().map(|n| &n[..]),
+                            b"bt".to_vec(),
+                            state.globals,
+                        )
+                        .map_err(RuntimeError)?;
+                    Ok(CallbackResult::TailCall {
+                        function: Function::Closure(closure),
+                        args: Vec::new(),
+                        continuation: Continuation::new_immediate(|res| {
+                            Ok(CallbackResult::Return(res?))
+                        }),
+                    })
+                },
+            ))
+        }),
+    )
+    .unwrap();
+}
+
+// The state shared by `load`, `loadfile` and `dofile`, also used by `require` to load Lua modules.
+#[derive(Collect)]
+#[collect(empty_drop)]
+pub(super) struct LoadState<'gc> {
+    pub(super) globals: Table<'gc>,
+    interned_strings: InternedStringSet<'gc>,
+    pub(super) io: StaticCollect<Rc<dyn IoHost>>,
+}
+
+impl<'gc> Clone for LoadState<'gc> {
+    fn clone(&self) -> LoadState<'gc> {
+        LoadState {
+            globals: self.globals,
+            interned_strings: self.interned_strings,
+            io: StaticCollect(self.io.0.clone()),
+        }
+    }
+}
+
+impl<'gc> LoadState<'gc> {
+    pub(super) fn new(
+        globals: Table<'gc>,
+        interned_strings: InternedStringSet<'gc>,
+        io: Rc<dyn IoHost>,
+    ) -> LoadState<'gc> {
+        LoadState {
+            globals,
+            interned_strings,
+            io: StaticCollect(io),
+        }
+    }
+
+    // Loads the named file through the `IoHost`, or standard input if there is no name, returning
+    // the error message on failure.
+    pub(super) fn load_file(
+        &self,
+        mc: MutationContext<'gc, '_>,
+        file_name: Option<&[u8]>,
+        mode: Vec<u8>,
+        env: Table<'gc>,
+    ) -> Result<Closure<'gc>, Value<'gc>> {
+        let chunk_name = match file_name {
+            Some(name) => [&b"@"[..], name].concat(),
+            None => b"=stdin".to_vec(),
+        };
+        let file = match file_name {
+            Some(name) => self.io.0.open(name, OpenMode::parse(b"r").unwrap()),
+            None => Ok(self.io.0.stdin()),
+        };
+        let source = file.and_then(|mut file| {
+            let mut source = Vec::new();
+            let mut buf = [0; 4096];
+            loop {
+                match file.read(&mut buf)? {
+                    0 => return Ok(source),
+                    len => source.extend_from_slice(&buf[0..len]),
+                }
+            }
+        });
+        let source = match source {
+            Ok(source) => source,
+            Err(err) => {
+                let msg = format!(
+                    "cannot open {}: {}",
+                    std::string::String::from_utf8_lossy(&chunk_name[1..]),
+                    err
+                );
+                return Err(Value::String(String::new(mc, msg.as_bytes())));
+            }
+        };
+
+        let mut prefixed = &source[..];
+        skip_prefix(&mut prefixed).unwrap();
+        let load = Load {
+            state: self.clone(),
+            chunk_name,
+            mode,
+            env,
+            source: Vec::new(),
+        };
+        load.load_closure(mc, prefixed)
+    }
+}
+
+// An in-progress load of a chunk, which is accumulated in `source` if it comes from a reader
+// function.
+#[derive(Collect)]
+#[collect(empty_drop)]
+struct Load<'gc> {
+    state: LoadState<'gc>,
+    chunk_name: Vec<u8>,
+    mode: Vec<u8>,
+    env: Table<'gc>,
+    source: Vec<u8>,
+}
+
+impl<'gc> Load<'gc> {
+    // Returns the results of `load`, either the loaded function or nil and an error message.
+    fn load(&self, mc: MutationContext<'gc, '_>, source: &[u8]) -> Vec<Value<'gc>> {
+        match self.load_closure(mc, source) {
+            Ok(closure) => vec![Value::Function(Function::Closure(closure))],
+            Err(msg) => vec![Value::Nil, msg],
+        }
+    }
+
+    fn load_closure(
+        &self,
+        mc: MutationContext<'gc, '_>,
+        source: &[u8],
+    ) -> Result<Closure<'gc>, Value<'gc>> {
+        let error = |msg: &str| {
+            let mut buf = chunk_id(&self.chunk_name);
+            buf.extend_from_slice(b": ");
+            buf.extend_from_slice(msg.as_bytes());
+            Value::String(String::new(mc, &buf))
+        };
+
+        let mode = std::string::String::from_utf8_lossy(&self.mode);
+        if source.starts_with(BINARY_SIGNATURE) {
+            if !mode.contains('b') {
+                return Err(error(&format!(
+                    "attempt to load a binary chunk (mode is '{}')",
+                    mode
+                )));
+            }
+            return Err(error("binary chunks are not supported"));
+        } else if !mode.contains('t') {
+            return Err(error(&format!(
+                "attempt to load a text chunk (mode is '{}')",
+                mode
+            )));
+        }
+
+        let proto = compile_named(mc, self.state.interned_strings, &self.chunk_name, source)
+            .map_err(|err| match err {
+                // Syntax errors are reported as "chunkname:line: message", like PUC-Rio Lua
+                Error::ParserError(err) => {
+                    let mut buf = chunk_id(&self.chunk_name);
+                    buf.extend_from_slice(format!(":{}: {}", err.line_number, err.kind).as_bytes());
+                    Value::String(String::new(mc, &buf))
+                }
+                err => error(&err.to_string()),
+            })?;
+        Closure::new(mc, proto, Some(self.env)).map_err(|err| error(&err.to_string()))
+    }
+}
+
+// The settings of `collectgarbage` which have no effect, because collection cannot be stopped and
+// the arena parameters are fixed.  They are kept so that the previous values can be returned.
+#[derive(Collect)]
+#[collect(require_static)]
+struct GcTuning {
+    running: Cell<bool>,
+    pause: Cell<i64>,
+    step_multiplier: Cell<i64>,
+}
+
+impl GcTuning {
+    fn new() -> GcTuning {
+        GcTuning {
+            running: Cell::new(true),
+            pause: Cell::new(200),
+            step_multiplier: Cell::new(100),
+        }
+    }
+}
+
+fn collect_garbage<'gc>(
+    mc: MutationContext<'gc, '_>,
+    (tuning, args): (Gc<'gc, GcTuning>, Vec<Value<'gc>>),
+) -> Result<CallbackResult<'gc>, Error<'gc>> {
+    let option = match args.get(0).cloned().unwrap_or(Value::Nil) {
+        Value::Nil => b"collect".to_vec(),
+        option => string_arg(option)?,
+    };
+    let arg = || integer_arg(args.get(1).cloned().unwrap_or(Value::Nil), Some(0));
+    let ret = match &option[..] {
+        b"collect" => {
+            // Collection cannot happen while a callback is running, so this requests that a full
+            // collection happens as soon as this callback returns.
+            mc.request_full_collection();
+            Value::Integer(0)
+        }
+        b"step" => {
+            mc.request_full_collection();
+            Value::Boolean(true)
+        }
+        b"count" => Value::Number(mc.gc_stats().total_allocated as f64 / 1024.0),
+        b"stop" => {
+            tuning.running.set(false);
+            Value::Integer(0)
+        }
+        b"restart" => {
+            tuning.running.set(true);
+            Value::Integer(0)
+        }
+        b"isrunning" => Value::Boolean(tuning.running.get()),
+        b"setpause" => Value::Integer(tuning.pause.replace(arg()?)),
+        b"setstepmul" => Value::Integer(tuning.step_multiplier.replace(arg()?)),
+        b"incremental" => set_collector_mode(mc, CollectorMode::Incremental),
+        b"generational" => set_collector_mode(mc, CollectorMode::Generational),
+        _ => {
+            let msg = format!(
+                "bad argument #1 to 'collectgarbage' (invalid option '{}')",
+                std::string::String::from_utf8_lossy(&option)
+            );
+            return Err(runtime_error(mc, &msg));
+        }
+    };
+    Ok(CallbackResult::Return(vec![ret]))
+}
+
+// Switches the collector to the given mode, returning the name of the previous mode.
+fn set_collector_mode<'gc>(mc: MutationContext<'gc, '_>, mode: CollectorMode) -> Value<'gc> {
+    let previous = mc.collector_mode();
+    mc.set_collector_mode(mode);
+    Value::String(String::new_static(match previous {
+        CollectorMode::Incremental => b"incremental",
+        CollectorMode::Generational => b"generational",
+    }))
+}
+
+// The state of an in-progress `print`, which must be suspended whenever a `__tostring` metamethod
+// is called.  `next` is the index of the next argument to convert.
+#[derive(Collect)]
+#[collect(empty_drop)]
+struct Print<'gc> {
+    io: StaticCollect<Rc<dyn IoHost>>,
+    args: Vec<Value<'gc>>,
+    line: Vec<u8>,
+    next: usize,
+}
+
+fn print_continue<'gc>(
+    mc: MutationContext<'gc, '_>,
+    mut print: Print<'gc>,
+) -> Result<CallbackResult<'gc>, Error<'gc>> {
+    while print.next < print.args.len() {
+        let value = print.args[print.next];
+        print.next += 1;
+        match meta_ops::tostring(mc, value)? {
+            MetaResult::Value(Value::String(s)) => print_push(&mut print, s),
+            MetaResult::Value(_) => unreachable!("tostring must return a string"),
+            MetaResult::Call(call) => {
+                return Ok(CallbackResult::TailCall {
+                    function: call.function,
+                    args: call.args,
+                    continuation: Continuation::new_sequence_with(print, |mut print, res| {
+                        let s = meta_ops::tostring_result(&res?)?;
+                        print_push(&mut print, s);
+                        Ok(sequence::from_fn_with(print, |mc, print| {
+                            print_continue(mc, print)
+                        }))
+                    }),
+                });
+            }
+        }
+    }
+
+    print.line.push(b'\n');
+    let mut stdout = print.io.0.stdout();
+    stdout.write(&print.line)?;
+    stdout.flush()?;
+    Ok(CallbackResult::Return(vec![]))
+}
+
+// Appends the converted argument before `next` to the line being printed
+fn print_push<'gc>(print: &mut Print<'gc>, s: String<'gc>) {
+    if print.next > 1 {
+        print.line.push(b'\t');
+    }
+    print.line.extend_from_slice(s.as_bytes());
+}
+
+// Calls the reader function given to `load` until it returns nil or an empty string, and then loads
+// the concatenation of the pieces it returned.
+fn load_reader<'gc>(
+    load: Load<'gc>,
+    reader: Function<'gc>,
+) -> Result<CallbackResult<'gc>, Error<'gc>> {
+    Ok(CallbackResult::TailCall {
+        function: reader,
+        args: Vec::new(),
+        continuation: Continuation::new_sequence_with((load, reader), |(load, reader), res| {
+            Ok(sequence::from_fn_with(
+                (load, reader, res),
+                |mc, (mut load, reader, res)| {
+                    let res = match res {
+                        Ok(res) => res,
+                        Err(err) => {
+                            let err = err.to_value(mc, load.state.interned_strings);
+                            return Ok(CallbackResult::Return(vec![Value::Nil, err]));
+                        }
+                    };
+                    match res.get(0).cloned().unwrap_or(Value::Nil) {
+                        Value::Nil => {}
+                        Value::String(piece) if piece.as_bytes().is_empty() => {}
+                        Value::String(piece) => {
+                            load.source.extend_from_slice(piece.as_bytes());
+                            return load_reader(load, reader);
+                        }
+                        _ => {
+                            return Ok(CallbackResult::Return(vec![
+                                Value::Nil,
+                                Value::String(String::new_static(
+                                    b"reader function must return a string",
+                                )),
+                            ]));
+                        }
+                    }
+                    let source = std::mem::take(&mut load.source);
+                    Ok(CallbackResult::Return(load.load(mc, &source)))
+                },
+            ))
+        }),
+    })
+}
+
+// Converts a string to a number following the rules of the Lua lexer, allowing leading and trailing
+// whitespace.  Returns nil if the string is not a valid numeral.
+fn string_to_number<'gc>(s: &[u8]) -> Value<'gc> {
+    let s = trim_space(s);
+    let unsigned = match s.first() {
+        Some(b'-') | Some(b'+') => &s[1..],
+        _ => s,
+    };
+    // Rust's float parsing also accepts words like "inf" and "NaN", which are not Lua numerals
+    if unsigned.is_empty()
+        || !s
+            .iter()
+            .all(|&c| c.is_ascii_hexdigit() || b"xXpP.+-".contains(&c))
+    {
+        return Value::Nil;
+    }
+
+    if let Some(i) = read_hex_integer(s) {
+        Value::Integer(i)
+    } else if let Some(i) = read_integer(s) {
+        Value::Integer(i)
+    } else if let Some(f) = read_hex_float(s) {
+        Value::Number(f)
+    } else if let Some(f) = read_float(s) {
+        Value::Number(f)
+    } else {
+        Value::Nil
+    }
+}
+
+// Converts a string to an integer in the given base, which must be in the range 2 to 36.  Like
+// PUC-Rio Lua, overflow wraps around.
+fn string_to_integer_base(s: &[u8], base: u32) -> Option<i64> {
+    let s = trim_space(s);
+    let (neg, digits) = match s.split_first() {
+        Some((b'-', rest)) => (true, rest),
+        _ => (false, s),
+    };
+    if digits.is_empty() {
+        return None;
+    }
+
+    let mut n: i64 = 0;
+    for &c in digits {
+        let d = (c as char).to_digit(base)?;
+        n = n.wrapping_mul(base as i64).wrapping_add(d as i64);
+    }
+    Some(if neg { n.wrapping_neg() } else { n })
+}
+
+fn trim_space(s: &[u8]) -> &[u8] {
+    let start = s
+        .iter()
+        .position(|c| !c.is_ascii_whitespace())
+        .unwrap_or(s.len());
+    let end = s
+        .iter()
+        .rposition(|c| !c.is_ascii_whitespace())
+        .map(|i| i + 1)
+        .unwrap_or(start);
+    &s[start..end]
 }
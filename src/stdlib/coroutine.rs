@@ -2,8 +2,8 @@ use gc_arena::MutationContext;
 use gc_sequence::{self as sequence, SequenceExt, SequenceResultExt};
 
 use crate::{
-    Callback, CallbackResult, Root, RuntimeError, String, Table, Thread, ThreadMode,
-    ThreadSequence, TypeError, Value,
+    Callback, CallbackResult, Error, Root, RuntimeError, String, Table, ThreadMode, ThreadSequence,
+    TypeError, Value,
 };
 
 pub fn load_coroutine<'gc>(mc: MutationContext<'gc, '_>, root: Root<'gc>, env: Table<'gc>) {
@@ -13,7 +13,7 @@ pub fn load_coroutine<'gc>(mc: MutationContext<'gc, '_>, root: Root<'gc>, env: T
         .set(
             mc,
             String::new_static(b"create"),
-            Callback::new_sequence(mc, |args| {
+            Callback::new_sequence_with_thread(mc, |current_thread, args| {
                 let function = match args.get(0).cloned().unwrap_or(Value::Nil) {
                     Value::Function(function) => function,
                     value => {
@@ -25,11 +25,14 @@ pub fn load_coroutine<'gc>(mc: MutationContext<'gc, '_>, root: Root<'gc>, env: T
                     }
                 };
 
-                Ok(sequence::from_fn_with(function, |mc, function| {
-                    let thread = Thread::new(mc, true);
-                    thread.start_suspended(mc, function).unwrap();
-                    Ok(CallbackResult::Return(vec![Value::Thread(thread)]))
-                }))
+                Ok(sequence::from_fn_with(
+                    (function, current_thread),
+                    |mc, (function, current_thread)| {
+                        let thread = current_thread.new_coroutine(mc);
+                        thread.start_suspended(mc, function).unwrap();
+                        Ok(CallbackResult::Return(vec![Value::Thread(thread)]))
+                    },
+                ))
             }),
         )
         .unwrap();
@@ -71,6 +74,8 @@ pub fn load_coroutine<'gc>(mc: MutationContext<'gc, '_>, root: Root<'gc>, env: T
                                     res.insert(0, Value::Boolean(true));
                                     res
                                 }
+                                // Exceeding a limit stops the resuming thread as well
+                                Err(Error::LimitError(err)) => return Err(err.into()),
                                 Err(err) => {
                                     vec![Value::Boolean(false), err.to_value(mc, interned_strings)]
                                 }
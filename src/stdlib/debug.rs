@@ -0,0 +1,590 @@
+use gc_arena::{GcCell, MutationContext};
+use gc_sequence as sequence;
+
+use crate::{
+    closure::chunk_id, Callback, CallbackResult, Closure, Function, FunctionName, Hook, HookMask,
+    LineNumber, Root, RuntimeError, String, Table, Thread, ThreadMode, Traceback, TypeError, Value,
+};
+
+use super::string::integer_arg;
+
+pub fn load_debug<'gc>(mc: MutationContext<'gc, '_>, root: Root<'gc>, env: Table<'gc>) {
+    let debug = Table::new(mc);
+
+    debug
+        .set(
+            mc,
+            String::new_static(b"traceback"),
+            Callback::new_sequence_with_thread(mc, |current_thread, mut args| {
+                let thread = thread_arg(current_thread, &mut args);
+                let message = args.get(0).cloned().unwrap_or(Value::Nil);
+                // By default, skip the frame of `debug.traceback` itself
+                let default_level = if thread == current_thread { 1 } else { 0 };
+                let level = integer_arg(
+                    args.get(1).cloned().unwrap_or(Value::Nil),
+                    Some(default_level),
+                )?;
+
+                Ok(sequence::from_fn_with(
+                    (thread, message, level),
+                    |mc, (thread, message, level)| {
+                        let mut buf = Vec::new();
+                        match message {
+                            Value::Nil => {}
+                            Value::String(_) | Value::Integer(_) | Value::Number(_) => {
+                                message.display(&mut buf).unwrap();
+                                buf.push(b'\n');
+                            }
+                            // Messages which are not strings are returned untouched
+                            message => return Ok(CallbackResult::Return(vec![message])),
+                        }
+
+                        // A thread which was killed by an error keeps the traceback of that error
+                        let traceback = match thread.mode() {
+                            ThreadMode::Stopped | ThreadMode::Results => thread.error_traceback(),
+                            _ => None,
+                        };
+                        let mut traceback = traceback
+                            .or_else(|| thread.traceback())
+                            .unwrap_or(Traceback { frames: Vec::new() });
+                        let level = (level.max(0) as usize).min(traceback.frames.len());
+                        traceback.frames.drain(0..level);
+
+                        buf.extend_from_slice(traceback.to_string().as_bytes());
+                        Ok(CallbackResult::Return(vec![Value::String(String::new(
+                            mc, &buf,
+                        ))]))
+                    },
+                ))
+            }),
+        )
+        .unwrap();
+
+    debug
+        .set(
+            mc,
+            String::new_static(b"getinfo"),
+            Callback::new_sequence_with_thread(mc, |current_thread, mut args| {
+                let thread = thread_arg(current_thread, &mut args);
+                let target = args.get(0).cloned().unwrap_or(Value::Nil);
+                let what = match args.get(1).cloned().unwrap_or(Value::Nil) {
+                    Value::Nil => b"flnSu".to_vec(),
+                    Value::String(what) => what.as_bytes().to_vec(),
+                    value => {
+                        return Err(TypeError {
+                            expected: "string",
+                            found: value.type_name(),
+                        }
+                        .into());
+                    }
+                };
+                if what.iter().any(|c| !b"flnSu".contains(c)) {
+                    return Err(RuntimeError(Value::String(String::new_static(
+                        b"bad argument #2 to 'getinfo' (invalid option)",
+                    )))
+                    .into());
+                }
+                let level = match target {
+                    Value::Function(_) => None,
+                    target => Some(integer_arg(target, None)?),
+                };
+
+                Ok(sequence::from_fn_with(
+                    (thread, target, level, what),
+                    |mc, (thread, target, level, what)| {
+                        let (function, current_line, name) = match level {
+                            None => match target {
+                                Value::Function(function) => {
+                                    (Some(function), None, FunctionName::Unknown)
+                                }
+                                _ => unreachable!(),
+                            },
+                            Some(level) if level < 0 => {
+                                return Ok(CallbackResult::Return(vec![Value::Nil]))
+                            }
+                            Some(level) => match thread.frame_info(level as usize) {
+                                Some(info) => (info.function, info.current_line, info.name),
+                                None => return Ok(CallbackResult::Return(vec![Value::Nil])),
+                            },
+                        };
+
+                        let info = Table::new(mc);
+                        let set = |key: &'static [u8], value: Value<'gc>| {
+                            info.set(mc, String::new_static(key), value).unwrap();
+                        };
+                        let closure = match function {
+                            Some(Function::Closure(closure)) => Some(closure),
+                            _ => None,
+                        };
+
+                        if what.contains(&b'S') {
+                            match closure {
+                                Some(closure) => {
+                                    let proto = &closure.0.proto;
+                                    set(b"source", Value::String(proto.chunk_name));
+                                    set(
+                                        b"short_src",
+                                        Value::String(String::new(
+                                            mc,
+                                            &chunk_id(proto.chunk_name.as_bytes()),
+                                        )),
+                                    );
+                                    set(b"linedefined", line_value(Some(proto.line_defined)));
+                                    set(
+                                        b"what",
+                                        Value::String(String::new_static(
+                                            if proto.line_defined == LineNumber(0) {
+                                                b"main"
+                                            } else {
+                                                b"Lua"
+                                            },
+                                        )),
+                                    );
+                                }
+                                None => {
+                                    set(b"source", Value::String(String::new_static(b"=[C]")));
+                                    set(b"short_src", Value::String(String::new_static(b"[C]")));
+                                    set(b"linedefined", Value::Integer(-1));
+                                    set(b"what", Value::String(String::new_static(b"C")));
+                                }
+                            }
+                        }
+                        if what.contains(&b'l') {
+                            set(b"currentline", line_value(current_line));
+                        }
+                        if what.contains(&b'u') {
+                            let (nups, nparams, isvararg) = match closure {
+                                Some(closure) => (
+                                    closure.upvalue_count() as i64,
+                                    closure.0.proto.fixed_params as i64,
+                                    closure.0.proto.has_varargs,
+                                ),
+                                None => (0, 0, true),
+                            };
+                            set(b"nups", Value::Integer(nups));
+                            set(b"nparams", Value::Integer(nparams));
+                            set(b"isvararg", Value::Boolean(isvararg));
+                        }
+                        if what.contains(&b'n') {
+                            let (name, namewhat) = function_name_fields(mc, name);
+                            set(b"name", name);
+                            set(b"namewhat", Value::String(String::new_static(namewhat)));
+                        }
+                        if what.contains(&b'f') {
+                            set(b"func", function.map(Value::Function).unwrap_or(Value::Nil));
+                        }
+
+                        Ok(CallbackResult::Return(vec![Value::Table(info)]))
+                    },
+                ))
+            }),
+        )
+        .unwrap();
+
+    debug
+        .set(
+            mc,
+            String::new_static(b"getlocal"),
+            Callback::new_sequence_with_thread(mc, |current_thread, mut args| {
+                let thread = thread_arg(current_thread, &mut args);
+                let target = args.get(0).cloned().unwrap_or(Value::Nil);
+                let n = integer_arg(args.get(1).cloned().unwrap_or(Value::Nil), None)?;
+                let level = match target {
+                    Value::Function(_) => None,
+                    target => Some(level_arg(target)?),
+                };
+
+                Ok(sequence::from_fn_with(
+                    (thread, target, level, n),
+                    |_, (thread, target, level, n)| {
+                        let level = match (level, target) {
+                            (Some(level), _) => level,
+                            // With a function, only the names of its parameters are available
+                            (None, Value::Function(Function::Closure(closure))) if n > 0 => {
+                                let name = closure.0.proto.local_variable(n as usize, 0);
+                                return Ok(CallbackResult::Return(vec![name
+                                    .map(|(name, _)| Value::String(name))
+                                    .unwrap_or(Value::Nil)]));
+                            }
+                            (None, _) => return Ok(CallbackResult::Return(vec![Value::Nil])),
+                        };
+                        check_level(thread, level)?;
+                        Ok(CallbackResult::Return(match thread.local(level, n) {
+                            Some((name, value)) => vec![Value::String(name), value],
+                            None => vec![Value::Nil],
+                        }))
+                    },
+                ))
+            }),
+        )
+        .unwrap();
+
+    debug
+        .set(
+            mc,
+            String::new_static(b"setlocal"),
+            Callback::new_sequence_with_thread(mc, |current_thread, mut args| {
+                let thread = thread_arg(current_thread, &mut args);
+                let level = level_arg(args.get(0).cloned().unwrap_or(Value::Nil))?;
+                let n = integer_arg(args.get(1).cloned().unwrap_or(Value::Nil), None)?;
+                let value = args.get(2).cloned().unwrap_or(Value::Nil);
+
+                Ok(sequence::from_fn_with(
+                    (thread, level, n, value),
+                    |mc, (thread, level, n, value)| {
+                        check_level(thread, level)?;
+                        Ok(CallbackResult::Return(vec![thread
+                            .set_local(mc, level, n, value)
+                            .map(Value::String)
+                            .unwrap_or(Value::Nil)]))
+                    },
+                ))
+            }),
+        )
+        .unwrap();
+
+    debug
+        .set(
+            mc,
+            String::new_static(b"getupvalue"),
+            Callback::new_sequence(mc, |args| {
+                let upvalue = upvalue_arg(
+                    args.get(0).cloned().unwrap_or(Value::Nil),
+                    args.get(1).cloned().unwrap_or(Value::Nil),
+                )?;
+
+                // The upvalue may refer to a register of a thread which is running this callback,
+                // so it can only be read once the callback is stepped.
+                Ok(sequence::from_fn_with(upvalue, |_, upvalue| {
+                    Ok(CallbackResult::Return(match upvalue {
+                        Some((closure, index)) => vec![
+                            Value::String(closure.0.proto.upvalue_names[index]),
+                            closure.upvalue(index).unwrap().get(),
+                        ],
+                        None => vec![Value::Nil],
+                    }))
+                }))
+            }),
+        )
+        .unwrap();
+
+    debug
+        .set(
+            mc,
+            String::new_static(b"setupvalue"),
+            Callback::new_sequence(mc, |args| {
+                let upvalue = upvalue_arg(
+                    args.get(0).cloned().unwrap_or(Value::Nil),
+                    args.get(1).cloned().unwrap_or(Value::Nil),
+                )?;
+                let value = args.get(2).cloned().unwrap_or(Value::Nil);
+
+                Ok(sequence::from_fn_with(
+                    (upvalue, value),
+                    |mc, (upvalue, value)| {
+                        Ok(CallbackResult::Return(match upvalue {
+                            Some((closure, index)) => {
+                                closure.upvalue(index).unwrap().set(mc, value);
+                                vec![Value::String(closure.0.proto.upvalue_names[index])]
+                            }
+                            None => vec![Value::Nil],
+                        }))
+                    },
+                ))
+            }),
+        )
+        .unwrap();
+
+    debug
+        .set(
+            mc,
+            String::new_static(b"upvalueid"),
+            Callback::new_immediate(mc, |args| {
+                let upvalue = upvalue_arg(
+                    args.get(0).cloned().unwrap_or(Value::Nil),
+                    args.get(1).cloned().unwrap_or(Value::Nil),
+                )?;
+                // Upvalues shared between closures have the same identity
+                Ok(CallbackResult::Return(vec![match upvalue {
+                    Some((closure, index)) => {
+                        let upvalue = closure.upvalue(index).unwrap();
+                        Value::Integer(GcCell::as_ptr(upvalue.0) as usize as i64)
+                    }
+                    None => Value::Nil,
+                }]))
+            }),
+        )
+        .unwrap();
+
+    debug
+        .set(
+            mc,
+            String::new_static(b"upvaluejoin"),
+            Callback::new_sequence(mc, |args| {
+                let invalid_index =
+                    || RuntimeError(Value::String(String::new_static(b"invalid upvalue index")));
+                let (closure, index) = upvalue_arg(
+                    args.get(0).cloned().unwrap_or(Value::Nil),
+                    args.get(1).cloned().unwrap_or(Value::Nil),
+                )?
+                .ok_or_else(invalid_index)?;
+                let (other_closure, other_index) = upvalue_arg(
+                    args.get(2).cloned().unwrap_or(Value::Nil),
+                    args.get(3).cloned().unwrap_or(Value::Nil),
+                )?
+                .ok_or_else(invalid_index)?;
+
+                Ok(sequence::from_fn_with(
+                    (closure, index, other_closure, other_index),
+                    |mc, (closure, index, other_closure, other_index)| {
+                        closure.set_upvalue(mc, index, other_closure.upvalue(other_index).unwrap());
+                        Ok(CallbackResult::Return(vec![]))
+                    },
+                ))
+            }),
+        )
+        .unwrap();
+
+    debug
+        .set(
+            mc,
+            String::new_static(b"getmetatable"),
+            Callback::new_immediate_with(mc, root.string_metatable, |string_metatable, args| {
+                // Unlike `getmetatable`, this ignores any `__metatable` field
+                let metatable = match args.get(0).cloned().unwrap_or(Value::Nil) {
+                    Value::String(_) => Some(*string_metatable),
+                    Value::Table(table) => table.metatable(),
+                    Value::UserData(userdata) => userdata.metatable(),
+                    _ => None,
+                };
+                Ok(CallbackResult::Return(vec![metatable
+                    .map(Value::Table)
+                    .unwrap_or(Value::Nil)]))
+            }),
+        )
+        .unwrap();
+
+    debug
+        .set(
+            mc,
+            String::new_static(b"setmetatable"),
+            Callback::new_sequence_with(mc, root.finalizers, |finalizers, args| {
+                let value = args.get(0).cloned().unwrap_or(Value::Nil);
+                let metatable = match args.get(1).cloned().unwrap_or(Value::Nil) {
+                    Value::Nil => None,
+                    Value::Table(metatable) => Some(metatable),
+                    value => {
+                        return Err(TypeError {
+                            expected: "nil or table",
+                            found: value.type_name(),
+                        }
+                        .into());
+                    }
+                };
+                match value {
+                    Value::Table(_) | Value::UserData(_) => {}
+                    value => {
+                        return Err(TypeError {
+                            expected: "table or userdata",
+                            found: value.type_name(),
+                        }
+                        .into());
+                    }
+                }
+
+                // Unlike `setmetatable`, this ignores any `__metatable` field
+                Ok(sequence::from_fn_with(
+                    (value, metatable, *finalizers),
+                    |mc, (value, metatable, finalizers)| {
+                        match value {
+                            Value::Table(table) => {
+                                table.set_metatable(mc, metatable);
+                            }
+                            Value::UserData(userdata) => {
+                                userdata.set_metatable(mc, metatable);
+                            }
+                            _ => unreachable!(),
+                        }
+                        if let Some(metatable) = metatable {
+                            if metatable.get(String::new_static(b"__gc")) != Value::Nil {
+                                finalizers.register(mc, value);
+                            }
+                        }
+                        Ok(CallbackResult::Return(vec![value]))
+                    },
+                ))
+            }),
+        )
+        .unwrap();
+
+    debug
+        .set(
+            mc,
+            String::new_static(b"sethook"),
+            Callback::new_sequence_with_thread(mc, |current_thread, mut args| {
+                let thread = thread_arg(current_thread, &mut args);
+                let hook = match args.get(0).cloned().unwrap_or(Value::Nil) {
+                    Value::Nil => None,
+                    Value::Function(function) => {
+                        let mask = match args.get(1).cloned().unwrap_or(Value::Nil) {
+                            Value::String(mask) => mask.as_bytes().to_vec(),
+                            value => {
+                                return Err(TypeError {
+                                    expected: "string",
+                                    found: value.type_name(),
+                                }
+                                .into());
+                            }
+                        };
+                        let count =
+                            integer_arg(args.get(2).cloned().unwrap_or(Value::Nil), Some(0))?;
+                        Some(Hook {
+                            function,
+                            mask: HookMask {
+                                call: mask.contains(&b'c'),
+                                ret: mask.contains(&b'r'),
+                                line: mask.contains(&b'l'),
+                            },
+                            count: count.max(0).min(u32::MAX as i64) as u32,
+                        })
+                    }
+                    value => {
+                        return Err(TypeError {
+                            expected: "function",
+                            found: value.type_name(),
+                        }
+                        .into());
+                    }
+                };
+
+                Ok(sequence::from_fn_with(
+                    (thread, hook),
+                    |mc, (thread, hook)| {
+                        thread.set_hook(mc, hook);
+                        Ok(CallbackResult::Return(vec![]))
+                    },
+                ))
+            }),
+        )
+        .unwrap();
+
+    debug
+        .set(
+            mc,
+            String::new_static(b"gethook"),
+            Callback::new_sequence_with_thread(mc, |current_thread, mut args| {
+                let thread = thread_arg(current_thread, &mut args);
+                Ok(sequence::from_fn_with(thread, |mc, thread| {
+                    Ok(CallbackResult::Return(match thread.hook() {
+                        Some(hook) => {
+                            let mut mask = Vec::new();
+                            if hook.mask.call {
+                                mask.push(b'c');
+                            }
+                            if hook.mask.ret {
+                                mask.push(b'r');
+                            }
+                            if hook.mask.line {
+                                mask.push(b'l');
+                            }
+                            vec![
+                                Value::Function(hook.function),
+                                Value::String(String::new(mc, &mask)),
+                                Value::Integer(hook.count as i64),
+                            ]
+                        }
+                        None => vec![Value::Nil],
+                    }))
+                }))
+            }),
+        )
+        .unwrap();
+
+    env.set(mc, String::new_static(b"debug"), debug).unwrap();
+}
+
+// Removes and returns the optional thread given as the first argument of most debug functions,
+// defaulting to the current thread.
+fn thread_arg<'gc>(current_thread: Thread<'gc>, args: &mut Vec<Value<'gc>>) -> Thread<'gc> {
+    match args.get(0).cloned() {
+        Some(Value::Thread(thread)) => {
+            args.remove(0);
+            thread
+        }
+        _ => current_thread,
+    }
+}
+
+fn level_arg<'gc>(value: Value<'gc>) -> Result<usize, RuntimeError<'gc>> {
+    match integer_arg(value, None) {
+        Ok(level) if level >= 0 => Ok(level as usize),
+        Ok(_) => Err(level_out_of_range()),
+        Err(err) => Err(RuntimeError(Value::String(String::new_static(
+            match err.found {
+                "nil" => b"bad argument (level expected, got no value)",
+                _ => b"bad argument (level expected)",
+            },
+        )))),
+    }
+}
+
+fn check_level<'gc>(thread: Thread<'gc>, level: usize) -> Result<(), RuntimeError<'gc>> {
+    match thread.frame_info(level) {
+        Some(_) => Ok(()),
+        None => Err(level_out_of_range()),
+    }
+}
+
+fn level_out_of_range<'gc>() -> RuntimeError<'gc> {
+    RuntimeError(Value::String(String::new_static(b"level out of range")))
+}
+
+// Returns the Lua closure and 0-based upvalue index referred to by a function and a 1-based upvalue
+// number, or `None` if the function has no such upvalue.
+fn upvalue_arg<'gc>(
+    function: Value<'gc>,
+    n: Value<'gc>,
+) -> Result<Option<(Closure<'gc>, usize)>, TypeError> {
+    let function = match function {
+        Value::Function(function) => function,
+        value => {
+            return Err(TypeError {
+                expected: "function",
+                found: value.type_name(),
+            });
+        }
+    };
+    let n = integer_arg(n, None)?;
+    Ok(match function {
+        Function::Closure(closure) if n >= 1 && (n as usize) <= closure.upvalue_count() => {
+            Some((closure, n as usize - 1))
+        }
+        _ => None,
+    })
+}
+
+fn line_value<'gc>(line: Option<LineNumber>) -> Value<'gc> {
+    match line {
+        Some(line) => Value::Integer(line.0 as i64),
+        None => Value::Integer(-1),
+    }
+}
+
+// Converts a function name to the `name` and `namewhat` fields returned by `debug.getinfo`
+fn function_name_fields<'gc>(
+    mc: MutationContext<'gc, '_>,
+    name: FunctionName,
+) -> (Value<'gc>, &'static [u8]) {
+    let string = |name: &str| Value::String(String::new(mc, name.as_bytes()));
+    match name {
+        FunctionName::Global(name) => (string(&name), b"global"),
+        FunctionName::Local(name) => (string(&name), b"local"),
+        FunctionName::Field(name) => (string(&name), b"field"),
+        FunctionName::Method(name) => (string(&name), b"method"),
+        FunctionName::UpValue(name) => (string(&name), b"upvalue"),
+        FunctionName::Metamethod(name) => (string(name), b"metamethod"),
+        FunctionName::ForIterator => (string("for iterator"), b"for iterator"),
+        FunctionName::Hook => (string("?"), b"hook"),
+        FunctionName::MainChunk | FunctionName::Unknown => (Value::Nil, b""),
+    }
+}
@@ -0,0 +1,1211 @@
+use std::cell::{Cell, RefCell, RefMut};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::rc::Rc;
+
+use gc_arena::{Collect, MutationContext, StaticCollect};
+use gc_sequence as sequence;
+
+use crate::{
+    lexer::{read_float, read_hex_float, read_hex_integer, read_integer},
+    Callback, CallbackResult, Error, Root, RuntimeError, String, Table, TypeError, UserData, Value,
+};
+
+use super::string::{integer_arg, runtime_error, string_arg};
+
+// The size of the read-ahead buffer kept for each open file.
+const READ_AHEAD_SIZE: usize = 4096;
+
+// The maximum length of a numeral read by the "n" format.
+const MAX_NUMERAL_LEN: usize = 200;
+
+/// How a file should be opened, as given by an `io.open` mode string.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct OpenMode {
+    pub read: bool,
+    pub write: bool,
+    pub append: bool,
+    pub truncate: bool,
+    pub create: bool,
+}
+
+impl OpenMode {
+    /// Parses a mode string as accepted by `io.open`: one of "r", "w" or "a", optionally followed
+    /// by "+", followed by any number of "b".
+    pub fn parse(mode: &[u8]) -> Option<OpenMode> {
+        let (&first, mut rest) = mode.split_first()?;
+        let update = rest.first() == Some(&b'+');
+        if update {
+            rest = &rest[1..];
+        }
+        if !rest.iter().all(|&c| c == b'b') {
+            return None;
+        }
+
+        Some(match first {
+            b'r' => OpenMode {
+                read: true,
+                write: update,
+                append: false,
+                truncate: false,
+                create: false,
+            },
+            b'w' => OpenMode {
+                read: update,
+                write: true,
+                append: false,
+                truncate: true,
+                create: true,
+            },
+            b'a' => OpenMode {
+                read: update,
+                write: true,
+                append: true,
+                truncate: false,
+                create: true,
+            },
+            _ => return None,
+        })
+    }
+}
+
+/// A file opened by an `IoHost`.
+///
+/// The default implementations fail, so a host only needs to implement the operations that a file
+/// supports.
+pub trait IoFile {
+    fn read(&mut self, _buf: &mut [u8]) -> Result<usize, io::Error> {
+        Err(bad_file_descriptor())
+    }
+
+    fn write(&mut self, _buf: &[u8]) -> Result<(), io::Error> {
+        Err(bad_file_descriptor())
+    }
+
+    fn flush(&mut self) -> Result<(), io::Error> {
+        Ok(())
+    }
+
+    fn seek(&mut self, _pos: SeekFrom) -> Result<u64, io::Error> {
+        Err(io::Error::new(io::ErrorKind::Other, "Illegal seek"))
+    }
+}
+
+/// The interface through which the `io` library accesses files.
+///
+/// Embedders can provide their own implementation, such as `MemoryIoHost`, to run scripts without
+/// access to the real filesystem.
+pub trait IoHost {
+    fn open(&self, path: &[u8], mode: OpenMode) -> Result<Box<dyn IoFile>, io::Error>;
+
+    fn stdin(&self) -> Box<dyn IoFile>;
+    fn stdout(&self) -> Box<dyn IoFile>;
+    fn stderr(&self) -> Box<dyn IoFile>;
+}
+
+/// An `IoHost` which uses the real filesystem and standard streams.
+#[derive(Default)]
+pub struct StdIoHost;
+
+impl IoHost for StdIoHost {
+    fn open(&self, path: &[u8], mode: OpenMode) -> Result<Box<dyn IoFile>, io::Error> {
+        let file = OpenOptions::new()
+            .read(mode.read)
+            .write(mode.write && !mode.append)
+            .append(mode.append)
+            .truncate(mode.truncate)
+            .create(mode.create)
+            .open(super::os::bytes_to_path(path))?;
+        Ok(Box::new(StdFile(file)))
+    }
+
+    fn stdin(&self) -> Box<dyn IoFile> {
+        Box::new(StdStream::Stdin)
+    }
+
+    fn stdout(&self) -> Box<dyn IoFile> {
+        Box::new(StdStream::Stdout)
+    }
+
+    fn stderr(&self) -> Box<dyn IoFile> {
+        Box::new(StdStream::Stderr)
+    }
+}
+
+struct StdFile(File);
+
+impl IoFile for StdFile {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, io::Error> {
+        self.0.read(buf)
+    }
+
+    fn write(&mut self, buf: &[u8]) -> Result<(), io::Error> {
+        self.0.write_all(buf)
+    }
+
+    fn flush(&mut self) -> Result<(), io::Error> {
+        self.0.flush()
+    }
+
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64, io::Error> {
+        self.0.seek(pos)
+    }
+}
+
+enum StdStream {
+    Stdin,
+    Stdout,
+    Stderr,
+}
+
+impl IoFile for StdStream {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, io::Error> {
+        match self {
+            StdStream::Stdin => io::stdin().read(buf),
+            _ => Err(bad_file_descriptor()),
+        }
+    }
+
+    fn write(&mut self, buf: &[u8]) -> Result<(), io::Error> {
+        match self {
+            StdStream::Stdin => Err(bad_file_descriptor()),
+            StdStream::Stdout => io::stdout().write_all(buf),
+            StdStream::Stderr => io::stderr().write_all(buf),
+        }
+    }
+
+    fn flush(&mut self) -> Result<(), io::Error> {
+        match self {
+            StdStream::Stdin => Ok(()),
+            StdStream::Stdout => io::stdout().flush(),
+            StdStream::Stderr => io::stderr().flush(),
+        }
+    }
+}
+
+// The shared contents of an in-memory file.
+type MemoryData = Rc<RefCell<Vec<u8>>>;
+
+/// An `IoHost` which keeps all files in memory, for tests and sandboxed Lua instances.
+///
+/// Standard input is empty unless set with `set_stdin`, and anything written to standard output
+/// or standard error is kept and can be retrieved with `stdout_contents` and `stderr_contents`.
+///
+/// Writes which would make a file larger than the maximum file size fail with an error, which is
+/// 16 MiB unless set with `set_max_file_size`.
+pub struct MemoryIoHost {
+    files: RefCell<HashMap<Vec<u8>, MemoryData>>,
+    stdin: MemoryData,
+    stdout: MemoryData,
+    stderr: MemoryData,
+    max_file_size: Cell<usize>,
+}
+
+impl Default for MemoryIoHost {
+    fn default() -> MemoryIoHost {
+        const MAX_FILE_SIZE: usize = 16 * 1024 * 1024;
+
+        MemoryIoHost {
+            files: RefCell::default(),
+            stdin: MemoryData::default(),
+            stdout: MemoryData::default(),
+            stderr: MemoryData::default(),
+            max_file_size: Cell::new(MAX_FILE_SIZE),
+        }
+    }
+}
+
+impl MemoryIoHost {
+    pub fn new() -> MemoryIoHost {
+        MemoryIoHost::default()
+    }
+
+    /// Sets the size in bytes past which writes to any file fail, including standard output and
+    /// standard error.  Only applies to files opened afterwards.
+    pub fn set_max_file_size(&self, max_file_size: usize) {
+        self.max_file_size.set(max_file_size);
+    }
+
+    /// Creates or replaces the file at the given path.
+    pub fn set_file(&self, path: &[u8], contents: &[u8]) {
+        self.files
+            .borrow_mut()
+            .insert(path.to_vec(), Rc::new(RefCell::new(contents.to_vec())));
+    }
+
+    /// Returns the contents of the file at the given path, if it exists.
+    pub fn file(&self, path: &[u8]) -> Option<Vec<u8>> {
+        self.files.borrow().get(path).map(|f| f.borrow().clone())
+    }
+
+    /// Sets the contents of standard input, which must be done before creating the Lua instance.
+    pub fn set_stdin(&self, contents: &[u8]) {
+        *self.stdin.borrow_mut() = contents.to_vec();
+    }
+
+    pub fn stdout_contents(&self) -> Vec<u8> {
+        self.stdout.borrow().clone()
+    }
+
+    pub fn stderr_contents(&self) -> Vec<u8> {
+        self.stderr.borrow().clone()
+    }
+}
+
+impl IoHost for MemoryIoHost {
+    fn open(&self, path: &[u8], mode: OpenMode) -> Result<Box<dyn IoFile>, io::Error> {
+        let mut files = self.files.borrow_mut();
+        let data = match files.get(path) {
+            Some(data) => data.clone(),
+            None if mode.create => {
+                let data = Rc::new(RefCell::new(Vec::new()));
+                files.insert(path.to_vec(), data.clone());
+                data
+            }
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    "No such file or directory",
+                ))
+            }
+        };
+        if mode.truncate {
+            data.borrow_mut().clear();
+        }
+        Ok(Box::new(MemoryFile {
+            data,
+            pos: 0,
+            mode,
+            max_size: self.max_file_size.get(),
+        }))
+    }
+
+    fn stdin(&self) -> Box<dyn IoFile> {
+        Box::new(MemoryFile {
+            data: self.stdin.clone(),
+            pos: 0,
+            mode: OpenMode::parse(b"r").unwrap(),
+            max_size: self.max_file_size.get(),
+        })
+    }
+
+    fn stdout(&self) -> Box<dyn IoFile> {
+        Box::new(MemoryFile {
+            data: self.stdout.clone(),
+            pos: 0,
+            mode: OpenMode::parse(b"a").unwrap(),
+            max_size: self.max_file_size.get(),
+        })
+    }
+
+    fn stderr(&self) -> Box<dyn IoFile> {
+        Box::new(MemoryFile {
+            data: self.stderr.clone(),
+            pos: 0,
+            mode: OpenMode::parse(b"a").unwrap(),
+            max_size: self.max_file_size.get(),
+        })
+    }
+}
+
+struct MemoryFile {
+    data: MemoryData,
+    pos: usize,
+    mode: OpenMode,
+    max_size: usize,
+}
+
+impl IoFile for MemoryFile {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, io::Error> {
+        if !self.mode.read {
+            return Err(bad_file_descriptor());
+        }
+        let data = self.data.borrow();
+        let start = self.pos.min(data.len());
+        let len = buf.len().min(data.len() - start);
+        buf[0..len].copy_from_slice(&data[start..start + len]);
+        self.pos = start + len;
+        Ok(len)
+    }
+
+    fn write(&mut self, buf: &[u8]) -> Result<(), io::Error> {
+        if !self.mode.write {
+            return Err(bad_file_descriptor());
+        }
+        let mut data = self.data.borrow_mut();
+        if self.mode.append {
+            self.pos = data.len();
+        }
+        // The position may have been set anywhere by seeking, so the file must not grow without
+        // bound.
+        let end = match self.pos.checked_add(buf.len()) {
+            Some(end) if end <= self.max_size.max(data.len()) => end,
+            _ => return Err(io::Error::new(io::ErrorKind::Other, "File too large")),
+        };
+        if end > data.len() {
+            data.resize(end, 0);
+        }
+        data[self.pos..end].copy_from_slice(buf);
+        self.pos = end;
+        Ok(())
+    }
+
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64, io::Error> {
+        let new_pos = match pos {
+            SeekFrom::Start(offset) => Some(offset as i64),
+            SeekFrom::Current(offset) => (self.pos as i64).checked_add(offset),
+            SeekFrom::End(offset) => (self.data.borrow().len() as i64).checked_add(offset),
+        };
+        match new_pos {
+            Some(pos) if pos >= 0 => {
+                self.pos = pos as usize;
+                Ok(pos as u64)
+            }
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Invalid argument",
+            )),
+        }
+    }
+}
+
+fn bad_file_descriptor() -> io::Error {
+    io::Error::new(io::ErrorKind::Other, "Bad file descriptor")
+}
+
+// The state of an open Lua file handle, held in a userdata.
+struct FileHandle {
+    // None once the file is closed
+    file: Option<Box<dyn IoFile>>,
+    // Whether this is one of the standard streams, which cannot be closed
+    standard: bool,
+    // Bytes which have been read from the file but not yet consumed
+    read_ahead: Vec<u8>,
+    read_pos: usize,
+}
+
+impl FileHandle {
+    fn new(file: Box<dyn IoFile>, standard: bool) -> FileHandle {
+        FileHandle {
+            file: Some(file),
+            standard,
+            read_ahead: Vec::new(),
+            read_pos: 0,
+        }
+    }
+
+    fn file(&mut self) -> &mut dyn IoFile {
+        self.file.as_mut().unwrap().as_mut()
+    }
+
+    fn peek(&mut self) -> Result<Option<u8>, io::Error> {
+        if self.read_pos >= self.read_ahead.len() {
+            let mut buf = vec![0; READ_AHEAD_SIZE];
+            let len = self.file().read(&mut buf)?;
+            buf.truncate(len);
+            self.read_ahead = buf;
+            self.read_pos = 0;
+        }
+        Ok(self.read_ahead.get(self.read_pos).cloned())
+    }
+
+    fn next(&mut self) -> Result<Option<u8>, io::Error> {
+        let c = self.peek()?;
+        if c.is_some() {
+            self.read_pos += 1;
+        }
+        Ok(c)
+    }
+
+    // Discards any read-ahead bytes, moving the underlying file back to the logical read position.
+    // Must be called before writing or seeking.
+    fn sync(&mut self) -> Result<(), io::Error> {
+        let unread = self.read_ahead.len() - self.read_pos;
+        self.read_ahead.clear();
+        self.read_pos = 0;
+        if unread > 0 {
+            self.file().seek(SeekFrom::Current(-(unread as i64)))?;
+        }
+        Ok(())
+    }
+
+    fn read_line(&mut self, keep_newline: bool) -> Result<Option<Vec<u8>>, io::Error> {
+        let mut line = Vec::new();
+        loop {
+            match self.next()? {
+                Some(b'\n') => {
+                    if keep_newline {
+                        line.push(b'\n');
+                    }
+                    return Ok(Some(line));
+                }
+                Some(c) => line.push(c),
+                None if line.is_empty() => return Ok(None),
+                None => return Ok(Some(line)),
+            }
+        }
+    }
+
+    fn read_all(&mut self) -> Result<Vec<u8>, io::Error> {
+        let mut res = self.read_ahead[self.read_pos..].to_vec();
+        self.read_ahead.clear();
+        self.read_pos = 0;
+        let mut buf = vec![0; READ_AHEAD_SIZE];
+        loop {
+            let len = self.file().read(&mut buf)?;
+            if len == 0 {
+                return Ok(res);
+            }
+            res.extend_from_slice(&buf[0..len]);
+        }
+    }
+
+    fn read_chars(&mut self, count: usize) -> Result<Option<Vec<u8>>, io::Error> {
+        if count == 0 {
+            return Ok(self.peek()?.map(|_| Vec::new()));
+        }
+        let mut res = Vec::new();
+        while res.len() < count {
+            if self.peek()?.is_none() {
+                break;
+            }
+            let available = &self.read_ahead[self.read_pos..];
+            let len = available.len().min(count - res.len());
+            res.extend_from_slice(&available[0..len]);
+            self.read_pos += len;
+        }
+        Ok(if res.is_empty() { None } else { Some(res) })
+    }
+
+    // Consumes the next byte and adds it to `buf` if it matches the given predicate.
+    fn accept(&mut self, buf: &mut Vec<u8>, pred: impl Fn(u8) -> bool) -> Result<bool, io::Error> {
+        match self.peek()? {
+            Some(c) if pred(c) && buf.len() < MAX_NUMERAL_LEN => {
+                buf.push(c);
+                self.read_pos += 1;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    fn accept_digits(&mut self, buf: &mut Vec<u8>, hex: bool) -> Result<usize, io::Error> {
+        let mut count = 0;
+        while self.accept(buf, |c| {
+            if hex {
+                c.is_ascii_hexdigit()
+            } else {
+                c.is_ascii_digit()
+            }
+        })? {
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    // Reads the longest prefix which could be a valid numeral, like `l_getn` in PUC-Rio Lua, and
+    // returns None if it is not actually a valid numeral.
+    fn read_number<'gc>(&mut self) -> Result<Option<Value<'gc>>, io::Error> {
+        while let Some(c) = self.peek()? {
+            if c.is_ascii_whitespace() {
+                self.read_pos += 1;
+            } else {
+                break;
+            }
+        }
+
+        let mut buf = Vec::new();
+        self.accept(&mut buf, |c| c == b'-' || c == b'+')?;
+        let mut hex = false;
+        let mut count = 0;
+        if self.accept(&mut buf, |c| c == b'0')? {
+            if self.accept(&mut buf, |c| c == b'x' || c == b'X')? {
+                hex = true;
+            } else {
+                count = 1;
+            }
+        }
+        count += self.accept_digits(&mut buf, hex)?;
+        if self.accept(&mut buf, |c| c == b'.')? {
+            count += self.accept_digits(&mut buf, hex)?;
+        }
+        if count > 0
+            && self.accept(&mut buf, |c| {
+                if hex {
+                    c == b'p' || c == b'P'
+                } else {
+                    c == b'e' || c == b'E'
+                }
+            })?
+        {
+            self.accept(&mut buf, |c| c == b'-' || c == b'+')?;
+            self.accept_digits(&mut buf, false)?;
+        }
+        if count == 0 {
+            return Ok(None);
+        }
+
+        let numeral = if buf.first() == Some(&b'+') {
+            &buf[1..]
+        } else {
+            &buf[..]
+        };
+        let integer = if hex {
+            read_hex_integer(numeral)
+        } else {
+            read_integer(numeral)
+        };
+        Ok(match integer {
+            Some(i) => Some(Value::Integer(i)),
+            None => {
+                let float = if hex {
+                    read_hex_float(numeral)
+                } else {
+                    read_float(numeral)
+                };
+                float.map(Value::Number)
+            }
+        })
+    }
+}
+
+// The shared state of the `io` library.
+#[derive(Collect)]
+#[collect(empty_drop)]
+struct IoState<'gc> {
+    host: StaticCollect<Rc<dyn IoHost>>,
+    file_metatable: Table<'gc>,
+    // Holds the default "input" and "output" files
+    defaults: Table<'gc>,
+}
+
+impl<'gc> Clone for IoState<'gc> {
+    fn clone(&self) -> IoState<'gc> {
+        IoState {
+            host: StaticCollect(self.host.0.clone()),
+            file_metatable: self.file_metatable,
+            defaults: self.defaults,
+        }
+    }
+}
+
+impl<'gc> IoState<'gc> {
+    fn new_file(
+        &self,
+        mc: MutationContext<'gc, '_>,
+        file: Box<dyn IoFile>,
+        standard: bool,
+    ) -> UserData<'gc> {
+        let file = UserData::new_static(mc, FileHandle::new(file, standard));
+        file.set_metatable(mc, Some(self.file_metatable));
+        file
+    }
+
+    fn open(
+        &self,
+        mc: MutationContext<'gc, '_>,
+        path: &[u8],
+        mode: OpenMode,
+    ) -> Result<UserData<'gc>, io::Error> {
+        let file = self.host.0.open(path, mode)?;
+        Ok(self.new_file(mc, file, false))
+    }
+
+    fn default_file(&self, name: &'static str) -> Result<UserData<'gc>, Error<'gc>> {
+        match self.defaults.get(String::new_static(name.as_bytes())) {
+            Value::UserData(file) if !is_closed(file) => Ok(file),
+            _ => Err(
+                RuntimeError(Value::String(String::new_static(if name == "input" {
+                    b"standard input file is closed"
+                } else {
+                    b"standard output file is closed"
+                })))
+                .into(),
+            ),
+        }
+    }
+
+    // Implements `io.input` and `io.output`.
+    fn set_default_file(
+        &self,
+        mc: MutationContext<'gc, '_>,
+        name: &'static str,
+        arg: Value<'gc>,
+    ) -> Result<CallbackResult<'gc>, Error<'gc>> {
+        let key = String::new_static(name.as_bytes());
+        match arg {
+            Value::Nil => {}
+            Value::String(path) => {
+                let mode = if name == "input" { b"r" } else { b"w" };
+                let file = self
+                    .open(mc, path.as_bytes(), OpenMode::parse(mode).unwrap())
+                    .map_err(|err| {
+                        runtime_error(
+                            mc,
+                            &format!(
+                                "cannot open file '{}' ({})",
+                                std::string::String::from_utf8_lossy(path.as_bytes()),
+                                err
+                            ),
+                        )
+                    })?;
+                self.defaults.set(mc, key, file)?;
+            }
+            arg => {
+                let file = file_arg(arg)?;
+                check_open(mc, &file)?;
+                self.defaults.set(mc, key, file)?;
+            }
+        }
+        Ok(CallbackResult::Return(vec![self.defaults.get(key)]))
+    }
+}
+
+pub fn load_io<'gc>(
+    mc: MutationContext<'gc, '_>,
+    _: Root<'gc>,
+    env: Table<'gc>,
+    host: Rc<dyn IoHost>,
+) {
+    let io = Table::new(mc);
+    let methods = Table::new(mc);
+    let state = IoState {
+        host: StaticCollect(host),
+        file_metatable: Table::new(mc),
+        defaults: Table::new(mc),
+    };
+
+    methods
+        .set(
+            mc,
+            String::new_static(b"close"),
+            io_callback(mc, &state, |mc, _, args| {
+                close_file(mc, file_arg(args.get(0).cloned().unwrap_or(Value::Nil))?)
+            }),
+        )
+        .unwrap();
+
+    methods
+        .set(
+            mc,
+            String::new_static(b"flush"),
+            io_callback(mc, &state, |mc, _, args| {
+                let file = file_arg(args.get(0).cloned().unwrap_or(Value::Nil))?;
+                let res = check_open(mc, &file)?.file().flush();
+                Ok(file_result(mc, res.map(|()| Value::Boolean(true))))
+            }),
+        )
+        .unwrap();
+
+    methods
+        .set(
+            mc,
+            String::new_static(b"lines"),
+            io_callback(mc, &state, |mc, _, args| {
+                let file = file_arg(args.get(0).cloned().unwrap_or(Value::Nil))?;
+                check_open(mc, &file)?;
+                Ok(CallbackResult::Return(vec![lines_iterator(
+                    mc,
+                    file,
+                    args[1..].to_vec(),
+                    false,
+                )
+                .into()]))
+            }),
+        )
+        .unwrap();
+
+    methods
+        .set(
+            mc,
+            String::new_static(b"read"),
+            io_callback(mc, &state, |mc, _, args| {
+                let file = file_arg(args.get(0).cloned().unwrap_or(Value::Nil))?;
+                read_file(mc, file, &args[1..])
+            }),
+        )
+        .unwrap();
+
+    methods
+        .set(
+            mc,
+            String::new_static(b"seek"),
+            io_callback(mc, &state, |mc, _, args| {
+                let file = file_arg(args.get(0).cloned().unwrap_or(Value::Nil))?;
+                let whence = match args.get(1).cloned().unwrap_or(Value::Nil) {
+                    Value::Nil => b"cur".to_vec(),
+                    whence => string_arg(whence)?,
+                };
+                let offset = integer_arg(args.get(2).cloned().unwrap_or(Value::Nil), Some(0))?;
+                let pos = match &whence[..] {
+                    b"set" if offset >= 0 => SeekFrom::Start(offset as u64),
+                    b"set" => {
+                        return Ok(file_result(
+                            mc,
+                            Err(io::Error::new(
+                                io::ErrorKind::InvalidInput,
+                                "Invalid argument",
+                            )),
+                        ))
+                    }
+                    b"cur" => SeekFrom::Current(offset),
+                    b"end" => SeekFrom::End(offset),
+                    _ => {
+                        return Err(runtime_error(
+                            mc,
+                            &format!(
+                                "bad argument #1 to 'seek' (invalid option '{}')",
+                                std::string::String::from_utf8_lossy(&whence)
+                            ),
+                        ))
+                    }
+                };
+                let mut handle = check_open(mc, &file)?;
+                let res = handle
+                    .sync()
+                    .and_then(|()| handle.file().seek(pos))
+                    .map(|pos| Value::Integer(pos as i64));
+                Ok(file_result(mc, res))
+            }),
+        )
+        .unwrap();
+
+    methods
+        .set(
+            mc,
+            String::new_static(b"setvbuf"),
+            io_callback(mc, &state, |mc, _, args| {
+                let file = file_arg(args.get(0).cloned().unwrap_or(Value::Nil))?;
+                let mode = string_arg(args.get(1).cloned().unwrap_or(Value::Nil))?;
+                integer_arg(args.get(2).cloned().unwrap_or(Value::Nil), Some(0))?;
+                check_open(mc, &file)?;
+                match &mode[..] {
+                    b"no" | b"full" | b"line" => {
+                        Ok(CallbackResult::Return(vec![Value::Boolean(true)]))
+                    }
+                    _ => Err(runtime_error(
+                        mc,
+                        &format!(
+                            "bad argument #1 to 'setvbuf' (invalid option '{}')",
+                            std::string::String::from_utf8_lossy(&mode)
+                        ),
+                    )),
+                }
+            }),
+        )
+        .unwrap();
+
+    methods
+        .set(
+            mc,
+            String::new_static(b"write"),
+            io_callback(mc, &state, |mc, _, args| {
+                let file = file_arg(args.get(0).cloned().unwrap_or(Value::Nil))?;
+                write_file(mc, file, &args[1..])
+            }),
+        )
+        .unwrap();
+
+    state
+        .file_metatable
+        .set(mc, String::new_static(b"__index"), methods)
+        .unwrap();
+    state
+        .file_metatable
+        .set(
+            mc,
+            String::new_static(b"__name"),
+            String::new_static(b"FILE*"),
+        )
+        .unwrap();
+    state
+        .file_metatable
+        .set(
+            mc,
+            String::new_static(b"__tostring"),
+            io_callback(mc, &state, |mc, _, args| {
+                let file = file_arg(args.get(0).cloned().unwrap_or(Value::Nil))?;
+                let s = if is_closed(file) {
+                    "file (closed)".to_owned()
+                } else {
+                    format!("file ({:p})", file.0.as_ptr())
+                };
+                Ok(CallbackResult::Return(vec![Value::String(String::new(
+                    mc,
+                    s.as_bytes(),
+                ))]))
+            }),
+        )
+        .unwrap();
+
+    let stdin = state.new_file(mc, state.host.0.stdin(), true);
+    let stdout = state.new_file(mc, state.host.0.stdout(), true);
+    let stderr = state.new_file(mc, state.host.0.stderr(), true);
+    io.set(mc, String::new_static(b"stdin"), stdin).unwrap();
+    io.set(mc, String::new_static(b"stdout"), stdout).unwrap();
+    io.set(mc, String::new_static(b"stderr"), stderr).unwrap();
+    state
+        .defaults
+        .set(mc, String::new_static(b"input"), stdin)
+        .unwrap();
+    state
+        .defaults
+        .set(mc, String::new_static(b"output"), stdout)
+        .unwrap();
+
+    io.set(
+        mc,
+        String::new_static(b"open"),
+        io_callback(mc, &state, |mc, state, args| {
+            let path = string_arg(args.get(0).cloned().unwrap_or(Value::Nil))?;
+            let mode = match args.get(1).cloned().unwrap_or(Value::Nil) {
+                Value::Nil => b"r".to_vec(),
+                mode => string_arg(mode)?,
+            };
+            let mode = OpenMode::parse(&mode).ok_or_else(|| {
+                RuntimeError(Value::String(String::new_static(
+                    b"bad argument #2 to 'open' (invalid mode)",
+                )))
+            })?;
+            Ok(match state.open(mc, &path, mode) {
+                Ok(file) => CallbackResult::Return(vec![Value::UserData(file)]),
+                Err(err) => path_result(mc, &path, err),
+            })
+        }),
+    )
+    .unwrap();
+
+    io.set(
+        mc,
+        String::new_static(b"close"),
+        io_callback(mc, &state, |mc, state, args| {
+            match args.get(0).cloned().unwrap_or(Value::Nil) {
+                Value::Nil => close_file(mc, state.default_file("output")?),
+                file => close_file(mc, file_arg(file)?),
+            }
+        }),
+    )
+    .unwrap();
+
+    io.set(
+        mc,
+        String::new_static(b"flush"),
+        io_callback(mc, &state, |mc, state, _| {
+            let file = state.default_file("output")?;
+            let res = check_open(mc, &file)?.file().flush();
+            Ok(file_result(mc, res.map(|()| Value::Boolean(true))))
+        }),
+    )
+    .unwrap();
+
+    io.set(
+        mc,
+        String::new_static(b"input"),
+        io_callback(mc, &state, |mc, state, args| {
+            state.set_default_file(mc, "input", args.get(0).cloned().unwrap_or(Value::Nil))
+        }),
+    )
+    .unwrap();
+
+    io.set(
+        mc,
+        String::new_static(b"output"),
+        io_callback(mc, &state, |mc, state, args| {
+            state.set_default_file(mc, "output", args.get(0).cloned().unwrap_or(Value::Nil))
+        }),
+    )
+    .unwrap();
+
+    io.set(
+        mc,
+        String::new_static(b"lines"),
+        io_callback(mc, &state, |mc, state, args| {
+            let formats = args.get(1..).unwrap_or(&[]).to_vec();
+            let iterator = match args.get(0).cloned().unwrap_or(Value::Nil) {
+                Value::Nil => lines_iterator(mc, state.default_file("input")?, formats, false),
+                path => {
+                    let path = string_arg(path)?;
+                    let file = state
+                        .open(mc, &path, OpenMode::parse(b"r").unwrap())
+                        .map_err(|err| {
+                            runtime_error(
+                                mc,
+                                &format!(
+                                    "{}: {}",
+                                    std::string::String::from_utf8_lossy(&path),
+                                    err
+                                ),
+                            )
+                        })?;
+                    lines_iterator(mc, file, formats, true)
+                }
+            };
+            Ok(CallbackResult::Return(vec![iterator.into()]))
+        }),
+    )
+    .unwrap();
+
+    io.set(
+        mc,
+        String::new_static(b"read"),
+        io_callback(mc, &state, |mc, state, args| {
+            read_file(mc, state.default_file("input")?, &args)
+        }),
+    )
+    .unwrap();
+
+    io.set(
+        mc,
+        String::new_static(b"write"),
+        io_callback(mc, &state, |mc, state, args| {
+            write_file(mc, state.default_file("output")?, &args)
+        }),
+    )
+    .unwrap();
+
+    io.set(
+        mc,
+        String::new_static(b"type"),
+        Callback::new_immediate(mc, |args| {
+            Ok(CallbackResult::Return(vec![
+                match args.get(0).cloned().unwrap_or(Value::Nil) {
+                    Value::UserData(file) if file.is_static::<FileHandle>() => {
+                        if is_closed(file) {
+                            Value::String(String::new_static(b"closed file"))
+                        } else {
+                            Value::String(String::new_static(b"file"))
+                        }
+                    }
+                    _ => Value::Nil,
+                },
+            ]))
+        }),
+    )
+    .unwrap();
+
+    env.set(mc, String::new_static(b"io"), io).unwrap();
+}
+
+// Creates a callback with access to the `io` library state and a `MutationContext`.
+fn io_callback<'gc, F>(mc: MutationContext<'gc, '_>, state: &IoState<'gc>, f: F) -> Callback<'gc>
+where
+    F: 'static
+        + Copy
+        + Fn(
+            MutationContext<'gc, '_>,
+            &IoState<'gc>,
+            Vec<Value<'gc>>,
+        ) -> Result<CallbackResult<'gc>, Error<'gc>>,
+{
+    Callback::new_sequence_with(mc, state.clone(), move |state, args| {
+        Ok(sequence::from_fn_with(
+            (state.clone(), args),
+            move |mc, (state, args)| f(mc, &state, args),
+        ))
+    })
+}
+
+fn file_arg<'gc>(value: Value<'gc>) -> Result<UserData<'gc>, TypeError> {
+    match value {
+        Value::UserData(file) if file.is_static::<FileHandle>() => Ok(file),
+        value => Err(TypeError {
+            expected: "FILE*",
+            found: value.type_name(),
+        }),
+    }
+}
+
+fn is_closed<'gc>(file: UserData<'gc>) -> bool {
+    file.read_static::<FileHandle>().unwrap().file.is_none()
+}
+
+fn check_open<'gc, 'a>(
+    mc: MutationContext<'gc, '_>,
+    file: &'a UserData<'gc>,
+) -> Result<RefMut<'a, FileHandle>, Error<'gc>> {
+    let handle = file.write_static::<FileHandle>(mc).unwrap();
+    if handle.file.is_none() {
+        return Err(RuntimeError(Value::String(String::new_static(
+            b"attempt to use a closed file",
+        )))
+        .into());
+    }
+    Ok(handle)
+}
+
+fn close_file<'gc>(
+    mc: MutationContext<'gc, '_>,
+    file: UserData<'gc>,
+) -> Result<CallbackResult<'gc>, Error<'gc>> {
+    let mut handle = check_open(mc, &file)?;
+    if handle.standard {
+        return Ok(CallbackResult::Return(vec![
+            Value::Nil,
+            Value::String(String::new_static(b"cannot close standard file")),
+        ]));
+    }
+    let res = handle.file.take().unwrap().flush();
+    Ok(file_result(mc, res.map(|()| Value::Boolean(true))))
+}
+
+// Implements `io.read` and `file:read`, returning a value for each format up to the first which
+// fails.
+fn read_file<'gc>(
+    mc: MutationContext<'gc, '_>,
+    file: UserData<'gc>,
+    formats: &[Value<'gc>],
+) -> Result<CallbackResult<'gc>, Error<'gc>> {
+    let invalid_format = |i: usize| {
+        runtime_error(
+            mc,
+            &format!("bad argument #{} to 'read' (invalid format)", i + 1),
+        )
+    };
+
+    let mut handle = check_open(mc, &file)?;
+    let default_formats = [Value::String(String::new_static(b"l"))];
+    let formats = if formats.is_empty() {
+        &default_formats[..]
+    } else {
+        formats
+    };
+
+    let to_value = |s: Option<Vec<u8>>| s.map(|s| Value::String(String::new(mc, &s)));
+    let mut res = Vec::new();
+    for (i, &format) in formats.iter().enumerate() {
+        let value = match format {
+            Value::Integer(_) | Value::Number(_) => match integer_arg(format, None)? {
+                n if n >= 0 => handle.read_chars(n as usize).map(to_value),
+                _ => return Err(invalid_format(i)),
+            },
+            Value::String(format) => {
+                let format = format.as_bytes();
+                let format = if format.first() == Some(&b'*') {
+                    &format[1..]
+                } else {
+                    format
+                };
+                match format.first() {
+                    Some(b'n') => handle.read_number(),
+                    Some(b'l') => handle.read_line(false).map(to_value),
+                    Some(b'L') => handle.read_line(true).map(to_value),
+                    Some(b'a') => handle.read_all().map(|s| to_value(Some(s))),
+                    _ => return Err(invalid_format(i)),
+                }
+            }
+            _ => return Err(invalid_format(i)),
+        };
+
+        match value {
+            Ok(Some(value)) => res.push(value),
+            Ok(None) => {
+                res.push(Value::Nil);
+                break;
+            }
+            Err(err) => return Ok(file_result(mc, Err(err))),
+        }
+    }
+    Ok(CallbackResult::Return(res))
+}
+
+// Implements `io.write` and `file:write`, returning the file on success.
+fn write_file<'gc>(
+    mc: MutationContext<'gc, '_>,
+    file: UserData<'gc>,
+    args: &[Value<'gc>],
+) -> Result<CallbackResult<'gc>, Error<'gc>> {
+    let mut handle = check_open(mc, &file)?;
+    let mut res = handle.sync();
+    for (i, &arg) in args.iter().enumerate() {
+        if res.is_err() {
+            break;
+        }
+        let mut bytes = Vec::new();
+        match arg {
+            Value::String(s) => bytes.extend_from_slice(s.as_bytes()),
+            Value::Integer(_) | Value::Number(_) => arg.display(&mut bytes).unwrap(),
+            arg => {
+                return Err(runtime_error(
+                    mc,
+                    &format!(
+                        "bad argument #{} to 'write' (string expected, got {})",
+                        i + 1,
+                        arg.type_name()
+                    ),
+                ));
+            }
+        }
+        res = handle.file().write(&bytes);
+    }
+    Ok(file_result(mc, res.map(|()| Value::UserData(file))))
+}
+
+// Creates the iterator returned by `io.lines` and `file:lines`, which reads from the file with the
+// given formats on each call and optionally closes the file once it reaches the end.
+fn lines_iterator<'gc>(
+    mc: MutationContext<'gc, '_>,
+    file: UserData<'gc>,
+    formats: Vec<Value<'gc>>,
+    close: bool,
+) -> Callback<'gc> {
+    Callback::new_sequence_with(mc, (file, formats, close), |state, _| {
+        Ok(sequence::from_fn_with(
+            state.clone(),
+            |mc, (file, formats, close)| {
+                if is_closed(file) {
+                    return Err(RuntimeError(Value::String(String::new_static(
+                        b"file is already closed",
+                    )))
+                    .into());
+                }
+                let res = match read_file(mc, file, &formats)? {
+                    CallbackResult::Return(res) => res,
+                    _ => unreachable!(),
+                };
+                match res.get(0) {
+                    Some(value) if value.to_bool() => Ok(CallbackResult::Return(res)),
+                    // A failed read returns an error message after the initial nil
+                    _ if res.len() > 1 => Err(RuntimeError(res[1]).into()),
+                    _ => {
+                        if close {
+                            close_file(mc, file)?;
+                        }
+                        Ok(CallbackResult::Return(vec![]))
+                    }
+                }
+            },
+        ))
+    })
+}
+
+// Converts the result of an I/O operation into either the given value or the triple of nil, an
+// error message and an error code, like `luaL_fileresult`.
+fn file_result<'gc>(
+    mc: MutationContext<'gc, '_>,
+    res: Result<Value<'gc>, io::Error>,
+) -> CallbackResult<'gc> {
+    CallbackResult::Return(match res {
+        Ok(value) => vec![value],
+        Err(err) => vec![
+            Value::Nil,
+            Value::String(String::new(mc, err.to_string().as_bytes())),
+            Value::Integer(err.raw_os_error().unwrap_or(0) as i64),
+        ],
+    })
+}
+
+// Like `file_result` for an error, but with the error message prefixed by a file path.
+fn path_result<'gc>(
+    mc: MutationContext<'gc, '_>,
+    path: &[u8],
+    err: io::Error,
+) -> CallbackResult<'gc> {
+    let mut msg = path.to_vec();
+    msg.extend_from_slice(format!(": {}", err).as_bytes());
+    CallbackResult::Return(vec![
+        Value::Nil,
+        Value::String(String::new(mc, &msg)),
+        Value::Integer(err.raw_os_error().unwrap_or(0) as i64),
+    ])
+}
@@ -1,7 +1,24 @@
 mod base;
 mod coroutine;
+mod debug;
+mod io;
 mod math;
+mod os;
+mod pack;
+mod package;
+mod pattern;
+mod string;
+mod table;
+mod utf8;
 
 pub use base::load_base;
 pub use coroutine::load_coroutine;
+pub use debug::load_debug;
+pub use io::{load_io, IoFile, IoHost, MemoryIoHost, OpenMode, StdIoHost};
 pub use math::load_math;
+pub use os::{load_os, OsHost, SandboxOsHost, StdOsHost};
+pub use pack::PackError;
+pub use package::{load_package, lua_searcher};
+pub use string::load_string;
+pub use table::load_table;
+pub use utf8::load_utf8;
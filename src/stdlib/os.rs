@@ -0,0 +1,703 @@
+use std::convert::TryFrom;
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::path::PathBuf;
+use std::process;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use gc_arena::{MutationContext, StaticCollect};
+use gc_sequence as sequence;
+
+use crate::{Callback, CallbackResult, Error, Root, RuntimeError, String, Table, TypeError, Value};
+
+use super::string::{integer_arg, runtime_error, string_arg};
+
+/// The interface through which the `os` library accesses the host system.
+///
+/// Embedders can provide their own implementation to supply a fake clock or environment, or to
+/// disable access to the filesystem entirely.
+pub trait OsHost {
+    /// The current time, in seconds since the Unix epoch.
+    fn time(&self) -> i64;
+
+    /// An approximation of the processor time used by the program, in seconds.
+    fn clock(&self) -> f64;
+
+    /// The offset of local time from UTC at the given time, in seconds.
+    fn utc_offset(&self, _time: i64) -> i64 {
+        0
+    }
+
+    /// Returns the value of the given environment variable, if it is set.
+    fn getenv(&self, name: &[u8]) -> Option<Vec<u8>>;
+
+    /// Creates a new empty temporary file and returns its name.
+    fn tmpname(&self) -> Result<Vec<u8>, io::Error>;
+
+    /// Deletes the given file or empty directory.
+    fn remove(&self, path: &[u8]) -> Result<(), io::Error>;
+
+    /// Renames the file or directory `from` to `to`.
+    fn rename(&self, from: &[u8], to: &[u8]) -> Result<(), io::Error>;
+
+    /// Exits the host program with the given exit code.  Hosts which do not allow exiting should
+    /// return an error, which is raised as a Lua error.
+    fn exit(&self, code: i32) -> Result<(), io::Error>;
+}
+
+/// An `OsHost` which uses the real system clock, environment and filesystem.
+///
+/// Local time is treated as UTC, and `clock` measures the wall-clock time since the host was
+/// created, as `std` has no portable way to query either the local timezone or processor time.
+pub struct StdOsHost {
+    start: Instant,
+}
+
+impl StdOsHost {
+    pub fn new() -> StdOsHost {
+        StdOsHost {
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Default for StdOsHost {
+    fn default() -> StdOsHost {
+        StdOsHost::new()
+    }
+}
+
+impl OsHost for StdOsHost {
+    fn time(&self) -> i64 {
+        system_time()
+    }
+
+    fn clock(&self) -> f64 {
+        self.start.elapsed().as_secs_f64()
+    }
+
+    fn getenv(&self, name: &[u8]) -> Option<Vec<u8>> {
+        let name = std::str::from_utf8(name).ok()?;
+        std::env::var_os(name).map(|v| path_to_bytes(v.into()))
+    }
+
+    fn tmpname(&self) -> Result<Vec<u8>, io::Error> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        loop {
+            let mut path = std::env::temp_dir();
+            path.push(format!(
+                "lua_{}_{}",
+                process::id(),
+                COUNTER.fetch_add(1, Ordering::Relaxed)
+            ));
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(_) => return Ok(path_to_bytes(path)),
+                Err(ref err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    fn remove(&self, path: &[u8]) -> Result<(), io::Error> {
+        let path = bytes_to_path(path);
+        if fs::metadata(&path)?.is_dir() {
+            fs::remove_dir(path)
+        } else {
+            fs::remove_file(path)
+        }
+    }
+
+    fn rename(&self, from: &[u8], to: &[u8]) -> Result<(), io::Error> {
+        fs::rename(bytes_to_path(from), bytes_to_path(to))
+    }
+
+    fn exit(&self, code: i32) -> Result<(), io::Error> {
+        process::exit(code)
+    }
+}
+
+/// An `OsHost` for sandboxed Lua instances, which has access to the system clock but no access to
+/// the environment or filesystem, and which does not allow exiting.
+pub struct SandboxOsHost {
+    start: Instant,
+}
+
+impl SandboxOsHost {
+    pub fn new() -> SandboxOsHost {
+        SandboxOsHost {
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Default for SandboxOsHost {
+    fn default() -> SandboxOsHost {
+        SandboxOsHost::new()
+    }
+}
+
+impl OsHost for SandboxOsHost {
+    fn time(&self) -> i64 {
+        system_time()
+    }
+
+    fn clock(&self) -> f64 {
+        self.start.elapsed().as_secs_f64()
+    }
+
+    fn getenv(&self, _name: &[u8]) -> Option<Vec<u8>> {
+        None
+    }
+
+    fn tmpname(&self) -> Result<Vec<u8>, io::Error> {
+        Err(sandbox_error())
+    }
+
+    fn remove(&self, _path: &[u8]) -> Result<(), io::Error> {
+        Err(sandbox_error())
+    }
+
+    fn rename(&self, _from: &[u8], _to: &[u8]) -> Result<(), io::Error> {
+        Err(sandbox_error())
+    }
+
+    fn exit(&self, _code: i32) -> Result<(), io::Error> {
+        Err(sandbox_error())
+    }
+}
+
+pub fn load_os<'gc>(
+    mc: MutationContext<'gc, '_>,
+    _: Root<'gc>,
+    env: Table<'gc>,
+    host: Rc<dyn OsHost>,
+) {
+    let os = Table::new(mc);
+
+    os.set(
+        mc,
+        String::new_static(b"time"),
+        os_callback(mc, &host, |mc, host, args| {
+            let time = match args.get(0).cloned().unwrap_or(Value::Nil) {
+                Value::Nil => host.time(),
+                Value::Table(table) => date_table_time(mc, host, table)?,
+                value => {
+                    return Err(TypeError {
+                        expected: "table",
+                        found: value.type_name(),
+                    }
+                    .into());
+                }
+            };
+            Ok(CallbackResult::Return(vec![Value::Integer(time)]))
+        }),
+    )
+    .unwrap();
+
+    os.set(
+        mc,
+        String::new_static(b"clock"),
+        os_callback(mc, &host, |_, host, _| {
+            Ok(CallbackResult::Return(vec![Value::Number(host.clock())]))
+        }),
+    )
+    .unwrap();
+
+    os.set(
+        mc,
+        String::new_static(b"date"),
+        os_callback(mc, &host, |mc, host, args| {
+            let format = match args.get(0).cloned().unwrap_or(Value::Nil) {
+                Value::Nil => b"%c".to_vec(),
+                format => string_arg(format)?,
+            };
+            let time = match args.get(1).cloned().unwrap_or(Value::Nil) {
+                Value::Nil => host.time(),
+                time => integer_arg(time, None)?,
+            };
+
+            let (utc, format) = match format.split_first() {
+                Some((b'!', rest)) => (true, rest),
+                _ => (false, &format[..]),
+            };
+            let offset = if utc { 0 } else { host.utc_offset(time) };
+            let date = time
+                .checked_add(offset)
+                .map(Date::from_time)
+                .ok_or_else(|| runtime_error(mc, "time out-of-bounds"))?;
+
+            if format.starts_with(b"*t") {
+                let table = Table::new(mc);
+                date.set_fields(mc, table)?;
+                Ok(CallbackResult::Return(vec![Value::Table(table)]))
+            } else {
+                let res = date
+                    .format(format, utc, offset)
+                    .map_err(|err| runtime_error(mc, &err))?;
+                Ok(CallbackResult::Return(vec![Value::String(String::new(
+                    mc, &res,
+                ))]))
+            }
+        }),
+    )
+    .unwrap();
+
+    os.set(
+        mc,
+        String::new_static(b"difftime"),
+        Callback::new_immediate(mc, |args| {
+            let t2 = integer_arg(args.get(0).cloned().unwrap_or(Value::Nil), None)?;
+            let t1 = integer_arg(args.get(1).cloned().unwrap_or(Value::Nil), Some(0))?;
+            Ok(CallbackResult::Return(vec![Value::Number(
+                t2 as f64 - t1 as f64,
+            )]))
+        }),
+    )
+    .unwrap();
+
+    os.set(
+        mc,
+        String::new_static(b"getenv"),
+        os_callback(mc, &host, |mc, host, args| {
+            let name = string_arg(args.get(0).cloned().unwrap_or(Value::Nil))?;
+            Ok(CallbackResult::Return(vec![match host.getenv(&name) {
+                Some(value) => Value::String(String::new(mc, &value)),
+                None => Value::Nil,
+            }]))
+        }),
+    )
+    .unwrap();
+
+    os.set(
+        mc,
+        String::new_static(b"tmpname"),
+        os_callback(mc, &host, |mc, host, _| match host.tmpname() {
+            Ok(name) => Ok(CallbackResult::Return(vec![Value::String(String::new(
+                mc, &name,
+            ))])),
+            Err(_) => Err(RuntimeError(Value::String(String::new_static(
+                b"unable to generate a unique filename",
+            )))
+            .into()),
+        }),
+    )
+    .unwrap();
+
+    os.set(
+        mc,
+        String::new_static(b"remove"),
+        os_callback(mc, &host, |mc, host, args| {
+            let path = string_arg(args.get(0).cloned().unwrap_or(Value::Nil))?;
+            Ok(file_result(mc, &path, host.remove(&path)))
+        }),
+    )
+    .unwrap();
+
+    os.set(
+        mc,
+        String::new_static(b"rename"),
+        os_callback(mc, &host, |mc, host, args| {
+            let from = string_arg(args.get(0).cloned().unwrap_or(Value::Nil))?;
+            let to = string_arg(args.get(1).cloned().unwrap_or(Value::Nil))?;
+            Ok(file_result(mc, &from, host.rename(&from, &to)))
+        }),
+    )
+    .unwrap();
+
+    os.set(
+        mc,
+        String::new_static(b"exit"),
+        os_callback(mc, &host, |mc, host, args| {
+            let code = match args.get(0).cloned().unwrap_or(Value::Nil) {
+                Value::Nil | Value::Boolean(true) => 0,
+                Value::Boolean(false) => 1,
+                code => integer_arg(code, None)? as i32,
+            };
+            match host.exit(code) {
+                Ok(()) => Ok(CallbackResult::Return(vec![])),
+                Err(err) => Err(runtime_error(mc, &err.to_string())),
+            }
+        }),
+    )
+    .unwrap();
+
+    env.set(mc, String::new_static(b"os"), os).unwrap();
+}
+
+// Creates a callback with access to the `OsHost` and a `MutationContext`.
+fn os_callback<'gc, F>(mc: MutationContext<'gc, '_>, host: &Rc<dyn OsHost>, f: F) -> Callback<'gc>
+where
+    F: 'static
+        + Copy
+        + Fn(
+            MutationContext<'gc, '_>,
+            &dyn OsHost,
+            Vec<Value<'gc>>,
+        ) -> Result<CallbackResult<'gc>, Error<'gc>>,
+{
+    let host = host.clone();
+    Callback::new_sequence(mc, move |args| {
+        Ok(sequence::from_fn_with(
+            (StaticCollect(host.clone()), args),
+            move |mc, (host, args)| f(mc, &*host.0, args),
+        ))
+    })
+}
+
+// Returns `true` on success, or nil, an error message and an error code on failure, like the
+// results of `luaL_fileresult`.
+fn file_result<'gc>(
+    mc: MutationContext<'gc, '_>,
+    path: &[u8],
+    res: Result<(), io::Error>,
+) -> CallbackResult<'gc> {
+    CallbackResult::Return(match res {
+        Ok(()) => vec![Value::Boolean(true)],
+        Err(err) => {
+            let mut msg = path.to_vec();
+            msg.extend_from_slice(format!(": {}", err).as_bytes());
+            vec![
+                Value::Nil,
+                Value::String(String::new(mc, &msg)),
+                Value::Integer(err.raw_os_error().unwrap_or(0) as i64),
+            ]
+        }
+    })
+}
+
+fn system_time() -> i64 {
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_secs() as i64,
+        Err(err) => -(err.duration().as_secs() as i64),
+    }
+}
+
+fn sandbox_error() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::PermissionDenied,
+        "operation not permitted in sandbox",
+    )
+}
+
+#[cfg(unix)]
+fn path_to_bytes(path: PathBuf) -> Vec<u8> {
+    use std::os::unix::ffi::OsStringExt;
+    path.into_os_string().into_vec()
+}
+
+#[cfg(not(unix))]
+fn path_to_bytes(path: PathBuf) -> Vec<u8> {
+    path.to_string_lossy().into_owned().into_bytes()
+}
+
+#[cfg(unix)]
+pub(super) fn bytes_to_path(bytes: &[u8]) -> PathBuf {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+    PathBuf::from(OsStr::from_bytes(bytes))
+}
+
+#[cfg(not(unix))]
+pub(super) fn bytes_to_path(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(std::string::String::from_utf8_lossy(bytes).into_owned())
+}
+
+// Converts the fields of a date table as accepted by `os.time` into a time, normalizing the fields
+// of the table like C's `mktime`.
+fn date_table_time<'gc>(
+    mc: MutationContext<'gc, '_>,
+    host: &dyn OsHost,
+    table: Table<'gc>,
+) -> Result<i64, Error<'gc>> {
+    let field = |name: &'static str, default: Option<i64>| -> Result<i64, Error<'gc>> {
+        match table.get(String::new_static(name.as_bytes())) {
+            Value::Nil => default.ok_or_else(|| {
+                runtime_error(mc, &format!("field '{}' missing in date table", name))
+            }),
+            value => {
+                let v = value.to_integer().ok_or_else(|| {
+                    runtime_error(mc, &format!("field '{}' is not an integer", name))
+                })?;
+                i32::try_from(v)
+                    .map(i64::from)
+                    .map_err(|_| runtime_error(mc, &format!("field '{}' is out-of-bound", name)))
+            }
+        }
+    };
+
+    let year = field("year", None)?;
+    let month = field("month", None)?;
+    let day = field("day", None)?;
+    let hour = field("hour", Some(12))?;
+    let min = field("min", Some(0))?;
+    let sec = field("sec", Some(0))?;
+
+    let days = days_from_civil(
+        year + (month - 1).div_euclid(12),
+        (month - 1).rem_euclid(12) + 1,
+        1,
+    ) + (day - 1);
+    let local = days * 86400 + hour * 3600 + min * 60 + sec;
+    let time = local - host.utc_offset(local);
+
+    Date::from_time(local).set_fields(mc, table)?;
+    Ok(time)
+}
+
+// Returns the number of days since the Unix epoch of the given date in the proleptic Gregorian
+// calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+// The inverse of `days_from_civil`, returns the year, month and day.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+fn is_leap_year(year: i64) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+const WEEKDAYS: [&str; 7] = [
+    "Sunday",
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+];
+
+const MONTHS: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+// A broken down calendar time, like C's `struct tm`.
+struct Date {
+    year: i64,
+    // 1 - 12
+    month: i64,
+    // 1 - 31
+    day: i64,
+    hour: i64,
+    min: i64,
+    sec: i64,
+    // 0 - 6, Sunday is 0
+    wday: i64,
+    // 1 - 366
+    yday: i64,
+}
+
+impl Date {
+    fn from_time(time: i64) -> Date {
+        let days = time.div_euclid(86400);
+        let secs = time.rem_euclid(86400);
+        let (year, month, day) = civil_from_days(days);
+        Date {
+            year,
+            month,
+            day,
+            hour: secs / 3600,
+            min: secs / 60 % 60,
+            sec: secs % 60,
+            wday: (days + 4).rem_euclid(7),
+            yday: days - days_from_civil(year, 1, 1) + 1,
+        }
+    }
+
+    fn set_fields<'gc>(
+        &self,
+        mc: MutationContext<'gc, '_>,
+        table: Table<'gc>,
+    ) -> Result<(), Error<'gc>> {
+        table.set(mc, String::new_static(b"year"), self.year)?;
+        table.set(mc, String::new_static(b"month"), self.month)?;
+        table.set(mc, String::new_static(b"day"), self.day)?;
+        table.set(mc, String::new_static(b"hour"), self.hour)?;
+        table.set(mc, String::new_static(b"min"), self.min)?;
+        table.set(mc, String::new_static(b"sec"), self.sec)?;
+        table.set(mc, String::new_static(b"wday"), self.wday + 1)?;
+        table.set(mc, String::new_static(b"yday"), self.yday)?;
+        table.set(mc, String::new_static(b"isdst"), false)?;
+        Ok(())
+    }
+
+    // Returns the ISO 8601 week-based year and week number.
+    fn iso_week(&self) -> (i64, i64) {
+        // Monday is 0
+        let iso_wday = (self.wday + 6) % 7;
+        let week = (self.yday - 1 - iso_wday + 10) / 7;
+        if week < 1 {
+            let prev = self.year - 1;
+            let prev_days = if is_leap_year(prev) { 366 } else { 365 };
+            let prev_yday = self.yday + prev_days;
+            (prev, (prev_yday - 1 - iso_wday + 10) / 7)
+        } else {
+            let days = if is_leap_year(self.year) { 366 } else { 365 };
+            if week == 53 && self.yday - iso_wday > days - 3 {
+                (self.year + 1, 1)
+            } else {
+                (self.year, week)
+            }
+        }
+    }
+
+    // Formats the date according to the given `strftime` style format string in the "C" locale.
+    fn format(
+        &self,
+        format: &[u8],
+        utc: bool,
+        offset: i64,
+    ) -> Result<Vec<u8>, std::string::String> {
+        let mut out = Vec::new();
+        let mut i = 0;
+        while i < format.len() {
+            let c = format[i];
+            i += 1;
+            if c != b'%' {
+                out.push(c);
+                continue;
+            }
+
+            let mut conversion = format.get(i).cloned();
+            let valid = match conversion {
+                Some(b'E') => match format.get(i + 1) {
+                    Some(b'c') | Some(b'C') | Some(b'x') | Some(b'X') | Some(b'y') | Some(b'Y') => {
+                        true
+                    }
+                    _ => false,
+                },
+                Some(b'O') => match format.get(i + 1) {
+                    Some(b'd') | Some(b'e') | Some(b'H') | Some(b'I') | Some(b'm') | Some(b'M')
+                    | Some(b'S') | Some(b'u') | Some(b'U') | Some(b'V') | Some(b'w')
+                    | Some(b'W') | Some(b'y') => true,
+                    _ => false,
+                },
+                Some(c) => b"aAbBcCdDeFgGhHIjmMnprRStTuUVwWxXyYzZ%".contains(&c),
+                None => false,
+            };
+            if !valid {
+                let end = (i + 2).min(format.len());
+                return Err(format!(
+                    "bad argument #1 to 'date' (invalid conversion specifier '%{}')",
+                    std::string::String::from_utf8_lossy(&format[i..end])
+                ));
+            }
+            if conversion == Some(b'E') || conversion == Some(b'O') {
+                // Modifiers have no effect in the "C" locale
+                i += 1;
+                conversion = format.get(i).cloned();
+            }
+            i += 1;
+
+            self.format_conversion(&mut out, conversion.unwrap(), utc, offset);
+        }
+        Ok(out)
+    }
+
+    fn format_conversion(&self, out: &mut Vec<u8>, conversion: u8, utc: bool, offset: i64) {
+        use std::io::Write;
+
+        let hour12 = if self.hour % 12 == 0 {
+            12
+        } else {
+            self.hour % 12
+        };
+        let weekday = WEEKDAYS[self.wday as usize];
+        let month = MONTHS[(self.month - 1) as usize];
+        match conversion {
+            b'a' => out.extend_from_slice(&weekday.as_bytes()[0..3]),
+            b'A' => out.extend_from_slice(weekday.as_bytes()),
+            b'b' | b'h' => out.extend_from_slice(&month.as_bytes()[0..3]),
+            b'B' => out.extend_from_slice(month.as_bytes()),
+            b'c' => {
+                self.format_conversion(out, b'a', utc, offset);
+                out.push(b' ');
+                self.format_conversion(out, b'b', utc, offset);
+                write!(out, " {:2} ", self.day).unwrap();
+                self.format_conversion(out, b'T', utc, offset);
+                write!(out, " {}", self.year).unwrap();
+            }
+            b'C' => write!(out, "{:02}", self.year.div_euclid(100)).unwrap(),
+            b'd' => write!(out, "{:02}", self.day).unwrap(),
+            b'D' => write!(
+                out,
+                "{:02}/{:02}/{:02}",
+                self.month,
+                self.day,
+                self.year.rem_euclid(100)
+            )
+            .unwrap(),
+            b'e' => write!(out, "{:2}", self.day).unwrap(),
+            b'F' => write!(out, "{}-{:02}-{:02}", self.year, self.month, self.day).unwrap(),
+            b'g' => write!(out, "{:02}", self.iso_week().0.rem_euclid(100)).unwrap(),
+            b'G' => write!(out, "{}", self.iso_week().0).unwrap(),
+            b'H' => write!(out, "{:02}", self.hour).unwrap(),
+            b'I' => write!(out, "{:02}", hour12).unwrap(),
+            b'j' => write!(out, "{:03}", self.yday).unwrap(),
+            b'm' => write!(out, "{:02}", self.month).unwrap(),
+            b'M' => write!(out, "{:02}", self.min).unwrap(),
+            b'n' => out.push(b'\n'),
+            b'p' => out.extend_from_slice(if self.hour < 12 { b"AM" } else { b"PM" }),
+            b'r' => {
+                write!(out, "{:02}:{:02}:{:02} ", hour12, self.min, self.sec).unwrap();
+                self.format_conversion(out, b'p', utc, offset);
+            }
+            b'R' => write!(out, "{:02}:{:02}", self.hour, self.min).unwrap(),
+            b'S' => write!(out, "{:02}", self.sec).unwrap(),
+            b't' => out.push(b'\t'),
+            b'T' | b'X' => write!(out, "{:02}:{:02}:{:02}", self.hour, self.min, self.sec).unwrap(),
+            b'u' => write!(out, "{}", (self.wday + 6) % 7 + 1).unwrap(),
+            b'U' => write!(out, "{:02}", (self.yday - 1 + 7 - self.wday) / 7).unwrap(),
+            b'V' => write!(out, "{:02}", self.iso_week().1).unwrap(),
+            b'w' => write!(out, "{}", self.wday).unwrap(),
+            b'W' => write!(out, "{:02}", (self.yday - 1 + 7 - (self.wday + 6) % 7) / 7).unwrap(),
+            b'x' => self.format_conversion(out, b'D', utc, offset),
+            b'y' => write!(out, "{:02}", self.year.rem_euclid(100)).unwrap(),
+            b'Y' => write!(out, "{}", self.year).unwrap(),
+            b'z' => {
+                let sign = if offset < 0 { '-' } else { '+' };
+                let offset = offset.unsigned_abs() / 60;
+                write!(out, "{}{:02}{:02}", sign, offset / 60, offset % 60).unwrap();
+            }
+            b'Z' => {
+                if utc || offset == 0 {
+                    out.extend_from_slice(b"UTC");
+                } else {
+                    self.format_conversion(out, b'z', utc, offset);
+                }
+            }
+            b'%' => out.push(b'%'),
+            _ => unreachable!(),
+        }
+    }
+}
@@ -0,0 +1,457 @@
+use std::error::Error as StdError;
+use std::fmt;
+
+use gc_arena::{Collect, MutationContext};
+
+use crate::{Error, String, TypeError, Value};
+
+use super::string::string_arg;
+
+// Sizes of the native C types that the `string.pack` format language refers to.
+const SIZE_INT: usize = 4;
+const SIZE_LONG: usize = 8;
+const SIZE_LUA_INTEGER: usize = 8;
+const SIZE_SIZE_T: usize = 8;
+const NATIVE_ALIGN: usize = 8;
+
+// The maximum size of an integer in a format string.
+const MAX_INTEGER_SIZE: usize = 16;
+
+// The maximum size of a packed result or an explicit size in a format string.
+const MAX_SIZE: usize = i32::MAX as usize;
+
+#[derive(Debug, Clone, Copy, Collect)]
+#[collect(require_static)]
+pub enum PackError {
+    InvalidOption(u8),
+    MissingSize(u8),
+    SizeOutOfLimits(usize),
+    AlignmentNotPowerOfTwo,
+    InvalidNextOption,
+    ResultTooLarge,
+    VariableSize,
+    IntegerOverflow,
+    StringLengthOverflow,
+    StringTooLong,
+    StringContainsZeros,
+    PositionOutOfString,
+    DataTooShort,
+    UnfinishedString,
+    IntegerDoesNotFit(usize),
+}
+
+impl StdError for PackError {}
+
+impl fmt::Display for PackError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PackError::InvalidOption(c) => {
+                write!(fmt, "invalid format option '{}'", char::from(*c))
+            }
+            PackError::MissingSize(c) => {
+                write!(fmt, "missing size for format option '{}'", char::from(*c))
+            }
+            PackError::SizeOutOfLimits(size) => write!(
+                fmt,
+                "integral size ({}) out of limits [1,{}]",
+                size, MAX_INTEGER_SIZE
+            ),
+            PackError::AlignmentNotPowerOfTwo => {
+                write!(fmt, "format asks for alignment not power of 2")
+            }
+            PackError::InvalidNextOption => write!(fmt, "invalid next option for option 'X'"),
+            PackError::ResultTooLarge => write!(fmt, "format result too large"),
+            PackError::VariableSize => write!(fmt, "variable-size format in packsize"),
+            PackError::IntegerOverflow => write!(fmt, "integer overflow"),
+            PackError::StringLengthOverflow => {
+                write!(fmt, "string length does not fit in given size")
+            }
+            PackError::StringTooLong => write!(fmt, "string longer than given size"),
+            PackError::StringContainsZeros => write!(fmt, "string contains zeros"),
+            PackError::PositionOutOfString => write!(fmt, "initial position out of string"),
+            PackError::DataTooShort => write!(fmt, "data string too short"),
+            PackError::UnfinishedString => write!(fmt, "unfinished string for format 'z'"),
+            PackError::IntegerDoesNotFit(size) => {
+                write!(fmt, "{}-byte integer does not fit into Lua Integer", size)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Item {
+    Int { signed: bool },
+    Float,
+    Double,
+    // A fixed size string
+    Char,
+    // A string preceded by its length
+    String,
+    // A zero terminated string
+    ZString,
+    Padding,
+    PaddingAlign,
+    Nop,
+}
+
+struct Format<'a> {
+    format: &'a [u8],
+    little_endian: bool,
+    max_align: usize,
+}
+
+impl<'a> Format<'a> {
+    fn new(format: &'a [u8]) -> Format<'a> {
+        Format {
+            format,
+            little_endian: cfg!(target_endian = "little"),
+            max_align: 1,
+        }
+    }
+
+    fn is_finished(&self) -> bool {
+        self.format.is_empty()
+    }
+
+    // Reads the next item in the format string along with its size, returning an error if the
+    // format string is malformed.
+    fn next_option(&mut self) -> Result<(Item, usize), PackError> {
+        let (&opt, rest) = self.format.split_first().unwrap();
+        self.format = rest;
+        Ok(match opt {
+            b'b' => (Item::Int { signed: true }, 1),
+            b'B' => (Item::Int { signed: false }, 1),
+            b'h' => (Item::Int { signed: true }, 2),
+            b'H' => (Item::Int { signed: false }, 2),
+            b'l' => (Item::Int { signed: true }, SIZE_LONG),
+            b'L' => (Item::Int { signed: false }, SIZE_LONG),
+            b'j' => (Item::Int { signed: true }, SIZE_LUA_INTEGER),
+            b'J' => (Item::Int { signed: false }, SIZE_LUA_INTEGER),
+            b'T' => (Item::Int { signed: false }, SIZE_SIZE_T),
+            b'f' => (Item::Float, 4),
+            b'd' | b'n' => (Item::Double, 8),
+            b'i' => (
+                Item::Int { signed: true },
+                self.read_limited_size(SIZE_INT)?,
+            ),
+            b'I' => (
+                Item::Int { signed: false },
+                self.read_limited_size(SIZE_INT)?,
+            ),
+            b's' => (Item::String, self.read_limited_size(SIZE_SIZE_T)?),
+            b'c' => match self.read_size() {
+                Some(size) => (Item::Char, size),
+                None => return Err(PackError::MissingSize(opt)),
+            },
+            b'z' => (Item::ZString, 0),
+            b'x' => (Item::Padding, 1),
+            b'X' => (Item::PaddingAlign, 0),
+            b' ' => (Item::Nop, 0),
+            b'<' => {
+                self.little_endian = true;
+                (Item::Nop, 0)
+            }
+            b'>' => {
+                self.little_endian = false;
+                (Item::Nop, 0)
+            }
+            b'=' => {
+                self.little_endian = cfg!(target_endian = "little");
+                (Item::Nop, 0)
+            }
+            b'!' => {
+                self.max_align = self.read_limited_size(NATIVE_ALIGN)?;
+                (Item::Nop, 0)
+            }
+            opt => return Err(PackError::InvalidOption(opt)),
+        })
+    }
+
+    // Reads the next item in the format string, returning the item, its size, and the amount of
+    // padding required to align it given the `total_size` of everything before it.
+    fn next_item(&mut self, total_size: usize) -> Result<(Item, usize, usize), PackError> {
+        let (item, size) = self.next_option()?;
+        let mut align = size;
+        if item == Item::PaddingAlign {
+            if self.is_finished() {
+                return Err(PackError::InvalidNextOption);
+            }
+            match self.next_option()? {
+                (Item::Char, _) | (_, 0) => return Err(PackError::InvalidNextOption),
+                (_, next_size) => align = next_size,
+            }
+        }
+
+        let padding = if align <= 1 || item == Item::Char {
+            0
+        } else {
+            let align = align.min(self.max_align);
+            if !align.is_power_of_two() {
+                return Err(PackError::AlignmentNotPowerOfTwo);
+            }
+            (align - (total_size & (align - 1))) & (align - 1)
+        };
+
+        Ok((item, size, padding))
+    }
+
+    fn read_size(&mut self) -> Option<usize> {
+        let digits = self
+            .format
+            .iter()
+            .take_while(|c| c.is_ascii_digit())
+            .count();
+        if digits == 0 {
+            return None;
+        }
+
+        let mut size: usize = 0;
+        let mut read = 0;
+        for &c in &self.format[0..digits] {
+            if size > (MAX_SIZE - 9) / 10 {
+                break;
+            }
+            size = size * 10 + (c - b'0') as usize;
+            read += 1;
+        }
+        self.format = &self.format[read..];
+        Some(size)
+    }
+
+    fn read_limited_size(&mut self, default: usize) -> Result<usize, PackError> {
+        match self.read_size() {
+            None => Ok(default),
+            Some(size) if size >= 1 && size <= MAX_INTEGER_SIZE => Ok(size),
+            Some(size) => Err(PackError::SizeOutOfLimits(size)),
+        }
+    }
+}
+
+/// Implements `string.pack`, serializing the given values according to the format string.
+pub fn pack<'gc>(format: &[u8], args: &[Value<'gc>]) -> Result<Vec<u8>, Error<'gc>> {
+    let mut format = Format::new(format);
+    let mut out = Vec::new();
+    let mut args = args.iter().cloned();
+    let mut next_arg = move || args.next().unwrap_or(Value::Nil);
+
+    while !format.is_finished() {
+        let (item, size, padding) = format.next_item(out.len())?;
+        out.resize(out.len() + padding, 0);
+
+        match item {
+            Item::Int { signed } => {
+                let arg = next_arg();
+                let n = arg.to_integer().ok_or(TypeError {
+                    expected: "integer",
+                    found: arg.type_name(),
+                })?;
+                if size < SIZE_LUA_INTEGER {
+                    let in_range = if signed {
+                        let limit = 1i64 << (size * 8 - 1);
+                        -limit <= n && n < limit
+                    } else {
+                        (n as u64) < 1u64 << (size * 8)
+                    };
+                    if !in_range {
+                        return Err(PackError::IntegerOverflow.into());
+                    }
+                }
+                pack_int(
+                    &mut out,
+                    n as u64,
+                    format.little_endian,
+                    size,
+                    signed && n < 0,
+                );
+            }
+            Item::Float => {
+                let n = number_arg(next_arg())? as f32;
+                pack_bytes(&mut out, &n.to_bits().to_le_bytes(), format.little_endian);
+            }
+            Item::Double => {
+                let n = number_arg(next_arg())?;
+                pack_bytes(&mut out, &n.to_bits().to_le_bytes(), format.little_endian);
+            }
+            Item::Char => {
+                let s = string_arg(next_arg())?;
+                if s.len() > size {
+                    return Err(PackError::StringTooLong.into());
+                }
+                out.extend_from_slice(&s);
+                out.resize(out.len() + size - s.len(), 0);
+            }
+            Item::String => {
+                let s = string_arg(next_arg())?;
+                if size < 8 && s.len() as u64 >= 1u64 << (size * 8) {
+                    return Err(PackError::StringLengthOverflow.into());
+                }
+                pack_int(&mut out, s.len() as u64, format.little_endian, size, false);
+                out.extend_from_slice(&s);
+            }
+            Item::ZString => {
+                let s = string_arg(next_arg())?;
+                if s.contains(&0) {
+                    return Err(PackError::StringContainsZeros.into());
+                }
+                out.extend_from_slice(&s);
+                out.push(0);
+            }
+            Item::Padding => out.push(0),
+            Item::PaddingAlign | Item::Nop => {}
+        }
+
+        if out.len() > MAX_SIZE {
+            return Err(PackError::ResultTooLarge.into());
+        }
+    }
+
+    Ok(out)
+}
+
+/// Implements `string.packsize`, returning the size of a string produced by `string.pack` with the
+/// given format, which may not contain variable-length options.
+pub fn pack_size(format: &[u8]) -> Result<usize, PackError> {
+    let mut format = Format::new(format);
+    let mut total_size: usize = 0;
+
+    while !format.is_finished() {
+        let (item, size, padding) = format.next_item(total_size)?;
+        if item == Item::String || item == Item::ZString {
+            return Err(PackError::VariableSize);
+        }
+        total_size = total_size
+            .checked_add(padding + size)
+            .filter(|&s| s <= MAX_SIZE)
+            .ok_or(PackError::ResultTooLarge)?;
+    }
+
+    Ok(total_size)
+}
+
+/// Implements `string.unpack`, deserializing values from `data` starting at the 0-based byte
+/// offset `pos`.  Returns the unpacked values followed by the 1-based position of the first unread
+/// byte.
+pub fn unpack<'gc>(
+    mc: MutationContext<'gc, '_>,
+    format: &[u8],
+    data: &[u8],
+    mut pos: usize,
+) -> Result<Vec<Value<'gc>>, PackError> {
+    if pos > data.len() {
+        return Err(PackError::PositionOutOfString);
+    }
+
+    let mut format = Format::new(format);
+    let mut results = Vec::new();
+
+    while !format.is_finished() {
+        let (item, size, padding) = format.next_item(pos)?;
+        if padding + size > data.len() - pos {
+            return Err(PackError::DataTooShort);
+        }
+        pos += padding;
+        let bytes = &data[pos..pos + size];
+
+        match item {
+            Item::Int { signed } => {
+                let n = unpack_int(bytes, format.little_endian, signed)?;
+                results.push(Value::Integer(n));
+            }
+            Item::Float => {
+                let mut buf = [0; 4];
+                unpack_bytes(&mut buf, bytes, format.little_endian);
+                results.push(Value::Number(f32::from_bits(u32::from_le_bytes(buf)) as f64));
+            }
+            Item::Double => {
+                let mut buf = [0; 8];
+                unpack_bytes(&mut buf, bytes, format.little_endian);
+                results.push(Value::Number(f64::from_bits(u64::from_le_bytes(buf))));
+            }
+            Item::Char => {
+                results.push(Value::String(String::new(mc, bytes)));
+            }
+            Item::String => {
+                let len = unpack_int(bytes, format.little_endian, false)? as u64;
+                if len > (data.len() - pos - size) as u64 {
+                    return Err(PackError::DataTooShort);
+                }
+                let start = pos + size;
+                let len = len as usize;
+                results.push(Value::String(String::new(mc, &data[start..start + len])));
+                pos += len;
+            }
+            Item::ZString => {
+                let len = data[pos..]
+                    .iter()
+                    .position(|&c| c == 0)
+                    .ok_or(PackError::UnfinishedString)?;
+                results.push(Value::String(String::new(mc, &data[pos..pos + len])));
+                pos += len + 1;
+            }
+            Item::Padding | Item::PaddingAlign | Item::Nop => {}
+        }
+
+        pos += size;
+    }
+
+    results.push(Value::Integer(pos as i64 + 1));
+    Ok(results)
+}
+
+// Appends the low `size` bytes of `n`, sign extending with 0xff bytes past 8 bytes if `negative` is
+// set.
+fn pack_int(out: &mut Vec<u8>, n: u64, little_endian: bool, size: usize, negative: bool) {
+    let mut bytes = [if negative { 0xff } else { 0 }; MAX_INTEGER_SIZE];
+    let len = size.min(8);
+    bytes[0..len].copy_from_slice(&n.to_le_bytes()[0..len]);
+    pack_bytes(out, &bytes[0..size], little_endian);
+}
+
+fn unpack_int(bytes: &[u8], little_endian: bool, signed: bool) -> Result<i64, PackError> {
+    let size = bytes.len();
+    let mut buf = [0; MAX_INTEGER_SIZE];
+    unpack_bytes(&mut buf[0..size], bytes, little_endian);
+
+    let len = size.min(8);
+    let mut le = [0; 8];
+    le[0..len].copy_from_slice(&buf[0..len]);
+    let mut n = u64::from_le_bytes(le);
+
+    if size < 8 {
+        if signed {
+            let mask = 1u64 << (size * 8 - 1);
+            n = (n ^ mask).wrapping_sub(mask);
+        }
+    } else if size > 8 {
+        let extension = if !signed || (n as i64) >= 0 { 0 } else { 0xff };
+        if buf[8..size].iter().any(|&b| b != extension) {
+            return Err(PackError::IntegerDoesNotFit(size));
+        }
+    }
+
+    Ok(n as i64)
+}
+
+// Appends little endian `bytes` to the output in the requested byte order.
+fn pack_bytes(out: &mut Vec<u8>, bytes: &[u8], little_endian: bool) {
+    if little_endian {
+        out.extend_from_slice(bytes);
+    } else {
+        out.extend(bytes.iter().rev());
+    }
+}
+
+// Reads `bytes` in the given byte order into `buf` in little endian order.
+fn unpack_bytes(buf: &mut [u8], bytes: &[u8], little_endian: bool) {
+    buf.copy_from_slice(bytes);
+    if !little_endian {
+        buf.reverse();
+    }
+}
+
+fn number_arg<'gc>(value: Value<'gc>) -> Result<f64, TypeError> {
+    value.to_number().ok_or(TypeError {
+        expected: "number",
+        found: value.type_name(),
+    })
+}
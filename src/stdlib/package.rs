@@ -0,0 +1,409 @@
+use std::rc::Rc;
+
+use gc_arena::{Collect, MutationContext, StaticCollect};
+use gc_sequence as sequence;
+
+use crate::{
+    Callback, CallbackResult, Continuation, Error, Function, IoHost, OpenMode, Root, RuntimeError,
+    String, Table, Value,
+};
+
+use super::base::LoadState;
+use super::string::{runtime_error, string_arg};
+
+// The default value of `package.path`.
+const DEFAULT_PATH: &[u8] = b"./?.lua;./?/init.lua";
+
+// Describes the path configuration, as in PUC-Rio Lua: the directory separator, the template
+// separator, the substitution point, the executable directory mark and the ignore mark.
+const CONFIG: &[u8] = b"/\n;\n?\n!\n-\n";
+
+// The libraries which are added to `package.loaded`, along with `_G`.
+const LIBRARIES: &[&[u8]] = &[
+    b"coroutine",
+    b"debug",
+    b"io",
+    b"math",
+    b"os",
+    b"package",
+    b"string",
+    b"table",
+    b"utf8",
+];
+
+/// Loads the `package` library and `require` into the given environment, using the `package`
+/// table from the given `Root`.
+///
+/// This must be loaded after all the other libraries, so that they can be added to
+/// `package.loaded`.
+pub fn load_package<'gc>(
+    mc: MutationContext<'gc, '_>,
+    root: Root<'gc>,
+    env: Table<'gc>,
+    io: Rc<dyn IoHost>,
+) {
+    let package = root.package;
+    let loaded = Table::new(mc);
+    let searchers = Table::new(mc);
+
+    package
+        .set(mc, String::new_static(b"loaded"), loaded)
+        .unwrap();
+    package
+        .set(mc, String::new_static(b"preload"), Table::new(mc))
+        .unwrap();
+    package
+        .set(
+            mc,
+            String::new_static(b"path"),
+            String::new_static(DEFAULT_PATH),
+        )
+        .unwrap();
+    package
+        .set(mc, String::new_static(b"cpath"), String::new_static(b""))
+        .unwrap();
+    package
+        .set(
+            mc,
+            String::new_static(b"config"),
+            String::new_static(CONFIG),
+        )
+        .unwrap();
+    package
+        .set(mc, String::new_static(b"searchers"), searchers)
+        .unwrap();
+
+    searchers
+        .set(
+            mc,
+            1,
+            Callback::new_sequence_with(mc, package, |&package, args| {
+                let name = string_arg(args.get(0).cloned().unwrap_or(Value::Nil))?;
+                let preload = match package.get(String::new_static(b"preload")) {
+                    Value::Table(preload) => preload,
+                    _ => {
+                        return Err(RuntimeError(Value::String(String::new_static(
+                            b"'package.preload' must be a table",
+                        )))
+                        .into());
+                    }
+                };
+                let loader = preload.get(args[0]);
+                Ok(sequence::from_fn_with(
+                    (name, loader),
+                    |mc, (name, loader)| {
+                        Ok(CallbackResult::Return(match loader {
+                            Value::Nil => {
+                                let mut msg = b"\n\tno field package.preload['".to_vec();
+                                msg.extend_from_slice(&name);
+                                msg.extend_from_slice(b"']");
+                                vec![Value::String(String::new(mc, &msg))]
+                            }
+                            loader => vec![loader, Value::String(String::new_static(b":preload:"))],
+                        }))
+                    },
+                ))
+            }),
+        )
+        .unwrap();
+    searchers
+        .set(mc, 2, lua_searcher(mc, root, env, io.clone()))
+        .unwrap();
+
+    package
+        .set(
+            mc,
+            String::new_static(b"searchpath"),
+            Callback::new_sequence_with(mc, StaticCollect(io), |io, args| {
+                let name = string_arg(args.get(0).cloned().unwrap_or(Value::Nil))?;
+                let path = string_arg(args.get(1).cloned().unwrap_or(Value::Nil))?;
+                let sep = match args.get(2).cloned().unwrap_or(Value::Nil) {
+                    Value::Nil => b".".to_vec(),
+                    sep => string_arg(sep)?,
+                };
+                let rep = match args.get(3).cloned().unwrap_or(Value::Nil) {
+                    Value::Nil => b"/".to_vec(),
+                    rep => string_arg(rep)?,
+                };
+                let res = search_path(&*io.0, &name, &path, &sep, &rep);
+                Ok(sequence::from_fn_with(res, |mc, res| {
+                    Ok(CallbackResult::Return(match res {
+                        Ok(file_name) => vec![Value::String(String::new(mc, &file_name))],
+                        Err(msg) => vec![Value::Nil, Value::String(String::new(mc, &msg))],
+                    }))
+                }))
+            }),
+        )
+        .unwrap();
+
+    env.set(
+        mc,
+        String::new_static(b"require"),
+        Callback::new_sequence_with(mc, package, |&package, args| {
+            Ok(sequence::from_fn_with(
+                (package, args),
+                |mc, (package, args)| require(mc, package, args),
+            ))
+        }),
+    )
+    .unwrap();
+
+    env.set(mc, String::new_static(b"package"), package)
+        .unwrap();
+
+    loaded.set(mc, String::new_static(b"_G"), env).unwrap();
+    for &name in LIBRARIES {
+        let name = String::new_static(name);
+        loaded.set(mc, name, env.get(name)).unwrap();
+    }
+}
+
+/// Creates a searcher for `package.searchers` which finds Lua modules using `package.path`, and
+/// loads them through the given `IoHost`.
+pub fn lua_searcher<'gc>(
+    mc: MutationContext<'gc, '_>,
+    root: Root<'gc>,
+    env: Table<'gc>,
+    io: Rc<dyn IoHost>,
+) -> Callback<'gc> {
+    Callback::new_sequence_with(
+        mc,
+        (root.package, LoadState::new(env, root.interned_strings, io)),
+        |(package, load_state), args| {
+            let name = string_arg(args.get(0).cloned().unwrap_or(Value::Nil))?;
+            let path = match package.get(String::new_static(b"path")) {
+                Value::String(path) => path.as_bytes().to_vec(),
+                _ => {
+                    return Err(RuntimeError(Value::String(String::new_static(
+                        b"'package.path' must be a string",
+                    )))
+                    .into());
+                }
+            };
+            let load_state = load_state.clone();
+            Ok(sequence::from_fn_with(
+                (load_state, name, path),
+                |mc, (load_state, name, path)| {
+                    let file_name = match search_path(&*load_state.io.0, &name, &path, b".", b"/") {
+                        Ok(file_name) => file_name,
+                        Err(msg) => {
+                            return Ok(CallbackResult::Return(vec![Value::String(String::new(
+                                mc, &msg,
+                            ))]));
+                        }
+                    };
+                    let globals = load_state.globals;
+                    match load_state.load_file(mc, Some(&file_name), b"bt".to_vec(), globals) {
+                        Ok(closure) => Ok(CallbackResult::Return(vec![
+                            Value::Function(Function::Closure(closure)),
+                            Value::String(String::new(mc, &file_name)),
+                        ])),
+                        Err(msg) => {
+                            let mut buf = Vec::new();
+                            msg.display(&mut buf).unwrap();
+                            Err(runtime_error(
+                                mc,
+                                &format!(
+                                    "error loading module '{}' from file '{}':\n\t{}",
+                                    std::string::String::from_utf8_lossy(&name),
+                                    std::string::String::from_utf8_lossy(&file_name),
+                                    std::string::String::from_utf8_lossy(&buf),
+                                ),
+                            ))
+                        }
+                    }
+                },
+            ))
+        },
+    )
+}
+
+// Searches for `name` in the ';' separated templates of `path`, like `package.searchpath`.  Every
+// occurrence of `sep` in the name is first replaced by `rep`.  Returns the first file name which can
+// be opened for reading, or else a message listing each file which was tried.
+fn search_path(
+    io: &dyn IoHost,
+    name: &[u8],
+    path: &[u8],
+    sep: &[u8],
+    rep: &[u8],
+) -> Result<Vec<u8>, Vec<u8>> {
+    let name = if sep.is_empty() {
+        name.to_vec()
+    } else {
+        replace(name, sep, rep)
+    };
+
+    let mut msg = Vec::new();
+    for template in path.split(|&c| c == b';') {
+        if template.is_empty() {
+            continue;
+        }
+        let file_name = replace(template, b"?", &name);
+        if io.open(&file_name, OpenMode::parse(b"r").unwrap()).is_ok() {
+            return Ok(file_name);
+        }
+        msg.extend_from_slice(b"\n\tno file '");
+        msg.extend_from_slice(&file_name);
+        msg.extend_from_slice(b"'");
+    }
+    Err(msg)
+}
+
+fn replace(s: &[u8], from: &[u8], to: &[u8]) -> Vec<u8> {
+    let mut res = Vec::new();
+    let mut i = 0;
+    while i < s.len() {
+        if s[i..].starts_with(from) {
+            res.extend_from_slice(to);
+            i += from.len();
+        } else {
+            res.push(s[i]);
+            i += 1;
+        }
+    }
+    res
+}
+
+// Implements `require`, returning the cached module from `package.loaded` if there is one, and
+// otherwise calling each of `package.searchers` in turn to find a loader.
+fn require<'gc>(
+    mc: MutationContext<'gc, '_>,
+    package: Table<'gc>,
+    args: Vec<Value<'gc>>,
+) -> Result<CallbackResult<'gc>, Error<'gc>> {
+    let name = match args.get(0).cloned().unwrap_or(Value::Nil) {
+        Value::String(name) => name,
+        value => {
+            string_arg(value)?;
+            return Err(RuntimeError(Value::String(String::new_static(
+                b"bad argument #1 to 'require' (string expected)",
+            )))
+            .into());
+        }
+    };
+    let loaded = match package.get(String::new_static(b"loaded")) {
+        Value::Table(loaded) => loaded,
+        _ => {
+            return Err(RuntimeError(Value::String(String::new_static(
+                b"'package.loaded' must be a table",
+            )))
+            .into());
+        }
+    };
+    match loaded.get(name) {
+        Value::Nil | Value::Boolean(false) => {}
+        module => return Ok(CallbackResult::Return(vec![module])),
+    }
+    let searchers = match package.get(String::new_static(b"searchers")) {
+        Value::Table(searchers) => searchers,
+        _ => {
+            return Err(RuntimeError(Value::String(String::new_static(
+                b"'package.searchers' must be a table",
+            )))
+            .into());
+        }
+    };
+
+    require_continue(
+        mc,
+        Require {
+            name,
+            loaded,
+            searchers,
+            index: 1,
+            messages: Vec::new(),
+        },
+    )
+}
+
+// The state of an in-progress `require`, which must be suspended while calling each searcher and
+// the final loader.
+#[derive(Collect)]
+#[collect(empty_drop)]
+struct Require<'gc> {
+    name: String<'gc>,
+    loaded: Table<'gc>,
+    searchers: Table<'gc>,
+    // The index of the next searcher to call
+    index: i64,
+    // The messages returned by each searcher which did not find the module
+    messages: Vec<u8>,
+}
+
+fn require_continue<'gc>(
+    mc: MutationContext<'gc, '_>,
+    require: Require<'gc>,
+) -> Result<CallbackResult<'gc>, Error<'gc>> {
+    let searcher = match require.searchers.get(require.index) {
+        Value::Function(searcher) => searcher,
+        Value::Nil => {
+            let mut msg = b"module '".to_vec();
+            msg.extend_from_slice(require.name.as_bytes());
+            msg.extend_from_slice(b"' not found:");
+            msg.extend_from_slice(&require.messages);
+            return Err(RuntimeError(Value::String(String::new(mc, &msg))).into());
+        }
+        _ => {
+            return Err(RuntimeError(Value::String(String::new_static(
+                b"'package.searchers' must contain only functions",
+            )))
+            .into());
+        }
+    };
+
+    Ok(CallbackResult::TailCall {
+        function: searcher,
+        args: vec![Value::String(require.name)],
+        continuation: Continuation::new_sequence_with(require, |require, res| {
+            let res = res?;
+            Ok(sequence::from_fn_with(
+                (require, res),
+                |mc, (mut require, res)| match res.get(0).cloned().unwrap_or(Value::Nil) {
+                    Value::Function(loader) => {
+                        let extra = res.get(1).cloned().unwrap_or(Value::Nil);
+                        Ok(require_load(require, loader, extra))
+                    }
+                    Value::String(msg) => {
+                        require.messages.extend_from_slice(msg.as_bytes());
+                        require.index += 1;
+                        require_continue(mc, require)
+                    }
+                    _ => {
+                        require.index += 1;
+                        require_continue(mc, require)
+                    }
+                },
+            ))
+        }),
+    })
+}
+
+// Calls the loader found by a searcher, storing its result in `package.loaded`.
+fn require_load<'gc>(
+    require: Require<'gc>,
+    loader: Function<'gc>,
+    extra: Value<'gc>,
+) -> CallbackResult<'gc> {
+    CallbackResult::TailCall {
+        function: loader,
+        args: vec![Value::String(require.name), extra],
+        continuation: Continuation::new_sequence_with(require, |require, res| {
+            let module = res?.get(0).cloned().unwrap_or(Value::Nil);
+            Ok(sequence::from_fn_with(
+                (require, module),
+                |mc, (require, module)| {
+                    if module != Value::Nil {
+                        require.loaded.set(mc, require.name, module)?;
+                    }
+                    if require.loaded.get(require.name) == Value::Nil {
+                        require.loaded.set(mc, require.name, true)?;
+                    }
+                    Ok(CallbackResult::Return(vec![require
+                        .loaded
+                        .get(require.name)]))
+                },
+            ))
+        }),
+    }
+}
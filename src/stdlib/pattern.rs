@@ -0,0 +1,450 @@
+use std::error::Error as StdError;
+use std::fmt;
+
+use gc_arena::Collect;
+
+const ESCAPE: u8 = b'%';
+const SPECIALS: &[u8] = b"^$*+?.([%-";
+const MAX_CAPTURES: usize = 32;
+const MAX_MATCH_DEPTH: usize = 200;
+
+#[derive(Debug, Clone, Copy, Collect)]
+#[collect(require_static)]
+pub enum PatternError {
+    EndsWithEscape,
+    MissingBracket,
+    MissingBalanceArguments,
+    MissingFrontierBracket,
+    InvalidCaptureIndex(usize),
+    InvalidPatternCapture,
+    UnfinishedCapture,
+    TooManyCaptures,
+    TooComplex,
+    InvalidReplacement,
+}
+
+impl StdError for PatternError {}
+
+impl fmt::Display for PatternError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PatternError::EndsWithEscape => write!(fmt, "malformed pattern (ends with '%')"),
+            PatternError::MissingBracket => write!(fmt, "malformed pattern (missing ']')"),
+            PatternError::MissingBalanceArguments => {
+                write!(fmt, "malformed pattern (missing arguments to '%b')")
+            }
+            PatternError::MissingFrontierBracket => {
+                write!(fmt, "missing '[' after '%f' in pattern")
+            }
+            PatternError::InvalidCaptureIndex(i) => write!(fmt, "invalid capture index %{}", i),
+            PatternError::InvalidPatternCapture => write!(fmt, "invalid pattern capture"),
+            PatternError::UnfinishedCapture => write!(fmt, "unfinished capture"),
+            PatternError::TooManyCaptures => write!(fmt, "too many captures"),
+            PatternError::TooComplex => write!(fmt, "pattern too complex"),
+            PatternError::InvalidReplacement => {
+                write!(fmt, "invalid use of '%' in replacement string")
+            }
+        }
+    }
+}
+
+/// A single capture from a successful match.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Collect)]
+#[collect(require_static)]
+pub enum Capture {
+    /// A position capture `()`, holding a 1-based position in the source.
+    Position(usize),
+    /// A substring capture, holding the byte range of the capture in the source.
+    Range(usize, usize),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum CaptureLen {
+    Unfinished,
+    Position,
+    Len(usize),
+}
+
+/// Lua 5.3 pattern matching over byte strings.
+///
+/// The pattern given here should not include a leading `^` anchor, anchoring is up to the caller.
+pub struct MatchState<'a> {
+    src: &'a [u8],
+    pattern: &'a [u8],
+    depth: usize,
+    level: usize,
+    captures: [(usize, CaptureLen); MAX_CAPTURES],
+}
+
+impl<'a> MatchState<'a> {
+    pub fn new(src: &'a [u8], pattern: &'a [u8]) -> MatchState<'a> {
+        MatchState {
+            src,
+            pattern,
+            depth: MAX_MATCH_DEPTH,
+            level: 0,
+            captures: [(0, CaptureLen::Unfinished); MAX_CAPTURES],
+        }
+    }
+
+    /// Attempts to match the entire pattern starting at the given source position, returning the
+    /// end position of the match.
+    pub fn try_match(&mut self, start: usize) -> Result<Option<usize>, PatternError> {
+        self.depth = MAX_MATCH_DEPTH;
+        self.level = 0;
+        self.do_match(start, 0)
+    }
+
+    /// Returns the `i`th capture of the last successful match from `start` to `end`.  If the
+    /// pattern has no captures, the 0th capture is the whole match.
+    pub fn capture(&self, i: usize, start: usize, end: usize) -> Result<Capture, PatternError> {
+        if i >= self.level {
+            if i == 0 {
+                Ok(Capture::Range(start, end))
+            } else {
+                Err(PatternError::InvalidCaptureIndex(i + 1))
+            }
+        } else {
+            let (init, len) = self.captures[i];
+            match len {
+                CaptureLen::Unfinished => Err(PatternError::UnfinishedCapture),
+                CaptureLen::Position => Ok(Capture::Position(init + 1)),
+                CaptureLen::Len(len) => Ok(Capture::Range(init, init + len)),
+            }
+        }
+    }
+
+    /// Returns all of the captures of the last successful match from `start` to `end`.  If the
+    /// pattern has no captures and `whole_if_none` is true, the whole match is returned as the only
+    /// capture.
+    pub fn captures(
+        &self,
+        start: usize,
+        end: usize,
+        whole_if_none: bool,
+    ) -> Result<Vec<Capture>, PatternError> {
+        let count = if self.level == 0 && whole_if_none {
+            1
+        } else {
+            self.level
+        };
+        (0..count).map(|i| self.capture(i, start, end)).collect()
+    }
+
+    fn do_match(&mut self, mut s: usize, mut p: usize) -> Result<Option<usize>, PatternError> {
+        if self.depth == 0 {
+            return Err(PatternError::TooComplex);
+        }
+        self.depth -= 1;
+
+        let res = loop {
+            if p == self.pattern.len() {
+                break Some(s);
+            }
+
+            match self.pattern[p] {
+                b'(' => {
+                    break if self.pattern.get(p + 1) == Some(&b')') {
+                        self.start_capture(s, p + 2, CaptureLen::Position)?
+                    } else {
+                        self.start_capture(s, p + 1, CaptureLen::Unfinished)?
+                    };
+                }
+                b')' => break self.end_capture(s, p + 1)?,
+                b'$' if p + 1 == self.pattern.len() => {
+                    break if s == self.src.len() { Some(s) } else { None };
+                }
+                ESCAPE if self.pattern.get(p + 1) == Some(&b'b') => {
+                    match self.match_balance(s, p + 2)? {
+                        Some(end) => {
+                            s = end;
+                            p += 4;
+                            continue;
+                        }
+                        None => break None,
+                    }
+                }
+                ESCAPE if self.pattern.get(p + 1) == Some(&b'f') => {
+                    p += 2;
+                    if self.pattern.get(p) != Some(&b'[') {
+                        return Err(PatternError::MissingFrontierBracket);
+                    }
+                    let ep = self.class_end(p)?;
+                    let previous = if s == 0 { 0 } else { self.src[s - 1] };
+                    let current = self.src.get(s).cloned().unwrap_or(0);
+                    if !self.match_bracket_class(previous, p, ep - 1)
+                        && self.match_bracket_class(current, p, ep - 1)
+                    {
+                        p = ep;
+                        continue;
+                    }
+                    break None;
+                }
+                ESCAPE
+                    if self
+                        .pattern
+                        .get(p + 1)
+                        .map(u8::is_ascii_digit)
+                        .unwrap_or(false) =>
+                {
+                    match self.match_capture(s, self.pattern[p + 1])? {
+                        Some(end) => {
+                            s = end;
+                            p += 2;
+                            continue;
+                        }
+                        None => break None,
+                    }
+                }
+                _ => {
+                    let ep = self.class_end(p)?;
+                    let suffix = self.pattern.get(ep).cloned();
+                    if !self.single_match(s, p, ep) {
+                        if suffix == Some(b'*') || suffix == Some(b'?') || suffix == Some(b'-') {
+                            p = ep + 1;
+                            continue;
+                        }
+                        break None;
+                    }
+
+                    match suffix {
+                        Some(b'?') => match self.do_match(s + 1, ep + 1)? {
+                            Some(end) => break Some(end),
+                            None => {
+                                p = ep + 1;
+                                continue;
+                            }
+                        },
+                        Some(b'+') => break self.max_expand(s + 1, p, ep)?,
+                        Some(b'*') => break self.max_expand(s, p, ep)?,
+                        Some(b'-') => break self.min_expand(s, p, ep)?,
+                        _ => {
+                            s += 1;
+                            p = ep;
+                            continue;
+                        }
+                    }
+                }
+            }
+        };
+
+        self.depth += 1;
+        Ok(res)
+    }
+
+    fn max_expand(&mut self, s: usize, p: usize, ep: usize) -> Result<Option<usize>, PatternError> {
+        let mut i = 0;
+        while self.single_match(s + i, p, ep) {
+            i += 1;
+        }
+        loop {
+            if let Some(end) = self.do_match(s + i, ep + 1)? {
+                return Ok(Some(end));
+            }
+            if i == 0 {
+                return Ok(None);
+            }
+            i -= 1;
+        }
+    }
+
+    fn min_expand(
+        &mut self,
+        mut s: usize,
+        p: usize,
+        ep: usize,
+    ) -> Result<Option<usize>, PatternError> {
+        loop {
+            if let Some(end) = self.do_match(s, ep + 1)? {
+                return Ok(Some(end));
+            } else if self.single_match(s, p, ep) {
+                s += 1;
+            } else {
+                return Ok(None);
+            }
+        }
+    }
+
+    fn start_capture(
+        &mut self,
+        s: usize,
+        p: usize,
+        len: CaptureLen,
+    ) -> Result<Option<usize>, PatternError> {
+        if self.level >= MAX_CAPTURES {
+            return Err(PatternError::TooManyCaptures);
+        }
+        self.captures[self.level] = (s, len);
+        self.level += 1;
+        let res = self.do_match(s, p)?;
+        if res.is_none() {
+            self.level -= 1;
+        }
+        Ok(res)
+    }
+
+    fn end_capture(&mut self, s: usize, p: usize) -> Result<Option<usize>, PatternError> {
+        let l = self.capture_to_close()?;
+        self.captures[l].1 = CaptureLen::Len(s - self.captures[l].0);
+        let res = self.do_match(s, p)?;
+        if res.is_none() {
+            self.captures[l].1 = CaptureLen::Unfinished;
+        }
+        Ok(res)
+    }
+
+    fn capture_to_close(&self) -> Result<usize, PatternError> {
+        for l in (0..self.level).rev() {
+            if let CaptureLen::Unfinished = self.captures[l].1 {
+                return Ok(l);
+            }
+        }
+        Err(PatternError::InvalidPatternCapture)
+    }
+
+    fn match_balance(&self, s: usize, p: usize) -> Result<Option<usize>, PatternError> {
+        if p + 1 >= self.pattern.len() {
+            return Err(PatternError::MissingBalanceArguments);
+        }
+        if s >= self.src.len() || self.src[s] != self.pattern[p] {
+            return Ok(None);
+        }
+
+        let (open, close) = (self.pattern[p], self.pattern[p + 1]);
+        let mut depth = 1;
+        for i in s + 1..self.src.len() {
+            if self.src[i] == close {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok(Some(i + 1));
+                }
+            } else if self.src[i] == open {
+                depth += 1;
+            }
+        }
+        Ok(None)
+    }
+
+    fn match_capture(&self, s: usize, index: u8) -> Result<Option<usize>, PatternError> {
+        let index = (index - b'0') as usize;
+        if index == 0 || index > self.level {
+            return Err(PatternError::InvalidCaptureIndex(index));
+        }
+        let (init, len) = match self.captures[index - 1] {
+            (_, CaptureLen::Unfinished) => return Err(PatternError::InvalidCaptureIndex(index)),
+            (_, CaptureLen::Position) => return Ok(None),
+            (init, CaptureLen::Len(len)) => (init, len),
+        };
+        if self.src.len() - s >= len && self.src[init..init + len] == self.src[s..s + len] {
+            Ok(Some(s + len))
+        } else {
+            Ok(None)
+        }
+    }
+
+    // Returns the index just past the single character class starting at `p`.
+    fn class_end(&self, mut p: usize) -> Result<usize, PatternError> {
+        let c = self.pattern[p];
+        p += 1;
+        if c == ESCAPE {
+            if p >= self.pattern.len() {
+                return Err(PatternError::EndsWithEscape);
+            }
+            Ok(p + 1)
+        } else if c == b'[' {
+            if self.pattern.get(p) == Some(&b'^') {
+                p += 1;
+            }
+            loop {
+                if p >= self.pattern.len() {
+                    return Err(PatternError::MissingBracket);
+                }
+                let c = self.pattern[p];
+                p += 1;
+                if c == ESCAPE && p < self.pattern.len() {
+                    p += 1;
+                }
+                if self.pattern.get(p) == Some(&b']') {
+                    return Ok(p + 1);
+                }
+            }
+        } else {
+            Ok(p)
+        }
+    }
+
+    fn single_match(&self, s: usize, p: usize, ep: usize) -> bool {
+        match self.src.get(s) {
+            None => false,
+            Some(&c) => match self.pattern[p] {
+                b'.' => true,
+                ESCAPE => match_class(c, self.pattern[p + 1]),
+                b'[' => self.match_bracket_class(c, p, ep - 1),
+                pc => pc == c,
+            },
+        }
+    }
+
+    // Matches the set starting with the '[' at `p` and ending with the ']' at `ec`.
+    fn match_bracket_class(&self, c: u8, mut p: usize, ec: usize) -> bool {
+        let mut sig = true;
+        if self.pattern[p + 1] == b'^' {
+            sig = false;
+            p += 1;
+        }
+        p += 1;
+        while p < ec {
+            if self.pattern[p] == ESCAPE {
+                p += 1;
+                if match_class(c, self.pattern[p]) {
+                    return sig;
+                }
+            } else if self.pattern[p + 1] == b'-' && p + 2 < ec {
+                p += 2;
+                if self.pattern[p - 2] <= c && c <= self.pattern[p] {
+                    return sig;
+                }
+            } else if self.pattern[p] == c {
+                return sig;
+            }
+            p += 1;
+        }
+        !sig
+    }
+}
+
+/// Returns true if the pattern contains any special characters, otherwise it may be matched as a
+/// plain string.
+pub fn has_specials(pattern: &[u8]) -> bool {
+    pattern.iter().any(|c| SPECIALS.contains(c))
+}
+
+/// Finds the first occurrence of `needle` in `haystack`.
+pub fn find_plain(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    if needle.is_empty() {
+        Some(0)
+    } else {
+        haystack.windows(needle.len()).position(|w| w == needle)
+    }
+}
+
+fn match_class(c: u8, class: u8) -> bool {
+    let res = match class.to_ascii_lowercase() {
+        b'a' => c.is_ascii_alphabetic(),
+        b'c' => c.is_ascii_control(),
+        b'd' => c.is_ascii_digit(),
+        b'g' => c.is_ascii_graphic(),
+        b'l' => c.is_ascii_lowercase(),
+        b'p' => c.is_ascii_punctuation(),
+        b's' => c == b' ' || (c >= b'\t' && c <= b'\r'),
+        b'u' => c.is_ascii_uppercase(),
+        b'w' => c.is_ascii_alphanumeric(),
+        b'x' => c.is_ascii_hexdigit(),
+        _ => return class == c,
+    };
+    if class.is_ascii_uppercase() {
+        !res
+    } else {
+        res
+    }
+}
@@ -0,0 +1,1233 @@
+use std::cell::Cell;
+use std::fmt::Write as _;
+use std::io::Write as _;
+use std::rc::Rc;
+use std::{f64, i64};
+
+use gc_arena::{Collect, MutationContext};
+use gc_sequence as sequence;
+
+use crate::{
+    meta_ops::{self, MetaCall, MetaResult},
+    stdlib::pack::{self, PackError},
+    stdlib::pattern::{find_plain, has_specials, Capture, MatchState, PatternError},
+    Callback, CallbackResult, Continuation, Error, Root, RuntimeError, String, Table, TypeError,
+    Value,
+};
+
+pub fn load_string<'gc>(mc: MutationContext<'gc, '_>, root: Root<'gc>, env: Table<'gc>) {
+    let string = Table::new(mc);
+
+    string
+        .set(
+            mc,
+            String::new_static(b"len"),
+            Callback::new_immediate(mc, |args| {
+                let s = string_arg(args.get(0).cloned().unwrap_or(Value::Nil))?;
+                Ok(CallbackResult::Return(vec![Value::Integer(s.len() as i64)]))
+            }),
+        )
+        .unwrap();
+
+    string
+        .set(
+            mc,
+            String::new_static(b"sub"),
+            new_callback(mc, |mc, args| {
+                let s = string_arg(args.get(0).cloned().unwrap_or(Value::Nil))?;
+                let i = integer_arg(args.get(1).cloned().unwrap_or(Value::Nil), Some(1))?;
+                let j = integer_arg(args.get(2).cloned().unwrap_or(Value::Nil), Some(-1))?;
+                let (start, end) = substring_range(s.len(), i, j);
+                Ok(CallbackResult::Return(vec![Value::String(String::new(
+                    mc,
+                    &s[start..end],
+                ))]))
+            }),
+        )
+        .unwrap();
+
+    string
+        .set(
+            mc,
+            String::new_static(b"upper"),
+            new_callback(mc, |mc, args| {
+                let s = string_arg(args.get(0).cloned().unwrap_or(Value::Nil))?;
+                Ok(CallbackResult::Return(vec![Value::String(String::new(
+                    mc,
+                    &s.to_ascii_uppercase(),
+                ))]))
+            }),
+        )
+        .unwrap();
+
+    string
+        .set(
+            mc,
+            String::new_static(b"lower"),
+            new_callback(mc, |mc, args| {
+                let s = string_arg(args.get(0).cloned().unwrap_or(Value::Nil))?;
+                Ok(CallbackResult::Return(vec![Value::String(String::new(
+                    mc,
+                    &s.to_ascii_lowercase(),
+                ))]))
+            }),
+        )
+        .unwrap();
+
+    string
+        .set(
+            mc,
+            String::new_static(b"rep"),
+            new_callback(mc, |mc, args| {
+                let s = string_arg(args.get(0).cloned().unwrap_or(Value::Nil))?;
+                let n = integer_arg(args.get(1).cloned().unwrap_or(Value::Nil), None)?;
+                let sep = match args.get(2).cloned().unwrap_or(Value::Nil) {
+                    Value::Nil => Vec::new(),
+                    sep => string_arg(sep)?,
+                };
+
+                if n <= 0 {
+                    return Ok(CallbackResult::Return(vec![Value::String(
+                        String::new_static(b""),
+                    )]));
+                }
+
+                let n = n as usize;
+                let total = s
+                    .len()
+                    .checked_add(sep.len())
+                    .and_then(|l| l.checked_mul(n))
+                    .filter(|&l| l < i32::MAX as usize);
+                let mut res = match total {
+                    Some(total) => Vec::with_capacity(total),
+                    None => return Err(runtime_error(mc, "resulting string too large")),
+                };
+                for i in 0..n {
+                    if i != 0 {
+                        res.extend_from_slice(&sep);
+                    }
+                    res.extend_from_slice(&s);
+                }
+                Ok(CallbackResult::Return(vec![Value::String(String::new(
+                    mc, &res,
+                ))]))
+            }),
+        )
+        .unwrap();
+
+    string
+        .set(
+            mc,
+            String::new_static(b"reverse"),
+            new_callback(mc, |mc, args| {
+                let mut s = string_arg(args.get(0).cloned().unwrap_or(Value::Nil))?;
+                s.reverse();
+                Ok(CallbackResult::Return(vec![Value::String(String::new(
+                    mc, &s,
+                ))]))
+            }),
+        )
+        .unwrap();
+
+    string
+        .set(
+            mc,
+            String::new_static(b"byte"),
+            Callback::new_immediate(mc, |args| {
+                let s = string_arg(args.get(0).cloned().unwrap_or(Value::Nil))?;
+                let i = integer_arg(args.get(1).cloned().unwrap_or(Value::Nil), Some(1))?;
+                let j = integer_arg(args.get(2).cloned().unwrap_or(Value::Nil), Some(i))?;
+                let (start, end) = substring_range(s.len(), i, j);
+                Ok(CallbackResult::Return(
+                    s[start..end]
+                        .iter()
+                        .map(|&b| Value::Integer(b as i64))
+                        .collect(),
+                ))
+            }),
+        )
+        .unwrap();
+
+    string
+        .set(
+            mc,
+            String::new_static(b"char"),
+            new_callback(mc, |mc, args| {
+                let mut res = Vec::with_capacity(args.len());
+                for arg in args {
+                    match integer_arg(arg, None)? {
+                        c if c >= 0 && c <= 255 => res.push(c as u8),
+                        _ => {
+                            return Err(runtime_error(
+                                mc,
+                                "bad argument to 'char' (value out of range)",
+                            ))
+                        }
+                    }
+                }
+                Ok(CallbackResult::Return(vec![Value::String(String::new(
+                    mc, &res,
+                ))]))
+            }),
+        )
+        .unwrap();
+
+    string
+        .set(
+            mc,
+            String::new_static(b"format"),
+            new_callback(mc, |mc, mut args| {
+                let format = string_arg(args.get(0).cloned().unwrap_or(Value::Nil))?;
+                args.remove(0);
+                let mut to_convert: Vec<usize> = string_conversions(&format)
+                    .into_iter()
+                    .filter(|&i| i < args.len())
+                    .collect();
+                to_convert.reverse();
+                format_continue(
+                    mc,
+                    Format {
+                        format,
+                        args,
+                        to_convert,
+                    },
+                )
+            }),
+        )
+        .unwrap();
+
+    string
+        .set(
+            mc,
+            String::new_static(b"find"),
+            new_callback(mc, |mc, args| str_find(mc, &args, true)),
+        )
+        .unwrap();
+
+    string
+        .set(
+            mc,
+            String::new_static(b"match"),
+            new_callback(mc, |mc, args| str_find(mc, &args, false)),
+        )
+        .unwrap();
+
+    string
+        .set(
+            mc,
+            String::new_static(b"gmatch"),
+            new_callback(mc, |mc, args| {
+                let src = String::new(mc, &string_arg(args.get(0).cloned().unwrap_or(Value::Nil))?);
+                let pattern =
+                    String::new(mc, &string_arg(args.get(1).cloned().unwrap_or(Value::Nil))?);
+
+                // The position to continue matching from, and the end of the last match.
+                let state = Rc::new(Cell::new((0, None)));
+                let iterator =
+                    Callback::new_sequence_with(mc, (src, pattern), move |&(src, pattern), _| {
+                        let state = state.clone();
+                        Ok(sequence::from_fn_with(
+                            (src, pattern),
+                            move |mc, (src, pattern)| {
+                                let src_bytes = src.as_bytes();
+                                let (mut position, last_match) = state.get();
+                                let mut ms = MatchState::new(src_bytes, pattern.as_bytes());
+                                while position <= src_bytes.len() {
+                                    match ms
+                                        .try_match(position)
+                                        .map_err(|err| pattern_error(mc, err))?
+                                    {
+                                        Some(end) if Some(end) != last_match => {
+                                            state.set((end, Some(end)));
+                                            let captures = ms
+                                                .captures(position, end, true)
+                                                .map_err(|err| pattern_error(mc, err))?;
+                                            return Ok(CallbackResult::Return(capture_values(
+                                                mc, src_bytes, &captures,
+                                            )));
+                                        }
+                                        _ => position += 1,
+                                    }
+                                }
+                                state.set((position, last_match));
+                                Ok(CallbackResult::Return(vec![]))
+                            },
+                        ))
+                    });
+                Ok(CallbackResult::Return(vec![iterator.into()]))
+            }),
+        )
+        .unwrap();
+
+    string
+        .set(
+            mc,
+            String::new_static(b"gsub"),
+            Callback::new_sequence_with(mc, root.string_metatable, |string_metatable, args| {
+                let string_metatable = *string_metatable;
+                Ok(sequence::from_fn_with(
+                    (args, string_metatable),
+                    |mc, (args, string_metatable)| {
+                        let src = string_arg(args.get(0).cloned().unwrap_or(Value::Nil))?;
+                        let pattern = string_arg(args.get(1).cloned().unwrap_or(Value::Nil))?;
+                        let replacement = match args.get(2).cloned().unwrap_or(Value::Nil) {
+                            Value::Integer(_) | Value::Number(_) => {
+                                Value::String(String::new(mc, &string_arg(args[2])?))
+                            }
+                            Value::String(_) | Value::Table(_) | Value::Function(_) => args[2],
+                            value => {
+                                return Err(TypeError {
+                                    expected: "string, function or table",
+                                    found: value.type_name(),
+                                }
+                                .into());
+                            }
+                        };
+                        let max_replacements = integer_arg(
+                            args.get(3).cloned().unwrap_or(Value::Nil),
+                            Some(src.len() as i64 + 1),
+                        )?;
+
+                        gsub_continue(
+                            mc,
+                            Gsub {
+                                src: String::new(mc, &src),
+                                anchor: pattern.first() == Some(&b'^'),
+                                pattern: String::new(mc, &pattern),
+                                replacement,
+                                string_metatable,
+                                max_replacements,
+                                replacements: 0,
+                                position: 0,
+                                last_match: None,
+                                current_match: (0, 0),
+                                result: Vec::new(),
+                            },
+                        )
+                    },
+                ))
+            }),
+        )
+        .unwrap();
+
+    string
+        .set(
+            mc,
+            String::new_static(b"pack"),
+            new_callback(mc, |mc, args| {
+                let format = string_arg(args.get(0).cloned().unwrap_or(Value::Nil))?;
+                let packed = pack::pack(&format, args.get(1..).unwrap_or(&[]))?;
+                Ok(CallbackResult::Return(vec![Value::String(String::new(
+                    mc, &packed,
+                ))]))
+            }),
+        )
+        .unwrap();
+
+    string
+        .set(
+            mc,
+            String::new_static(b"packsize"),
+            Callback::new_immediate(mc, |args| {
+                let format = string_arg(args.get(0).cloned().unwrap_or(Value::Nil))?;
+                let size = pack::pack_size(&format)?;
+                Ok(CallbackResult::Return(vec![Value::Integer(size as i64)]))
+            }),
+        )
+        .unwrap();
+
+    string
+        .set(
+            mc,
+            String::new_static(b"unpack"),
+            new_callback(mc, |mc, args| {
+                let format = string_arg(args.get(0).cloned().unwrap_or(Value::Nil))?;
+                let data = string_arg(args.get(1).cloned().unwrap_or(Value::Nil))?;
+                let pos = integer_arg(args.get(2).cloned().unwrap_or(Value::Nil), Some(1))?;
+                let pos = relative_position(pos, data.len()) - 1;
+                if pos < 0 {
+                    return Err(PackError::PositionOutOfString.into());
+                }
+                Ok(CallbackResult::Return(pack::unpack(
+                    mc,
+                    &format,
+                    &data,
+                    pos as usize,
+                )?))
+            }),
+        )
+        .unwrap();
+
+    root.string_metatable
+        .set(mc, String::new_static(b"__index"), string)
+        .unwrap();
+
+    env.set(mc, String::new_static(b"string"), string).unwrap();
+}
+
+// Creates a callback that has access to a `MutationContext` while running, for functions which
+// must allocate their results.
+pub(super) fn new_callback<'gc, F>(mc: MutationContext<'gc, '_>, f: F) -> Callback<'gc>
+where
+    F: 'static
+        + Copy
+        + Fn(MutationContext<'gc, '_>, Vec<Value<'gc>>) -> Result<CallbackResult<'gc>, Error<'gc>>,
+{
+    Callback::new_sequence(mc, move |args| {
+        Ok(sequence::from_fn_with(args, move |mc, args| f(mc, args)))
+    })
+}
+
+pub(super) fn runtime_error<'gc>(mc: MutationContext<'gc, '_>, msg: &str) -> Error<'gc> {
+    RuntimeError(Value::String(String::new(mc, msg.as_bytes()))).into()
+}
+
+fn pattern_error<'gc>(mc: MutationContext<'gc, '_>, err: PatternError) -> Error<'gc> {
+    runtime_error(mc, &err.to_string())
+}
+
+// Implements both `string.find` and `string.match`, which differ only in their return values.
+fn str_find<'gc>(
+    mc: MutationContext<'gc, '_>,
+    args: &[Value<'gc>],
+    find: bool,
+) -> Result<CallbackResult<'gc>, Error<'gc>> {
+    let src = string_arg(args.get(0).cloned().unwrap_or(Value::Nil))?;
+    let pattern = string_arg(args.get(1).cloned().unwrap_or(Value::Nil))?;
+    let init = integer_arg(args.get(2).cloned().unwrap_or(Value::Nil), Some(1))?;
+    let init = relative_position(init, src.len()).max(1) as usize;
+    if init > src.len() + 1 {
+        return Ok(CallbackResult::Return(vec![Value::Nil]));
+    }
+    let start = init - 1;
+
+    let plain = args.get(3).cloned().unwrap_or(Value::Nil).to_bool();
+    if find && (plain || !has_specials(&pattern)) {
+        return Ok(CallbackResult::Return(
+            match find_plain(&src[start..], &pattern) {
+                Some(i) => vec![
+                    Value::Integer((start + i + 1) as i64),
+                    Value::Integer((start + i + pattern.len()) as i64),
+                ],
+                None => vec![Value::Nil],
+            },
+        ));
+    }
+
+    let (anchor, pattern) = match pattern.split_first() {
+        Some((b'^', rest)) => (true, rest),
+        _ => (false, &pattern[..]),
+    };
+    let mut ms = MatchState::new(&src, pattern);
+    let mut position = start;
+    loop {
+        if let Some(end) = ms
+            .try_match(position)
+            .map_err(|err| pattern_error(mc, err))?
+        {
+            let captures = ms
+                .captures(position, end, !find)
+                .map_err(|err| pattern_error(mc, err))?;
+            let mut ret = Vec::new();
+            if find {
+                ret.push(Value::Integer(position as i64 + 1));
+                ret.push(Value::Integer(end as i64));
+            }
+            ret.extend(capture_values(mc, &src, &captures));
+            return Ok(CallbackResult::Return(ret));
+        }
+
+        position += 1;
+        if anchor || position > src.len() {
+            break;
+        }
+    }
+    Ok(CallbackResult::Return(vec![Value::Nil]))
+}
+
+fn capture_values<'gc>(
+    mc: MutationContext<'gc, '_>,
+    src: &[u8],
+    captures: &[Capture],
+) -> Vec<Value<'gc>> {
+    captures
+        .iter()
+        .map(|&capture| match capture {
+            Capture::Position(p) => Value::Integer(p as i64),
+            Capture::Range(start, end) => Value::String(String::new(mc, &src[start..end])),
+        })
+        .collect()
+}
+
+// The state of an in-progress `string.gsub`, which must be suspended whenever a replacement
+// function or `__index` metamethod is called.
+#[derive(Collect)]
+#[collect(empty_drop)]
+struct Gsub<'gc> {
+    src: String<'gc>,
+    pattern: String<'gc>,
+    anchor: bool,
+    replacement: Value<'gc>,
+    string_metatable: Table<'gc>,
+    max_replacements: i64,
+    replacements: i64,
+    position: usize,
+    last_match: Option<usize>,
+    current_match: (usize, usize),
+    result: Vec<u8>,
+}
+
+fn gsub_continue<'gc>(
+    mc: MutationContext<'gc, '_>,
+    mut gsub: Gsub<'gc>,
+) -> Result<CallbackResult<'gc>, Error<'gc>> {
+    let (src, pattern) = (gsub.src, gsub.pattern);
+    let src = src.as_bytes();
+    let pattern = if gsub.anchor {
+        &pattern.as_bytes()[1..]
+    } else {
+        pattern.as_bytes()
+    };
+    let mut ms = MatchState::new(src, pattern);
+
+    while gsub.replacements < gsub.max_replacements {
+        let start = gsub.position;
+        match ms.try_match(start).map_err(|err| pattern_error(mc, err))? {
+            Some(end) if Some(end) != gsub.last_match => {
+                gsub.replacements += 1;
+                gsub.position = end;
+                gsub.last_match = Some(end);
+                gsub.current_match = (start, end);
+
+                let call = match gsub.replacement {
+                    Value::Function(function) => {
+                        let captures = ms
+                            .captures(start, end, true)
+                            .map_err(|err| pattern_error(mc, err))?;
+                        Some(MetaCall {
+                            function,
+                            args: capture_values(mc, src, &captures),
+                        })
+                    }
+                    Value::Table(table) => {
+                        let key = ms
+                            .capture(0, start, end)
+                            .map_err(|err| pattern_error(mc, err))?;
+                        let key = capture_values(mc, src, &[key]).remove(0);
+                        match meta_ops::index(
+                            Some(gsub.string_metatable),
+                            Value::Table(table),
+                            key,
+                        )? {
+                            MetaResult::Value(value) => {
+                                gsub_replace_value(mc, &mut gsub, value)?;
+                                None
+                            }
+                            MetaResult::Call(call) => Some(call),
+                        }
+                    }
+                    Value::String(replacement) => {
+                        add_replacement(
+                            &mut gsub.result,
+                            src,
+                            replacement.as_bytes(),
+                            &ms,
+                            start,
+                            end,
+                        )
+                        .map_err(|err| pattern_error(mc, err))?;
+                        None
+                    }
+                    _ => unreachable!(),
+                };
+
+                if let Some(call) = call {
+                    return Ok(CallbackResult::TailCall {
+                        function: call.function,
+                        args: call.args,
+                        continuation: Continuation::new_sequence_with(gsub, |gsub, res| {
+                            let value = res?.get(0).cloned().unwrap_or(Value::Nil);
+                            Ok(sequence::from_fn_with(
+                                (gsub, value),
+                                |mc, (mut gsub, value)| {
+                                    gsub_replace_value(mc, &mut gsub, value)?;
+                                    if gsub.anchor {
+                                        gsub_finish(mc, gsub)
+                                    } else {
+                                        gsub_continue(mc, gsub)
+                                    }
+                                },
+                            ))
+                        }),
+                    });
+                }
+            }
+            _ if start < src.len() => {
+                gsub.result.push(src[start]);
+                gsub.position += 1;
+            }
+            _ => break,
+        }
+
+        if gsub.anchor {
+            break;
+        }
+    }
+
+    gsub_finish(mc, gsub)
+}
+
+fn gsub_finish<'gc>(
+    mc: MutationContext<'gc, '_>,
+    mut gsub: Gsub<'gc>,
+) -> Result<CallbackResult<'gc>, Error<'gc>> {
+    gsub.result
+        .extend_from_slice(&gsub.src.as_bytes()[gsub.position..]);
+    Ok(CallbackResult::Return(vec![
+        Value::String(String::new(mc, &gsub.result)),
+        Value::Integer(gsub.replacements),
+    ]))
+}
+
+// Adds the result of a replacement function or table lookup for the current match.  False or nil
+// keeps the original match.
+fn gsub_replace_value<'gc>(
+    mc: MutationContext<'gc, '_>,
+    gsub: &mut Gsub<'gc>,
+    value: Value<'gc>,
+) -> Result<(), Error<'gc>> {
+    match value {
+        Value::Nil | Value::Boolean(false) => {
+            let (start, end) = gsub.current_match;
+            gsub.result
+                .extend_from_slice(&gsub.src.as_bytes()[start..end]);
+        }
+        Value::String(_) | Value::Integer(_) | Value::Number(_) => {
+            value.display(&mut gsub.result).unwrap();
+        }
+        value => {
+            return Err(runtime_error(
+                mc,
+                &format!("invalid replacement value (a {})", value.type_name()),
+            ));
+        }
+    }
+    Ok(())
+}
+
+// Adds a replacement string for the given match, expanding `%0` - `%9` to captures and `%%` to `%`.
+fn add_replacement(
+    out: &mut Vec<u8>,
+    src: &[u8],
+    replacement: &[u8],
+    ms: &MatchState,
+    start: usize,
+    end: usize,
+) -> Result<(), PatternError> {
+    let mut i = 0;
+    while i < replacement.len() {
+        let c = replacement[i];
+        i += 1;
+        if c != b'%' {
+            out.push(c);
+            continue;
+        }
+
+        match replacement.get(i) {
+            Some(b'%') => out.push(b'%'),
+            Some(b'0') => out.extend_from_slice(&src[start..end]),
+            Some(&d) if d.is_ascii_digit() => match ms.capture((d - b'1') as usize, start, end)? {
+                Capture::Position(p) => write!(out, "{}", p).unwrap(),
+                Capture::Range(start, end) => out.extend_from_slice(&src[start..end]),
+            },
+            _ => return Err(PatternError::InvalidReplacement),
+        }
+        i += 1;
+    }
+    Ok(())
+}
+
+// Strings and numbers are both accepted where a string is expected, numbers are converted to their
+// string representation.
+pub(super) fn string_arg<'gc>(value: Value<'gc>) -> Result<Vec<u8>, TypeError> {
+    match value {
+        Value::String(s) => Ok(s.as_bytes().to_vec()),
+        Value::Integer(_) | Value::Number(_) => {
+            let mut bytes = Vec::new();
+            value.display(&mut bytes).unwrap();
+            Ok(bytes)
+        }
+        value => Err(TypeError {
+            expected: "string",
+            found: value.type_name(),
+        }),
+    }
+}
+
+pub(super) fn integer_arg<'gc>(value: Value<'gc>, default: Option<i64>) -> Result<i64, TypeError> {
+    match (value, default) {
+        (Value::Nil, Some(default)) => Ok(default),
+        (value, _) => value.to_integer().ok_or(TypeError {
+            expected: "integer",
+            found: value.type_name(),
+        }),
+    }
+}
+
+// Converts the (possibly negative) 1-based inclusive Lua string indexes `i` and `j` into a Rust
+// byte range of a string with the given length.
+fn substring_range(len: usize, i: i64, j: i64) -> (usize, usize) {
+    let start = relative_position(i, len).max(1);
+    let end = relative_position(j, len).min(len as i64);
+    if start > end {
+        (0, 0)
+    } else {
+        ((start - 1) as usize, end as usize)
+    }
+}
+
+// Converts a (possibly negative) 1-based Lua string position into a non-negative one, where 0 is
+// before the start of the string.
+pub(super) fn relative_position(pos: i64, len: usize) -> i64 {
+    let len = len as i64;
+    if pos >= 0 {
+        pos
+    } else if pos < -len {
+        0
+    } else {
+        len + pos + 1
+    }
+}
+
+enum FormatError {
+    Type(TypeError),
+    Message(std::string::String),
+}
+
+impl From<TypeError> for FormatError {
+    fn from(err: TypeError) -> FormatError {
+        FormatError::Type(err)
+    }
+}
+
+#[derive(Default)]
+struct FormatSpec {
+    left_align: bool,
+    plus_sign: bool,
+    space_sign: bool,
+    alternate: bool,
+    zero_pad: bool,
+    width: usize,
+    precision: Option<usize>,
+}
+
+impl FormatSpec {
+    // Writes the given sign or radix prefix and body, padded to the field width.  Zero padding is
+    // placed between the prefix and the body.
+    fn pad(&self, out: &mut Vec<u8>, prefix: &[u8], body: &[u8], allow_zero_pad: bool) {
+        let len = prefix.len() + body.len();
+        let fill = self.width.saturating_sub(len);
+        if self.left_align {
+            out.extend_from_slice(prefix);
+            out.extend_from_slice(body);
+            out.extend((0..fill).map(|_| b' '));
+        } else if self.zero_pad && allow_zero_pad {
+            out.extend_from_slice(prefix);
+            out.extend((0..fill).map(|_| b'0'));
+            out.extend_from_slice(body);
+        } else {
+            out.extend((0..fill).map(|_| b' '));
+            out.extend_from_slice(prefix);
+            out.extend_from_slice(body);
+        }
+    }
+
+    fn sign(&self, negative: bool) -> &'static [u8] {
+        if negative {
+            b"-"
+        } else if self.plus_sign {
+            b"+"
+        } else if self.space_sign {
+            b" "
+        } else {
+            b""
+        }
+    }
+}
+
+// The state of an in-progress `string.format`, which must be suspended whenever a `__tostring`
+// metamethod is called for a '%s' conversion.  `to_convert` holds the indexes of the remaining
+// arguments of '%s' conversions in reverse order, which are replaced by their converted strings.
+#[derive(Collect)]
+#[collect(empty_drop)]
+struct Format<'gc> {
+    format: Vec<u8>,
+    args: Vec<Value<'gc>>,
+    to_convert: Vec<usize>,
+}
+
+fn format_continue<'gc>(
+    mc: MutationContext<'gc, '_>,
+    mut format: Format<'gc>,
+) -> Result<CallbackResult<'gc>, Error<'gc>> {
+    while let Some(i) = format.to_convert.pop() {
+        match meta_ops::tostring(mc, format.args[i])? {
+            MetaResult::Value(value) => format.args[i] = value,
+            MetaResult::Call(call) => {
+                return Ok(CallbackResult::TailCall {
+                    function: call.function,
+                    args: call.args,
+                    continuation: Continuation::new_sequence_with(
+                        (format, i),
+                        |(mut format, i), res| {
+                            format.args[i] = Value::String(meta_ops::tostring_result(&res?)?);
+                            Ok(sequence::from_fn_with(format, format_continue))
+                        },
+                    ),
+                });
+            }
+        }
+    }
+
+    match format_string(&format.format, &format.args) {
+        Ok(res) => Ok(CallbackResult::Return(vec![Value::String(String::new(
+            mc, &res,
+        ))])),
+        Err(FormatError::Type(err)) => Err(err.into()),
+        Err(FormatError::Message(msg)) => Err(runtime_error(mc, &msg)),
+    }
+}
+
+// Returns the indexes of the arguments which are formatted by '%s' conversions.  Invalid
+// conversions are skipped here and reported by `format_string`.
+fn string_conversions(format: &[u8]) -> Vec<usize> {
+    let mut conversions = Vec::new();
+    let mut arg_index = 0;
+    let mut i = 0;
+    while i < format.len() {
+        i += 1;
+        if format[i - 1] != b'%' {
+            continue;
+        }
+        if format.get(i) == Some(&b'%') {
+            i += 1;
+            continue;
+        }
+        // Skip the flags, width and precision
+        while let Some(c) = format.get(i) {
+            if !(c.is_ascii_digit() || b"-+ #.".contains(c)) {
+                break;
+            }
+            i += 1;
+        }
+        if format.get(i) == Some(&b's') {
+            conversions.push(arg_index);
+        }
+        arg_index += 1;
+        i += 1;
+    }
+    conversions
+}
+
+// Implements `string.format`, following the rules of C's `sprintf` for each supported conversion.
+fn format_string<'gc>(format: &[u8], args: &[Value<'gc>]) -> Result<Vec<u8>, FormatError> {
+    let mut out = Vec::new();
+    let mut args = args.iter().cloned();
+    let mut arg_index = 1;
+    let mut i = 0;
+
+    while i < format.len() {
+        let c = format[i];
+        i += 1;
+        if c != b'%' {
+            out.push(c);
+            continue;
+        }
+
+        if format.get(i) == Some(&b'%') {
+            out.push(b'%');
+            i += 1;
+            continue;
+        }
+
+        let mut spec = FormatSpec::default();
+        let flags_start = i;
+        while let Some(&c) = format.get(i) {
+            match c {
+                b'-' => spec.left_align = true,
+                b'+' => spec.plus_sign = true,
+                b' ' => spec.space_sign = true,
+                b'#' => spec.alternate = true,
+                b'0' => spec.zero_pad = true,
+                _ => break,
+            }
+            i += 1;
+        }
+        if i - flags_start > 5 {
+            return Err(FormatError::Message(
+                "invalid format (repeated flags)".to_owned(),
+            ));
+        }
+
+        let read_number = |i: &mut usize| {
+            let mut n: usize = 0;
+            let mut digits = 0;
+            while let Some(&c) = format.get(*i) {
+                if !c.is_ascii_digit() {
+                    break;
+                }
+                n = n.saturating_mul(10).saturating_add((c - b'0') as usize);
+                digits += 1;
+                *i += 1;
+            }
+            (n, digits)
+        };
+
+        let (width, width_digits) = read_number(&mut i);
+        spec.width = width;
+        let mut precision_digits = 0;
+        if format.get(i) == Some(&b'.') {
+            i += 1;
+            let (precision, digits) = read_number(&mut i);
+            spec.precision = Some(precision);
+            precision_digits = digits;
+        }
+        if width_digits > 2 || precision_digits > 2 {
+            return Err(FormatError::Message(
+                "invalid format (width or precision too long)".to_owned(),
+            ));
+        }
+
+        let conversion = match format.get(i) {
+            Some(&c) => c,
+            None => {
+                return Err(FormatError::Message(
+                    "invalid conversion '%' to 'format'".to_owned(),
+                ));
+            }
+        };
+        i += 1;
+
+        let arg = match args.next() {
+            Some(arg) => arg,
+            None => {
+                return Err(FormatError::Message(format!(
+                    "bad argument #{} to 'format' (no value)",
+                    arg_index + 1
+                )));
+            }
+        };
+        arg_index += 1;
+
+        match conversion {
+            b'd' | b'i' => {
+                let n = integer_arg(arg, None)?;
+                let digits = integer_digits(n.wrapping_abs() as u64, 10, false, spec.precision);
+                spec.pad(
+                    &mut out,
+                    spec.sign(n < 0),
+                    &digits,
+                    spec.precision.is_none(),
+                );
+            }
+            b'u' | b'o' | b'x' | b'X' => {
+                let n = integer_arg(arg, None)? as u64;
+                let (radix, prefix): (u32, &[u8]) = match conversion {
+                    b'u' => (10, b""),
+                    b'o' => (8, b""),
+                    b'x' => (16, b"0x"),
+                    _ => (16, b"0X"),
+                };
+                let mut digits = integer_digits(n, radix, conversion == b'X', spec.precision);
+                let prefix = if spec.alternate && n != 0 && radix == 16 {
+                    prefix
+                } else {
+                    b""
+                };
+                if spec.alternate && radix == 8 && digits.first() != Some(&b'0') {
+                    digits.insert(0, b'0');
+                }
+                spec.pad(&mut out, prefix, &digits, spec.precision.is_none());
+            }
+            b'c' => {
+                let c = integer_arg(arg, None)?;
+                spec.pad(&mut out, b"", &[c as u8], false);
+            }
+            b'a' | b'A' | b'e' | b'E' | b'f' | b'F' | b'g' | b'G' => {
+                let n = arg.to_number().ok_or(TypeError {
+                    expected: "number",
+                    found: arg.type_name(),
+                })?;
+                let upper = conversion.is_ascii_uppercase();
+                let sign = spec.sign(n.is_sign_negative() && !n.is_nan());
+
+                if !n.is_finite() {
+                    let body: &[u8] = match (n.is_nan(), upper) {
+                        (true, false) => b"nan",
+                        (true, true) => b"NAN",
+                        (false, false) => b"inf",
+                        (false, true) => b"INF",
+                    };
+                    spec.pad(&mut out, sign, body, false);
+                    continue;
+                }
+
+                let n = n.abs();
+                match conversion {
+                    b'a' | b'A' => {
+                        let (prefix, body) =
+                            format_hex_float(n, spec.precision, upper, spec.alternate);
+                        let mut full_prefix = sign.to_vec();
+                        full_prefix.extend_from_slice(prefix);
+                        spec.pad(&mut out, &full_prefix, body.as_bytes(), true);
+                    }
+                    b'e' | b'E' => {
+                        let body =
+                            format_exponent(n, spec.precision.unwrap_or(6), upper, spec.alternate);
+                        spec.pad(&mut out, sign, body.as_bytes(), true);
+                    }
+                    b'f' | b'F' => {
+                        let body = format_fixed(n, spec.precision.unwrap_or(6), spec.alternate);
+                        spec.pad(&mut out, sign, body.as_bytes(), true);
+                    }
+                    _ => {
+                        let body = format_general(n, spec.precision, upper, spec.alternate);
+                        spec.pad(&mut out, sign, body.as_bytes(), true);
+                    }
+                }
+            }
+            b's' => {
+                let mut s = Vec::new();
+                arg.display(&mut s).unwrap();
+                if let Some(precision) = spec.precision {
+                    s.truncate(precision);
+                }
+                spec.pad(&mut out, b"", &s, false);
+            }
+            b'q' => {
+                if format[flags_start..i - 1].len() != 0 {
+                    return Err(FormatError::Message(
+                        "specifier '%q' cannot have modifiers".to_owned(),
+                    ));
+                }
+                quote_value(&mut out, arg)?;
+            }
+            c => {
+                return Err(FormatError::Message(format!(
+                    "invalid conversion '%{}' to 'format'",
+                    c as char
+                )));
+            }
+        }
+    }
+
+    Ok(out)
+}
+
+// Formats the digits of an unsigned integer, with the given minimum number of digits.
+fn integer_digits(n: u64, radix: u32, upper: bool, precision: Option<usize>) -> Vec<u8> {
+    let mut digits = match radix {
+        8 => format!("{:o}", n),
+        16 if upper => format!("{:X}", n),
+        16 => format!("{:x}", n),
+        _ => format!("{}", n),
+    }
+    .into_bytes();
+
+    match precision {
+        Some(0) if n == 0 => digits.clear(),
+        Some(precision) if precision > digits.len() => {
+            let zeros = precision - digits.len();
+            digits.splice(0..0, (0..zeros).map(|_| b'0'));
+        }
+        _ => {}
+    }
+    digits
+}
+
+// The following float formatting functions expect a finite, non-negative number.  The sign is
+// handled separately.
+
+fn format_fixed(n: f64, precision: usize, alternate: bool) -> std::string::String {
+    let mut s = format!("{:.*}", precision, n);
+    if alternate && precision == 0 {
+        s.push('.');
+    }
+    s
+}
+
+fn format_exponent(n: f64, precision: usize, upper: bool, alternate: bool) -> std::string::String {
+    let s = format!("{:.*e}", precision, n);
+    let e = s.find('e').unwrap();
+    let exponent: i32 = s[e + 1..].parse().unwrap();
+
+    let mut res = s[..e].to_owned();
+    if alternate && precision == 0 {
+        res.push('.');
+    }
+    res.push(if upper { 'E' } else { 'e' });
+    res.push(if exponent < 0 { '-' } else { '+' });
+    write!(res, "{:02}", exponent.abs()).unwrap();
+    res
+}
+
+fn format_general(
+    n: f64,
+    precision: Option<usize>,
+    upper: bool,
+    alternate: bool,
+) -> std::string::String {
+    let precision = match precision {
+        None => 6,
+        Some(0) => 1,
+        Some(p) => p,
+    };
+
+    let exponent = if n == 0.0 {
+        0
+    } else {
+        let s = format!("{:.*e}", precision - 1, n);
+        s[s.find('e').unwrap() + 1..].parse::<i32>().unwrap()
+    };
+
+    let mut res = if exponent >= -4 && exponent < precision as i32 {
+        format_fixed(n, (precision as i32 - 1 - exponent) as usize, alternate)
+    } else {
+        format_exponent(n, precision - 1, upper, alternate)
+    };
+
+    if !alternate {
+        let mantissa_end = res.find(|c| c == 'e' || c == 'E').unwrap_or(res.len());
+        if res[..mantissa_end].contains('.') {
+            let trimmed = res[..mantissa_end]
+                .trim_end_matches('0')
+                .trim_end_matches('.');
+            res = format!("{}{}", trimmed, &res[mantissa_end..]);
+        }
+    }
+    res
+}
+
+// Formats a float in the C99 hexadecimal format, returning the "0x" prefix and the rest of the
+// number separately.
+fn format_hex_float(
+    n: f64,
+    precision: Option<usize>,
+    upper: bool,
+    alternate: bool,
+) -> (&'static [u8], std::string::String) {
+    const FRACTION_DIGITS: usize = 13;
+
+    let bits = n.to_bits();
+    let biased_exponent = ((bits >> 52) & 0x7ff) as i32;
+    let mut fraction = bits & ((1 << 52) - 1);
+    let (mut leading, exponent) = if biased_exponent == 0 {
+        if fraction == 0 {
+            (0, 0)
+        } else {
+            (0, -1022)
+        }
+    } else {
+        (1, biased_exponent - 1023)
+    };
+
+    let digits = match precision {
+        None => format!("{:013x}", fraction)
+            .trim_end_matches('0')
+            .to_owned(),
+        Some(precision) if precision < FRACTION_DIGITS => {
+            let shift = 4 * (FRACTION_DIGITS - precision) as u32;
+            let remainder = fraction & ((1 << shift) - 1);
+            let half = 1 << (shift - 1);
+            fraction >>= shift;
+            if remainder > half || (remainder == half && fraction & 1 == 1) {
+                fraction += 1;
+                if fraction >> (4 * precision) != 0 {
+                    fraction = 0;
+                    leading += 1;
+                }
+            }
+            if precision == 0 {
+                std::string::String::new()
+            } else {
+                format!("{:0width$x}", fraction, width = precision)
+            }
+        }
+        Some(precision) => format!(
+            "{:013x}{}",
+            fraction,
+            "0".repeat(precision - FRACTION_DIGITS)
+        ),
+    };
+
+    let mut body = format!("{}", leading);
+    if !digits.is_empty() || alternate {
+        body.push('.');
+    }
+    body.push_str(&digits);
+    write!(body, "p{:+}", exponent).unwrap();
+
+    if upper {
+        (b"0X", body.to_ascii_uppercase())
+    } else {
+        (b"0x", body)
+    }
+}
+
+// Writes the given value as a Lua literal which reads back as the same value, for `%q`.
+fn quote_value<'gc>(out: &mut Vec<u8>, value: Value<'gc>) -> Result<(), FormatError> {
+    match value {
+        Value::String(s) => {
+            let s = s.as_bytes();
+            out.push(b'"');
+            for (i, &c) in s.iter().enumerate() {
+                match c {
+                    b'"' | b'\\' => {
+                        out.push(b'\\');
+                        out.push(c);
+                    }
+                    b'\n' => out.extend_from_slice(b"\\\n"),
+                    c if c.is_ascii_control() => {
+                        if s.get(i + 1).map(u8::is_ascii_digit).unwrap_or(false) {
+                            write!(out, "\\{:03}", c).unwrap();
+                        } else {
+                            write!(out, "\\{}", c).unwrap();
+                        }
+                    }
+                    c => out.push(c),
+                }
+            }
+            out.push(b'"');
+        }
+        Value::Integer(i) => {
+            if i == i64::MIN {
+                write!(out, "0x{:x}", i).unwrap();
+            } else {
+                write!(out, "{}", i).unwrap();
+            }
+        }
+        Value::Number(n) => {
+            if n == f64::INFINITY {
+                out.extend_from_slice(b"1e9999");
+            } else if n == f64::NEG_INFINITY {
+                out.extend_from_slice(b"-1e9999");
+            } else if n.is_nan() {
+                out.extend_from_slice(b"(0/0)");
+            } else {
+                if n.is_sign_negative() {
+                    out.push(b'-');
+                }
+                let (prefix, body) = format_hex_float(n.abs(), None, false, false);
+                out.extend_from_slice(prefix);
+                out.extend_from_slice(body.as_bytes());
+            }
+        }
+        Value::Nil | Value::Boolean(_) => value.display(out).unwrap(),
+        _ => {
+            return Err(FormatError::Message(
+                "bad argument to 'format' (value has no literal form)".to_owned(),
+            ));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,367 @@
+use std::i64;
+
+use gc_arena::{Collect, MutationContext};
+use gc_sequence as sequence;
+
+use crate::{
+    meta_ops::{self, MetaCall, MetaMethod, MetaResult},
+    Callback, CallbackResult, Continuation, Error, Function, Root, RuntimeError, String, Table,
+    TypeError, Value,
+};
+
+use super::string::{integer_arg, new_callback, runtime_error, string_arg};
+
+// The maximum number of values that `table.unpack` will return.
+const MAX_UNPACK: i64 = 1 << 20;
+
+pub fn load_table<'gc>(mc: MutationContext<'gc, '_>, _: Root<'gc>, env: Table<'gc>) {
+    let table = Table::new(mc);
+
+    table
+        .set(
+            mc,
+            String::new_static(b"insert"),
+            new_callback(mc, |mc, args| {
+                let t = table_arg(args.get(0).cloned().unwrap_or(Value::Nil))?;
+                let end = t.length() + 1;
+                match args.len() {
+                    2 => {
+                        t.set(mc, end, args[1])?;
+                    }
+                    3 => {
+                        let pos = integer_arg(args[1], None)?;
+                        if pos < 1 || pos > end {
+                            return Err(RuntimeError(Value::String(String::new_static(
+                                b"bad argument #2 to 'insert' (position out of bounds)",
+                            )))
+                            .into());
+                        }
+                        for i in (pos + 1..=end).rev() {
+                            t.set(mc, i, t.get(i - 1))?;
+                        }
+                        t.set(mc, pos, args[2])?;
+                    }
+                    _ => {
+                        return Err(RuntimeError(Value::String(String::new_static(
+                            b"wrong number of arguments to 'insert'",
+                        )))
+                        .into());
+                    }
+                }
+                Ok(CallbackResult::Return(vec![]))
+            }),
+        )
+        .unwrap();
+
+    table
+        .set(
+            mc,
+            String::new_static(b"remove"),
+            new_callback(mc, |mc, args| {
+                let t = table_arg(args.get(0).cloned().unwrap_or(Value::Nil))?;
+                let size = t.length();
+                let mut pos = integer_arg(args.get(1).cloned().unwrap_or(Value::Nil), Some(size))?;
+                if pos != size && (pos < 1 || pos > size + 1) {
+                    return Err(RuntimeError(Value::String(String::new_static(
+                        b"bad argument #2 to 'remove' (position out of bounds)",
+                    )))
+                    .into());
+                }
+                let removed = t.get(pos);
+                while pos < size {
+                    t.set(mc, pos, t.get(pos + 1))?;
+                    pos += 1;
+                }
+                t.set(mc, pos, Value::Nil)?;
+                Ok(CallbackResult::Return(vec![removed]))
+            }),
+        )
+        .unwrap();
+
+    table
+        .set(
+            mc,
+            String::new_static(b"concat"),
+            new_callback(mc, |mc, args| {
+                let t = table_arg(args.get(0).cloned().unwrap_or(Value::Nil))?;
+                let sep = match args.get(1).cloned().unwrap_or(Value::Nil) {
+                    Value::Nil => Vec::new(),
+                    sep => string_arg(sep)?,
+                };
+                let i = integer_arg(args.get(2).cloned().unwrap_or(Value::Nil), Some(1))?;
+                let j = integer_arg(args.get(3).cloned().unwrap_or(Value::Nil), Some(t.length()))?;
+
+                let mut res = Vec::new();
+                let mut k = i;
+                while k <= j {
+                    match t.get(k) {
+                        v @ Value::String(_) | v @ Value::Integer(_) | v @ Value::Number(_) => {
+                            v.display(&mut res).unwrap();
+                        }
+                        v => {
+                            return Err(runtime_error(
+                                mc,
+                                &format!(
+                                    "invalid value (at index {}) in table for 'concat' (a {})",
+                                    k,
+                                    v.type_name()
+                                ),
+                            ));
+                        }
+                    }
+                    if k == j {
+                        break;
+                    }
+                    res.extend_from_slice(&sep);
+                    k += 1;
+                }
+                Ok(CallbackResult::Return(vec![Value::String(String::new(
+                    mc, &res,
+                ))]))
+            }),
+        )
+        .unwrap();
+
+    table
+        .set(
+            mc,
+            String::new_static(b"pack"),
+            new_callback(mc, |mc, args| {
+                let t = Table::new(mc);
+                for (i, &arg) in args.iter().enumerate() {
+                    t.set(mc, i as i64 + 1, arg)?;
+                }
+                t.set(mc, String::new_static(b"n"), args.len() as i64)?;
+                Ok(CallbackResult::Return(vec![Value::Table(t)]))
+            }),
+        )
+        .unwrap();
+
+    let unpack = Callback::new_immediate(mc, |args| {
+        let t = table_arg(args.get(0).cloned().unwrap_or(Value::Nil))?;
+        let i = integer_arg(args.get(1).cloned().unwrap_or(Value::Nil), Some(1))?;
+        let j = integer_arg(args.get(2).cloned().unwrap_or(Value::Nil), Some(t.length()))?;
+        if i > j {
+            return Ok(CallbackResult::Return(vec![]));
+        }
+        match j.checked_sub(i) {
+            Some(n) if n < MAX_UNPACK => {}
+            _ => {
+                return Err(RuntimeError(Value::String(String::new_static(
+                    b"too many results to unpack",
+                )))
+                .into());
+            }
+        }
+        Ok(CallbackResult::Return((i..=j).map(|k| t.get(k)).collect()))
+    });
+    table
+        .set(mc, String::new_static(b"unpack"), unpack)
+        .unwrap();
+    // Lua 5.1 compatibility
+    env.set(mc, String::new_static(b"unpack"), unpack).unwrap();
+
+    table
+        .set(
+            mc,
+            String::new_static(b"move"),
+            new_callback(mc, |mc, args| {
+                let a1 = table_arg(args.get(0).cloned().unwrap_or(Value::Nil))?;
+                let f = integer_arg(args.get(1).cloned().unwrap_or(Value::Nil), None)?;
+                let e = integer_arg(args.get(2).cloned().unwrap_or(Value::Nil), None)?;
+                let t = integer_arg(args.get(3).cloned().unwrap_or(Value::Nil), None)?;
+                let a2 = match args.get(4).cloned().unwrap_or(Value::Nil) {
+                    Value::Nil => a1,
+                    a2 => table_arg(a2)?,
+                };
+
+                if e >= f {
+                    if f <= 0 && e >= i64::MAX + f {
+                        return Err(RuntimeError(Value::String(String::new_static(
+                            b"bad argument #3 to 'move' (too many elements to move)",
+                        )))
+                        .into());
+                    }
+                    let n = e - f;
+                    if t > i64::MAX - n {
+                        return Err(RuntimeError(Value::String(String::new_static(
+                            b"bad argument #4 to 'move' (destination wrap around)",
+                        )))
+                        .into());
+                    }
+                    if t > e || t <= f || a1 != a2 {
+                        for i in 0..=n {
+                            a2.set(mc, t + i, a1.get(f + i))?;
+                        }
+                    } else {
+                        for i in (0..=n).rev() {
+                            a2.set(mc, t + i, a1.get(f + i))?;
+                        }
+                    }
+                }
+                Ok(CallbackResult::Return(vec![Value::Table(a2)]))
+            }),
+        )
+        .unwrap();
+
+    table
+        .set(
+            mc,
+            String::new_static(b"sort"),
+            new_callback(mc, |mc, args| {
+                let t = table_arg(args.get(0).cloned().unwrap_or(Value::Nil))?;
+                let comparator = match args.get(1).cloned().unwrap_or(Value::Nil) {
+                    Value::Nil => None,
+                    value => {
+                        let callable = match value {
+                            Value::Function(_) => true,
+                            value => !matches!(
+                                meta_ops::get_metamethod(value, MetaMethod::Call),
+                                Value::Nil
+                            ),
+                        };
+                        if !callable {
+                            return Err(runtime_error(
+                                mc,
+                                &format!(
+                                    "bad argument #2 to 'sort' (function expected, got {})",
+                                    value.type_name()
+                                ),
+                            ));
+                        }
+                        Some(meta_ops::call(value)?)
+                    }
+                };
+
+                let len = t.length();
+                if len >= i32::MAX as i64 {
+                    return Err(RuntimeError(Value::String(String::new_static(
+                        b"bad argument #1 to 'sort' (array too big)",
+                    )))
+                    .into());
+                }
+                let values: Vec<Value<'gc>> = (1..=len).map(|i| t.get(i)).collect();
+                sort_continue(
+                    mc,
+                    Sort {
+                        table: t,
+                        comparator,
+                        buffer: Vec::with_capacity(values.len()),
+                        values,
+                        width: 1,
+                        left: 0,
+                        i: 0,
+                        j: 1,
+                    },
+                )
+            }),
+        )
+        .unwrap();
+
+    env.set(mc, String::new_static(b"table"), table).unwrap();
+}
+
+fn table_arg<'gc>(value: Value<'gc>) -> Result<Table<'gc>, TypeError> {
+    match value {
+        Value::Table(table) => Ok(table),
+        value => Err(TypeError {
+            expected: "table",
+            found: value.type_name(),
+        }),
+    }
+}
+
+// The state of an in-progress `table.sort`, which must be suspended whenever the comparator or a
+// `__lt` metamethod is called.
+//
+// Sorting is done with a bottom-up merge sort, merging runs of `width` values from `values` into
+// `buffer`.  The current merge is of the runs starting at `left` and `left + width`, with `i` and
+// `j` being the next unmerged values of each run.
+#[derive(Collect)]
+#[collect(empty_drop)]
+struct Sort<'gc> {
+    table: Table<'gc>,
+    // The comparator function, along with the values that its `__call` chain prepends to its
+    // arguments when the comparator given was not itself a function.
+    comparator: Option<(Function<'gc>, Vec<Value<'gc>>)>,
+    values: Vec<Value<'gc>>,
+    buffer: Vec<Value<'gc>>,
+    width: usize,
+    left: usize,
+    i: usize,
+    j: usize,
+}
+
+fn sort_continue<'gc>(
+    mc: MutationContext<'gc, '_>,
+    mut sort: Sort<'gc>,
+) -> Result<CallbackResult<'gc>, Error<'gc>> {
+    let len = sort.values.len();
+    while sort.width < len {
+        let mid = (sort.left + sort.width).min(len);
+        let end = (sort.left + 2 * sort.width).min(len);
+
+        if sort.i < mid && sort.j < end {
+            let (a, b) = (sort.values[sort.j], sort.values[sort.i]);
+            let res = match &sort.comparator {
+                Some((function, prefix)) => MetaResult::Call(MetaCall {
+                    function: *function,
+                    args: prefix
+                        .iter()
+                        .cloned()
+                        .chain([a, b].iter().cloned())
+                        .collect(),
+                }),
+                None => meta_ops::less_than(a, b)?,
+            };
+            match res {
+                MetaResult::Value(v) => sort_merge_next(&mut sort, v.to_bool()),
+                MetaResult::Call(call) => {
+                    return Ok(CallbackResult::TailCall {
+                        function: call.function,
+                        args: call.args,
+                        continuation: Continuation::new_sequence_with(sort, |sort, res| {
+                            let less = res?.get(0).cloned().unwrap_or(Value::Nil).to_bool();
+                            Ok(sequence::from_fn_with(
+                                (sort, less),
+                                |mc, (mut sort, less)| {
+                                    sort_merge_next(&mut sort, less);
+                                    sort_continue(mc, sort)
+                                },
+                            ))
+                        }),
+                    });
+                }
+            }
+        } else {
+            sort.buffer.extend_from_slice(&sort.values[sort.i..mid]);
+            sort.buffer.extend_from_slice(&sort.values[sort.j..end]);
+            sort.left = end;
+            if sort.left >= len {
+                std::mem::swap(&mut sort.values, &mut sort.buffer);
+                sort.buffer.clear();
+                sort.width *= 2;
+                sort.left = 0;
+            }
+            sort.i = sort.left;
+            sort.j = (sort.left + sort.width).min(len);
+        }
+    }
+
+    for (i, &value) in sort.values.iter().enumerate() {
+        sort.table.set(mc, i as i64 + 1, value)?;
+    }
+    Ok(CallbackResult::Return(vec![]))
+}
+
+// Moves the next value of the current merge into the output, taking from the right run only if its
+// value is strictly less than the left one so that the sort is stable.
+fn sort_merge_next<'gc>(sort: &mut Sort<'gc>, right_is_less: bool) {
+    if right_is_less {
+        sort.buffer.push(sort.values[sort.j]);
+        sort.j += 1;
+    } else {
+        sort.buffer.push(sort.values[sort.i]);
+        sort.i += 1;
+    }
+}
@@ -0,0 +1,274 @@
+use gc_arena::MutationContext;
+
+use crate::{
+    lexer::encode_utf8, Callback, CallbackResult, Error, Root, RuntimeError, String, Table, Value,
+};
+
+use super::string::{integer_arg, new_callback, relative_position, string_arg};
+
+// The maximum code point which can be encoded by `utf8.char` or decoded in lax mode.
+const MAX_UTF: u32 = 0x7FFF_FFFF;
+
+// The maximum valid unicode code point, which is the maximum allowed in strict mode.
+const MAX_UNICODE: u32 = 0x10_FFFF;
+
+const CHAR_PATTERN: &[u8] = b"[\0-\x7F\xC2-\xF4][\x80-\xBF]*";
+
+pub fn load_utf8<'gc>(mc: MutationContext<'gc, '_>, _: Root<'gc>, env: Table<'gc>) {
+    let utf8 = Table::new(mc);
+
+    utf8.set(
+        mc,
+        String::new_static(b"charpattern"),
+        String::new_static(CHAR_PATTERN),
+    )
+    .unwrap();
+
+    utf8.set(
+        mc,
+        String::new_static(b"char"),
+        new_callback(mc, |mc, args| {
+            let mut res = Vec::new();
+            for arg in args {
+                match integer_arg(arg, None)? {
+                    c if c >= 0 && c <= MAX_UTF as i64 => encode_utf8(c as u32, &mut res),
+                    _ => return Err(utf8_error(b"bad argument to 'char' (value out of range)")),
+                }
+            }
+            Ok(CallbackResult::Return(vec![Value::String(String::new(
+                mc, &res,
+            ))]))
+        }),
+    )
+    .unwrap();
+
+    utf8.set(
+        mc,
+        String::new_static(b"codepoint"),
+        Callback::new_immediate(mc, |args| {
+            let s = string_arg(args.get(0).cloned().unwrap_or(Value::Nil))?;
+            let i = integer_arg(args.get(1).cloned().unwrap_or(Value::Nil), Some(1))?;
+            let i = relative_position(i, s.len());
+            let j = integer_arg(args.get(2).cloned().unwrap_or(Value::Nil), Some(i))?;
+            let j = relative_position(j, s.len());
+            let strict = !args.get(3).cloned().unwrap_or(Value::Nil).to_bool();
+            if i < 1 {
+                return Err(utf8_error(
+                    b"bad argument #2 to 'codepoint' (out of bounds)",
+                ));
+            }
+            if j > s.len() as i64 {
+                return Err(utf8_error(
+                    b"bad argument #3 to 'codepoint' (out of bounds)",
+                ));
+            }
+
+            let mut res = Vec::new();
+            let mut pos = (i - 1) as usize;
+            while pos < j as usize {
+                let (c, len) = decode_utf8(&s[pos..], strict)
+                    .ok_or_else(|| utf8_error(b"invalid UTF-8 code"))?;
+                res.push(Value::Integer(c as i64));
+                pos += len;
+            }
+            Ok(CallbackResult::Return(res))
+        }),
+    )
+    .unwrap();
+
+    utf8.set(
+        mc,
+        String::new_static(b"len"),
+        Callback::new_immediate(mc, |args| {
+            let s = string_arg(args.get(0).cloned().unwrap_or(Value::Nil))?;
+            let i = integer_arg(args.get(1).cloned().unwrap_or(Value::Nil), Some(1))?;
+            let i = relative_position(i, s.len()) - 1;
+            let j = integer_arg(args.get(2).cloned().unwrap_or(Value::Nil), Some(-1))?;
+            let j = relative_position(j, s.len()) - 1;
+            let strict = !args.get(3).cloned().unwrap_or(Value::Nil).to_bool();
+            if i < 0 || i > s.len() as i64 {
+                return Err(utf8_error(
+                    b"bad argument #2 to 'len' (initial position out of bounds)",
+                ));
+            }
+            if j >= s.len() as i64 {
+                return Err(utf8_error(
+                    b"bad argument #3 to 'len' (final position out of bounds)",
+                ));
+            }
+
+            let mut n = 0;
+            let mut pos = i;
+            while pos <= j {
+                match decode_utf8(&s[pos as usize..], strict) {
+                    Some((_, len)) => pos += len as i64,
+                    None => {
+                        return Ok(CallbackResult::Return(vec![
+                            Value::Nil,
+                            Value::Integer(pos + 1),
+                        ]));
+                    }
+                }
+                n += 1;
+            }
+            Ok(CallbackResult::Return(vec![Value::Integer(n)]))
+        }),
+    )
+    .unwrap();
+
+    utf8.set(
+        mc,
+        String::new_static(b"offset"),
+        Callback::new_immediate(mc, |args| {
+            let s = string_arg(args.get(0).cloned().unwrap_or(Value::Nil))?;
+            let mut n = integer_arg(args.get(1).cloned().unwrap_or(Value::Nil), None)?;
+            let default_i = if n >= 0 { 1 } else { s.len() as i64 + 1 };
+            let i = integer_arg(args.get(2).cloned().unwrap_or(Value::Nil), Some(default_i))?;
+            let mut pos = relative_position(i, s.len()) - 1;
+            if pos < 0 || pos > s.len() as i64 {
+                return Err(utf8_error(
+                    b"bad argument #3 to 'offset' (position out of bounds)",
+                ));
+            }
+
+            let is_continuation = |pos: i64| {
+                s.get(pos as usize)
+                    .map(|&c| c & 0xC0 == 0x80)
+                    .unwrap_or(false)
+            };
+
+            if n == 0 {
+                // Find the beginning of the current byte sequence
+                while pos > 0 && is_continuation(pos) {
+                    pos -= 1;
+                }
+            } else {
+                if is_continuation(pos) {
+                    return Err(utf8_error(b"initial position is a continuation byte"));
+                }
+                if n < 0 {
+                    while n < 0 && pos > 0 {
+                        // Move back to the start of the previous character
+                        pos -= 1;
+                        while pos > 0 && is_continuation(pos) {
+                            pos -= 1;
+                        }
+                        n += 1;
+                    }
+                } else {
+                    // Do not move for the first character
+                    n -= 1;
+                    while n > 0 && pos < s.len() as i64 {
+                        // Move forward to the start of the next character
+                        pos += 1;
+                        while is_continuation(pos) {
+                            pos += 1;
+                        }
+                        n -= 1;
+                    }
+                }
+            }
+
+            Ok(CallbackResult::Return(vec![if n == 0 {
+                Value::Integer(pos + 1)
+            } else {
+                Value::Nil
+            }]))
+        }),
+    )
+    .unwrap();
+
+    let codes_strict = Callback::new_immediate(mc, |args| codes_next(args, true));
+    let codes_lax = Callback::new_immediate(mc, |args| codes_next(args, false));
+    utf8.set(
+        mc,
+        String::new_static(b"codes"),
+        Callback::new_immediate_with(mc, (codes_strict, codes_lax), |&(strict, lax), args| {
+            let s = args.get(0).cloned().unwrap_or(Value::Nil);
+            string_arg(s)?;
+            let iterator = if args.get(1).cloned().unwrap_or(Value::Nil).to_bool() {
+                lax
+            } else {
+                strict
+            };
+            Ok(CallbackResult::Return(vec![
+                iterator.into(),
+                s,
+                Value::Integer(0),
+            ]))
+        }),
+    )
+    .unwrap();
+
+    env.set(mc, String::new_static(b"utf8"), utf8).unwrap();
+}
+
+// The generic-for iterator returned by `utf8.codes`.
+fn codes_next<'gc>(args: Vec<Value<'gc>>, strict: bool) -> Result<CallbackResult<'gc>, Error<'gc>> {
+    let s = string_arg(args.get(0).cloned().unwrap_or(Value::Nil))?;
+    let n = integer_arg(args.get(1).cloned().unwrap_or(Value::Nil), None)?;
+    let is_continuation = |pos: usize| s.get(pos).map(|&c| c & 0xC0 == 0x80).unwrap_or(false);
+
+    let mut pos = if n <= 0 { 0 } else { n as usize - 1 };
+    if n > 0 && pos < s.len() {
+        // Skip the current character
+        pos += 1;
+        while is_continuation(pos) {
+            pos += 1;
+        }
+    }
+    if pos >= s.len() {
+        return Ok(CallbackResult::Return(vec![]));
+    }
+
+    match decode_utf8(&s[pos..], strict) {
+        Some((c, len)) if !is_continuation(pos + len) => Ok(CallbackResult::Return(vec![
+            Value::Integer(pos as i64 + 1),
+            Value::Integer(c as i64),
+        ])),
+        _ => Err(utf8_error(b"invalid UTF-8 code")),
+    }
+}
+
+// Decodes a single UTF-8 sequence from the start of `s`, returning the code point and the length of
+// the sequence.  Overlong encodings are always rejected, and in strict mode so are surrogates and
+// values past `MAX_UNICODE`.
+fn decode_utf8(s: &[u8], strict: bool) -> Option<(u32, usize)> {
+    // The minimum value for each sequence length, to detect overlong encodings
+    const LIMITS: [u32; 6] = [!0, 0x80, 0x800, 0x1_0000, 0x20_0000, 0x400_0000];
+
+    let mut c = *s.get(0)? as u32;
+    let mut res: u32;
+    let mut count = 0;
+    if c < 0x80 {
+        res = c;
+    } else {
+        res = 0;
+        while c & 0x40 != 0 {
+            count += 1;
+            let cc = *s.get(count)? as u32;
+            if cc & 0xC0 != 0x80 {
+                return None;
+            }
+            res = (res << 6) | (cc & 0x3F);
+            c <<= 1;
+        }
+        if count > 5 {
+            return None;
+        }
+        res |= (c & 0x7F) << (count * 5);
+        if res > MAX_UTF || res < LIMITS[count] {
+            return None;
+        }
+    }
+
+    if strict && (res > MAX_UNICODE || (0xD800 <= res && res <= 0xDFFF)) {
+        return None;
+    }
+
+    Some((res, count + 1))
+}
+
+fn utf8_error<'gc>(msg: &'static [u8]) -> Error<'gc> {
+    RuntimeError(Value::String(String::new_static(msg))).into()
+}
@@ -48,10 +48,18 @@ impl<'gc> String<'gc> {
             b[..len].copy_from_slice(s);
             String::Short32(len as u8, Gc::allocate(mc, b))
         } else {
-            String::Long(Gc::allocate(mc, s.to_vec().into_boxed_slice()))
+            String::long(mc, s.to_vec().into_boxed_slice())
         }
     }
 
+    // Long strings own their bytes outside of the allocation, which counts towards the memory limit
+    fn long(mc: MutationContext<'gc, '_>, bytes: Box<[u8]>) -> String<'gc> {
+        let len = bytes.len();
+        let bytes = Gc::allocate(mc, bytes);
+        Gc::set_external_size(mc, bytes, len);
+        String::Long(bytes)
+    }
+
     pub fn new_static(s: &'static [u8]) -> String<'gc> {
         String::Static(s)
     }
@@ -77,9 +85,14 @@ impl<'gc> String<'gc> {
                 Value::Thread(_) => {
                     return Err(StringError::Concat { bad_type: "thread" });
                 }
+                Value::UserData(_) => {
+                    return Err(StringError::Concat {
+                        bad_type: "userdata",
+                    });
+                }
             }
         }
-        Ok(String::Long(Gc::allocate(mc, bytes.into_boxed_slice())))
+        Ok(String::long(mc, bytes.into_boxed_slice()))
     }
 
     pub fn as_bytes(&self) -> &[u8] {
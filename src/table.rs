@@ -5,9 +5,9 @@ use std::{fmt, i64, mem};
 use num_traits::cast;
 use rustc_hash::FxHashMap;
 
-use gc_arena::{Collect, GcCell, MutationContext};
+use gc_arena::{Collect, CollectionContext, Finalizable, GcCell, MutationContext};
 
-use crate::Value;
+use crate::{String, Value};
 
 #[derive(Debug, Copy, Clone, Collect)]
 #[collect(require_copy)]
@@ -60,19 +60,160 @@ impl<'gc> Table<'gc> {
         key: K,
         value: V,
     ) -> Result<Value<'gc>, InvalidTableKey> {
-        self.0.write(mc).set(key.into(), value.into())
+        let mut state = self.0.write(mc);
+        let res = state.set(key.into(), value.into());
+        GcCell::set_external_size(mc, self.0, state.external_size());
+        res
     }
 
     pub fn length(&self) -> i64 {
         self.0.read().length()
     }
+
+    /// Returns the key and value which follow the given key, with the same semantics as Lua's
+    /// `next`.  A nil key returns the first key and value, and `None` is returned after the last.
+    pub fn next<K: Into<Value<'gc>>>(
+        &self,
+        key: K,
+    ) -> Result<Option<(Value<'gc>, Value<'gc>)>, InvalidNextKey> {
+        self.0.read().next(key.into())
+    }
+
+    /// Returns an iterator over the key value pairs of this table.
+    ///
+    /// The table is not borrowed between calls to `Iterator::next`, so existing keys may be
+    /// assigned to (including being set to nil) during iteration.  If new keys are added, the
+    /// iteration order is unspecified and the iterator may end early.
+    pub fn iter(&self) -> TableIter<'gc> {
+        TableIter {
+            table: *self,
+            key: Some(Value::Nil),
+        }
+    }
+
+    pub fn metatable(&self) -> Option<Table<'gc>> {
+        self.0.read().metatable
+    }
+
+    /// Sets the metatable for this table, returning the previous metatable.
+    ///
+    /// The `__mode` field of the metatable is read here, so like in PUC-Rio Lua it should not be
+    /// changed after the metatable is set.  If it contains 'k' the keys of this table are weak, and
+    /// if it contains 'v' the values are.  Entries whose weak key or value is only reachable through
+    /// weak references are removed by the garbage collector, and a value whose key is weak is only
+    /// kept alive while the key is reachable.  Strings are never removed from weak tables.
+    pub fn set_metatable(
+        &self,
+        mc: MutationContext<'gc, '_>,
+        metatable: Option<Table<'gc>>,
+    ) -> Option<Table<'gc>> {
+        // The mode is read before borrowing this table, which may be its own metatable
+        let mode = TableMode::new(metatable);
+        let mut state = self.0.write(mc);
+        state.mode = mode;
+        mem::replace(&mut state.metatable, metatable)
+    }
 }
 
-#[derive(Debug, Collect, Default)]
-#[collect(empty_drop)]
+#[derive(Debug, Clone, Copy, Collect)]
+#[collect(require_copy)]
+pub struct TableIter<'gc> {
+    table: Table<'gc>,
+    // The last key returned, or `None` if iteration has finished
+    key: Option<Value<'gc>>,
+}
+
+impl<'gc> Iterator for TableIter<'gc> {
+    type Item = (Value<'gc>, Value<'gc>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let next = self.table.next(self.key?).ok().and_then(|next| next);
+        self.key = next.map(|(key, _)| key);
+        next
+    }
+}
+
+#[derive(Debug, Clone, Copy, Collect)]
+#[collect(require_static)]
+pub struct InvalidNextKey;
+
+impl StdError for InvalidNextKey {}
+
+impl fmt::Display for InvalidNextKey {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "invalid key to 'next'")
+    }
+}
+
+#[derive(Debug, Default)]
 pub struct TableState<'gc> {
     array: Vec<Value<'gc>>,
-    map: FxHashMap<TableKey<'gc>, Value<'gc>>,
+    // Maps each key in the map part to its index in `entries`.
+    map: FxHashMap<TableKey<'gc>, usize>,
+    // The keys and values of the map part in insertion order.  Setting an existing key to nil leaves
+    // a dead entry with a nil value, so that iteration can continue past it, and dead entries are
+    // only removed when the map part must grow.
+    entries: Vec<(Value<'gc>, Value<'gc>)>,
+    metatable: Option<Table<'gc>>,
+    mode: TableMode,
+}
+
+// Safe, does not implement drop and only skips tracing entries which are removed by `clear_weak`
+unsafe impl<'gc> Collect for TableState<'gc> {
+    fn trace(&self, cc: CollectionContext) {
+        self.metatable.trace(cc);
+
+        let mode = self.mode;
+        if mode != TableMode::default() {
+            cc.defer_weak();
+        }
+
+        for value in &self.array {
+            if !(mode.weak_values && is_collectable(*value)) {
+                value.trace(cc);
+            }
+        }
+
+        for (key, value) in &self.entries {
+            let weak_key = mode.weak_keys && is_collectable(*key);
+            if !weak_key {
+                key.trace(cc);
+            }
+            // The value of an ephemeron entry is only traced once its key is reached
+            if !(mode.weak_values && is_collectable(*value)) && (!weak_key || key.is_reached(cc)) {
+                value.trace(cc);
+            }
+        }
+    }
+
+    fn trace_ephemerons(&self, cc: CollectionContext) {
+        if self.mode.weak_keys && !self.mode.weak_values {
+            for (key, value) in &self.entries {
+                if key.is_reached(cc) {
+                    value.trace(cc);
+                }
+            }
+        }
+    }
+
+    fn clear_weak(&mut self, cc: CollectionContext) {
+        let mode = self.mode;
+        if mode.weak_values {
+            for value in &mut self.array {
+                if !value.is_reached(cc) {
+                    *value = Value::Nil;
+                }
+            }
+        }
+
+        let len = self.entries.len();
+        self.entries.retain(|&(key, value)| {
+            (!mode.weak_keys || key.is_reached(cc)) && (!mode.weak_values || value.is_reached(cc))
+        });
+        if self.entries.len() != len {
+            self.rebuild_map();
+        }
+    }
 }
 
 impl<'gc> TableState<'gc> {
@@ -84,7 +225,7 @@ impl<'gc> TableState<'gc> {
         }
 
         if let Ok(key) = TableKey::new(key) {
-            self.map.get(&key).cloned().unwrap_or(Value::Nil)
+            self.map_get(&key)
         } else {
             Value::Nil
         }
@@ -103,10 +244,13 @@ impl<'gc> TableState<'gc> {
         }
 
         let hash_key = TableKey::new(key)?;
-        if value == Value::Nil {
-            Ok(self.map.remove(&hash_key).unwrap_or(Value::Nil))
-        } else if self.map.len() < self.map.capacity() {
-            Ok(self.map.insert(hash_key, value).unwrap_or(Value::Nil))
+        if let Some(&entry) = self.map.get(&hash_key) {
+            Ok(mem::replace(&mut self.entries[entry].1, value))
+        } else if value == Value::Nil {
+            Ok(Value::Nil)
+        } else if self.entries.len() < self.entries.capacity() {
+            self.insert_entry(hash_key, value);
+            Ok(Value::Nil)
         } else {
             // If a new element does not fit in either the array or map part of the table, we need
             // to grow.  First, we find the total count of array candidate elements across the array
@@ -126,10 +270,12 @@ impl<'gc> TableState<'gc> {
                 }
             }
 
-            for k in self.map.keys() {
-                if let Some(i) = to_array_index(k.0) {
-                    array_counts[highest_bit(i)] += 1;
-                    array_total += 1;
+            for (k, v) in &self.entries {
+                if *v != Value::Nil {
+                    if let Some(i) = to_array_index(*k) {
+                        array_counts[highest_bit(i)] += 1;
+                        array_total += 1;
+                    }
                 }
             }
 
@@ -157,33 +303,37 @@ impl<'gc> TableState<'gc> {
             }
 
             let old_array_size = self.array.len();
-            let old_map_size = self.map.len();
             if optimal_size > old_array_size {
                 // If we're growing the array part, we need to grow the array and take any newly valid
                 // array keys from the map part.
-
                 self.array.reserve(optimal_size - old_array_size);
                 let capacity = self.array.capacity();
                 self.array.resize(capacity, Value::Nil);
+            }
 
-                let array = &mut self.array;
-                self.map.retain(|k, v| {
-                    if let Some(i) = to_array_index(k.0) {
-                        if i < array.len() {
-                            array[i] = *v;
-                            return false;
-                        }
+            // Remove any dead entries and any entries which now belong in the array part, and then
+            // rebuild the key map.
+            let array = &mut self.array;
+            self.entries.retain(|&(k, v)| {
+                if v == Value::Nil {
+                    return false;
+                }
+                if let Some(i) = to_array_index(k) {
+                    if i < array.len() {
+                        array[i] = v;
+                        return false;
                     }
-                    true
-                });
-            } else {
-                // If we aren't growing the array, we're adding a new element to the map that won't
-                // fit in the advertised capacity.  The capacity of std::collections::HashMap is
-                // just a lower-bound, so we may actually be able to insert past the capacity
-                // without the advertised capacity growing, so to make sure that we don't try to
-                // grow repeatedly, we need to make sure the capacity actually increases.  We simply
-                // double the capacity here.
-                self.map.reserve(old_map_size);
+                }
+                true
+            });
+            self.rebuild_map();
+
+            if optimal_size <= old_array_size {
+                // If we aren't growing the array, we're adding a new element to the map part, so
+                // we make sure that its capacity actually increases to avoid doing this again on
+                // the next insertion.
+                self.entries.reserve(self.entries.len().max(1));
+                self.map.reserve(self.entries.capacity() - self.map.len());
             }
 
             // Now we can insert the new key value pair
@@ -192,8 +342,48 @@ impl<'gc> TableState<'gc> {
                     return Ok(mem::replace(&mut self.array[index], value));
                 }
             }
-            Ok(self.map.insert(hash_key, value).unwrap_or(Value::Nil))
+            self.insert_entry(hash_key, value);
+            Ok(Value::Nil)
+        }
+    }
+
+    /// Returns the key and value which follow the given key in this table's iteration order, or
+    /// `None` if the given key is the last.  A nil key returns the first key and value.
+    ///
+    /// Like Lua's `next`, iteration continues correctly if existing keys are assigned to (including
+    /// being set to nil) during traversal, but the order is unspecified if new keys are added.
+    /// Returns an error if the given key is not in the table.
+    pub fn next(
+        &self,
+        key: Value<'gc>,
+    ) -> Result<Option<(Value<'gc>, Value<'gc>)>, InvalidNextKey> {
+        // The position to continue from, counting array indexes first and then map entries
+        let start = if key == Value::Nil {
+            0
+        } else {
+            match to_array_index(key) {
+                Some(index) if index < self.array.len() => index + 1,
+                _ => match TableKey::new(key).ok().and_then(|key| self.map.get(&key)) {
+                    Some(&entry) => self.array.len() + entry + 1,
+                    None => return Err(InvalidNextKey),
+                },
+            }
+        };
+
+        for i in start..self.array.len() {
+            if self.array[i] != Value::Nil {
+                return Ok(Some((Value::Integer(i as i64 + 1), self.array[i])));
+            }
+        }
+
+        let start = start.saturating_sub(self.array.len());
+        for &(k, v) in &self.entries[start..] {
+            if v != Value::Nil {
+                return Ok(Some((k, v)));
+            }
         }
+
+        Ok(None)
     }
 
     /// Returns a 'border' for this table.
@@ -223,7 +413,7 @@ impl<'gc> TableState<'gc> {
         if !self.array.is_empty() && self.array[array_len as usize - 1] == Value::Nil {
             // If the array part ends in a Nil, there must be a border inside it
             binary_search(0, array_len, |i| self.array[i as usize - 1] == Value::Nil)
-        } else if self.map.is_empty() {
+        } else if self.entries.is_empty() {
             // If there is no border in the arraay but the map part is empty, then the array length
             // is a border
             array_len
@@ -232,7 +422,7 @@ impl<'gc> TableState<'gc> {
             // in the map part as the max for a binary search.
             let min = array_len;
             let mut max = array_len.checked_add(1).unwrap();
-            while self.map.contains_key(&TableKey(Value::Integer(max))) {
+            while self.map_get(&TableKey(Value::Integer(max))) != Value::Nil {
                 if max == i64::MAX {
                     // If we can't find a nil entry by doubling, then the table is pathalogical.  We
                     // return the favor with a pathalogical answer: i64::MAX + 1 can't exist in the
@@ -248,10 +438,64 @@ impl<'gc> TableState<'gc> {
 
             // We have found a max where table[max] == nil, so we can now binary search
             binary_search(min, max, |i| {
-                !self.map.contains_key(&TableKey(Value::Integer(i)))
+                self.map_get(&TableKey(Value::Integer(i))) == Value::Nil
             })
         }
     }
+
+    // The memory owned by the array and map parts, counting one control byte per map bucket
+    fn external_size(&self) -> usize {
+        self.array.capacity() * mem::size_of::<Value>()
+            + self.map.capacity() * (mem::size_of::<(TableKey, usize)>() + 1)
+            + self.entries.capacity() * mem::size_of::<(Value, Value)>()
+    }
+
+    fn map_get(&self, key: &TableKey<'gc>) -> Value<'gc> {
+        self.map
+            .get(key)
+            .map(|&entry| self.entries[entry].1)
+            .unwrap_or(Value::Nil)
+    }
+
+    fn rebuild_map(&mut self) {
+        self.map.clear();
+        for (i, &(k, _)) in self.entries.iter().enumerate() {
+            self.map.insert(TableKey(k), i);
+        }
+    }
+
+    fn insert_entry(&mut self, key: TableKey<'gc>, value: Value<'gc>) {
+        self.entries.push((key.0, value));
+        self.map.insert(key, self.entries.len() - 1);
+    }
+}
+
+// Which parts of a table's entries are weak, set from the `__mode` field of its metatable
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct TableMode {
+    weak_keys: bool,
+    weak_values: bool,
+}
+
+impl TableMode {
+    fn new<'gc>(metatable: Option<Table<'gc>>) -> TableMode {
+        match metatable.map(|mt| mt.get(String::new_static(b"__mode"))) {
+            Some(Value::String(mode)) => TableMode {
+                weak_keys: mode.as_bytes().contains(&b'k'),
+                weak_values: mode.as_bytes().contains(&b'v'),
+            },
+            _ => TableMode::default(),
+        }
+    }
+}
+
+// Whether the given value is an object which may be removed from a weak table.  Strings are values
+// in Lua, so they are never removed.
+fn is_collectable<'gc>(value: Value<'gc>) -> bool {
+    matches!(
+        value,
+        Value::Table(_) | Value::Function(_) | Value::Thread(_) | Value::UserData(_)
+    )
 }
 
 // Value which implements Hash and Eq, and cannot contain Nil or NaN values.
@@ -293,6 +537,10 @@ impl<'gc> Hash for TableKey<'gc> {
                 Hash::hash(&7, state);
                 t.hash(state);
             }
+            Value::UserData(u) => {
+                Hash::hash(&8, state);
+                u.hash(state);
+            }
         }
     }
 }
@@ -0,0 +1,110 @@
+use std::cell::Cell;
+use std::time::Instant;
+
+use gc_arena::Collect;
+
+use crate::LimitError;
+
+/// Limits on the resources that scripts may use, for running untrusted code.
+///
+/// When a limit is exceeded, the running thread is stopped with `Error::LimitError`, which cannot
+/// be caught by `pcall` or `coroutine.resume` and always unwinds back to the host.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Limits {
+    /// The maximum number of VM instructions that may be run.
+    pub instructions: Option<u64>,
+    /// The point in time after which no more VM instructions may be run.
+    pub deadline: Option<Instant>,
+    /// The maximum number of bytes that may be allocated in the arena, including memory used by
+    /// the standard library.  This counts the buffers owned by tables, strings, functions and thread
+    /// stacks, but not memory owned by userdata.  The arena is fully collected once before this
+    /// limit is enforced.
+    pub memory: Option<usize>,
+}
+
+/// Tracks the resources used by every thread sharing this budget against its `Limits`.
+///
+/// Threads check their budget before each run of the VM, so the instruction limit is exact, but a
+/// deadline or memory limit may be overshot by up to a few hundred instructions.  Time spent in
+/// callbacks is not interrupted.
+#[derive(Debug, Default, Collect)]
+#[collect(require_static)]
+pub struct ExecutionBudget {
+    limits: Cell<Limits>,
+    instructions: Cell<u64>,
+    // Set after requesting a full collection because the memory limit was exceeded, if the limit
+    // is still exceeded afterwards it is enforced
+    collection_requested: Cell<bool>,
+}
+
+impl ExecutionBudget {
+    pub fn new(limits: Limits) -> ExecutionBudget {
+        ExecutionBudget {
+            limits: Cell::new(limits),
+            ..ExecutionBudget::default()
+        }
+    }
+
+    pub fn limits(&self) -> Limits {
+        self.limits.get()
+    }
+
+    /// Sets new limits and resets the count of instructions run.
+    pub fn set_limits(&self, limits: Limits) {
+        self.limits.set(limits);
+        self.instructions.set(0);
+        self.collection_requested.set(false);
+    }
+
+    /// The number of VM instructions run since the limits were last set.
+    pub fn instructions(&self) -> u64 {
+        self.instructions.get()
+    }
+
+    // Returns the number of instructions that may be run next, at most `granularity`, or `None`
+    // if a full collection should happen before running any.
+    pub(crate) fn allowance(
+        &self,
+        total_allocated: usize,
+        granularity: u32,
+    ) -> Result<Option<u32>, LimitError> {
+        let limits = self.limits.get();
+
+        if let Some(memory) = limits.memory {
+            if total_allocated > memory {
+                if self.collection_requested.get() {
+                    // The garbage left behind by the failed script is collected before the limit
+                    // is enforced again
+                    self.collection_requested.set(false);
+                    return Err(LimitError::Memory);
+                }
+                self.collection_requested.set(true);
+                return Ok(None);
+            }
+            self.collection_requested.set(false);
+        }
+
+        if let Some(deadline) = limits.deadline {
+            if Instant::now() >= deadline {
+                return Err(LimitError::Deadline);
+            }
+        }
+
+        match limits.instructions {
+            Some(limit) => {
+                let remaining = limit.saturating_sub(self.instructions.get());
+                if remaining == 0 {
+                    Err(LimitError::Instructions)
+                } else {
+                    Ok(Some(remaining.min(granularity as u64) as u32))
+                }
+            }
+            None => Ok(Some(granularity)),
+        }
+    }
+
+    pub(crate) fn consume(&self, instructions: u32) {
+        self.instructions
+            .set(self.instructions.get().saturating_add(instructions as u64));
+    }
+}
@@ -75,6 +75,7 @@ impl fmt::Display for BadThreadMode {
 pub enum ThreadError {
     ExpectedVariable(bool),
     BadCall(TypeError),
+    CallChainTooLong,
     BadYield,
 }
 
@@ -90,7 +91,29 @@ impl fmt::Display for ThreadError {
                 write!(fmt, "operation expects constant lua thread")
             }
             ThreadError::BadCall(type_error) => fmt::Display::fmt(type_error, fmt),
+            ThreadError::CallChainTooLong => write!(fmt, "'__call' chain too long; possible loop"),
             ThreadError::BadYield => write!(fmt, "yield from unyieldable function"),
         }
     }
 }
+
+/// A limit set by `Limits` was exceeded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Collect)]
+#[collect(require_static)]
+pub enum LimitError {
+    Instructions,
+    Deadline,
+    Memory,
+}
+
+impl StdError for LimitError {}
+
+impl fmt::Display for LimitError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LimitError::Instructions => write!(fmt, "instruction limit exceeded"),
+            LimitError::Deadline => write!(fmt, "deadline exceeded"),
+            LimitError::Memory => write!(fmt, "memory limit exceeded"),
+        }
+    }
+}
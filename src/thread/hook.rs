@@ -0,0 +1,67 @@
+use gc_arena::Collect;
+
+use crate::{Function, LineNumber};
+
+/// A debug hook function and the events it is called for, like the hooks set by `lua_sethook` in
+/// PUC-Rio Lua.
+///
+/// The hook is called with the name of the event as its first argument, and for line events, the
+/// new line number as its second argument.  Hooks are only called for Lua functions, and are
+/// disabled while the hook function itself is running.
+#[derive(Debug, Clone, Copy, Collect)]
+#[collect(require_copy)]
+pub struct Hook<'gc> {
+    pub function: Function<'gc>,
+    pub mask: HookMask,
+    /// If non-zero, the hook is also called with a "count" event after every `count` instructions.
+    pub count: u32,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Collect)]
+#[collect(require_static)]
+pub struct HookMask {
+    /// Call the hook when a Lua function is entered
+    pub call: bool,
+    /// Call the hook just before a Lua function returns
+    pub ret: bool,
+    /// Call the hook when a Lua function starts executing a new line, or jumps back to an earlier
+    /// instruction
+    pub line: bool,
+}
+
+#[derive(Debug, Clone, Copy, Collect)]
+#[collect(require_static)]
+pub(crate) enum HookEvent {
+    Call,
+    Return,
+    Line(LineNumber),
+    Count,
+}
+
+impl HookEvent {
+    pub(crate) fn name(self) -> &'static [u8] {
+        match self {
+            HookEvent::Call => b"call",
+            HookEvent::Return => b"return",
+            HookEvent::Line(_) => b"line",
+            HookEvent::Count => b"count",
+        }
+    }
+}
+
+// The progress of the hook of a thread through the instructions it runs
+#[derive(Debug, Default, Collect)]
+#[collect(require_static)]
+pub(crate) struct HookState {
+    // Instructions left until the next count event
+    pub counter: u32,
+    // The frame index and program counter of the last instruction checked for events, used to find
+    // when a new line starts
+    pub last_pc: Option<(usize, usize)>,
+    // The frame index and program counter of the next instruction to run, once its `pending`
+    // events have been fired
+    pub checked: Option<(usize, usize)>,
+    pub pending: Vec<HookEvent>,
+    // While the hook function is running, the number of frames below it
+    pub running: Option<usize>,
+}
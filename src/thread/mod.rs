@@ -1,9 +1,15 @@
+mod budget;
 mod error;
+mod hook;
 mod thread;
+mod traceback;
 mod vm;
 
-pub use error::{BadThreadMode, BinaryOperatorError, ThreadError};
-pub use thread::{Thread, ThreadMode, ThreadSequence};
+pub use budget::{ExecutionBudget, Limits};
+pub use error::{BadThreadMode, BinaryOperatorError, LimitError, ThreadError};
+pub use hook::{Hook, HookMask};
+pub use thread::{FrameInfo, Thread, ThreadMode, ThreadSequence};
+pub use traceback::{FunctionName, Traceback, TracebackFrame};
 
-pub(crate) use thread::LuaFrame;
+pub(crate) use thread::{location_message, HookCheck, LuaFrame, MetaReturn};
 pub(crate) use vm::run_vm;
@@ -2,13 +2,24 @@ use std::collections::btree_map::Entry as BTreeEntry;
 use std::collections::BTreeMap;
 use std::fmt::{self, Debug};
 use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+use std::{iter, mem};
 
-use gc_arena::{Collect, GcCell, MutationContext};
+use gc_arena::{Collect, FinalizationQueue, GcCell, MutationContext};
 use gc_sequence::Sequence;
 
 use crate::{
-    thread::run_vm, BadThreadMode, CallbackResult, CallbackReturn, Closure, Continuation, Error,
-    Function, RegisterIndex, ThreadError, TypeError, UpValue, UpValueState, Value, VarCount,
+    closure::chunk_id,
+    meta_ops::{self, MetaCall},
+    thread::{
+        budget::ExecutionBudget,
+        hook::{Hook, HookEvent, HookState},
+        run_vm,
+        traceback::{called_function_name, FunctionName, Traceback, TracebackFrame},
+    },
+    BadThreadMode, Callback, CallbackResult, CallbackReturn, Closure, Continuation, Error,
+    Function, LineNumber, OpCode, RegisterIndex, RuntimeError, String, Table, ThreadError, UpValue,
+    UpValueState, Value, VarCount,
 };
 
 #[derive(Clone, Copy, Collect)]
@@ -59,6 +70,49 @@ pub(crate) struct ThreadState<'gc> {
     open_upvalues: BTreeMap<usize, UpValue<'gc>>,
     result: Option<Result<Vec<Value<'gc>>, Error<'gc>>>,
     allow_yield: bool,
+    string_metatable: Option<Table<'gc>>,
+    // The traceback of the last error to unwind the entire thread
+    error_traceback: Option<Traceback>,
+    hook: Option<Hook<'gc>>,
+    hook_state: HookState,
+    budget: Option<Rc<ExecutionBudget>>,
+    finalizers: Option<FinalizationQueue<'gc, Value<'gc>>>,
+    // While a finalizer is running, the number of frames below it
+    finalizing: Option<usize>,
+}
+
+/// Information about a function on the call stack of a thread.
+#[derive(Debug, Clone)]
+pub struct FrameInfo<'gc> {
+    /// The running function, if known.  Callbacks do not keep track of the function they were
+    /// called through, so this is only available for Lua functions.
+    pub function: Option<Function<'gc>>,
+    pub current_line: Option<LineNumber>,
+    pub name: FunctionName,
+}
+
+// The result of checking for hook events before running the next instruction of a Lua frame.
+pub(crate) enum HookCheck<'gc> {
+    // No hook is active, run instructions as normal
+    Unhooked,
+    // A hook is active and there are no events to fire, so run exactly one instruction
+    Step,
+    // The hook must be called before running the next instruction
+    Call(MetaCall<'gc>),
+}
+
+// Describes what to do with the result of a metamethod call once it returns to the calling Lua
+// frame.
+#[derive(Debug, Clone, Copy, Collect)]
+#[collect(require_static)]
+pub(crate) enum MetaReturn {
+    // Discard the result
+    None,
+    // Place the first result in the given register
+    Register(RegisterIndex),
+    // Skip the next instruction if the first result, converted to a boolean, is equal to the given
+    // value.
+    SkipIf(bool),
 }
 
 pub(crate) struct LuaFrame<'gc, 'a> {
@@ -77,10 +131,10 @@ pub(crate) struct LuaRegisters<'gc, 'a> {
 
 impl<'gc> ThreadSequence<'gc> {
     /// Thread must be `Stopped` in order to call a function on it.
-    pub fn call_function(
+    pub fn call_function<F: Into<Value<'gc>>>(
         mc: MutationContext<'gc, '_>,
         thread: Thread<'gc>,
-        function: Function<'gc>,
+        function: F,
         args: &[Value<'gc>],
     ) -> Result<ThreadSequence<'gc>, BadThreadMode> {
         thread.start(mc, function, args)?;
@@ -108,7 +162,13 @@ impl<'gc> Sequence<'gc> for ThreadSequence<'gc> {
 }
 
 impl<'gc> Thread<'gc> {
-    pub fn new(mc: MutationContext<'gc, '_>, allow_yield: bool) -> Thread<'gc> {
+    /// Creates a new thread.  String values indexed by Lua code running on this thread will use the
+    /// given string metatable.
+    pub fn new(
+        mc: MutationContext<'gc, '_>,
+        string_metatable: Option<Table<'gc>>,
+        allow_yield: bool,
+    ) -> Thread<'gc> {
         Thread(GcCell::allocate(
             mc,
             ThreadState {
@@ -117,10 +177,26 @@ impl<'gc> Thread<'gc> {
                 open_upvalues: BTreeMap::new(),
                 result: None,
                 allow_yield,
+                string_metatable,
+                error_traceback: None,
+                hook: None,
+                hook_state: HookState::default(),
+                budget: None,
+                finalizers: None,
+                finalizing: None,
             },
         ))
     }
 
+    /// Creates a new thread for a coroutine started from this thread, which shares the string
+    /// metatable and execution budget of this thread.
+    pub(crate) fn new_coroutine(self, mc: MutationContext<'gc, '_>) -> Thread<'gc> {
+        let state = self.0.read();
+        let thread = Thread::new(mc, state.string_metatable, true);
+        thread.0.write(mc).budget = state.budget.clone();
+        thread
+    }
+
     pub fn mode(self) -> ThreadMode {
         if let Ok(state) = self.0.try_read() {
             get_mode(&state)
@@ -130,15 +206,31 @@ impl<'gc> Thread<'gc> {
     }
 
     /// If this thread is `Stopped`, start a new function with the given arguments.
-    pub fn start(
+    ///
+    /// The function may be any value with a `__call` metamethod.  If the given value is not
+    /// callable, the thread will finish with an error result.
+    pub fn start<F: Into<Value<'gc>>>(
         self,
         mc: MutationContext<'gc, '_>,
-        function: Function<'gc>,
+        function: F,
         args: &[Value<'gc>],
     ) -> Result<(), BadThreadMode> {
         let mut state = self.0.write(mc);
         check_mode(&state, ThreadMode::Stopped)?;
-        ext_call_function(self, &mut state, mc, function, args);
+        state.error_traceback = None;
+        let callee = function.into();
+        match meta_ops::call(callee) {
+            Ok((function, prefix)) => {
+                if prefix.is_empty() {
+                    ext_call_function(self, &mut state, mc, function, args);
+                } else {
+                    let mut call_args = prefix;
+                    call_args.extend_from_slice(args);
+                    ext_call_function(self, &mut state, mc, function, &call_args);
+                }
+            }
+            Err(err) => unwind(self, &mut state, mc, err.into()),
+        }
         Ok(())
     }
 
@@ -150,6 +242,7 @@ impl<'gc> Thread<'gc> {
     ) -> Result<(), BadThreadMode> {
         let mut state = self.0.write(mc);
         check_mode(&state, ThreadMode::Stopped)?;
+        state.error_traceback = None;
         state.frames.push(Frame::StartCoroutine(function));
         Ok(())
     }
@@ -201,9 +294,150 @@ impl<'gc> Thread<'gc> {
         Ok(())
     }
 
+    /// Returns the chunk name and current line of the function at the given level of the call stack,
+    /// where level 0 is the innermost running function.
+    ///
+    /// Returns `None` if there is no function at that level, if it is not a Lua function, or if the
+    /// thread is currently borrowed because it is in the middle of calling a callback.
+    pub fn location(self, level: usize) -> Option<(String<'gc>, LineNumber)> {
+        let state = self.0.try_read().ok()?;
+        lua_location(&state, frame_index(&state, level)?)
+    }
+
+    /// Returns information about the function at the given level of the call stack, where level 0
+    /// is the innermost running function.
+    ///
+    /// Returns `None` if there is no function at that level or if the thread is currently borrowed.
+    pub fn frame_info(self, level: usize) -> Option<FrameInfo<'gc>> {
+        let state = self.0.try_read().ok()?;
+        let index = frame_index(&state, level)?;
+        match state.frames[index] {
+            Frame::Lua { bottom, .. } => {
+                let function = match state.values[bottom] {
+                    Value::Function(function) => Some(function),
+                    _ => None,
+                };
+                let name = match function {
+                    Some(Function::Closure(closure))
+                        if closure.0.proto.line_defined == LineNumber(0) =>
+                    {
+                        FunctionName::MainChunk
+                    }
+                    _ => caller_name(&state, index),
+                };
+                Some(FrameInfo {
+                    function,
+                    current_line: lua_location(&state, index).map(|(_, line)| line),
+                    name,
+                })
+            }
+            _ => Some(FrameInfo {
+                function: None,
+                current_line: None,
+                name: caller_name(&state, index),
+            }),
+        }
+    }
+
+    /// Returns the name and value of a local variable of the Lua function at the given level of
+    /// the call stack, like `lua_getlocal` in PUC-Rio Lua.
+    ///
+    /// Local variables are numbered from 1 in the order they were declared, counting only those in
+    /// scope at the current instruction of the function.  Negative numbers refer to the variable
+    /// arguments of the function, starting from -1.
+    pub fn local(self, level: usize, n: i64) -> Option<(String<'gc>, Value<'gc>)> {
+        let state = self.0.try_read().ok()?;
+        let (name, index) = local_slot(&state, frame_index(&state, level)?, n)?;
+        Some((name, *state.values.get(index)?))
+    }
+
+    /// Sets the value of a local variable of the Lua function at the given level of the call stack,
+    /// numbered in the same way as `Thread::local`.  Returns the name of the variable, or `None` if
+    /// there is no such variable.
+    pub fn set_local(
+        self,
+        mc: MutationContext<'gc, '_>,
+        level: usize,
+        n: i64,
+        value: Value<'gc>,
+    ) -> Option<String<'gc>> {
+        let mut state = self.0.try_write(mc).ok()?;
+        let (name, index) = local_slot(&state, frame_index(&state, level)?, n)?;
+        *state.values.get_mut(index)? = value;
+        Some(name)
+    }
+
+    /// Sets or removes the debug hook of this thread.
+    pub fn set_hook(self, mc: MutationContext<'gc, '_>, hook: Option<Hook<'gc>>) {
+        let mut state = self.0.write(mc);
+        state.hook = hook;
+        state.hook_state = HookState {
+            counter: hook.map(|hook| hook.count).unwrap_or(0),
+            running: state.hook_state.running,
+            ..HookState::default()
+        };
+    }
+
+    /// Returns the debug hook of this thread, or `None` if there is no hook or the thread is
+    /// currently borrowed.
+    pub fn hook(self) -> Option<Hook<'gc>> {
+        self.0.try_read().ok()?.hook
+    }
+
+    /// Sets or removes the execution budget that limits the resources used by this thread.
+    /// Coroutines created by this thread share the same budget.
+    pub fn set_budget(self, mc: MutationContext<'gc, '_>, budget: Option<Rc<ExecutionBudget>>) {
+        self.0.write(mc).budget = budget;
+    }
+
+    /// Returns the execution budget of this thread, or `None` if there is no budget or the thread
+    /// is currently borrowed.
+    pub fn budget(self) -> Option<Rc<ExecutionBudget>> {
+        self.0.try_read().ok()?.budget.clone()
+    }
+
+    /// Sets or removes the queue of objects whose `__gc` metamethods are called by this thread.
+    ///
+    /// Whenever this thread steps a Lua function and no finalizer is already running, it takes the
+    /// next queued object and calls its `__gc` metamethod with it, before running any more
+    /// instructions.  Errors raised by finalizers are ignored.  Coroutines do not run finalizers.
+    pub fn set_finalizers(
+        self,
+        mc: MutationContext<'gc, '_>,
+        finalizers: Option<FinalizationQueue<'gc, Value<'gc>>>,
+    ) {
+        self.0.write(mc).finalizers = finalizers;
+    }
+
+    /// Returns a traceback of the current call stack of this thread.
+    ///
+    /// Returns `None` if the thread is currently borrowed because it is in the middle of calling a
+    /// callback.
+    pub fn traceback(self) -> Option<Traceback> {
+        Some(capture_traceback(&*self.0.try_read().ok()?))
+    }
+
+    /// Returns the traceback captured when the most recent error unwound this entire thread, if the
+    /// thread has not been started again since.
+    pub fn error_traceback(self) -> Option<Traceback> {
+        self.0.try_read().ok()?.error_traceback.clone()
+    }
+
     /// If the thread is in `Running` mode, either run the Lua VM for a while or step any callback
     /// that we are waiting on.
     pub fn step(self, mc: MutationContext<'gc, '_>) -> Result<(), BadThreadMode> {
+        let res = self.step_frame(mc);
+        // The memory owned by the stack counts towards the memory limit, and is recorded after every
+        // step, since the limit is checked once per step.
+        if let Ok(state) = self.0.try_read() {
+            let size = state.values.capacity() * mem::size_of::<Value>()
+                + state.frames.capacity() * mem::size_of::<Frame>();
+            GcCell::set_external_size(mc, self.0, size);
+        }
+        res
+    }
+
+    fn step_frame(self, mc: MutationContext<'gc, '_>) -> Result<(), BadThreadMode> {
         let mut state = self.0.write(mc);
         check_mode(&state, ThreadMode::Running)?;
         match state.frames.last_mut() {
@@ -228,22 +462,40 @@ impl<'gc> Thread<'gc> {
                 }
             }
             Some(Frame::Lua { .. }) => {
+                if call_finalizer(self, &mut state, mc) {
+                    return Ok(());
+                }
+
                 const VM_GRANULARITY: u32 = 256;
-                let mut instructions = VM_GRANULARITY;
+                let mut instructions = match &state.budget {
+                    Some(budget) => match budget.allowance(mc.total_allocated(), VM_GRANULARITY) {
+                        Ok(Some(instructions)) => instructions,
+                        Ok(None) => {
+                            // Give the arena a chance to collect before enforcing the memory limit
+                            mc.request_full_collection();
+                            return Ok(());
+                        }
+                        Err(err) => {
+                            unwind(self, &mut state, mc, err.into());
+                            return Ok(());
+                        }
+                    },
+                    None => VM_GRANULARITY,
+                };
+                let allowance = instructions;
 
                 loop {
                     let lua_frame = LuaFrame {
                         state: &mut state,
                         thread: self,
                     };
-                    match run_vm(mc, lua_frame, instructions) {
+                    match run_vm(mc, lua_frame, &mut instructions) {
                         Err(err) => {
                             unwind(self, &mut state, mc, err);
                             break;
                         }
-                        Ok(i) => {
+                        Ok(()) => {
                             if let Some(Frame::Lua { .. }) = state.frames.last() {
-                                instructions = i;
                                 if instructions == 0 {
                                     break;
                                 }
@@ -253,6 +505,10 @@ impl<'gc> Thread<'gc> {
                         }
                     }
                 }
+
+                if let Some(budget) = &state.budget {
+                    budget.consume(allowance - instructions);
+                }
             }
             _ => panic!("no callback or lua frame"),
         }
@@ -261,6 +517,22 @@ impl<'gc> Thread<'gc> {
     }
 }
 
+impl<'gc> Thread<'gc> {
+    // Reads a value from the stack of this thread by absolute index, used for open upvalues
+    pub(crate) fn stack_value(self, index: usize) -> Value<'gc> {
+        self.0.read().values[index]
+    }
+
+    pub(crate) fn set_stack_value(
+        self,
+        mc: MutationContext<'gc, '_>,
+        index: usize,
+        value: Value<'gc>,
+    ) {
+        self.0.write(mc).values[index] = value;
+    }
+}
+
 impl<'gc, 'a> LuaFrame<'gc, 'a> {
     // Returns the active closure for this Lua frame
     pub(crate) fn closure(&self) -> Closure<'gc> {
@@ -273,6 +545,95 @@ impl<'gc, 'a> LuaFrame<'gc, 'a> {
         }
     }
 
+    // Returns the metatable for string values used by this thread
+    pub(crate) fn string_metatable(&self) -> Option<Table<'gc>> {
+        self.state.string_metatable
+    }
+
+    // Checks whether the hook of this thread must be called before running the next instruction
+    // of this Lua frame.  Each event fires once per instruction, even though the instruction is
+    // checked again after each call of the hook returns.
+    pub(crate) fn check_hook(&mut self) -> HookCheck<'gc> {
+        let state = &mut *self.state;
+        let hook = match state.hook {
+            Some(hook) => hook,
+            None => return HookCheck::Unhooked,
+        };
+        let frame_index = state.frames.len() - 1;
+        let hook_state = &mut state.hook_state;
+        if let Some(running) = hook_state.running {
+            if frame_index >= running {
+                return HookCheck::Unhooked;
+            }
+            hook_state.running = None;
+        }
+
+        let (bottom, pc) = match state.frames[frame_index] {
+            // The hook cannot be called while the frame holds a variable number of values above its
+            // registers, so events are skipped for such instructions.
+            Frame::Lua {
+                is_variable: true, ..
+            } => return HookCheck::Step,
+            Frame::Lua { bottom, pc, .. } => (bottom, pc),
+            _ => panic!("top frame is not lua frame"),
+        };
+
+        if hook_state.checked != Some((frame_index, pc)) {
+            let proto = match state.values[bottom] {
+                Value::Function(Function::Closure(closure)) => closure.0.proto,
+                _ => panic!("thread bottom is not a closure"),
+            };
+
+            hook_state.checked = Some((frame_index, pc));
+            hook_state.pending.clear();
+            if hook.mask.call && pc == 0 {
+                hook_state.pending.push(HookEvent::Call);
+            }
+            if hook.count != 0 {
+                hook_state.counter = hook_state.counter.saturating_sub(1);
+                if hook_state.counter == 0 {
+                    hook_state.counter = hook.count;
+                    hook_state.pending.push(HookEvent::Count);
+                }
+            }
+            if hook.mask.line {
+                // After returning from a call, compare with the line of the calling instruction
+                let last_pc = match hook_state.last_pc {
+                    Some((last_frame, last_pc)) if last_frame == frame_index => Some(last_pc),
+                    _ => pc.checked_sub(1),
+                };
+                let line = proto.opcode_line(pc);
+                let new_line = match last_pc {
+                    Some(last_pc) => pc <= last_pc || proto.opcode_line(last_pc) != line,
+                    None => true,
+                };
+                if let (true, Some(line)) = (new_line, line) {
+                    hook_state.pending.push(HookEvent::Line(line));
+                }
+            }
+            if let (true, OpCode::Return { .. }) = (hook.mask.ret, proto.opcodes[pc]) {
+                hook_state.pending.push(HookEvent::Return);
+            }
+            hook_state.last_pc = Some((frame_index, pc));
+        }
+
+        if hook_state.pending.is_empty() {
+            hook_state.checked = None;
+            HookCheck::Step
+        } else {
+            let event = hook_state.pending.remove(0);
+            hook_state.running = Some(frame_index + 1);
+            let mut args = vec![Value::String(String::new_static(event.name()))];
+            if let HookEvent::Line(line) = event {
+                args.push(Value::Integer(line.0 as i64));
+            }
+            HookCheck::Call(MetaCall {
+                function: hook.function,
+                args,
+            })
+        }
+    }
+
     // returns a view of the Lua frame's registers
     pub(crate) fn registers<'b>(&'b mut self) -> LuaRegisters<'gc, 'b> {
         match self.state.frames.last_mut() {
@@ -352,15 +713,17 @@ impl<'gc, 'a> LuaFrame<'gc, 'a> {
                     return Err(ThreadError::ExpectedVariable(*is_variable));
                 }
 
-                *expected_returns = Some(returns);
+                *expected_returns = Some(LuaReturn::Normal(returns));
                 let function_index = *base + func.0 as usize;
                 let arg_count = args
                     .to_constant()
                     .map(|c| c as usize)
                     .unwrap_or(self.state.values.len() - function_index - 1);
+                let (function, arg_count) =
+                    resolve_call(&mut self.state.values, function_index, arg_count)?;
 
-                match self.state.values[function_index] {
-                    Value::Function(Function::Closure(closure)) => {
+                match function {
+                    Function::Closure(closure) => {
                         let fixed_params = closure.0.proto.fixed_params as usize;
                         let stack_size = closure.0.proto.stack_size as usize;
 
@@ -384,8 +747,9 @@ impl<'gc, 'a> LuaFrame<'gc, 'a> {
                         });
                         Ok(())
                     }
-                    Value::Function(Function::Callback(callback)) => {
+                    Function::Callback(callback) => {
                         let ret = callback.call(
+                            self.thread,
                             self.state.values[function_index + 1..function_index + 1 + arg_count]
                                 .to_vec(),
                         );
@@ -393,10 +757,6 @@ impl<'gc, 'a> LuaFrame<'gc, 'a> {
                         callback_return(self.thread, &mut self.state, mc, ret);
                         Ok(())
                     }
-                    val => Err(ThreadError::BadCall(TypeError {
-                        expected: "function",
-                        found: val.type_name(),
-                    })),
                 }
             }
             _ => panic!("top frame is not lua frame"),
@@ -425,7 +785,7 @@ impl<'gc, 'a> LuaFrame<'gc, 'a> {
                 }
 
                 let arg_count = arg_count as usize;
-                *expected_returns = Some(returns);
+                *expected_returns = Some(LuaReturn::Normal(returns));
                 let given_function_index = *base + func.0 as usize;
                 let function_index = given_function_index + 1 + arg_count;
                 self.state
@@ -435,9 +795,11 @@ impl<'gc, 'a> LuaFrame<'gc, 'a> {
                     self.state.values[function_index + i] =
                         self.state.values[given_function_index + i];
                 }
+                let (function, arg_count) =
+                    resolve_call(&mut self.state.values, function_index, arg_count)?;
 
-                match self.state.values[function_index] {
-                    Value::Function(Function::Closure(closure)) => {
+                match function {
+                    Function::Closure(closure) => {
                         let fixed_params = closure.0.proto.fixed_params as usize;
                         let stack_size = closure.0.proto.stack_size as usize;
 
@@ -460,8 +822,9 @@ impl<'gc, 'a> LuaFrame<'gc, 'a> {
                         });
                         Ok(())
                     }
-                    Value::Function(Function::Callback(callback)) => {
+                    Function::Callback(callback) => {
                         let ret = callback.call(
+                            self.thread,
                             self.state.values[function_index + 1..function_index + 1 + arg_count]
                                 .to_vec(),
                         );
@@ -469,10 +832,6 @@ impl<'gc, 'a> LuaFrame<'gc, 'a> {
                         callback_return(self.thread, &mut self.state, mc, ret);
                         Ok(())
                     }
-                    val => Err(ThreadError::BadCall(TypeError {
-                        expected: "function",
-                        found: val.type_name(),
-                    })),
                 }
             }
             _ => panic!("top frame is not lua frame"),
@@ -505,9 +864,11 @@ impl<'gc, 'a> LuaFrame<'gc, 'a> {
                     .to_constant()
                     .map(|c| c as usize)
                     .unwrap_or(self.state.values.len() - function_index - 1);
+                let (function, arg_count) =
+                    resolve_call(&mut self.state.values, function_index, arg_count)?;
 
-                match self.state.values[function_index] {
-                    Value::Function(Function::Closure(closure)) => {
+                match function {
+                    Function::Closure(closure) => {
                         self.state.values[bottom] = self.state.values[function_index];
                         for i in 0..arg_count {
                             self.state.values[bottom + 1 + i] =
@@ -537,8 +898,9 @@ impl<'gc, 'a> LuaFrame<'gc, 'a> {
                         });
                         Ok(())
                     }
-                    Value::Function(Function::Callback(callback)) => {
+                    Function::Callback(callback) => {
                         let ret = callback.call(
+                            self.thread,
                             self.state.values[function_index + 1..function_index + 1 + arg_count]
                                 .to_vec(),
                         );
@@ -546,16 +908,75 @@ impl<'gc, 'a> LuaFrame<'gc, 'a> {
                         callback_return(self.thread, &mut self.state, mc, ret);
                         Ok(())
                     }
-                    val => Err(ThreadError::BadCall(TypeError {
-                        expected: "function",
-                        found: val.type_name(),
-                    })),
                 }
             }
             _ => panic!("top frame is not lua frame"),
         }
     }
 
+    // Calls the given metamethod function with the given arguments above the registers of the
+    // current Lua frame.  Once the function returns, its result will be handled according to the
+    // given `MetaReturn`.
+    pub(crate) fn call_meta_function(
+        self,
+        mc: MutationContext<'gc, '_>,
+        meta_call: MetaCall<'gc>,
+        meta_return: MetaReturn,
+    ) -> Result<(), ThreadError> {
+        match self.state.frames.last_mut() {
+            Some(Frame::Lua {
+                expected_returns,
+                is_variable,
+                base,
+                stack_size,
+                ..
+            }) => {
+                if *is_variable {
+                    return Err(ThreadError::ExpectedVariable(false));
+                }
+
+                *expected_returns = Some(LuaReturn::Meta(meta_return));
+                let function_index = *base + *stack_size;
+                let arg_count = meta_call.args.len();
+
+                match meta_call.function {
+                    Function::Closure(closure) => {
+                        let fixed_params = closure.0.proto.fixed_params as usize;
+                        let stack_size = closure.0.proto.stack_size as usize;
+
+                        self.state.values.truncate(function_index);
+                        self.state.values.push(Value::Function(meta_call.function));
+                        self.state.values.extend(meta_call.args);
+
+                        let base = if arg_count > fixed_params {
+                            self.state.values[function_index + 1..].rotate_left(fixed_params);
+                            function_index + 1 + (arg_count - fixed_params)
+                        } else {
+                            function_index + 1
+                        };
+
+                        self.state.values.resize(base + stack_size, Value::Nil);
+
+                        self.state.frames.push(Frame::Lua {
+                            bottom: function_index,
+                            base,
+                            is_variable: false,
+                            pc: 0,
+                            stack_size,
+                            expected_returns: None,
+                        });
+                    }
+                    Function::Callback(callback) => {
+                        let ret = callback.call(self.thread, meta_call.args);
+                        callback_return(self.thread, self.state, mc, ret);
+                    }
+                }
+                Ok(())
+            }
+            _ => panic!("top frame is not lua frame"),
+        }
+    }
+
     // Return to the upper frame with results starting at the given register index.
     pub(crate) fn return_upper(
         mut self,
@@ -595,29 +1016,47 @@ impl<'gc, 'a> LuaFrame<'gc, 'a> {
                         is_variable,
                         base,
                         stack_size,
+                        pc,
                         ..
                     }) => {
-                        let expected_returns =
-                            expected_returns.expect("no expected returns for upper lua frame");
-                        let returning = expected_returns
-                            .to_constant()
-                            .map(|c| c as usize)
-                            .unwrap_or(count);
-
-                        for i in 0..returning.min(count) {
-                            self.state.values[bottom + i] = self.state.values[start + i]
-                        }
+                        match expected_returns.expect("no expected returns for upper lua frame") {
+                            LuaReturn::Normal(expected_returns) => {
+                                let returning = expected_returns
+                                    .to_constant()
+                                    .map(|c| c as usize)
+                                    .unwrap_or(count);
+
+                                for i in 0..returning.min(count) {
+                                    self.state.values[bottom + i] = self.state.values[start + i]
+                                }
 
-                        for i in count..returning {
-                            self.state.values[bottom + i] = Value::Nil;
-                        }
+                                for i in count..returning {
+                                    self.state.values[bottom + i] = Value::Nil;
+                                }
 
-                        if expected_returns.is_variable() {
-                            self.state.values.truncate(bottom + returning);
-                            *is_variable = true;
-                        } else {
-                            self.state.values.resize(*base + *stack_size, Value::Nil);
-                            *is_variable = false;
+                                if expected_returns.is_variable() {
+                                    self.state.values.truncate(bottom + returning);
+                                    *is_variable = true;
+                                } else {
+                                    self.state.values.resize(*base + *stack_size, Value::Nil);
+                                    *is_variable = false;
+                                }
+                            }
+                            LuaReturn::Meta(meta_return) => {
+                                let result = if count > 0 {
+                                    self.state.values[start]
+                                } else {
+                                    Value::Nil
+                                };
+                                self.state.values.resize(*base + *stack_size, Value::Nil);
+                                *is_variable = false;
+                                finish_meta_return(
+                                    &mut self.state.values[*base..],
+                                    pc,
+                                    meta_return,
+                                    result,
+                                );
+                            }
                         }
                     }
                     None => {
@@ -718,7 +1157,7 @@ enum Frame<'gc> {
         is_variable: bool,
         pc: usize,
         stack_size: usize,
-        expected_returns: Option<VarCount>,
+        expected_returns: Option<LuaReturn>,
     },
     Continuation {
         bottom: usize,
@@ -731,6 +1170,16 @@ enum Frame<'gc> {
     ),
 }
 
+#[derive(Clone, Copy, Collect)]
+#[collect(require_static)]
+enum LuaReturn {
+    // Normal function call, place return values at the bottom of the returning function's stack,
+    // as normal.
+    Normal(VarCount),
+    // Return from a metamethod call, the first return value is handled by the given `MetaReturn`.
+    Meta(MetaReturn),
+}
+
 fn get_mode<'gc>(state: &ThreadState<'gc>) -> ThreadMode {
     if state.result.is_some() {
         ThreadMode::Results
@@ -793,7 +1242,7 @@ fn ext_call_function<'gc>(
                 state.values[base + i] = args.get(i).cloned().unwrap_or(Value::Nil);
             }
             for i in 0..var_params {
-                state.values[1 + i] = args[fixed_params + i]
+                state.values[bottom + 1 + i] = args[fixed_params + i]
             }
 
             state.frames.push(Frame::Lua {
@@ -806,12 +1255,36 @@ fn ext_call_function<'gc>(
             });
         }
         Function::Callback(callback) => {
-            let ret = callback.call(args.to_vec());
+            let ret = callback.call(thread, args.to_vec());
             callback_return(thread, state, mc, ret);
         }
     }
 }
 
+// Resolves the value at `function_index` on the stack, which is followed by `arg_count` arguments,
+// into a function to call.  If the value is not a function but has a `__call` metamethod, the
+// function and the rest of the `__call` chain are inserted at `function_index` so that the called
+// value becomes one of its arguments.  Returns the function and the new argument count.
+fn resolve_call<'gc>(
+    values: &mut Vec<Value<'gc>>,
+    function_index: usize,
+    arg_count: usize,
+) -> Result<(Function<'gc>, usize), ThreadError> {
+    match values[function_index] {
+        Value::Function(function) => Ok((function, arg_count)),
+        callee => {
+            let (function, prefix) = meta_ops::call(callee)?;
+            values.truncate(function_index + 1 + arg_count);
+            let prefix_len = prefix.len();
+            values.splice(
+                function_index..function_index + 1,
+                iter::once(Value::Function(function)).chain(prefix),
+            );
+            Ok((function, arg_count + prefix_len))
+        }
+    }
+}
+
 // Return to the top Lua frame from an external call
 fn return_to_lua<'gc>(state: &mut ThreadState<'gc>, rets: &[Value<'gc>]) {
     match state.frames.last_mut() {
@@ -820,32 +1293,263 @@ fn return_to_lua<'gc>(state: &mut ThreadState<'gc>, rets: &[Value<'gc>]) {
             is_variable,
             base,
             stack_size,
+            pc,
             ..
-        }) => {
-            let ret_count = expected_returns
-                .take()
-                .expect("no expected returns for lua frame");
-            let return_len = ret_count
-                .to_constant()
-                .map(|c| c as usize)
-                .unwrap_or(rets.len());
+        }) => match expected_returns
+            .take()
+            .expect("no expected returns for lua frame")
+        {
+            LuaReturn::Normal(ret_count) => {
+                let return_len = ret_count
+                    .to_constant()
+                    .map(|c| c as usize)
+                    .unwrap_or(rets.len());
 
-            let bottom = state.values.len();
-            state.values.resize(bottom + return_len, Value::Nil);
+                let bottom = state.values.len();
+                state.values.resize(bottom + return_len, Value::Nil);
 
-            for i in 0..return_len.min(rets.len()) {
-                state.values[bottom + i] = rets[i];
-            }
+                for i in 0..return_len.min(rets.len()) {
+                    state.values[bottom + i] = rets[i];
+                }
 
-            *is_variable = ret_count.is_variable();
-            if !ret_count.is_variable() {
+                *is_variable = ret_count.is_variable();
+                if !ret_count.is_variable() {
+                    state.values.resize(*base + *stack_size, Value::Nil);
+                }
+            }
+            LuaReturn::Meta(meta_return) => {
                 state.values.resize(*base + *stack_size, Value::Nil);
+                *is_variable = false;
+                finish_meta_return(
+                    &mut state.values[*base..],
+                    pc,
+                    meta_return,
+                    rets.get(0).cloned().unwrap_or(Value::Nil),
+                );
             }
-        }
+        },
         _ => panic!("no lua frame to return to"),
     };
 }
 
+// Returns the index of the frame at the given level of the call stack, counting only frames which
+// are running a function.  The frame left by a yielding callback counts as that callback.
+fn frame_index<'gc>(state: &ThreadState<'gc>, level: usize) -> Option<usize> {
+    state
+        .frames
+        .iter()
+        .enumerate()
+        .rev()
+        .filter(|(_, frame)| match frame {
+            Frame::Lua { .. }
+            | Frame::Callback(_)
+            | Frame::Continuation { .. }
+            | Frame::ResumeCoroutine => true,
+            Frame::StartCoroutine(_) => false,
+        })
+        .nth(level)
+        .map(|(index, _)| index)
+}
+
+// Returns the index of the current instruction of the Lua frame with the given index.
+fn current_pc<'gc>(state: &ThreadState<'gc>, frame_index: usize) -> Option<usize> {
+    match state.frames[frame_index] {
+        Frame::Lua { pc, .. } => {
+            if state.hook_state.running == Some(frame_index + 1) {
+                // The hook is called before the instruction at the program counter is run
+                Some(pc)
+            } else {
+                // Otherwise, the program counter is incremented before each instruction is run, so
+                // the current instruction is the one before it.
+                pc.checked_sub(1)
+            }
+        }
+        _ => None,
+    }
+}
+
+// Returns the chunk name and line of the current instruction of the Lua frame with the given index.
+fn lua_location<'gc>(
+    state: &ThreadState<'gc>,
+    frame_index: usize,
+) -> Option<(String<'gc>, LineNumber)> {
+    match state.frames[frame_index] {
+        Frame::Lua { bottom, .. } => match state.values[bottom] {
+            Value::Function(Function::Closure(closure)) => {
+                let line = closure
+                    .0
+                    .proto
+                    .opcode_line(current_pc(state, frame_index)?)?;
+                Some((closure.0.proto.chunk_name, line))
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// Returns the name of a local variable of the Lua frame with the given index and the index of its
+// value on the stack.
+fn local_slot<'gc>(
+    state: &ThreadState<'gc>,
+    frame_index: usize,
+    n: i64,
+) -> Option<(String<'gc>, usize)> {
+    match state.frames[frame_index] {
+        Frame::Lua { bottom, base, .. } => {
+            let proto = match state.values[bottom] {
+                Value::Function(Function::Closure(closure)) => closure.0.proto,
+                _ => return None,
+            };
+            if n < 0 {
+                // Variable arguments are stored between the function and its registers
+                let index = bottom.checked_add(n.checked_neg()? as usize)?;
+                if proto.has_varargs && index < base {
+                    Some((String::new_static(b"(vararg)"), index))
+                } else {
+                    None
+                }
+            } else {
+                let pc = current_pc(state, frame_index)?;
+                let (name, register) = proto.local_variable(n as usize, pc)?;
+                Some((name, base + register.0 as usize))
+            }
+        }
+        _ => None,
+    }
+}
+
+// Prefixes a message with a location in the form "chunkname:line: ", like `luaL_where` in PUC-Rio
+// Lua.
+pub(crate) fn location_message(chunk_name: String, line: LineNumber, message: &[u8]) -> Vec<u8> {
+    let mut buf = chunk_id(chunk_name.as_bytes());
+    buf.extend_from_slice(format!(":{}: ", line).as_bytes());
+    buf.extend_from_slice(message);
+    buf
+}
+
+// Captures a traceback of all frames in the given thread state, innermost first.
+fn capture_traceback<'gc>(state: &ThreadState<'gc>) -> Traceback {
+    let mut frames = Vec::new();
+    for (i, frame) in state.frames.iter().enumerate().rev() {
+        match frame {
+            Frame::Lua { bottom, .. } => {
+                if let Value::Function(Function::Closure(closure)) = state.values[*bottom] {
+                    let proto = &closure.0.proto;
+                    frames.push(TracebackFrame::Lua {
+                        chunk: std::string::String::from_utf8_lossy(&chunk_id(
+                            proto.chunk_name.as_bytes(),
+                        ))
+                        .into_owned(),
+                        line_defined: proto.line_defined,
+                        current_line: current_pc(state, i).and_then(|pc| proto.opcode_line(pc)),
+                        name: if proto.line_defined == LineNumber(0) {
+                            FunctionName::MainChunk
+                        } else {
+                            caller_name(state, i)
+                        },
+                    });
+                }
+            }
+            Frame::Callback(_) | Frame::Continuation { .. } | Frame::ResumeCoroutine => {
+                frames.push(TracebackFrame::Callback {
+                    name: caller_name(state, i),
+                });
+            }
+            Frame::StartCoroutine(_) => {}
+        }
+    }
+    if state.allow_yield {
+        frames.push(TracebackFrame::Coroutine);
+    }
+    Traceback { frames }
+}
+
+// Finds the name of the function running in the frame at the given index from the code in the frame
+// that called it, if that is a Lua frame.
+fn caller_name<'gc>(state: &ThreadState<'gc>, frame_index: usize) -> FunctionName {
+    if frame_index > 0 && state.hook_state.running == Some(frame_index) {
+        return FunctionName::Hook;
+    }
+    match frame_index.checked_sub(1) {
+        Some(caller) => match state.frames[caller] {
+            Frame::Lua { bottom, .. } => match state.values[bottom] {
+                Value::Function(Function::Closure(closure)) => match current_pc(state, caller) {
+                    Some(pc) => called_function_name(&closure.0.proto, pc),
+                    None => FunctionName::Unknown,
+                },
+                _ => FunctionName::Unknown,
+            },
+            _ => FunctionName::Unknown,
+        },
+        None => FunctionName::Unknown,
+    }
+}
+
+// Calls the `__gc` metamethod of the next object queued for finalization above the registers of
+// the current Lua frame, unless another finalizer is running.  Returns true if a finalizer was
+// called.
+fn call_finalizer<'gc>(
+    thread: Thread<'gc>,
+    state: &mut ThreadState<'gc>,
+    mc: MutationContext<'gc, '_>,
+) -> bool {
+    if let Some(finalizing) = state.finalizing {
+        if state.frames.len() > finalizing {
+            return false;
+        }
+        state.finalizing = None;
+    }
+
+    let finalizers = match state.finalizers {
+        Some(finalizers) => finalizers,
+        None => return false,
+    };
+    // Like the hook, the finalizer cannot be called while the frame holds a variable number of
+    // values above its registers
+    if let Some(Frame::Lua {
+        is_variable: true, ..
+    }) = state.frames.last()
+    {
+        return false;
+    }
+
+    while let Some(object) = finalizers.take(mc) {
+        let finalizer = match meta_ops::get_metatable(object)
+            .map(|metatable| metatable.get(String::new_static(b"__gc")))
+        {
+            Some(Value::Function(finalizer)) => finalizer,
+            _ => continue,
+        };
+
+        // Calls the finalizer in protected mode, discarding its results or error
+        let protected = Callback::new_immediate_with(mc, finalizer, |&finalizer, args| {
+            Ok(CallbackResult::TailCall {
+                function: finalizer,
+                args,
+                continuation: Continuation::new(|_| {
+                    CallbackReturn::Immediate(Ok(CallbackResult::Return(Vec::new())))
+                }),
+            })
+        });
+
+        state.finalizing = Some(state.frames.len());
+        LuaFrame { thread, state }
+            .call_meta_function(
+                mc,
+                MetaCall {
+                    function: Function::Callback(protected),
+                    args: vec![object],
+                },
+                MetaReturn::None,
+            )
+            .expect("finalizer frame is not variable");
+        return true;
+    }
+
+    false
+}
+
 // TODO: `unwind`, `return_ext`, and `callback_return` have to be merged somehow, because otherwise
 // they are a stack overflow risk in pathalogical or malicious cases.
 
@@ -855,7 +1559,76 @@ fn unwind<'gc>(
     mc: MutationContext<'gc, '_>,
     error: Error<'gc>,
 ) {
+    // Exceeding a limit cannot be caught, so it unwinds the entire thread without calling any
+    // continuations or error handlers
+    if let Error::LimitError(_) = error {
+        state.error_traceback = Some(capture_traceback(state));
+        state.frames.clear();
+        state.hook_state.running = None;
+        close_upvalues(thread, state, mc, 0);
+        state.values.clear();
+        state.result = Some(Err(error));
+        return;
+    }
+
+    // Errors that are not raised with a Lua value, such as errors from the VM or from callbacks
+    // called by Lua, become string errors with the location of the Lua code that caused them.
+    let error = match (error, state.frames.last()) {
+        (Error::RuntimeError(error), _) => Error::RuntimeError(error),
+        (error, Some(Frame::Lua { .. })) => match lua_location(state, state.frames.len() - 1) {
+            Some((chunk_name, line)) => {
+                let message = location_message(chunk_name, line, error.to_string().as_bytes());
+                RuntimeError(Value::String(String::new(mc, &message))).into()
+            }
+            None => error,
+        },
+        (error, _) => error,
+    };
+
+    // If the nearest continuation has an error handler, call it with the error before unwinding any
+    // frames.  The result of the handler is then raised as a new error, which unwinds to the same
+    // continuation now that its handler has been taken.
+    let error_handler = state.frames.iter_mut().rev().find_map(|frame| match frame {
+        Frame::Continuation {
+            continuation: Some(continuation),
+            ..
+        } => Some(continuation.take_error_handler()),
+        _ => None,
+    });
+    if let Some(Some(error_handler)) = error_handler {
+        let error = match error {
+            Error::RuntimeError(error) => error.0,
+            error => Value::String(String::new(mc, error.to_string().as_bytes())),
+        };
+        let bottom = state.values.len();
+        state.frames.push(Frame::Continuation {
+            continuation: Some(Continuation::new_immediate(|res| {
+                let error = res?.get(0).cloned().unwrap_or(Value::Nil);
+                Err(RuntimeError(error).into())
+            })),
+            bottom,
+        });
+        ext_call_function(thread, state, mc, error_handler, &[error]);
+        return;
+    }
+
+    // If nothing will catch this error, record where it came from before unwinding
+    let caught = state.frames.iter().any(|frame| match frame {
+        Frame::Continuation { .. } => true,
+        _ => false,
+    });
+    if !caught {
+        state.error_traceback = Some(capture_traceback(state));
+    }
+
     while let Some(mut top_frame) = state.frames.pop() {
+        // An error from inside a hook function may unwind past the frame that called the hook
+        if let Some(running) = state.hook_state.running {
+            if state.frames.len() < running {
+                state.hook_state.running = None;
+            }
+        }
+
         if let Frame::Continuation {
             continuation,
             bottom,
@@ -938,6 +1711,27 @@ fn callback_return<'gc>(
     }
 }
 
+// Handle the result of a metamethod call that has returned to a Lua frame with the given registers
+// and program counter.
+fn finish_meta_return<'gc>(
+    stack_frame: &mut [Value<'gc>],
+    pc: &mut usize,
+    meta_return: MetaReturn,
+    result: Value<'gc>,
+) {
+    match meta_return {
+        MetaReturn::None => {}
+        MetaReturn::Register(reg) => {
+            stack_frame[reg.0 as usize] = result;
+        }
+        MetaReturn::SkipIf(skip_if) => {
+            if result.to_bool() == skip_if {
+                *pc += 1;
+            }
+        }
+    }
+}
+
 fn close_upvalues<'gc>(
     thread: Thread<'gc>,
     state: &mut ThreadState<'gc>,
@@ -0,0 +1,382 @@
+use std::fmt;
+use std::string::String as StdString;
+
+use gc_arena::Collect;
+
+use crate::{Constant, ConstantIndex8, FunctionProto, LineNumber, OpCode, RegisterIndex};
+
+/// A snapshot of the call stack of a thread, innermost call first, in a form that can outlive the
+/// arena.
+#[derive(Debug, Clone, PartialEq, Eq, Collect)]
+#[collect(require_static)]
+pub struct Traceback {
+    pub frames: Vec<TracebackFrame>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Collect)]
+#[collect(require_static)]
+pub enum TracebackFrame {
+    /// A Lua function, along with the chunk it was defined in (formatted for display), the line it
+    /// was defined on and the line that it is currently executing.
+    Lua {
+        chunk: StdString,
+        line_defined: LineNumber,
+        current_line: Option<LineNumber>,
+        name: FunctionName,
+    },
+    /// A Rust callback which is running or waiting on the results of a call it made.
+    Callback { name: FunctionName },
+    /// The bottom of the call stack of a coroutine, below which are the frames of whichever thread
+    /// resumed it.
+    Coroutine,
+}
+
+/// The name of a called function, derived from the code that called it.
+#[derive(Debug, Clone, PartialEq, Eq, Collect)]
+#[collect(require_static)]
+pub enum FunctionName {
+    /// The main function of a chunk
+    MainChunk,
+    Global(StdString),
+    Local(StdString),
+    Field(StdString),
+    Method(StdString),
+    UpValue(StdString),
+    Metamethod(&'static str),
+    ForIterator,
+    /// A debug hook function, called by the VM rather than by any code
+    Hook,
+    Unknown,
+}
+
+impl fmt::Display for Traceback {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "stack traceback:")?;
+        for frame in &self.frames {
+            write!(fmt, "\n\t{}", frame)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for TracebackFrame {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TracebackFrame::Lua {
+                chunk,
+                line_defined,
+                current_line,
+                name,
+            } => {
+                match current_line {
+                    Some(line) => write!(fmt, "{}:{}: in ", chunk, line)?,
+                    None => write!(fmt, "{}: in ", chunk)?,
+                }
+                match name {
+                    FunctionName::MainChunk => write!(fmt, "main chunk"),
+                    FunctionName::Unknown => write!(fmt, "function <{}:{}>", chunk, line_defined),
+                    name => write!(fmt, "{}", name),
+                }
+            }
+            TracebackFrame::Callback { name } => match name {
+                FunctionName::Unknown | FunctionName::MainChunk => write!(fmt, "[C]: in ?"),
+                name => write!(fmt, "[C]: in {}", name),
+            },
+            TracebackFrame::Coroutine => write!(fmt, "(...coroutine boundary...)"),
+        }
+    }
+}
+
+impl fmt::Display for FunctionName {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FunctionName::MainChunk => write!(fmt, "main chunk"),
+            FunctionName::Global(name) => write!(fmt, "function '{}'", name),
+            FunctionName::Local(name) => write!(fmt, "local '{}'", name),
+            FunctionName::Field(name) => write!(fmt, "field '{}'", name),
+            FunctionName::Method(name) => write!(fmt, "method '{}'", name),
+            FunctionName::UpValue(name) => write!(fmt, "upvalue '{}'", name),
+            FunctionName::Metamethod(name) => write!(fmt, "metamethod '{}'", name),
+            FunctionName::ForIterator => write!(fmt, "for iterator 'for iterator'"),
+            FunctionName::Hook => write!(fmt, "hook '?'"),
+            FunctionName::Unknown => write!(fmt, "?"),
+        }
+    }
+}
+
+// Finds the name of the function called by the opcode at `pc` in the given prototype, like
+// `funcnamefromcode` in PUC-Rio Lua.
+pub(crate) fn called_function_name(proto: &FunctionProto, pc: usize) -> FunctionName {
+    match proto.opcodes.get(pc) {
+        Some(&OpCode::Call { func, .. }) | Some(&OpCode::TailCall { func, .. }) => {
+            register_name(proto, pc, func)
+        }
+        Some(&OpCode::GenericForCall { .. }) => FunctionName::ForIterator,
+        Some(&op) => match metamethod_name(op) {
+            Some(name) => FunctionName::Metamethod(name),
+            None => FunctionName::Unknown,
+        },
+        None => FunctionName::Unknown,
+    }
+}
+
+// Finds a name for the value in the given register just before the opcode at `last_pc` runs, either
+// from the local variable stored in it or by looking at the opcode which last set it.
+fn register_name(proto: &FunctionProto, last_pc: usize, reg: RegisterIndex) -> FunctionName {
+    let local =
+        proto.local_variables.iter().rev().find(|local| {
+            local.register == reg && local.start_pc <= last_pc && last_pc < local.end_pc
+        });
+    if let Some(local) = local {
+        return FunctionName::Local(StdString::from_utf8_lossy(local.name.as_bytes()).into_owned());
+    }
+
+    let constant_name = |key: ConstantIndex8| match proto.constants.get(key.0 as usize) {
+        Some(Constant::String(s)) => Some(StdString::from_utf8_lossy(s.as_bytes()).into_owned()),
+        _ => None,
+    };
+
+    let set_pc = match find_set_register(proto, last_pc, reg) {
+        Some(set_pc) => set_pc,
+        None => return FunctionName::Unknown,
+    };
+    match proto.opcodes[set_pc] {
+        OpCode::Move { source, .. } if source.0 < reg.0 => register_name(proto, set_pc, source),
+        OpCode::GetUpTableC { table, key, .. } => match constant_name(key) {
+            Some(name) => {
+                let is_env = proto
+                    .upvalue_names
+                    .get(table.0 as usize)
+                    .map(|n| n.as_bytes() == b"_ENV")
+                    .unwrap_or(false);
+                if is_env {
+                    FunctionName::Global(name)
+                } else {
+                    FunctionName::Field(name)
+                }
+            }
+            None => FunctionName::Unknown,
+        },
+        OpCode::GetTableC { key, .. } => constant_name(key)
+            .map(FunctionName::Field)
+            .unwrap_or(FunctionName::Unknown),
+        OpCode::SelfC { key, .. } => constant_name(key)
+            .map(FunctionName::Method)
+            .unwrap_or(FunctionName::Unknown),
+        OpCode::GetUpValue { source, .. } => proto
+            .upvalue_names
+            .get(source.0 as usize)
+            .map(|n| FunctionName::UpValue(StdString::from_utf8_lossy(n.as_bytes()).into_owned()))
+            .unwrap_or(FunctionName::Unknown),
+        _ => FunctionName::Unknown,
+    }
+}
+
+// Returns the index of the last opcode before `last_pc` that sets the given register, or None if
+// this cannot be determined because a jump may skip over it, like `findsetreg` in PUC-Rio Lua.
+fn find_set_register(proto: &FunctionProto, last_pc: usize, reg: RegisterIndex) -> Option<usize> {
+    let reg = reg.0 as usize;
+    let mut set_pc = None;
+    // Any opcode before this point may be jumped over
+    let mut jump_target = 0;
+    for (pc, &op) in proto.opcodes[0..last_pc].iter().enumerate() {
+        let changed = match op {
+            OpCode::LoadNil { dest, count } => {
+                reg >= dest.0 as usize && reg < dest.0 as usize + count as usize
+            }
+            OpCode::VarArgs { dest, count } => match count.to_constant() {
+                Some(count) => reg >= dest.0 as usize && reg < dest.0 as usize + count as usize,
+                None => reg >= dest.0 as usize,
+            },
+            OpCode::Call { func, .. } | OpCode::TailCall { func, .. } => reg >= func.0 as usize,
+            OpCode::GenericForCall { base, .. } => reg >= base.0 as usize + 3,
+            OpCode::NumericForPrep { base, .. }
+            | OpCode::NumericForLoop { base, .. }
+            | OpCode::GenericForLoop { base, .. } => {
+                reg >= base.0 as usize && reg <= base.0 as usize + 3
+            }
+            OpCode::SelfR { base, .. } | OpCode::SelfC { base, .. } => {
+                reg == base.0 as usize || reg == base.0 as usize + 1
+            }
+            OpCode::Jump { offset, .. } => {
+                let target = (pc as isize + 1 + offset as isize) as usize;
+                if target > pc && target <= last_pc && target > jump_target {
+                    jump_target = target;
+                }
+                false
+            }
+            op => opcode_dest(op) == Some(RegisterIndex(reg as u8)),
+        };
+        if changed {
+            set_pc = if pc < jump_target { None } else { Some(pc) };
+        }
+    }
+    set_pc
+}
+
+// The register written by opcodes which write a single register named `dest`
+fn opcode_dest(op: OpCode) -> Option<RegisterIndex> {
+    match op {
+        OpCode::Move { dest, .. }
+        | OpCode::LoadConstant { dest, .. }
+        | OpCode::LoadBool { dest, .. }
+        | OpCode::NewTable { dest, .. }
+        | OpCode::GetTableR { dest, .. }
+        | OpCode::GetTableC { dest, .. }
+        | OpCode::GetUpTableR { dest, .. }
+        | OpCode::GetUpTableC { dest, .. }
+        | OpCode::TestSet { dest, .. }
+        | OpCode::Closure { dest, .. }
+        | OpCode::Concat { dest, .. }
+        | OpCode::GetUpValue { dest, .. }
+        | OpCode::Length { dest, .. }
+        | OpCode::Not { dest, .. }
+        | OpCode::Minus { dest, .. }
+        | OpCode::BitNot { dest, .. } => Some(dest),
+        op => arithmetic_dest(op),
+    }
+}
+
+fn arithmetic_dest(op: OpCode) -> Option<RegisterIndex> {
+    match op {
+        OpCode::AddRR { dest, .. }
+        | OpCode::AddRC { dest, .. }
+        | OpCode::AddCR { dest, .. }
+        | OpCode::AddCC { dest, .. }
+        | OpCode::SubRR { dest, .. }
+        | OpCode::SubRC { dest, .. }
+        | OpCode::SubCR { dest, .. }
+        | OpCode::SubCC { dest, .. }
+        | OpCode::MulRR { dest, .. }
+        | OpCode::MulRC { dest, .. }
+        | OpCode::MulCR { dest, .. }
+        | OpCode::MulCC { dest, .. }
+        | OpCode::DivRR { dest, .. }
+        | OpCode::DivRC { dest, .. }
+        | OpCode::DivCR { dest, .. }
+        | OpCode::DivCC { dest, .. }
+        | OpCode::IDivRR { dest, .. }
+        | OpCode::IDivRC { dest, .. }
+        | OpCode::IDivCR { dest, .. }
+        | OpCode::IDivCC { dest, .. }
+        | OpCode::ModRR { dest, .. }
+        | OpCode::ModRC { dest, .. }
+        | OpCode::ModCR { dest, .. }
+        | OpCode::ModCC { dest, .. }
+        | OpCode::PowRR { dest, .. }
+        | OpCode::PowRC { dest, .. }
+        | OpCode::PowCR { dest, .. }
+        | OpCode::PowCC { dest, .. }
+        | OpCode::BitAndRR { dest, .. }
+        | OpCode::BitAndRC { dest, .. }
+        | OpCode::BitAndCR { dest, .. }
+        | OpCode::BitAndCC { dest, .. }
+        | OpCode::BitOrRR { dest, .. }
+        | OpCode::BitOrRC { dest, .. }
+        | OpCode::BitOrCR { dest, .. }
+        | OpCode::BitOrCC { dest, .. }
+        | OpCode::BitXorRR { dest, .. }
+        | OpCode::BitXorRC { dest, .. }
+        | OpCode::BitXorCR { dest, .. }
+        | OpCode::BitXorCC { dest, .. }
+        | OpCode::ShiftLeftRR { dest, .. }
+        | OpCode::ShiftLeftRC { dest, .. }
+        | OpCode::ShiftLeftCR { dest, .. }
+        | OpCode::ShiftLeftCC { dest, .. }
+        | OpCode::ShiftRightRR { dest, .. }
+        | OpCode::ShiftRightRC { dest, .. }
+        | OpCode::ShiftRightCR { dest, .. }
+        | OpCode::ShiftRightCC { dest, .. } => Some(dest),
+        _ => None,
+    }
+}
+
+// The metamethod that may be called by the given opcode, without the leading "__"
+fn metamethod_name(op: OpCode) -> Option<&'static str> {
+    Some(match op {
+        OpCode::GetTableR { .. }
+        | OpCode::GetTableC { .. }
+        | OpCode::GetUpTableR { .. }
+        | OpCode::GetUpTableC { .. }
+        | OpCode::SelfR { .. }
+        | OpCode::SelfC { .. } => "index",
+        OpCode::SetTableRR { .. }
+        | OpCode::SetTableRC { .. }
+        | OpCode::SetTableCR { .. }
+        | OpCode::SetTableCC { .. }
+        | OpCode::SetUpTableRR { .. }
+        | OpCode::SetUpTableRC { .. }
+        | OpCode::SetUpTableCR { .. }
+        | OpCode::SetUpTableCC { .. } => "newindex",
+        OpCode::EqRR { .. } | OpCode::EqRC { .. } | OpCode::EqCR { .. } | OpCode::EqCC { .. } => {
+            "eq"
+        }
+        OpCode::LessRR { .. }
+        | OpCode::LessRC { .. }
+        | OpCode::LessCR { .. }
+        | OpCode::LessCC { .. } => "lt",
+        OpCode::LessEqRR { .. }
+        | OpCode::LessEqRC { .. }
+        | OpCode::LessEqCR { .. }
+        | OpCode::LessEqCC { .. } => "le",
+        OpCode::Concat { .. } => "concat",
+        OpCode::Length { .. } => "len",
+        OpCode::Minus { .. } => "unm",
+        OpCode::BitNot { .. } => "bnot",
+        op => return arithmetic_metamethod_name(op),
+    })
+}
+
+fn arithmetic_metamethod_name(op: OpCode) -> Option<&'static str> {
+    Some(match op {
+        OpCode::AddRR { .. }
+        | OpCode::AddRC { .. }
+        | OpCode::AddCR { .. }
+        | OpCode::AddCC { .. } => "add",
+        OpCode::SubRR { .. }
+        | OpCode::SubRC { .. }
+        | OpCode::SubCR { .. }
+        | OpCode::SubCC { .. } => "sub",
+        OpCode::MulRR { .. }
+        | OpCode::MulRC { .. }
+        | OpCode::MulCR { .. }
+        | OpCode::MulCC { .. } => "mul",
+        OpCode::DivRR { .. }
+        | OpCode::DivRC { .. }
+        | OpCode::DivCR { .. }
+        | OpCode::DivCC { .. } => "div",
+        OpCode::IDivRR { .. }
+        | OpCode::IDivRC { .. }
+        | OpCode::IDivCR { .. }
+        | OpCode::IDivCC { .. } => "idiv",
+        OpCode::ModRR { .. }
+        | OpCode::ModRC { .. }
+        | OpCode::ModCR { .. }
+        | OpCode::ModCC { .. } => "mod",
+        OpCode::PowRR { .. }
+        | OpCode::PowRC { .. }
+        | OpCode::PowCR { .. }
+        | OpCode::PowCC { .. } => "pow",
+        OpCode::BitAndRR { .. }
+        | OpCode::BitAndRC { .. }
+        | OpCode::BitAndCR { .. }
+        | OpCode::BitAndCC { .. } => "band",
+        OpCode::BitOrRR { .. }
+        | OpCode::BitOrRC { .. }
+        | OpCode::BitOrCR { .. }
+        | OpCode::BitOrCC { .. } => "bor",
+        OpCode::BitXorRR { .. }
+        | OpCode::BitXorRC { .. }
+        | OpCode::BitXorCR { .. }
+        | OpCode::BitXorCC { .. } => "bxor",
+        OpCode::ShiftLeftRR { .. }
+        | OpCode::ShiftLeftRC { .. }
+        | OpCode::ShiftLeftCR { .. }
+        | OpCode::ShiftLeftCC { .. } => "shl",
+        OpCode::ShiftRightRR { .. }
+        | OpCode::ShiftRightRC { .. }
+        | OpCode::ShiftRightCR { .. }
+        | OpCode::ShiftRightCC { .. } => "shr",
+        _ => return None,
+    })
+}
@@ -1,26 +1,43 @@
-use gc_arena::{Gc, MutationContext};
+use gc_arena::MutationContext;
 
 use crate::{
-    thread::LuaFrame, BinaryOperatorError, Closure, ClosureState, Error, Function, OpCode,
-    RegisterIndex, String, Table, TypeError, UpValueDescriptor, Value, VarCount,
+    meta_ops::{self, MetaMethod, MetaResult},
+    thread::{HookCheck, LuaFrame, MetaReturn},
+    BinaryOperatorError, Closure, Error, Function, OpCode, RegisterIndex, Table, UpValueDescriptor,
+    Value, VarCount,
 };
 
 // Runs the VM for the given number of instructions or until the current LuaFrame may have been
-// changed.  Returns the number of instructions that were not run, or 0 if all requested
-// instructions were run.
+// changed.  `instructions` is decremented for every instruction that is started, so afterwards it
+// holds the number of instructions that were not run, even if an instruction raised an error.
+//
+// If the thread has a debug hook, only a single instruction is run at a time, and calling the hook
+// counts as running an instruction.
 pub(crate) fn run_vm<'gc>(
     mc: MutationContext<'gc, '_>,
     mut lua_frame: LuaFrame<'gc, '_>,
-    mut instructions: u32,
-) -> Result<u32, Error<'gc>> {
-    assert_ne!(instructions, 0);
+    instructions: &mut u32,
+) -> Result<(), Error<'gc>> {
+    assert_ne!(*instructions, 0);
+
+    let hooked = match lua_frame.check_hook() {
+        HookCheck::Unhooked => false,
+        HookCheck::Step => true,
+        HookCheck::Call(call) => {
+            *instructions -= 1;
+            lua_frame.call_meta_function(mc, call, MetaReturn::None)?;
+            return Ok(());
+        }
+    };
 
     let current_function = lua_frame.closure();
+    let string_metatable = lua_frame.string_metatable();
     let mut registers = lua_frame.registers();
 
     loop {
         let op = current_function.0.proto.opcodes[*registers.pc];
         *registers.pc += 1;
+        *instructions -= 1;
 
         match op {
             OpCode::Move { dest, source } => {
@@ -54,96 +71,155 @@ pub(crate) fn run_vm<'gc>(
             }
 
             OpCode::GetTableR { dest, table, key } => {
-                registers.stack_frame[dest.0 as usize] =
-                    get_table(registers.stack_frame[table.0 as usize])?
-                        .get(registers.stack_frame[key.0 as usize]);
+                match meta_ops::index(
+                    string_metatable,
+                    registers.stack_frame[table.0 as usize],
+                    registers.stack_frame[key.0 as usize],
+                )? {
+                    MetaResult::Value(v) => registers.stack_frame[dest.0 as usize] = v,
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(mc, call, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::GetTableC { dest, table, key } => {
-                registers.stack_frame[dest.0 as usize] =
-                    get_table(registers.stack_frame[table.0 as usize])?
-                        .get(current_function.0.proto.constants[key.0 as usize].to_value())
+                match meta_ops::index(
+                    string_metatable,
+                    registers.stack_frame[table.0 as usize],
+                    current_function.0.proto.constants[key.0 as usize].to_value(),
+                )? {
+                    MetaResult::Value(v) => registers.stack_frame[dest.0 as usize] = v,
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(mc, call, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::SetTableRR { table, key, value } => {
-                get_table(registers.stack_frame[table.0 as usize])?.set(
+                if let Some(call) = meta_ops::new_index(
                     mc,
+                    registers.stack_frame[table.0 as usize],
                     registers.stack_frame[key.0 as usize],
                     registers.stack_frame[value.0 as usize],
-                )?;
+                )? {
+                    lua_frame.call_meta_function(mc, call, MetaReturn::None)?;
+                    break;
+                }
             }
 
             OpCode::SetTableRC { table, key, value } => {
-                get_table(registers.stack_frame[table.0 as usize])?.set(
+                if let Some(call) = meta_ops::new_index(
                     mc,
+                    registers.stack_frame[table.0 as usize],
                     registers.stack_frame[key.0 as usize],
                     current_function.0.proto.constants[value.0 as usize].to_value(),
-                )?;
+                )? {
+                    lua_frame.call_meta_function(mc, call, MetaReturn::None)?;
+                    break;
+                }
             }
 
             OpCode::SetTableCR { table, key, value } => {
-                get_table(registers.stack_frame[table.0 as usize])?.set(
+                if let Some(call) = meta_ops::new_index(
                     mc,
+                    registers.stack_frame[table.0 as usize],
                     current_function.0.proto.constants[key.0 as usize].to_value(),
                     registers.stack_frame[value.0 as usize],
-                )?;
+                )? {
+                    lua_frame.call_meta_function(mc, call, MetaReturn::None)?;
+                    break;
+                }
             }
 
             OpCode::SetTableCC { table, key, value } => {
-                get_table(registers.stack_frame[table.0 as usize])?.set(
+                if let Some(call) = meta_ops::new_index(
                     mc,
+                    registers.stack_frame[table.0 as usize],
                     current_function.0.proto.constants[key.0 as usize].to_value(),
                     current_function.0.proto.constants[value.0 as usize].to_value(),
-                )?;
+                )? {
+                    lua_frame.call_meta_function(mc, call, MetaReturn::None)?;
+                    break;
+                }
             }
 
             OpCode::GetUpTableR { dest, table, key } => {
-                registers.stack_frame[dest.0 as usize] = get_table(
-                    registers.get_upvalue(current_function.0.upvalues[table.0 as usize]),
-                )?
-                .get(registers.stack_frame[key.0 as usize]);
+                match meta_ops::index(
+                    string_metatable,
+                    registers.get_upvalue(current_function.0.upvalues[table.0 as usize].get()),
+                    registers.stack_frame[key.0 as usize],
+                )? {
+                    MetaResult::Value(v) => registers.stack_frame[dest.0 as usize] = v,
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(mc, call, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::GetUpTableC { dest, table, key } => {
-                registers.stack_frame[dest.0 as usize] =
-                    get_table(registers.get_upvalue(current_function.0.upvalues[table.0 as usize]))?
-                        .get(current_function.0.proto.constants[key.0 as usize].to_value())
+                match meta_ops::index(
+                    string_metatable,
+                    registers.get_upvalue(current_function.0.upvalues[table.0 as usize].get()),
+                    current_function.0.proto.constants[key.0 as usize].to_value(),
+                )? {
+                    MetaResult::Value(v) => registers.stack_frame[dest.0 as usize] = v,
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(mc, call, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::SetUpTableRR { table, key, value } => {
-                get_table(registers.get_upvalue(current_function.0.upvalues[table.0 as usize]))?
-                    .set(
-                        mc,
-                        registers.stack_frame[key.0 as usize],
-                        registers.stack_frame[value.0 as usize],
-                    )?;
+                if let Some(call) = meta_ops::new_index(
+                    mc,
+                    registers.get_upvalue(current_function.0.upvalues[table.0 as usize].get()),
+                    registers.stack_frame[key.0 as usize],
+                    registers.stack_frame[value.0 as usize],
+                )? {
+                    lua_frame.call_meta_function(mc, call, MetaReturn::None)?;
+                    break;
+                }
             }
 
             OpCode::SetUpTableRC { table, key, value } => {
-                get_table(registers.get_upvalue(current_function.0.upvalues[table.0 as usize]))?
-                    .set(
-                        mc,
-                        registers.stack_frame[key.0 as usize],
-                        current_function.0.proto.constants[value.0 as usize].to_value(),
-                    )?;
+                if let Some(call) = meta_ops::new_index(
+                    mc,
+                    registers.get_upvalue(current_function.0.upvalues[table.0 as usize].get()),
+                    registers.stack_frame[key.0 as usize],
+                    current_function.0.proto.constants[value.0 as usize].to_value(),
+                )? {
+                    lua_frame.call_meta_function(mc, call, MetaReturn::None)?;
+                    break;
+                }
             }
 
             OpCode::SetUpTableCR { table, key, value } => {
-                get_table(registers.get_upvalue(current_function.0.upvalues[table.0 as usize]))?
-                    .set(
-                        mc,
-                        current_function.0.proto.constants[key.0 as usize].to_value(),
-                        registers.stack_frame[value.0 as usize],
-                    )?;
+                if let Some(call) = meta_ops::new_index(
+                    mc,
+                    registers.get_upvalue(current_function.0.upvalues[table.0 as usize].get()),
+                    current_function.0.proto.constants[key.0 as usize].to_value(),
+                    registers.stack_frame[value.0 as usize],
+                )? {
+                    lua_frame.call_meta_function(mc, call, MetaReturn::None)?;
+                    break;
+                }
             }
 
             OpCode::SetUpTableCC { table, key, value } => {
-                get_table(registers.get_upvalue(current_function.0.upvalues[table.0 as usize]))?
-                    .set(
-                        mc,
-                        current_function.0.proto.constants[key.0 as usize].to_value(),
-                        current_function.0.proto.constants[value.0 as usize].to_value(),
-                    )?;
+                if let Some(call) = meta_ops::new_index(
+                    mc,
+                    registers.get_upvalue(current_function.0.upvalues[table.0 as usize].get()),
+                    current_function.0.proto.constants[key.0 as usize].to_value(),
+                    current_function.0.proto.constants[value.0 as usize].to_value(),
+                )? {
+                    lua_frame.call_meta_function(mc, call, MetaReturn::None)?;
+                    break;
+                }
             }
 
             OpCode::Call {
@@ -212,12 +288,12 @@ pub(crate) fn run_vm<'gc>(
                             upvalues.push(registers.open_upvalue(mc, reg));
                         }
                         UpValueDescriptor::Outer(uvindex) => {
-                            upvalues.push(current_function.0.upvalues[uvindex.0 as usize]);
+                            upvalues.push(current_function.0.upvalues[uvindex.0 as usize].get());
                         }
                     }
                 }
 
-                let closure = Closure(Gc::allocate(mc, ClosureState { proto, upvalues }));
+                let closure = Closure::with_upvalues(mc, proto, upvalues);
                 registers.stack_frame[dest.0 as usize] =
                     Value::Function(Function::Closure(closure));
             }
@@ -292,16 +368,28 @@ pub(crate) fn run_vm<'gc>(
 
             OpCode::SelfR { base, table, key } => {
                 let table = registers.stack_frame[table.0 as usize];
-                let key = current_function.0.proto.constants[key.0 as usize].to_value();
+                let key = registers.stack_frame[key.0 as usize];
                 registers.stack_frame[base.0 as usize + 1] = table;
-                registers.stack_frame[base.0 as usize] = get_table(table)?.get(key);
+                match meta_ops::index(string_metatable, table, key)? {
+                    MetaResult::Value(v) => registers.stack_frame[base.0 as usize] = v,
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(mc, call, MetaReturn::Register(base))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::SelfC { base, table, key } => {
                 let table = registers.stack_frame[table.0 as usize];
                 let key = current_function.0.proto.constants[key.0 as usize].to_value();
                 registers.stack_frame[base.0 as usize + 1] = table;
-                registers.stack_frame[base.0 as usize] = get_table(table)?.get(key);
+                match meta_ops::index(string_metatable, table, key)? {
+                    MetaResult::Value(v) => registers.stack_frame[base.0 as usize] = v,
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(mc, call, MetaReturn::Register(base))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::Concat {
@@ -309,32 +397,39 @@ pub(crate) fn run_vm<'gc>(
                 source,
                 count,
             } => {
-                registers.stack_frame[dest.0 as usize] = Value::String(
-                    String::concat(
-                        mc,
-                        &registers.stack_frame
-                            [source.0 as usize..source.0 as usize + count as usize],
-                    )
-                    .unwrap(),
-                );
+                match meta_ops::concat(
+                    mc,
+                    &registers.stack_frame[source.0 as usize..source.0 as usize + count as usize],
+                )? {
+                    MetaResult::Value(v) => registers.stack_frame[dest.0 as usize] = v,
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(mc, call, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::GetUpValue { source, dest } => {
                 registers.stack_frame[dest.0 as usize] =
-                    registers.get_upvalue(current_function.0.upvalues[source.0 as usize]);
+                    registers.get_upvalue(current_function.0.upvalues[source.0 as usize].get());
             }
 
             OpCode::SetUpValue { source, dest } => {
                 registers.set_upvalue(
                     mc,
-                    current_function.0.upvalues[dest.0 as usize],
+                    current_function.0.upvalues[dest.0 as usize].get(),
                     registers.stack_frame[source.0 as usize],
                 );
             }
 
             OpCode::Length { dest, source } => {
-                registers.stack_frame[dest.0 as usize] =
-                    Value::Integer(get_table(registers.stack_frame[source.0 as usize])?.length());
+                match meta_ops::length(registers.stack_frame[source.0 as usize])? {
+                    MetaResult::Value(v) => registers.stack_frame[dest.0 as usize] = v,
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(mc, call, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::EqRR {
@@ -342,10 +437,19 @@ pub(crate) fn run_vm<'gc>(
                 left,
                 right,
             } => {
-                let left = registers.stack_frame[left.0 as usize];
-                let right = registers.stack_frame[right.0 as usize];
-                if (left == right) == skip_if {
-                    *registers.pc += 1;
+                match meta_ops::equal(
+                    registers.stack_frame[left.0 as usize],
+                    registers.stack_frame[right.0 as usize],
+                )? {
+                    MetaResult::Value(v) => {
+                        if v.to_bool() == skip_if {
+                            *registers.pc += 1;
+                        }
+                    }
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(mc, call, MetaReturn::SkipIf(skip_if))?;
+                        break;
+                    }
                 }
             }
 
@@ -354,10 +458,19 @@ pub(crate) fn run_vm<'gc>(
                 left,
                 right,
             } => {
-                let left = registers.stack_frame[left.0 as usize];
-                let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                if (left == right) == skip_if {
-                    *registers.pc += 1;
+                match meta_ops::equal(
+                    registers.stack_frame[left.0 as usize],
+                    current_function.0.proto.constants[right.0 as usize].to_value(),
+                )? {
+                    MetaResult::Value(v) => {
+                        if v.to_bool() == skip_if {
+                            *registers.pc += 1;
+                        }
+                    }
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(mc, call, MetaReturn::SkipIf(skip_if))?;
+                        break;
+                    }
                 }
             }
 
@@ -366,10 +479,19 @@ pub(crate) fn run_vm<'gc>(
                 left,
                 right,
             } => {
-                let left = current_function.0.proto.constants[left.0 as usize].to_value();
-                let right = registers.stack_frame[right.0 as usize];
-                if (left == right) == skip_if {
-                    *registers.pc += 1;
+                match meta_ops::equal(
+                    current_function.0.proto.constants[left.0 as usize].to_value(),
+                    registers.stack_frame[right.0 as usize],
+                )? {
+                    MetaResult::Value(v) => {
+                        if v.to_bool() == skip_if {
+                            *registers.pc += 1;
+                        }
+                    }
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(mc, call, MetaReturn::SkipIf(skip_if))?;
+                        break;
+                    }
                 }
             }
 
@@ -378,10 +500,19 @@ pub(crate) fn run_vm<'gc>(
                 left,
                 right,
             } => {
-                let left = current_function.0.proto.constants[left.0 as usize].to_value();
-                let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                if (left == right) == skip_if {
-                    *registers.pc += 1;
+                match meta_ops::equal(
+                    current_function.0.proto.constants[left.0 as usize].to_value(),
+                    current_function.0.proto.constants[right.0 as usize].to_value(),
+                )? {
+                    MetaResult::Value(v) => {
+                        if v.to_bool() == skip_if {
+                            *registers.pc += 1;
+                        }
+                    }
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(mc, call, MetaReturn::SkipIf(skip_if))?;
+                        break;
+                    }
                 }
             }
 
@@ -390,10 +521,19 @@ pub(crate) fn run_vm<'gc>(
                 left,
                 right,
             } => {
-                let left = registers.stack_frame[left.0 as usize];
-                let right = registers.stack_frame[right.0 as usize];
-                if (left.less_than(right).ok_or(BinaryOperatorError::LessThan)?) == skip_if {
-                    *registers.pc += 1;
+                match meta_ops::less_than(
+                    registers.stack_frame[left.0 as usize],
+                    registers.stack_frame[right.0 as usize],
+                )? {
+                    MetaResult::Value(v) => {
+                        if v.to_bool() == skip_if {
+                            *registers.pc += 1;
+                        }
+                    }
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(mc, call, MetaReturn::SkipIf(skip_if))?;
+                        break;
+                    }
                 }
             }
 
@@ -402,10 +542,19 @@ pub(crate) fn run_vm<'gc>(
                 left,
                 right,
             } => {
-                let left = registers.stack_frame[left.0 as usize];
-                let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                if (left.less_than(right).ok_or(BinaryOperatorError::LessThan)?) == skip_if {
-                    *registers.pc += 1;
+                match meta_ops::less_than(
+                    registers.stack_frame[left.0 as usize],
+                    current_function.0.proto.constants[right.0 as usize].to_value(),
+                )? {
+                    MetaResult::Value(v) => {
+                        if v.to_bool() == skip_if {
+                            *registers.pc += 1;
+                        }
+                    }
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(mc, call, MetaReturn::SkipIf(skip_if))?;
+                        break;
+                    }
                 }
             }
 
@@ -414,10 +563,19 @@ pub(crate) fn run_vm<'gc>(
                 left,
                 right,
             } => {
-                let left = current_function.0.proto.constants[left.0 as usize].to_value();
-                let right = registers.stack_frame[right.0 as usize];
-                if (left.less_than(right).ok_or(BinaryOperatorError::LessThan)?) == skip_if {
-                    *registers.pc += 1;
+                match meta_ops::less_than(
+                    current_function.0.proto.constants[left.0 as usize].to_value(),
+                    registers.stack_frame[right.0 as usize],
+                )? {
+                    MetaResult::Value(v) => {
+                        if v.to_bool() == skip_if {
+                            *registers.pc += 1;
+                        }
+                    }
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(mc, call, MetaReturn::SkipIf(skip_if))?;
+                        break;
+                    }
                 }
             }
 
@@ -426,10 +584,19 @@ pub(crate) fn run_vm<'gc>(
                 left,
                 right,
             } => {
-                let left = current_function.0.proto.constants[left.0 as usize].to_value();
-                let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                if (left.less_than(right).ok_or(BinaryOperatorError::LessThan)?) == skip_if {
-                    *registers.pc += 1;
+                match meta_ops::less_than(
+                    current_function.0.proto.constants[left.0 as usize].to_value(),
+                    current_function.0.proto.constants[right.0 as usize].to_value(),
+                )? {
+                    MetaResult::Value(v) => {
+                        if v.to_bool() == skip_if {
+                            *registers.pc += 1;
+                        }
+                    }
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(mc, call, MetaReturn::SkipIf(skip_if))?;
+                        break;
+                    }
                 }
             }
 
@@ -438,14 +605,20 @@ pub(crate) fn run_vm<'gc>(
                 left,
                 right,
             } => {
-                let left = registers.stack_frame[left.0 as usize];
-                let right = registers.stack_frame[right.0 as usize];
-                if (left
-                    .less_equal(right)
-                    .ok_or(BinaryOperatorError::LessEqual)?)
-                    == skip_if
-                {
-                    *registers.pc += 1;
+                match meta_ops::less_equal(
+                    mc,
+                    registers.stack_frame[left.0 as usize],
+                    registers.stack_frame[right.0 as usize],
+                )? {
+                    MetaResult::Value(v) => {
+                        if v.to_bool() == skip_if {
+                            *registers.pc += 1;
+                        }
+                    }
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(mc, call, MetaReturn::SkipIf(skip_if))?;
+                        break;
+                    }
                 }
             }
 
@@ -454,14 +627,20 @@ pub(crate) fn run_vm<'gc>(
                 left,
                 right,
             } => {
-                let left = registers.stack_frame[left.0 as usize];
-                let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                if (left
-                    .less_equal(right)
-                    .ok_or(BinaryOperatorError::LessEqual)?)
-                    == skip_if
-                {
-                    *registers.pc += 1;
+                match meta_ops::less_equal(
+                    mc,
+                    registers.stack_frame[left.0 as usize],
+                    current_function.0.proto.constants[right.0 as usize].to_value(),
+                )? {
+                    MetaResult::Value(v) => {
+                        if v.to_bool() == skip_if {
+                            *registers.pc += 1;
+                        }
+                    }
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(mc, call, MetaReturn::SkipIf(skip_if))?;
+                        break;
+                    }
                 }
             }
 
@@ -470,14 +649,20 @@ pub(crate) fn run_vm<'gc>(
                 left,
                 right,
             } => {
-                let left = current_function.0.proto.constants[left.0 as usize].to_value();
-                let right = registers.stack_frame[right.0 as usize];
-                if (left
-                    .less_equal(right)
-                    .ok_or(BinaryOperatorError::LessEqual)?)
-                    == skip_if
-                {
-                    *registers.pc += 1;
+                match meta_ops::less_equal(
+                    mc,
+                    current_function.0.proto.constants[left.0 as usize].to_value(),
+                    registers.stack_frame[right.0 as usize],
+                )? {
+                    MetaResult::Value(v) => {
+                        if v.to_bool() == skip_if {
+                            *registers.pc += 1;
+                        }
+                    }
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(mc, call, MetaReturn::SkipIf(skip_if))?;
+                        break;
+                    }
                 }
             }
 
@@ -486,14 +671,20 @@ pub(crate) fn run_vm<'gc>(
                 left,
                 right,
             } => {
-                let left = current_function.0.proto.constants[left.0 as usize].to_value();
-                let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                if (left
-                    .less_equal(right)
-                    .ok_or(BinaryOperatorError::LessEqual)?)
-                    == skip_if
-                {
-                    *registers.pc += 1;
+                match meta_ops::less_equal(
+                    mc,
+                    current_function.0.proto.constants[left.0 as usize].to_value(),
+                    current_function.0.proto.constants[right.0 as usize].to_value(),
+                )? {
+                    MetaResult::Value(v) => {
+                        if v.to_bool() == skip_if {
+                            *registers.pc += 1;
+                        }
+                    }
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(mc, call, MetaReturn::SkipIf(skip_if))?;
+                        break;
+                    }
                 }
             }
 
@@ -503,393 +694,704 @@ pub(crate) fn run_vm<'gc>(
             }
 
             OpCode::Minus { dest, source } => {
-                let value = registers.stack_frame[source.0 as usize];
-                registers.stack_frame[dest.0 as usize] =
-                    value.negate().ok_or(BinaryOperatorError::UnaryNegate)?;
+                match meta_ops::unary(MetaMethod::Unm, registers.stack_frame[source.0 as usize])? {
+                    MetaResult::Value(v) => registers.stack_frame[dest.0 as usize] = v,
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(mc, call, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::BitNot { dest, source } => {
-                let value = registers.stack_frame[source.0 as usize];
-                registers.stack_frame[dest.0 as usize] = value
-                    .bitwise_not()
-                    .ok_or(BinaryOperatorError::BitNot)?;
+                match meta_ops::unary(MetaMethod::BNot, registers.stack_frame[source.0 as usize])? {
+                    MetaResult::Value(v) => registers.stack_frame[dest.0 as usize] = v,
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(mc, call, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::AddRR { dest, left, right } => {
-                let left = registers.stack_frame[left.0 as usize];
-                let right = registers.stack_frame[right.0 as usize];
-                registers.stack_frame[dest.0 as usize] =
-                    left.add(right).ok_or(BinaryOperatorError::Add)?;
+                match meta_ops::arithmetic(
+                    MetaMethod::Add,
+                    registers.stack_frame[left.0 as usize],
+                    registers.stack_frame[right.0 as usize],
+                )? {
+                    MetaResult::Value(v) => registers.stack_frame[dest.0 as usize] = v,
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(mc, call, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::AddRC { dest, left, right } => {
-                let left = registers.stack_frame[left.0 as usize];
-                let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                registers.stack_frame[dest.0 as usize] =
-                    left.add(right).ok_or(BinaryOperatorError::Add)?;
+                match meta_ops::arithmetic(
+                    MetaMethod::Add,
+                    registers.stack_frame[left.0 as usize],
+                    current_function.0.proto.constants[right.0 as usize].to_value(),
+                )? {
+                    MetaResult::Value(v) => registers.stack_frame[dest.0 as usize] = v,
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(mc, call, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::AddCR { dest, left, right } => {
-                let left = current_function.0.proto.constants[left.0 as usize].to_value();
-                let right = registers.stack_frame[right.0 as usize];
-                registers.stack_frame[dest.0 as usize] =
-                    left.add(right).ok_or(BinaryOperatorError::Add)?;
+                match meta_ops::arithmetic(
+                    MetaMethod::Add,
+                    current_function.0.proto.constants[left.0 as usize].to_value(),
+                    registers.stack_frame[right.0 as usize],
+                )? {
+                    MetaResult::Value(v) => registers.stack_frame[dest.0 as usize] = v,
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(mc, call, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::AddCC { dest, left, right } => {
-                let left = current_function.0.proto.constants[left.0 as usize].to_value();
-                let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                registers.stack_frame[dest.0 as usize] =
-                    left.add(right).ok_or(BinaryOperatorError::Add)?;
+                match meta_ops::arithmetic(
+                    MetaMethod::Add,
+                    current_function.0.proto.constants[left.0 as usize].to_value(),
+                    current_function.0.proto.constants[right.0 as usize].to_value(),
+                )? {
+                    MetaResult::Value(v) => registers.stack_frame[dest.0 as usize] = v,
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(mc, call, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::SubRR { dest, left, right } => {
-                let left = registers.stack_frame[left.0 as usize];
-                let right = registers.stack_frame[right.0 as usize];
-                registers.stack_frame[dest.0 as usize] =
-                    left.subtract(right).ok_or(BinaryOperatorError::Add)?;
+                match meta_ops::arithmetic(
+                    MetaMethod::Sub,
+                    registers.stack_frame[left.0 as usize],
+                    registers.stack_frame[right.0 as usize],
+                )? {
+                    MetaResult::Value(v) => registers.stack_frame[dest.0 as usize] = v,
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(mc, call, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::SubRC { dest, left, right } => {
-                let left = registers.stack_frame[left.0 as usize];
-                let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                registers.stack_frame[dest.0 as usize] =
-                    left.subtract(right).ok_or(BinaryOperatorError::Subtract)?;
+                match meta_ops::arithmetic(
+                    MetaMethod::Sub,
+                    registers.stack_frame[left.0 as usize],
+                    current_function.0.proto.constants[right.0 as usize].to_value(),
+                )? {
+                    MetaResult::Value(v) => registers.stack_frame[dest.0 as usize] = v,
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(mc, call, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::SubCR { dest, left, right } => {
-                let left = current_function.0.proto.constants[left.0 as usize].to_value();
-                let right = registers.stack_frame[right.0 as usize];
-                registers.stack_frame[dest.0 as usize] =
-                    left.subtract(right).ok_or(BinaryOperatorError::Subtract)?;
+                match meta_ops::arithmetic(
+                    MetaMethod::Sub,
+                    current_function.0.proto.constants[left.0 as usize].to_value(),
+                    registers.stack_frame[right.0 as usize],
+                )? {
+                    MetaResult::Value(v) => registers.stack_frame[dest.0 as usize] = v,
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(mc, call, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::SubCC { dest, left, right } => {
-                let left = current_function.0.proto.constants[left.0 as usize].to_value();
-                let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                registers.stack_frame[dest.0 as usize] =
-                    left.subtract(right).ok_or(BinaryOperatorError::Subtract)?;
+                match meta_ops::arithmetic(
+                    MetaMethod::Sub,
+                    current_function.0.proto.constants[left.0 as usize].to_value(),
+                    current_function.0.proto.constants[right.0 as usize].to_value(),
+                )? {
+                    MetaResult::Value(v) => registers.stack_frame[dest.0 as usize] = v,
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(mc, call, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::MulRR { dest, left, right } => {
-                let left = registers.stack_frame[left.0 as usize];
-                let right = registers.stack_frame[right.0 as usize];
-                registers.stack_frame[dest.0 as usize] =
-                    left.multiply(right).ok_or(BinaryOperatorError::Multiply)?;
+                match meta_ops::arithmetic(
+                    MetaMethod::Mul,
+                    registers.stack_frame[left.0 as usize],
+                    registers.stack_frame[right.0 as usize],
+                )? {
+                    MetaResult::Value(v) => registers.stack_frame[dest.0 as usize] = v,
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(mc, call, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::MulRC { dest, left, right } => {
-                let left = registers.stack_frame[left.0 as usize];
-                let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                registers.stack_frame[dest.0 as usize] =
-                    left.multiply(right).ok_or(BinaryOperatorError::Multiply)?;
+                match meta_ops::arithmetic(
+                    MetaMethod::Mul,
+                    registers.stack_frame[left.0 as usize],
+                    current_function.0.proto.constants[right.0 as usize].to_value(),
+                )? {
+                    MetaResult::Value(v) => registers.stack_frame[dest.0 as usize] = v,
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(mc, call, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::MulCR { dest, left, right } => {
-                let left = current_function.0.proto.constants[left.0 as usize].to_value();
-                let right = registers.stack_frame[right.0 as usize];
-                registers.stack_frame[dest.0 as usize] =
-                    left.multiply(right).ok_or(BinaryOperatorError::Multiply)?;
+                match meta_ops::arithmetic(
+                    MetaMethod::Mul,
+                    current_function.0.proto.constants[left.0 as usize].to_value(),
+                    registers.stack_frame[right.0 as usize],
+                )? {
+                    MetaResult::Value(v) => registers.stack_frame[dest.0 as usize] = v,
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(mc, call, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::MulCC { dest, left, right } => {
-                let left = current_function.0.proto.constants[left.0 as usize].to_value();
-                let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                registers.stack_frame[dest.0 as usize] =
-                    left.multiply(right).ok_or(BinaryOperatorError::Multiply)?;
+                match meta_ops::arithmetic(
+                    MetaMethod::Mul,
+                    current_function.0.proto.constants[left.0 as usize].to_value(),
+                    current_function.0.proto.constants[right.0 as usize].to_value(),
+                )? {
+                    MetaResult::Value(v) => registers.stack_frame[dest.0 as usize] = v,
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(mc, call, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::DivRR { dest, left, right } => {
-                let left = registers.stack_frame[left.0 as usize];
-                let right = registers.stack_frame[right.0 as usize];
-                registers.stack_frame[dest.0 as usize] = left
-                    .float_divide(right)
-                    .ok_or(BinaryOperatorError::FloatDivide)?;
+                match meta_ops::arithmetic(
+                    MetaMethod::Div,
+                    registers.stack_frame[left.0 as usize],
+                    registers.stack_frame[right.0 as usize],
+                )? {
+                    MetaResult::Value(v) => registers.stack_frame[dest.0 as usize] = v,
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(mc, call, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::DivRC { dest, left, right } => {
-                let left = registers.stack_frame[left.0 as usize];
-                let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                registers.stack_frame[dest.0 as usize] = left
-                    .float_divide(right)
-                    .ok_or(BinaryOperatorError::FloatDivide)?;
+                match meta_ops::arithmetic(
+                    MetaMethod::Div,
+                    registers.stack_frame[left.0 as usize],
+                    current_function.0.proto.constants[right.0 as usize].to_value(),
+                )? {
+                    MetaResult::Value(v) => registers.stack_frame[dest.0 as usize] = v,
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(mc, call, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::DivCR { dest, left, right } => {
-                let left = current_function.0.proto.constants[left.0 as usize].to_value();
-                let right = registers.stack_frame[right.0 as usize];
-                registers.stack_frame[dest.0 as usize] = left
-                    .float_divide(right)
-                    .ok_or(BinaryOperatorError::FloatDivide)?;
+                match meta_ops::arithmetic(
+                    MetaMethod::Div,
+                    current_function.0.proto.constants[left.0 as usize].to_value(),
+                    registers.stack_frame[right.0 as usize],
+                )? {
+                    MetaResult::Value(v) => registers.stack_frame[dest.0 as usize] = v,
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(mc, call, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::DivCC { dest, left, right } => {
-                let left = current_function.0.proto.constants[left.0 as usize].to_value();
-                let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                registers.stack_frame[dest.0 as usize] = left
-                    .float_divide(right)
-                    .ok_or(BinaryOperatorError::FloatDivide)?;
+                match meta_ops::arithmetic(
+                    MetaMethod::Div,
+                    current_function.0.proto.constants[left.0 as usize].to_value(),
+                    current_function.0.proto.constants[right.0 as usize].to_value(),
+                )? {
+                    MetaResult::Value(v) => registers.stack_frame[dest.0 as usize] = v,
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(mc, call, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::IDivRR { dest, left, right } => {
-                let left = registers.stack_frame[left.0 as usize];
-                let right = registers.stack_frame[right.0 as usize];
-                registers.stack_frame[dest.0 as usize] = left
-                    .floor_divide(right)
-                    .ok_or(BinaryOperatorError::FloorDivide)?;
+                match meta_ops::arithmetic(
+                    MetaMethod::IDiv,
+                    registers.stack_frame[left.0 as usize],
+                    registers.stack_frame[right.0 as usize],
+                )? {
+                    MetaResult::Value(v) => registers.stack_frame[dest.0 as usize] = v,
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(mc, call, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::IDivRC { dest, left, right } => {
-                let left = registers.stack_frame[left.0 as usize];
-                let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                registers.stack_frame[dest.0 as usize] = left
-                    .floor_divide(right)
-                    .ok_or(BinaryOperatorError::FloorDivide)?;
+                match meta_ops::arithmetic(
+                    MetaMethod::IDiv,
+                    registers.stack_frame[left.0 as usize],
+                    current_function.0.proto.constants[right.0 as usize].to_value(),
+                )? {
+                    MetaResult::Value(v) => registers.stack_frame[dest.0 as usize] = v,
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(mc, call, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::IDivCR { dest, left, right } => {
-                let left = current_function.0.proto.constants[left.0 as usize].to_value();
-                let right = registers.stack_frame[right.0 as usize];
-                registers.stack_frame[dest.0 as usize] = left
-                    .floor_divide(right)
-                    .ok_or(BinaryOperatorError::FloorDivide)?;
+                match meta_ops::arithmetic(
+                    MetaMethod::IDiv,
+                    current_function.0.proto.constants[left.0 as usize].to_value(),
+                    registers.stack_frame[right.0 as usize],
+                )? {
+                    MetaResult::Value(v) => registers.stack_frame[dest.0 as usize] = v,
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(mc, call, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::IDivCC { dest, left, right } => {
-                let left = current_function.0.proto.constants[left.0 as usize].to_value();
-                let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                registers.stack_frame[dest.0 as usize] = left
-                    .floor_divide(right)
-                    .ok_or(BinaryOperatorError::FloorDivide)?;
+                match meta_ops::arithmetic(
+                    MetaMethod::IDiv,
+                    current_function.0.proto.constants[left.0 as usize].to_value(),
+                    current_function.0.proto.constants[right.0 as usize].to_value(),
+                )? {
+                    MetaResult::Value(v) => registers.stack_frame[dest.0 as usize] = v,
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(mc, call, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::ModRR { dest, left, right } => {
-                let left = registers.stack_frame[left.0 as usize];
-                let right = registers.stack_frame[right.0 as usize];
-                registers.stack_frame[dest.0 as usize] =
-                    left.modulo(right).ok_or(BinaryOperatorError::Modulo)?;
+                match meta_ops::arithmetic(
+                    MetaMethod::Mod,
+                    registers.stack_frame[left.0 as usize],
+                    registers.stack_frame[right.0 as usize],
+                )? {
+                    MetaResult::Value(v) => registers.stack_frame[dest.0 as usize] = v,
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(mc, call, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::ModRC { dest, left, right } => {
-                let left = registers.stack_frame[left.0 as usize];
-                let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                registers.stack_frame[dest.0 as usize] =
-                    left.modulo(right).ok_or(BinaryOperatorError::Modulo)?;
+                match meta_ops::arithmetic(
+                    MetaMethod::Mod,
+                    registers.stack_frame[left.0 as usize],
+                    current_function.0.proto.constants[right.0 as usize].to_value(),
+                )? {
+                    MetaResult::Value(v) => registers.stack_frame[dest.0 as usize] = v,
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(mc, call, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::ModCR { dest, left, right } => {
-                let left = current_function.0.proto.constants[left.0 as usize].to_value();
-                let right = registers.stack_frame[right.0 as usize];
-                registers.stack_frame[dest.0 as usize] =
-                    left.modulo(right).ok_or(BinaryOperatorError::Modulo)?;
+                match meta_ops::arithmetic(
+                    MetaMethod::Mod,
+                    current_function.0.proto.constants[left.0 as usize].to_value(),
+                    registers.stack_frame[right.0 as usize],
+                )? {
+                    MetaResult::Value(v) => registers.stack_frame[dest.0 as usize] = v,
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(mc, call, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::ModCC { dest, left, right } => {
-                let left = current_function.0.proto.constants[left.0 as usize].to_value();
-                let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                registers.stack_frame[dest.0 as usize] =
-                    left.modulo(right).ok_or(BinaryOperatorError::Modulo)?;
+                match meta_ops::arithmetic(
+                    MetaMethod::Mod,
+                    current_function.0.proto.constants[left.0 as usize].to_value(),
+                    current_function.0.proto.constants[right.0 as usize].to_value(),
+                )? {
+                    MetaResult::Value(v) => registers.stack_frame[dest.0 as usize] = v,
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(mc, call, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::PowRR { dest, left, right } => {
-                let left = registers.stack_frame[left.0 as usize];
-                let right = registers.stack_frame[right.0 as usize];
-                registers.stack_frame[dest.0 as usize] = left
-                    .exponentiate(right)
-                    .ok_or(BinaryOperatorError::Exponentiate)?;
+                match meta_ops::arithmetic(
+                    MetaMethod::Pow,
+                    registers.stack_frame[left.0 as usize],
+                    registers.stack_frame[right.0 as usize],
+                )? {
+                    MetaResult::Value(v) => registers.stack_frame[dest.0 as usize] = v,
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(mc, call, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::PowRC { dest, left, right } => {
-                let left = registers.stack_frame[left.0 as usize];
-                let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                registers.stack_frame[dest.0 as usize] = left
-                    .exponentiate(right)
-                    .ok_or(BinaryOperatorError::Exponentiate)?;
+                match meta_ops::arithmetic(
+                    MetaMethod::Pow,
+                    registers.stack_frame[left.0 as usize],
+                    current_function.0.proto.constants[right.0 as usize].to_value(),
+                )? {
+                    MetaResult::Value(v) => registers.stack_frame[dest.0 as usize] = v,
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(mc, call, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::PowCR { dest, left, right } => {
-                let left = current_function.0.proto.constants[left.0 as usize].to_value();
-                let right = registers.stack_frame[right.0 as usize];
-                registers.stack_frame[dest.0 as usize] = left
-                    .exponentiate(right)
-                    .ok_or(BinaryOperatorError::Exponentiate)?;
+                match meta_ops::arithmetic(
+                    MetaMethod::Pow,
+                    current_function.0.proto.constants[left.0 as usize].to_value(),
+                    registers.stack_frame[right.0 as usize],
+                )? {
+                    MetaResult::Value(v) => registers.stack_frame[dest.0 as usize] = v,
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(mc, call, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::PowCC { dest, left, right } => {
-                let left = current_function.0.proto.constants[left.0 as usize].to_value();
-                let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                registers.stack_frame[dest.0 as usize] = left
-                    .exponentiate(right)
-                    .ok_or(BinaryOperatorError::Exponentiate)?;
+                match meta_ops::arithmetic(
+                    MetaMethod::Pow,
+                    current_function.0.proto.constants[left.0 as usize].to_value(),
+                    current_function.0.proto.constants[right.0 as usize].to_value(),
+                )? {
+                    MetaResult::Value(v) => registers.stack_frame[dest.0 as usize] = v,
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(mc, call, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::BitAndRR { dest, left, right } => {
-                let left = registers.stack_frame[left.0 as usize];
-                let right = registers.stack_frame[right.0 as usize];
-                registers.stack_frame[dest.0 as usize] =
-                    left.bitwise_and(right).ok_or(BinaryOperatorError::BitAnd)?;
+                match meta_ops::arithmetic(
+                    MetaMethod::BAnd,
+                    registers.stack_frame[left.0 as usize],
+                    registers.stack_frame[right.0 as usize],
+                )? {
+                    MetaResult::Value(v) => registers.stack_frame[dest.0 as usize] = v,
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(mc, call, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::BitAndRC { dest, left, right } => {
-                let left = registers.stack_frame[left.0 as usize];
-                let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                registers.stack_frame[dest.0 as usize] =
-                    left.bitwise_and(right).ok_or(BinaryOperatorError::BitAnd)?;
+                match meta_ops::arithmetic(
+                    MetaMethod::BAnd,
+                    registers.stack_frame[left.0 as usize],
+                    current_function.0.proto.constants[right.0 as usize].to_value(),
+                )? {
+                    MetaResult::Value(v) => registers.stack_frame[dest.0 as usize] = v,
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(mc, call, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::BitAndCR { dest, left, right } => {
-                let left = current_function.0.proto.constants[left.0 as usize].to_value();
-                let right = registers.stack_frame[right.0 as usize];
-                registers.stack_frame[dest.0 as usize] =
-                    left.bitwise_and(right).ok_or(BinaryOperatorError::BitAnd)?;
+                match meta_ops::arithmetic(
+                    MetaMethod::BAnd,
+                    current_function.0.proto.constants[left.0 as usize].to_value(),
+                    registers.stack_frame[right.0 as usize],
+                )? {
+                    MetaResult::Value(v) => registers.stack_frame[dest.0 as usize] = v,
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(mc, call, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::BitAndCC { dest, left, right } => {
-                let left = current_function.0.proto.constants[left.0 as usize].to_value();
-                let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                registers.stack_frame[dest.0 as usize] =
-                    left.bitwise_and(right).ok_or(BinaryOperatorError::BitAnd)?;
+                match meta_ops::arithmetic(
+                    MetaMethod::BAnd,
+                    current_function.0.proto.constants[left.0 as usize].to_value(),
+                    current_function.0.proto.constants[right.0 as usize].to_value(),
+                )? {
+                    MetaResult::Value(v) => registers.stack_frame[dest.0 as usize] = v,
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(mc, call, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::BitOrRR { dest, left, right } => {
-                let left = registers.stack_frame[left.0 as usize];
-                let right = registers.stack_frame[right.0 as usize];
-                registers.stack_frame[dest.0 as usize] =
-                    left.bitwise_or(right).ok_or(BinaryOperatorError::BitOr)?;
+                match meta_ops::arithmetic(
+                    MetaMethod::BOr,
+                    registers.stack_frame[left.0 as usize],
+                    registers.stack_frame[right.0 as usize],
+                )? {
+                    MetaResult::Value(v) => registers.stack_frame[dest.0 as usize] = v,
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(mc, call, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::BitOrRC { dest, left, right } => {
-                let left = registers.stack_frame[left.0 as usize];
-                let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                registers.stack_frame[dest.0 as usize] =
-                    left.bitwise_or(right).ok_or(BinaryOperatorError::BitOr)?;
+                match meta_ops::arithmetic(
+                    MetaMethod::BOr,
+                    registers.stack_frame[left.0 as usize],
+                    current_function.0.proto.constants[right.0 as usize].to_value(),
+                )? {
+                    MetaResult::Value(v) => registers.stack_frame[dest.0 as usize] = v,
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(mc, call, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::BitOrCR { dest, left, right } => {
-                let left = current_function.0.proto.constants[left.0 as usize].to_value();
-                let right = registers.stack_frame[right.0 as usize];
-                registers.stack_frame[dest.0 as usize] =
-                    left.bitwise_or(right).ok_or(BinaryOperatorError::BitOr)?;
+                match meta_ops::arithmetic(
+                    MetaMethod::BOr,
+                    current_function.0.proto.constants[left.0 as usize].to_value(),
+                    registers.stack_frame[right.0 as usize],
+                )? {
+                    MetaResult::Value(v) => registers.stack_frame[dest.0 as usize] = v,
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(mc, call, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::BitOrCC { dest, left, right } => {
-                let left = current_function.0.proto.constants[left.0 as usize].to_value();
-                let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                registers.stack_frame[dest.0 as usize] =
-                    left.bitwise_or(right).ok_or(BinaryOperatorError::BitOr)?;
+                match meta_ops::arithmetic(
+                    MetaMethod::BOr,
+                    current_function.0.proto.constants[left.0 as usize].to_value(),
+                    current_function.0.proto.constants[right.0 as usize].to_value(),
+                )? {
+                    MetaResult::Value(v) => registers.stack_frame[dest.0 as usize] = v,
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(mc, call, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::BitXorRR { dest, left, right } => {
-                let left = registers.stack_frame[left.0 as usize];
-                let right = registers.stack_frame[right.0 as usize];
-                registers.stack_frame[dest.0 as usize] =
-                    left.bitwise_xor(right).ok_or(BinaryOperatorError::BitXor)?;
+                match meta_ops::arithmetic(
+                    MetaMethod::BXor,
+                    registers.stack_frame[left.0 as usize],
+                    registers.stack_frame[right.0 as usize],
+                )? {
+                    MetaResult::Value(v) => registers.stack_frame[dest.0 as usize] = v,
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(mc, call, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::BitXorRC { dest, left, right } => {
-                let left = registers.stack_frame[left.0 as usize];
-                let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                registers.stack_frame[dest.0 as usize] =
-                    left.bitwise_xor(right).ok_or(BinaryOperatorError::BitXor)?;
+                match meta_ops::arithmetic(
+                    MetaMethod::BXor,
+                    registers.stack_frame[left.0 as usize],
+                    current_function.0.proto.constants[right.0 as usize].to_value(),
+                )? {
+                    MetaResult::Value(v) => registers.stack_frame[dest.0 as usize] = v,
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(mc, call, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::BitXorCR { dest, left, right } => {
-                let left = current_function.0.proto.constants[left.0 as usize].to_value();
-                let right = registers.stack_frame[right.0 as usize];
-                registers.stack_frame[dest.0 as usize] =
-                    left.bitwise_xor(right).ok_or(BinaryOperatorError::BitXor)?;
+                match meta_ops::arithmetic(
+                    MetaMethod::BXor,
+                    current_function.0.proto.constants[left.0 as usize].to_value(),
+                    registers.stack_frame[right.0 as usize],
+                )? {
+                    MetaResult::Value(v) => registers.stack_frame[dest.0 as usize] = v,
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(mc, call, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::BitXorCC { dest, left, right } => {
-                let left = current_function.0.proto.constants[left.0 as usize].to_value();
-                let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                registers.stack_frame[dest.0 as usize] =
-                    left.bitwise_xor(right).ok_or(BinaryOperatorError::BitXor)?;
+                match meta_ops::arithmetic(
+                    MetaMethod::BXor,
+                    current_function.0.proto.constants[left.0 as usize].to_value(),
+                    current_function.0.proto.constants[right.0 as usize].to_value(),
+                )? {
+                    MetaResult::Value(v) => registers.stack_frame[dest.0 as usize] = v,
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(mc, call, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::ShiftLeftRR { dest, left, right } => {
-                let left = registers.stack_frame[left.0 as usize];
-                let right = registers.stack_frame[right.0 as usize];
-                registers.stack_frame[dest.0 as usize] = left
-                    .shift_left(right)
-                    .ok_or(BinaryOperatorError::ShiftLeft)?;
+                match meta_ops::arithmetic(
+                    MetaMethod::Shl,
+                    registers.stack_frame[left.0 as usize],
+                    registers.stack_frame[right.0 as usize],
+                )? {
+                    MetaResult::Value(v) => registers.stack_frame[dest.0 as usize] = v,
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(mc, call, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::ShiftLeftRC { dest, left, right } => {
-                let left = registers.stack_frame[left.0 as usize];
-                let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                registers.stack_frame[dest.0 as usize] = left
-                    .shift_left(right)
-                    .ok_or(BinaryOperatorError::ShiftLeft)?;
+                match meta_ops::arithmetic(
+                    MetaMethod::Shl,
+                    registers.stack_frame[left.0 as usize],
+                    current_function.0.proto.constants[right.0 as usize].to_value(),
+                )? {
+                    MetaResult::Value(v) => registers.stack_frame[dest.0 as usize] = v,
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(mc, call, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::ShiftLeftCR { dest, left, right } => {
-                let left = current_function.0.proto.constants[left.0 as usize].to_value();
-                let right = registers.stack_frame[right.0 as usize];
-                registers.stack_frame[dest.0 as usize] = left
-                    .shift_left(right)
-                    .ok_or(BinaryOperatorError::ShiftLeft)?;
+                match meta_ops::arithmetic(
+                    MetaMethod::Shl,
+                    current_function.0.proto.constants[left.0 as usize].to_value(),
+                    registers.stack_frame[right.0 as usize],
+                )? {
+                    MetaResult::Value(v) => registers.stack_frame[dest.0 as usize] = v,
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(mc, call, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::ShiftLeftCC { dest, left, right } => {
-                let left = current_function.0.proto.constants[left.0 as usize].to_value();
-                let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                registers.stack_frame[dest.0 as usize] = left
-                    .shift_left(right)
-                    .ok_or(BinaryOperatorError::ShiftLeft)?;
+                match meta_ops::arithmetic(
+                    MetaMethod::Shl,
+                    current_function.0.proto.constants[left.0 as usize].to_value(),
+                    current_function.0.proto.constants[right.0 as usize].to_value(),
+                )? {
+                    MetaResult::Value(v) => registers.stack_frame[dest.0 as usize] = v,
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(mc, call, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::ShiftRightRR { dest, left, right } => {
-                let left = registers.stack_frame[left.0 as usize];
-                let right = registers.stack_frame[right.0 as usize];
-                registers.stack_frame[dest.0 as usize] = left
-                    .shift_right(right)
-                    .ok_or(BinaryOperatorError::ShiftRight)?;
+                match meta_ops::arithmetic(
+                    MetaMethod::Shr,
+                    registers.stack_frame[left.0 as usize],
+                    registers.stack_frame[right.0 as usize],
+                )? {
+                    MetaResult::Value(v) => registers.stack_frame[dest.0 as usize] = v,
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(mc, call, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::ShiftRightRC { dest, left, right } => {
-                let left = registers.stack_frame[left.0 as usize];
-                let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                registers.stack_frame[dest.0 as usize] = left
-                    .shift_right(right)
-                    .ok_or(BinaryOperatorError::ShiftRight)?;
+                match meta_ops::arithmetic(
+                    MetaMethod::Shr,
+                    registers.stack_frame[left.0 as usize],
+                    current_function.0.proto.constants[right.0 as usize].to_value(),
+                )? {
+                    MetaResult::Value(v) => registers.stack_frame[dest.0 as usize] = v,
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(mc, call, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::ShiftRightCR { dest, left, right } => {
-                let left = current_function.0.proto.constants[left.0 as usize].to_value();
-                let right = registers.stack_frame[right.0 as usize];
-                registers.stack_frame[dest.0 as usize] = left
-                    .shift_right(right)
-                    .ok_or(BinaryOperatorError::ShiftRight)?;
+                match meta_ops::arithmetic(
+                    MetaMethod::Shr,
+                    current_function.0.proto.constants[left.0 as usize].to_value(),
+                    registers.stack_frame[right.0 as usize],
+                )? {
+                    MetaResult::Value(v) => registers.stack_frame[dest.0 as usize] = v,
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(mc, call, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
 
             OpCode::ShiftRightCC { dest, left, right } => {
-                let left = current_function.0.proto.constants[left.0 as usize].to_value();
-                let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                registers.stack_frame[dest.0 as usize] = left
-                    .shift_right(right)
-                    .ok_or(BinaryOperatorError::ShiftRight)?;
+                match meta_ops::arithmetic(
+                    MetaMethod::Shr,
+                    current_function.0.proto.constants[left.0 as usize].to_value(),
+                    current_function.0.proto.constants[right.0 as usize].to_value(),
+                )? {
+                    MetaResult::Value(v) => registers.stack_frame[dest.0 as usize] = v,
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(mc, call, MetaReturn::Register(dest))?;
+                        break;
+                    }
+                }
             }
         }
 
-        if instructions == 0 {
+        if *instructions == 0 || hooked {
             break;
-        } else {
-            instructions -= 1
         }
     }
 
-    Ok(instructions)
-}
-
-fn get_table<'gc>(value: Value<'gc>) -> Result<Table<'gc>, TypeError> {
-    match value {
-        Value::Table(t) => Ok(t),
-        val => Err(TypeError {
-            expected: "table",
-            found: val.type_name(),
-        }),
-    }
+    Ok(())
 }
 
 fn add_offset(pc: usize, offset: i16) -> usize {
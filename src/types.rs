@@ -27,6 +27,17 @@ pub struct UpValueIndex(pub u8);
 #[collect(require_static)]
 pub struct PrototypeIndex(pub u8);
 
+/// A 1-based line number in the source of a chunk
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq, Ord, PartialOrd, Hash, Collect)]
+#[collect(require_static)]
+pub struct LineNumber(pub u64);
+
+impl fmt::Display for LineNumber {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{}", self.0)
+    }
+}
+
 /// A one byte Option value that can either be Some(0-254) or None
 #[derive(Copy, Clone, Eq, PartialEq, Collect)]
 #[collect(require_static)]
@@ -0,0 +1,157 @@
+use std::any::TypeId;
+use std::cell::{Ref, RefMut};
+use std::fmt::{self, Debug};
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::mem;
+
+use gc_arena::{Collect, GcCell, MutationContext, StaticCollect};
+
+use crate::Table;
+
+/// A `'static` marker type that names a possibly `'gc`-bearing userdata type for every `'gc`
+/// lifetime, so that userdata holding that type can be safely downcast.
+///
+/// ```ignore
+/// struct NodeRoot;
+///
+/// impl<'gc> UserDataRoot<'gc> for NodeRoot {
+///     type Data = Node<'gc>;
+/// }
+/// ```
+pub trait UserDataRoot<'gc>: 'static {
+    type Data: 'gc + Collect;
+}
+
+/// The `UserDataRoot` used for userdata holding a plain `'static` type.
+pub struct StaticRoot<T: 'static>(PhantomData<T>);
+
+impl<'gc, T: 'static> UserDataRoot<'gc> for StaticRoot<T> {
+    type Data = StaticCollect<T>;
+}
+
+/// A garbage collected Lua userdata value holding an arbitrary Rust object, along with an optional
+/// metatable.
+///
+/// Userdata are compared and hashed by identity.
+#[derive(Debug, Copy, Clone, Collect)]
+#[collect(require_copy)]
+pub struct UserData<'gc>(pub GcCell<'gc, UserDataState<'gc>>);
+
+// Safe, does not implement drop
+#[derive(Collect)]
+#[collect(unsafe_drop)]
+pub struct UserDataState<'gc> {
+    data: Box<dyn Collect + 'gc>,
+    type_id: StaticCollect<TypeId>,
+    metatable: Option<Table<'gc>>,
+}
+
+impl<'gc> Debug for UserDataState<'gc> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("UserDataState")
+            .field("type_id", &self.type_id.0)
+            .field("metatable", &self.metatable)
+            .finish()
+    }
+}
+
+impl<'gc> PartialEq for UserData<'gc> {
+    fn eq(&self, other: &UserData<'gc>) -> bool {
+        GcCell::ptr_eq(self.0, other.0)
+    }
+}
+
+impl<'gc> Eq for UserData<'gc> {}
+
+impl<'gc> Hash for UserData<'gc> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.as_ptr().hash(state);
+    }
+}
+
+impl<'gc> UserData<'gc> {
+    /// Creates a new userdata holding a value of the `'gc`-bearing type named by `R`.
+    pub fn new<R: UserDataRoot<'gc>>(mc: MutationContext<'gc, '_>, data: R::Data) -> UserData<'gc> {
+        UserData(GcCell::allocate(
+            mc,
+            UserDataState {
+                data: Box::new(data),
+                type_id: StaticCollect(TypeId::of::<R>()),
+                metatable: None,
+            },
+        ))
+    }
+
+    /// Creates a new userdata holding a `'static` value which contains no garbage collected
+    /// pointers.
+    pub fn new_static<T: 'static>(mc: MutationContext<'gc, '_>, data: T) -> UserData<'gc> {
+        UserData::new::<StaticRoot<T>>(mc, StaticCollect(data))
+    }
+
+    /// Returns true if this userdata holds the type named by `R`.
+    pub fn is<R: UserDataRoot<'gc>>(&self) -> bool {
+        self.0.read().type_id.0 == TypeId::of::<R>()
+    }
+
+    /// Returns true if this userdata was created with `UserData::new_static::<T>`.
+    pub fn is_static<T: 'static>(&self) -> bool {
+        self.is::<StaticRoot<T>>()
+    }
+
+    /// Borrows the held value if it is of the type named by `R`.
+    pub fn read<'a, R: UserDataRoot<'gc>>(&'a self) -> Option<Ref<'a, R::Data>> {
+        let state = self.0.read();
+        if state.type_id.0 == TypeId::of::<R>() {
+            Some(Ref::map(state, |state| unsafe {
+                // Safe, `UserData::new` guarantees that the data is an `R::Data` when the type ids
+                // match.
+                &*(state.data.as_ref() as *const dyn Collect as *const R::Data)
+            }))
+        } else {
+            None
+        }
+    }
+
+    /// Mutably borrows the held value if it is of the type named by `R`.
+    pub fn write<'a, R: UserDataRoot<'gc>>(
+        &'a self,
+        mc: MutationContext<'gc, '_>,
+    ) -> Option<RefMut<'a, R::Data>> {
+        if !self.is::<R>() {
+            return None;
+        }
+        Some(RefMut::map(self.0.write(mc), |state| unsafe {
+            // Safe, `UserData::new` guarantees that the data is an `R::Data` when the type ids
+            // match.
+            &mut *(state.data.as_mut() as *mut dyn Collect as *mut R::Data)
+        }))
+    }
+
+    /// Borrows the held value if it is a `'static` value of type `T`.
+    pub fn read_static<'a, T: 'static>(&'a self) -> Option<Ref<'a, T>> {
+        self.read::<StaticRoot<T>>().map(|r| Ref::map(r, |r| &r.0))
+    }
+
+    /// Mutably borrows the held value if it is a `'static` value of type `T`.
+    pub fn write_static<'a, T: 'static>(
+        &'a self,
+        mc: MutationContext<'gc, '_>,
+    ) -> Option<RefMut<'a, T>> {
+        self.write::<StaticRoot<T>>(mc)
+            .map(|r| RefMut::map(r, |r| &mut r.0))
+    }
+
+    pub fn metatable(&self) -> Option<Table<'gc>> {
+        self.0.read().metatable
+    }
+
+    /// Sets the metatable for this userdata, returning the previous metatable.
+    pub fn set_metatable(
+        &self,
+        mc: MutationContext<'gc, '_>,
+        metatable: Option<Table<'gc>>,
+    ) -> Option<Table<'gc>> {
+        mem::replace(&mut self.0.write(mc).metatable, metatable)
+    }
+}
@@ -1,10 +1,10 @@
-use std::{f64, i64, io};
+use std::{f64, i64, io, ptr};
 
-use gc_arena::{Collect, Gc, GcCell};
+use gc_arena::{Collect, CollectionContext, Finalizable, Gc, GcCell};
 
 use crate::{
     lexer::{read_float, read_hex_float},
-    Callback, Closure, String, Table, Thread,
+    Callback, Closure, String, Table, Thread, UserData,
 };
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Collect)]
@@ -25,6 +25,7 @@ pub enum Value<'gc> {
     Table(Table<'gc>),
     Function(Function<'gc>),
     Thread(Thread<'gc>),
+    UserData(UserData<'gc>),
 }
 
 impl<'gc> PartialEq for Value<'gc> {
@@ -55,6 +56,9 @@ impl<'gc> PartialEq for Value<'gc> {
 
             (Value::Thread(a), Value::Thread(b)) => a == b,
             (Value::Thread(_), _) => false,
+
+            (Value::UserData(a), Value::UserData(b)) => a == b,
+            (Value::UserData(_), _) => false,
         }
     }
 }
@@ -69,6 +73,7 @@ impl<'gc> Value<'gc> {
             Value::Table(_) => "table",
             Value::Function(_) => "function",
             Value::Thread(_) => "thread",
+            Value::UserData(_) => "userdata",
         }
     }
 
@@ -266,6 +271,33 @@ impl<'gc> Value<'gc> {
             Value::Function(Function::Closure(c)) => write!(w, "<function {:?}>", Gc::as_ptr(c.0)),
             Value::Function(Function::Callback(c)) => write!(w, "<function {:?}>", Gc::as_ptr(c.0)),
             Value::Thread(t) => write!(w, "<thread {:?}>", GcCell::as_ptr(t.0)),
+            Value::UserData(u) => write!(w, "<userdata {:?}>", GcCell::as_ptr(u.0)),
+        }
+    }
+}
+
+// Strings and values which are not objects have a null address and are always reached, so that
+// they are never finalized or removed from weak tables.
+impl<'gc> Finalizable<'gc> for Value<'gc> {
+    fn as_ptr(&self) -> *const () {
+        match *self {
+            Value::Table(t) => t.0.as_ptr() as *const (),
+            Value::Function(Function::Closure(c)) => Gc::as_ptr(c.0) as *const (),
+            Value::Function(Function::Callback(c)) => Gc::as_ptr(c.0) as *const (),
+            Value::Thread(t) => GcCell::as_ptr(t.0) as *const (),
+            Value::UserData(u) => GcCell::as_ptr(u.0) as *const (),
+            _ => ptr::null(),
+        }
+    }
+
+    fn is_reached(&self, cc: CollectionContext) -> bool {
+        match *self {
+            Value::Table(t) => GcCell::is_reached(cc, t.0),
+            Value::Function(Function::Closure(c)) => Gc::is_reached(cc, c.0),
+            Value::Function(Function::Callback(c)) => Gc::is_reached(cc, c.0),
+            Value::Thread(t) => GcCell::is_reached(cc, t.0),
+            Value::UserData(u) => GcCell::is_reached(cc, u.0),
+            _ => true,
         }
     }
 }
@@ -300,6 +332,12 @@ impl<'gc> From<Table<'gc>> for Value<'gc> {
     }
 }
 
+impl<'gc> From<UserData<'gc>> for Value<'gc> {
+    fn from(v: UserData<'gc>) -> Value<'gc> {
+        Value::UserData(v)
+    }
+}
+
 impl<'gc> From<Function<'gc>> for Value<'gc> {
     fn from(v: Function<'gc>) -> Value<'gc> {
         Value::Function(v)
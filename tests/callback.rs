@@ -95,3 +95,101 @@ fn tail_call_trivial_callback() -> Result<(), Box<StaticError>> {
 
     Ok(())
 }
+
+#[test]
+fn callback_metamethod() -> Result<(), Box<StaticError>> {
+    let mut lua = Lua::new();
+    lua.sequence(|root| {
+        sequence::from_fn_with(root, |mc, root| {
+            let callback = Callback::new_immediate(mc, |args| {
+                let a = args[0].to_integer().unwrap_or(1);
+                let b = args[1].to_integer().unwrap_or(1);
+                Ok(CallbackResult::Return(vec![Value::Integer(a * 100 + b)]))
+            });
+            root.globals
+                .set(mc, String::new_static(b"callback"), callback)?;
+            Ok(())
+        })
+        .and_then_with(root, |mc, root, _| {
+            Ok(Closure::new(
+                mc,
+                compile(
+                    mc,
+                    root.interned_strings,
+                    &br#"
+                        local t = setmetatable({}, {__add = callback, __index = callback})
+                        return t + 2, 3 + t, t[4]
+                    "#[..],
+                )?,
+                Some(root.globals),
+            )?)
+        })
+        .and_chain_with(root, |mc, root, closure| {
+            Ok(ThreadSequence::call_function(
+                mc,
+                root.main_thread,
+                Function::Closure(closure),
+                &[],
+            )?)
+        })
+        .map_ok(|b| {
+            assert_eq!(
+                b,
+                vec![
+                    Value::Integer(102),
+                    Value::Integer(301),
+                    Value::Integer(104)
+                ]
+            )
+        })
+        .map_err(Error::to_static)
+        .boxed()
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn call_table_from_rust() -> Result<(), Box<StaticError>> {
+    let mut lua = Lua::new();
+    lua.sequence(|root| {
+        sequence::from_fn_with(root, |mc, root| {
+            Ok(Closure::new(
+                mc,
+                compile(
+                    mc,
+                    root.interned_strings,
+                    &br#"
+                        return setmetatable({n = 10}, {
+                            __call = function(self, a, b)
+                                return self.n + a + b
+                            end
+                        })
+                    "#[..],
+                )?,
+                Some(root.globals),
+            )?)
+        })
+        .and_chain_with(root, |mc, root, closure| {
+            Ok(ThreadSequence::call_function(
+                mc,
+                root.main_thread,
+                Function::Closure(closure),
+                &[],
+            )?)
+        })
+        .and_chain_with(root, |mc, root, res| {
+            Ok(ThreadSequence::call_function(
+                mc,
+                root.main_thread,
+                res[0],
+                &[Value::Integer(1), Value::Integer(2)],
+            )?)
+        })
+        .map_ok(|b| assert_eq!(b, vec![Value::Integer(13)]))
+        .map_err(Error::to_static)
+        .boxed()
+    })?;
+
+    Ok(())
+}
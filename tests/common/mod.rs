@@ -0,0 +1,33 @@
+use gc_sequence::{self as sequence, SequenceExt, SequenceResultExt};
+use luster::{compile, Closure, Error, Function, Lua, StaticError, ThreadSequence, Value};
+
+// Compiles and runs the given code on the main thread of `lua`.  If the code returns anything, it
+// must return exactly `true`.
+pub fn run_code(lua: &mut Lua, code: &'static [u8]) -> Result<(), StaticError> {
+    lua.sequence(move |root| {
+        sequence::from_fn_with(root, move |mc, root| {
+            Ok(Closure::new(
+                mc,
+                compile(mc, root.interned_strings, code)?,
+                Some(root.globals),
+            )?)
+        })
+        .and_chain_with(root, |mc, root, closure| {
+            Ok(ThreadSequence::call_function(
+                mc,
+                root.main_thread,
+                Function::Closure(closure),
+                &[],
+            )?)
+        })
+        .map_ok(|res| {
+            assert!(
+                res.is_empty() || res == vec![Value::Boolean(true)],
+                "unexpected results {:?}",
+                res
+            )
+        })
+        .map_err(Error::to_static)
+        .boxed()
+    })
+}
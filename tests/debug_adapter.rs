@@ -0,0 +1,203 @@
+use std::env;
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::Duration;
+
+struct Client {
+    child: Child,
+    stdin: ChildStdin,
+    messages: Receiver<String>,
+    seq: u32,
+}
+
+impl Client {
+    fn start() -> Client {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_luster"))
+            .arg("--debug-adapter")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let stdin = child.stdin.take().unwrap();
+        let mut stdout = BufReader::new(child.stdout.take().unwrap());
+
+        let (sender, messages) = mpsc::channel();
+        thread::spawn(move || loop {
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                if stdout.read_line(&mut line).unwrap() == 0 {
+                    return;
+                }
+                match line.trim_end() {
+                    "" => break,
+                    line => length = line["Content-Length: ".len()..].parse().unwrap(),
+                }
+            }
+            let mut body = vec![0; length];
+            stdout.read_exact(&mut body).unwrap();
+            if sender.send(String::from_utf8(body).unwrap()).is_err() {
+                return;
+            }
+        });
+
+        Client {
+            child,
+            stdin,
+            messages,
+            seq: 0,
+        }
+    }
+
+    fn request(&mut self, command: &str, arguments: &str) -> String {
+        self.seq += 1;
+        let body = format!(
+            r#"{{"seq":{},"type":"request","command":"{}","arguments":{}}}"#,
+            self.seq, command, arguments
+        );
+        write!(self.stdin, "Content-Length: {}\r\n\r\n{}", body.len(), body).unwrap();
+        self.stdin.flush().unwrap();
+        self.wait_for(&format!(r#""request_seq":{},"#, self.seq))
+    }
+
+    // Returns the next message containing the given text
+    fn wait_for(&mut self, text: &str) -> String {
+        loop {
+            let message = self
+                .messages
+                .recv_timeout(Duration::from_secs(10))
+                .expect("timed out waiting for the debug adapter");
+            if message.contains(text) {
+                return message;
+            }
+        }
+    }
+}
+
+#[test]
+fn debug_adapter() {
+    let program = env::temp_dir().join("luster_debug_adapter_test.lua");
+    fs::write(
+        &program,
+        "local function add(a, b)\n\
+         \x20   local sum = a + b\n\
+         \x20   return sum\n\
+         end\n\
+         local t = {x = 1}\n\
+         print(add(1, 2))\n",
+    )
+    .unwrap();
+    let path = program.to_str().unwrap().replace('\\', "\\\\");
+
+    let mut client = Client::start();
+    assert!(client
+        .request("initialize", r#"{"adapterID":"luster"}"#)
+        .contains(r#""success":true"#));
+    client.wait_for(r#""event":"initialized""#);
+    assert!(client
+        .request("launch", &format!(r#"{{"program":"{}"}}"#, path))
+        .contains(r#""success":true"#));
+    assert!(client
+        .request(
+            "setBreakpoints",
+            &format!(
+                r#"{{"source":{{"path":"{}"}},"breakpoints":[{{"line":2}}]}}"#,
+                path
+            ),
+        )
+        .contains(r#""verified":true,"line":2"#));
+
+    // Lines with no code and lines that are not positive integers cannot have breakpoints
+    let lines = r#"[{"line":2},{"line":100},{"line":-5},{"line":1.5},{"line":1e20}]"#;
+    let breakpoints = client.request(
+        "setBreakpoints",
+        &format!(
+            r#"{{"source":{{"path":"{}"}},"breakpoints":{}}}"#,
+            path, lines
+        ),
+    );
+    assert!(breakpoints.contains(
+        r#"[{"verified":true,"line":2},{"verified":false,"line":100},{"verified":false},"#
+    ));
+    assert!(breakpoints.contains(r#"{"verified":false},{"verified":false}]"#));
+
+    client.request("configurationDone", "{}");
+    assert!(client
+        .wait_for(r#""event":"stopped""#)
+        .contains(r#""reason":"breakpoint""#));
+
+    let stack = client.request("stackTrace", r#"{"threadId":1}"#);
+    assert!(stack.contains(r#""id":1,"name":"add","#));
+    assert!(stack.contains(r#""line":2,"#));
+    assert!(stack.contains(r#""id":2,"name":"main chunk","#));
+    let stack = client.request("stackTrace", r#"{"threadId":1,"startFrame":1,"levels":1}"#);
+    assert!(stack.contains(r#""totalFrames":2,"stackFrames":[{"id":2,"name":"main chunk","#));
+    assert!(stack.contains(r#""id":2,"#) && !stack.contains(r#""id":1,"#));
+    assert!(client
+        .request("stackTrace", r#"{"threadId":1,"startFrame":5}"#)
+        .contains(r#""totalFrames":2,"stackFrames":[]"#));
+
+    let scopes = client.request("scopes", r#"{"frameId":1}"#);
+    assert!(scopes.contains(r#""name":"Locals","variablesReference":1,"#));
+    let locals = client.request("variables", r#"{"variablesReference":1}"#);
+    assert!(locals.contains(r#""name":"a","value":"1","type":"number""#));
+    assert!(locals.contains(r#""name":"b","value":"2","type":"number""#));
+    assert!(!locals.contains(r#""name":"sum""#));
+
+    client.request("scopes", r#"{"frameId":2}"#);
+    let locals = client.request("variables", r#"{"variablesReference":3}"#);
+    assert!(locals.contains(r#""name":"t","#));
+    let fields = client.request("variables", r#"{"variablesReference":5}"#);
+    assert!(fields.contains(r#""name":"x","value":"1","#));
+
+    assert!(client
+        .request("evaluate", r#"{"expression":"a + b * 10","frameId":1}"#)
+        .contains(r#""result":"21""#));
+
+    client.request("next", r#"{"threadId":1}"#);
+    client.wait_for(r#""event":"stopped""#);
+    assert!(client
+        .request("stackTrace", r#"{"threadId":1}"#)
+        .contains(r#""name":"add","source""#));
+    assert!(client
+        .request("stackTrace", r#"{"threadId":1}"#)
+        .contains(r#""line":3,"#));
+
+    client.request("continue", r#"{"threadId":1}"#);
+    assert!(client
+        .wait_for(r#""event":"output""#)
+        .contains(r#""output":"3\n""#));
+    assert!(client
+        .wait_for(r#""event":"exited""#)
+        .contains(r#""exitCode":0"#));
+    client.request("disconnect", "{}");
+    assert!(client.child.wait().unwrap().success());
+
+    fs::remove_file(&program).unwrap();
+}
+
+#[test]
+fn malformed_messages() {
+    let mut client = Client::start();
+
+    // Deeply nested JSON is rejected without overflowing the stack, and the session continues
+    let body = format!("{}{}", "[".repeat(100_000), "]".repeat(100_000));
+    write!(
+        client.stdin,
+        "Content-Length: {}\r\n\r\n{}",
+        body.len(),
+        body
+    )
+    .unwrap();
+    assert!(client
+        .request("initialize", r#"{"adapterID":"luster"}"#)
+        .contains(r#""success":true"#));
+
+    // A message too large to read ends the session
+    write!(client.stdin, "Content-Length: 1000000000000\r\n\r\n").unwrap();
+    client.stdin.flush().unwrap();
+    client.child.wait().unwrap();
+}
@@ -0,0 +1,60 @@
+use luster::Lua;
+
+mod common;
+
+use common::run_code;
+
+fn tables(lua: &Lua) -> usize {
+    lua.heap_profile()
+        .iter()
+        .find(|allocations| {
+            allocations
+                .type_name
+                .starts_with("luster::table::TableState")
+        })
+        .map(|allocations| allocations.objects)
+        .unwrap_or(0)
+}
+
+#[test]
+fn heap_profile_finds_leak() {
+    let mut lua = Lua::new();
+    let before = tables(&lua);
+
+    run_code(
+        &mut lua,
+        br#"
+            leak = {}
+            for i = 1, 1000 do
+                leak[i] = {}
+            end
+            -- Finish any cycle in progress, so that the leak is freed by a single cycle below
+            collectgarbage()
+        "#,
+    )
+    .unwrap();
+    assert!(tables(&lua) >= before + 1000);
+
+    let cycles = lua.gc_stats().cycles;
+    run_code(&mut lua, b"leak = nil; collectgarbage()").unwrap();
+    let stats = lua.gc_stats();
+    assert!(stats.cycles > cycles);
+    assert!(stats.last_cycle.unwrap().objects_freed >= 1000);
+    assert_eq!(stats.last_cycle.unwrap().live_objects, stats.live_objects);
+    assert!(tables(&lua) <= before + 1);
+}
+
+#[test]
+fn collectgarbage_count() {
+    let mut lua = Lua::new();
+    run_code(
+        &mut lua,
+        br#"
+            local count = collectgarbage("count")
+            assert(type(count) == "number" and count > 0)
+            assert(collectgarbage("step") == true)
+            assert(collectgarbage("isrunning") == true)
+        "#,
+    )
+    .unwrap();
+}
@@ -0,0 +1,81 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use gc_sequence::{self as sequence, SequenceExt, SequenceResultExt};
+use luster::{
+    compile_named, Callback, CallbackResult, Closure, Error, Function, Hook, HookMask, Lua,
+    StaticError, ThreadSequence, Value,
+};
+
+#[test]
+fn line_hook() -> Result<(), Box<StaticError>> {
+    let events = Rc::new(RefCell::new(Vec::new()));
+
+    let mut lua = Lua::new();
+    lua.mutate(|mc, root| {
+        let events = events.clone();
+        let hook = Callback::new_immediate(mc, move |args| {
+            let event = match args.get(0) {
+                Some(Value::String(event)) => event.as_bytes().to_vec(),
+                _ => panic!("hook called without an event"),
+            };
+            let line = match args.get(1) {
+                Some(&Value::Integer(line)) => Some(line),
+                _ => None,
+            };
+            events.borrow_mut().push((event, line));
+            Ok(CallbackResult::Return(vec![]))
+        });
+        root.main_thread.set_hook(
+            mc,
+            Some(Hook {
+                function: Function::Callback(hook),
+                mask: HookMask {
+                    call: true,
+                    line: true,
+                    ..HookMask::default()
+                },
+                count: 0,
+            }),
+        );
+    });
+
+    lua.sequence(|root| {
+        sequence::from_fn_with(root, |mc, root| {
+            Ok(Closure::new(
+                mc,
+                compile_named(
+                    mc,
+                    root.interned_strings,
+                    b"=test",
+                    &b"local a = 1\nlocal b = 2\n\nlocal c = a + b\n"[..],
+                )?,
+                Some(root.globals),
+            )?)
+        })
+        .and_chain_with(root, |mc, root, closure| {
+            Ok(ThreadSequence::call_function(
+                mc,
+                root.main_thread,
+                Function::Closure(closure),
+                &[],
+            )?)
+        })
+        .map_ok(|_| ())
+        .map_err(Error::to_static)
+        .boxed()
+    })?;
+
+    assert_eq!(
+        *events.borrow(),
+        vec![
+            (b"call".to_vec(), None),
+            (b"line".to_vec(), Some(1)),
+            (b"line".to_vec(), Some(2)),
+            (b"line".to_vec(), Some(4)),
+        ]
+    );
+    assert!(lua.mutate(|_, root| root.main_thread.hook().is_some()));
+
+    Ok(())
+}
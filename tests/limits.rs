@@ -0,0 +1,118 @@
+use std::time::{Duration, Instant};
+
+use luster::{LimitError, Limits, Lua, StaticError};
+
+mod common;
+
+use common::run_code;
+
+#[test]
+fn instruction_limit() {
+    let mut lua = Lua::new();
+    lua.set_limits(Limits {
+        instructions: Some(10_000),
+        ..Limits::default()
+    });
+
+    match run_code(&mut lua, b"while true do end") {
+        Err(StaticError::LimitError(LimitError::Instructions)) => {}
+        res => panic!("unexpected result {:?}", res),
+    }
+    assert_eq!(lua.budget().instructions(), 10_000);
+
+    // The limit cannot be caught from Lua, by `pcall` or by resuming a coroutine
+    lua.set_limits(Limits {
+        instructions: Some(10_000),
+        ..Limits::default()
+    });
+    match run_code(
+        &mut lua,
+        br#"
+            pcall(function()
+                local co = coroutine.create(function()
+                    while true do end
+                end)
+                coroutine.resume(co)
+            end)
+            caught = true
+        "#,
+    ) {
+        Err(StaticError::LimitError(LimitError::Instructions)) => {}
+        res => panic!("unexpected result {:?}", res),
+    }
+
+    // Setting the limits again resets the count
+    lua.set_limits(Limits {
+        instructions: Some(10_000),
+        ..Limits::default()
+    });
+    run_code(&mut lua, b"assert(caught == nil) for i = 1, 100 do end").unwrap();
+}
+
+#[test]
+fn deadline() {
+    let mut lua = Lua::new();
+    lua.set_limits(Limits {
+        deadline: Some(Instant::now() + Duration::from_millis(50)),
+        ..Limits::default()
+    });
+
+    match run_code(&mut lua, b"while true do end") {
+        Err(StaticError::LimitError(LimitError::Deadline)) => {}
+        res => panic!("unexpected result {:?}", res),
+    }
+}
+
+#[test]
+fn memory_limit() {
+    let mut lua = Lua::new();
+    run_code(
+        &mut lua,
+        b"garbage = {} for i = 1, 1000 do garbage[i] = {} end",
+    )
+    .unwrap();
+    lua.set_limits(Limits {
+        memory: Some(lua.total_allocated() + 256 * 1024),
+        ..Limits::default()
+    });
+
+    // Garbage is collected before the limit is enforced
+    run_code(
+        &mut lua,
+        b"garbage = nil for i = 1, 10000 do local t = {1, 2, 3, 4} end",
+    )
+    .unwrap();
+
+    match run_code(
+        &mut lua,
+        b"local t = {} for i = 1, 1000000 do t[i] = {} end",
+    ) {
+        Err(StaticError::LimitError(LimitError::Memory)) => {}
+        res => panic!("unexpected result {:?}", res),
+    }
+}
+
+#[test]
+fn memory_limit_counts_buffers() {
+    let mut lua = Lua::new();
+    lua.set_limits(Limits {
+        memory: Some(lua.total_allocated() + 1024 * 1024),
+        ..Limits::default()
+    });
+
+    // A single table or string is only one object, but its buffer is counted
+    match run_code(&mut lua, b"local t = {} for i = 1, 5000000 do t[i] = i end") {
+        Err(StaticError::LimitError(LimitError::Memory)) => {}
+        res => panic!("unexpected result {:?}", res),
+    }
+    match run_code(
+        &mut lua,
+        b"local s = string.rep('x', 2 * 1024 * 1024) while true do end",
+    ) {
+        Err(StaticError::LimitError(LimitError::Memory)) => {}
+        res => panic!("unexpected result {:?}", res),
+    }
+
+    // Once the buffers are garbage they are freed along with their objects
+    run_code(&mut lua, b"local s = string.rep('x', 512 * 1024)").unwrap();
+}
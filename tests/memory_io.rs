@@ -0,0 +1,171 @@
+use std::rc::Rc;
+
+use luster::{Lua, LuaHost, MemoryIoHost, StaticError};
+
+mod common;
+
+use common::run_code;
+
+#[test]
+fn memory_io_host() -> Result<(), Box<StaticError>> {
+    let host = Rc::new(MemoryIoHost::new());
+    host.set_file(b"input.txt", b"first line\nsecond line\n42 0x10 -1.5e1\nrest");
+    host.set_stdin(b"from stdin\n");
+    let mut lua = Lua::new_with_host(LuaHost {
+        io: host.clone(),
+        ..LuaHost::std()
+    });
+
+    run_code(
+        &mut lua,
+        &br#"
+            local f = io.open("input.txt")
+            io.input(f)
+            local l1, l2 = io.read("l", "L")
+            local a, b, c = io.read("n", "n", "n")
+            local rest = f:read("a")
+            local eof = f:read("l")
+            f:close()
+
+            local out = io.open("output.txt", "w")
+            out:write("a", 1, " ", 2.5, "\n")
+            out:close()
+            local app = io.open("output.txt", "a+")
+            app:write("appended")
+            app:seek("set")
+            local appended = app:read("a")
+            app:close()
+
+            local lines = {}
+            for l in io.lines("input.txt") do
+                lines[#lines + 1] = l
+            end
+
+            local missing, msg = io.open("missing.txt")
+            io.write("to stdout")
+            io.stderr:write("to stderr")
+
+            return
+                l1 == "first line" and l2 == "second line\n" and
+                a == 42 and math.type(a) == "integer" and b == 16 and c == -15.0 and
+                rest == "\nrest" and eof == nil and
+                io.type(f) == "closed file" and io.type(io.stdout) == "file" and
+                io.type(42) == nil and
+                appended == "a1 2.5\nappended" and
+                #lines == 4 and lines[3] == "42 0x10 -1.5e1" and lines[4] == "rest" and
+                missing == nil and msg == "missing.txt: No such file or directory" and
+                io.input(io.stdin) == io.stdin and
+                io.read() == "from stdin" and io.read() == nil and
+                pcall(f.read, f) == false
+        "#[..],
+    )?;
+
+    assert_eq!(host.file(b"output.txt").unwrap(), b"a1 2.5\nappended");
+    assert_eq!(host.stdout_contents(), b"to stdout");
+    assert_eq!(host.stderr_contents(), b"to stderr");
+    Ok(())
+}
+
+#[test]
+fn sandboxed_io() -> Result<(), Box<StaticError>> {
+    let mut lua = Lua::new_with_host(LuaHost::sandboxed());
+    run_code(
+        &mut lua,
+        &br#"
+            local f = io.open("scratch", "w+")
+            f:write("hello\nworld")
+            f:seek("set", 6)
+            local world = f:read("a")
+            f:close()
+            return
+                world == "world" and
+                io.open("scratch"):read("a") == "hello\nworld" and
+                io.open("/etc/passwd") == nil
+        "#[..],
+    )?;
+    Ok(())
+}
+
+#[test]
+fn memory_file_size_limit() -> Result<(), Box<StaticError>> {
+    let mut lua = Lua::new_with_host(LuaHost::sandboxed());
+    run_code(
+        &mut lua,
+        &br#"
+            local f = io.open("scratch", "w")
+            f:seek("set", 2^62)
+            local ok, msg = f:write("x")
+            f:close()
+            return ok == nil and msg == "File too large"
+        "#[..],
+    )?;
+
+    let host = Rc::new(MemoryIoHost::new());
+    host.set_max_file_size(8);
+    let mut lua = Lua::new_with_host(LuaHost {
+        io: host.clone(),
+        ..LuaHost::std()
+    });
+    run_code(
+        &mut lua,
+        &br#"
+            local f = io.open("small", "w")
+            local first = f:write("12345678")
+            local ok, msg = f:write("9")
+            f:close()
+            return first ~= nil and ok == nil and msg == "File too large"
+        "#[..],
+    )?;
+    assert_eq!(host.file(b"small").unwrap(), b"12345678");
+    Ok(())
+}
+
+#[test]
+fn memory_loadfile() -> Result<(), Box<StaticError>> {
+    let host = Rc::new(MemoryIoHost::new());
+    host.set_file(b"lib.lua", b"local n = ... return (n or 20) * 2");
+    let mut lua = Lua::new_with_host(LuaHost {
+        io: host.clone(),
+        ..LuaHost::std()
+    });
+
+    run_code(
+        &mut lua,
+        &br#"
+            local missing, msg = loadfile("other.lua")
+            return
+                dofile("lib.lua") == 40 and
+                loadfile("lib.lua")(4) == 8 and
+                missing == nil and msg == "cannot open other.lua: No such file or directory"
+        "#[..],
+    )?;
+    Ok(())
+}
+
+#[test]
+fn print_tostring() -> Result<(), Box<StaticError>> {
+    let host = Rc::new(MemoryIoHost::new());
+    let mut lua = Lua::new_with_host(LuaHost {
+        io: host.clone(),
+        ..LuaHost::std()
+    });
+
+    run_code(
+        &mut lua,
+        &br#"
+            local point = setmetatable({}, {__tostring = function() return "point" end})
+            local named = setmetatable({}, {__name = "Named"})
+            print(1, point, nil, "s", point)
+            print(named)
+            print()
+            return not pcall(print, setmetatable({}, {__tostring = function() return 1 end}))
+        "#[..],
+    )?;
+
+    let stdout = host.stdout_contents();
+    let lines: Vec<&[u8]> = stdout.split(|&b| b == b'\n').collect();
+    assert_eq!(lines[0], b"1\tpoint\tnil\ts\tpoint");
+    assert!(lines[1].starts_with(b"Named: "));
+    assert_eq!(&lines[2..], &[b"", b""]);
+    Ok(())
+}
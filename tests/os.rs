@@ -0,0 +1,111 @@
+use std::cell::{Cell, RefCell};
+use std::io;
+use std::rc::Rc;
+
+use luster::{Lua, LuaHost, OsHost, StaticError};
+
+mod common;
+
+use common::run_code;
+
+#[derive(Default)]
+struct FakeOsHost {
+    exit_code: Cell<Option<i32>>,
+    removed: RefCell<Vec<Vec<u8>>>,
+}
+
+impl OsHost for FakeOsHost {
+    fn time(&self) -> i64 {
+        // 2001-09-09 01:46:40 UTC, a Sunday
+        1_000_000_000
+    }
+
+    fn clock(&self) -> f64 {
+        1.5
+    }
+
+    fn utc_offset(&self, _time: i64) -> i64 {
+        -5 * 3600
+    }
+
+    fn getenv(&self, name: &[u8]) -> Option<Vec<u8>> {
+        if name == b"HOME" {
+            Some(b"/home/lua".to_vec())
+        } else {
+            None
+        }
+    }
+
+    fn tmpname(&self) -> Result<Vec<u8>, io::Error> {
+        Ok(b"/tmp/fake".to_vec())
+    }
+
+    fn remove(&self, path: &[u8]) -> Result<(), io::Error> {
+        self.removed.borrow_mut().push(path.to_vec());
+        Ok(())
+    }
+
+    fn rename(&self, _from: &[u8], _to: &[u8]) -> Result<(), io::Error> {
+        Err(io::Error::new(io::ErrorKind::NotFound, "not found"))
+    }
+
+    fn exit(&self, code: i32) -> Result<(), io::Error> {
+        self.exit_code.set(Some(code));
+        Ok(())
+    }
+}
+
+#[test]
+fn fake_os_host() -> Result<(), Box<StaticError>> {
+    let host = Rc::new(FakeOsHost::default());
+    let mut lua = Lua::new_with_host(LuaHost {
+        os: host.clone(),
+        ..LuaHost::std()
+    });
+    run_code(
+        &mut lua,
+        &br#"
+            local t = os.date("*t")
+            local ok, msg = os.rename("a", "b")
+            return
+                os.time() == 1000000000 and
+                os.clock() == 1.5 and
+                os.date("!%Y-%m-%d %H:%M:%S") == "2001-09-09 01:46:40" and
+                os.date("%Y-%m-%d %H:%M:%S %z") == "2001-09-08 20:46:40 -0500" and
+                os.date("!%c") == "Sun Sep  9 01:46:40 2001" and
+                t.year == 2001 and t.month == 9 and t.day == 8 and t.hour == 20 and
+                t.wday == 7 and t.yday == 251 and t.isdst == false and
+                os.time(t) == 1000000000 and
+                os.time({year = 2001, month = 9, day = 8, hour = 20, min = 46, sec = 40}) ==
+                    1000000000 and
+                os.getenv("HOME") == "/home/lua" and
+                os.getenv("PATH") == nil and
+                os.tmpname() == "/tmp/fake" and
+                os.remove("some_file") == true and
+                ok == nil and msg == "a: not found" and
+                os.exit(3) == nil
+        "#[..],
+    )?;
+
+    assert_eq!(host.exit_code.get(), Some(3));
+    assert_eq!(&host.removed.borrow()[..], &[b"some_file".to_vec()]);
+    Ok(())
+}
+
+#[test]
+fn sandbox_os_host() -> Result<(), Box<StaticError>> {
+    let mut lua = Lua::new_with_host(LuaHost::sandboxed());
+    run_code(
+        &mut lua,
+        &br#"
+            return
+                math.type(os.time()) == "integer" and
+                os.getenv("PATH") == nil and
+                os.remove("some_file") == nil and
+                os.rename("a", "b") == nil and
+                pcall(os.tmpname) == false and
+                pcall(os.exit) == false
+        "#[..],
+    )?;
+    Ok(())
+}
@@ -0,0 +1,59 @@
+use std::rc::Rc;
+
+use gc_sequence as sequence;
+use luster::{Callback, CallbackResult, Lua, MemoryIoHost, StaticError, String, Table, Value};
+
+mod common;
+
+use common::run_code;
+
+#[test]
+fn native_module() -> Result<(), Box<StaticError>> {
+    let mut lua = Lua::new();
+    lua.register_module("native", |mc, _| {
+        Callback::new_immediate(mc, |_| Ok(CallbackResult::Return(vec![])))
+    });
+    lua.register_module("answer", |mc, _| {
+        Callback::new_sequence(mc, |_| {
+            Ok(sequence::from_fn(|mc| {
+                let module = Table::new(mc);
+                module.set(mc, String::new_static(b"value"), 42)?;
+                Ok(CallbackResult::Return(vec![Value::Table(module)]))
+            }))
+        })
+    });
+
+    run_code(
+        &mut lua,
+        &br#"
+            local answer = require("answer")
+            return
+                answer.value == 42 and require("answer") == answer and
+                require("native") == true
+        "#[..],
+    )?;
+    Ok(())
+}
+
+#[test]
+fn vfs_searcher() -> Result<(), Box<StaticError>> {
+    let vfs = Rc::new(MemoryIoHost::new());
+    vfs.set_file(b"./embedded.lua", b"return {dep = require('lib.dep')}");
+    vfs.set_file(b"./lib/dep/init.lua", b"return 'dep'");
+    vfs.set_file(b"./broken.lua", b"return +");
+
+    let mut lua = Lua::new();
+    lua.add_vfs_searcher(vfs);
+    run_code(
+        &mut lua,
+        &br#"
+            local ok, msg = pcall(require, "broken")
+            return
+                require("embedded").dep == "dep" and
+                #package.searchers == 3 and
+                ok == false and
+                msg:find("error loading module 'broken' from file './broken.lua'", 1, true) == 1
+        "#[..],
+    )?;
+    Ok(())
+}
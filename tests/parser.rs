@@ -1,8 +1,9 @@
 use luster::parser::{
     parse_chunk, Block, CallSuffix, Chunk, ConstructorField, Expression, FunctionCallStatement,
-    HeadExpression, PrimaryExpression, SimpleExpression, Statement, SuffixedExpression,
-    TableConstructor,
+    HeadExpression, LineAnnotated, ParserErrorKind, PrimaryExpression, SimpleExpression, Statement,
+    SuffixedExpression, TableConstructor,
 };
+use luster::LineNumber;
 
 #[test]
 fn test_function_call() {
@@ -14,66 +15,105 @@ fn test_function_call() {
         Chunk {
             block: Block {
                 statements: vec![
-                    Statement::FunctionCall(FunctionCallStatement {
-                        head: SuffixedExpression {
-                            primary: PrimaryExpression::Name(
-                                "print".as_bytes().to_vec().into_boxed_slice(),
-                            ),
-                            suffixes: vec![],
-                        },
-                        call: CallSuffix::Function(vec![
-                            Expression {
-                                head: Box::new(HeadExpression::Simple(SimpleExpression::Integer(
-                                    10,
-                                ))),
-                                tail: vec![],
+                    LineAnnotated {
+                        line_number: LineNumber(1),
+                        inner: Statement::FunctionCall(FunctionCallStatement {
+                            head: SuffixedExpression {
+                                primary: PrimaryExpression::Name(
+                                    "print".as_bytes().to_vec().into_boxed_slice(),
+                                ),
+                                suffixes: vec![],
+                            },
+                            call: CallSuffix::Function(vec![
+                                Expression {
+                                    head: Box::new(HeadExpression::Simple(
+                                        SimpleExpression::Integer(10,)
+                                    )),
+                                    tail: vec![],
+                                },
+                                Expression {
+                                    head: Box::new(HeadExpression::Simple(
+                                        SimpleExpression::Integer(20,)
+                                    )),
+                                    tail: vec![],
+                                },
+                            ]),
+                        })
+                    },
+                    LineAnnotated {
+                        line_number: LineNumber(1),
+                        inner: Statement::FunctionCall(FunctionCallStatement {
+                            head: SuffixedExpression {
+                                primary: PrimaryExpression::Name(
+                                    "print".as_bytes().to_vec().into_boxed_slice(),
+                                ),
+                                suffixes: vec![],
                             },
-                            Expression {
-                                head: Box::new(HeadExpression::Simple(SimpleExpression::Integer(
-                                    20,
+                            call: CallSuffix::Function(vec![Expression {
+                                head: Box::new(HeadExpression::Simple(SimpleExpression::String(
+                                    "foo".as_bytes().to_vec().into_boxed_slice(),
                                 ))),
                                 tail: vec![],
+                            },]),
+                        })
+                    },
+                    LineAnnotated {
+                        line_number: LineNumber(1),
+                        inner: Statement::FunctionCall(FunctionCallStatement {
+                            head: SuffixedExpression {
+                                primary: PrimaryExpression::Name(
+                                    "print".as_bytes().to_vec().into_boxed_slice(),
+                                ),
+                                suffixes: vec![],
                             },
-                        ]),
-                    }),
-                    Statement::FunctionCall(FunctionCallStatement {
-                        head: SuffixedExpression {
-                            primary: PrimaryExpression::Name(
-                                "print".as_bytes().to_vec().into_boxed_slice(),
-                            ),
-                            suffixes: vec![],
-                        },
-                        call: CallSuffix::Function(vec![Expression {
-                            head: Box::new(HeadExpression::Simple(SimpleExpression::String(
-                                "foo".as_bytes().to_vec().into_boxed_slice(),
-                            ))),
-                            tail: vec![],
-                        },]),
-                    }),
-                    Statement::FunctionCall(FunctionCallStatement {
-                        head: SuffixedExpression {
-                            primary: PrimaryExpression::Name(
-                                "print".as_bytes().to_vec().into_boxed_slice(),
-                            ),
-                            suffixes: vec![],
-                        },
-                        call: CallSuffix::Function(vec![Expression {
-                            head: Box::new(HeadExpression::Simple(
-                                SimpleExpression::TableConstructor(TableConstructor {
-                                    fields: vec![ConstructorField::Array(Expression {
-                                        head: Box::new(HeadExpression::Simple(
-                                            SimpleExpression::Float(30.0),
-                                        )),
-                                        tail: vec![],
-                                    }),],
-                                }),
-                            )),
-                            tail: vec![],
-                        },]),
-                    }),
+                            call: CallSuffix::Function(vec![Expression {
+                                head: Box::new(HeadExpression::Simple(
+                                    SimpleExpression::TableConstructor(TableConstructor {
+                                        fields: vec![ConstructorField::Array(Expression {
+                                            head: Box::new(HeadExpression::Simple(
+                                                SimpleExpression::Float(30.0),
+                                            )),
+                                            tail: vec![],
+                                        }),],
+                                    }),
+                                )),
+                                tail: vec![],
+                            },]),
+                        })
+                    },
                 ],
                 return_statement: None,
             },
         }
     );
 }
+
+#[test]
+fn test_line_numbers() {
+    let chunk = parse_chunk(
+        "local a = 1\n\n--[[\ncomment\n]] local b = [[\n]]\nlocal c\nreturn a".as_bytes(),
+        |s| s.to_vec().into_boxed_slice(),
+    )
+    .unwrap();
+    let lines: Vec<_> = chunk
+        .block
+        .statements
+        .iter()
+        .map(|s| s.line_number)
+        .collect();
+    assert_eq!(lines, vec![LineNumber(1), LineNumber(5), LineNumber(7)]);
+    assert_eq!(
+        chunk.block.return_statement.unwrap().line_number,
+        LineNumber(8)
+    );
+
+    let error = parse_chunk("local a = 1\nlocal b = = 2".as_bytes(), |s| {
+        s.to_vec().into_boxed_slice()
+    })
+    .unwrap_err();
+    assert_eq!(error.line_number, LineNumber(2));
+    match error.kind {
+        ParserErrorKind::Unexpected { .. } => {}
+        kind => panic!("unexpected parser error {:?}", kind),
+    }
+}
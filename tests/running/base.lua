@@ -0,0 +1,195 @@
+local function test_next()
+    local t = {1, 2, 3, a = 4, b = 5}
+    local count, sum = 0, 0
+    local k, v = next(t)
+    while k ~= nil do
+        count = count + 1
+        sum = sum + v
+        local nk, nv = next(t, k)
+        k, v = nk, nv
+    end
+    return
+        count == 5 and sum == 15 and
+        next({}) == nil and
+        not pcall(next, t, "missing")
+end
+
+local function test_next_clear()
+    local t = {}
+    for i = 1, 100 do
+        t["k" .. i] = i
+    end
+    local count = 0
+    for k in pairs(t) do
+        t[k] = nil
+        count = count + 1
+    end
+    return count == 100 and next(t) == nil
+end
+
+local function test_pairs()
+    local t = {10, 20, 30, x = "x"}
+    local keys = 0
+    for k, v in pairs(t) do
+        if t[k] ~= v then
+            return false
+        end
+        keys = keys + 1
+    end
+
+    local mt = {__pairs = function(t)
+        return function(_, k)
+            if k == nil then
+                return 1, "one"
+            end
+        end, t, nil
+    end}
+    local proxied = setmetatable({}, mt)
+    local seen
+    for k, v in pairs(proxied) do
+        seen = v
+    end
+
+    return keys == 4 and seen == "one" and not pcall(pairs, 1)
+end
+
+local function test_ipairs()
+    local t = {1, 2, 3, nil, 5}
+    local sum = 0
+    for i, v in ipairs(t) do
+        sum = sum + v
+    end
+
+    local proxied = setmetatable({}, {__index = function(_, i)
+        if i <= 3 then
+            return i * 10
+        end
+    end})
+    local psum = 0
+    for i, v in ipairs(proxied) do
+        psum = psum + v
+    end
+
+    return sum == 6 and psum == 60
+end
+
+local function test_tostring()
+    local named = setmetatable({}, {__name = "Thing"})
+    local custom = setmetatable({}, {__tostring = function() return "custom" end})
+    local bad = setmetatable({}, {__tostring = function() return 1 end})
+    return
+        tostring(nil) == "nil" and
+        tostring(true) == "true" and
+        tostring(12) == "12" and
+        tostring("s") == "s" and
+        tostring(custom) == "custom" and
+        string.sub(tostring(named), 1, 7) == "Thing: " and
+        not pcall(tostring, bad)
+end
+
+local function test_tonumber()
+    return
+        tonumber(10) == 10 and
+        tonumber("10") == 10 and
+        math.type(tonumber("10")) == "integer" and
+        tonumber("  0x10  ") == 16 and
+        tonumber("1e2") == 100.0 and
+        tonumber("1.5") == 1.5 and
+        tonumber("abc") == nil and
+        tonumber("") == nil and
+        tonumber("-") == nil and
+        tonumber("+") == nil and
+        tonumber("\t-\n") == nil and
+        tonumber("-5") == -5 and
+        tonumber("inf") == nil and
+        tonumber({}) == nil and
+        tonumber("ff", 16) == 255 and
+        tonumber("-101", 2) == -5 and
+        tonumber("zz", 36) == 1295 and
+        tonumber("8", 8) == nil and
+        not pcall(tonumber, "1", 1) and
+        not pcall(tonumber, "1", 37)
+end
+
+local function test_assert()
+    local a, b = assert(1, 2)
+    local ok1, msg1 = pcall(assert, false)
+    local ok2, msg2 = pcall(assert, nil, "message")
+    local ok3, msg3 = pcall(assert, false, {})
+    return
+        a == 1 and b == 2 and
+        not ok1 and msg1 == "assertion failed!" and
+        not ok2 and msg2 == "message" and
+        not ok3 and type(msg3) == "table"
+end
+
+local function test_xpcall()
+    local ok1, r1 = xpcall(function(a, b) return a + b end, error, 1, 2)
+    local ok2, r2 = xpcall(function() error("oops") end, function(e) return "handled " .. e end)
+
+    -- The handler runs before the stack is unwound, so upvalues of the erroring function are still
+    -- open
+    local inner
+    local ok3, r3 = xpcall(function()
+        local x = 1
+        inner = function() return x end
+        x = 2
+        error("err")
+    end, function(e) return inner() end)
+
+    local ok4, r4 = xpcall(function() error("first") end, function(e) error("second") end)
+
+    return
+        ok1 and r1 == 3 and
+        not ok2 and r2:find("^handled .*:128: oops$") ~= nil and
+        not ok3 and r3 == 2 and
+        not ok4 and r4:find(":140: second$") ~= nil
+end
+
+local function test_select_count()
+    return select("#") == 0 and select("#", nil, nil) == 2
+end
+
+local function test_rawlen_unpack()
+    local a, b, c = unpack({1, 2, 3})
+    return rawlen({1, 2}) == 2 and rawlen("abc") == 3 and a == 1 and b == 2 and c == 3
+end
+
+local function test_globals()
+    return _G.print == print and _G._G == _G
+end
+
+local function test_collectgarbage()
+    local garbage = {}
+    for i = 1, 1000 do
+        garbage[i] = {}
+    end
+    local before = collectgarbage("count")
+    garbage = nil
+    collectgarbage()
+    local after = collectgarbage("count")
+    collectgarbage("collect")
+    return
+        type(before) == "number" and after < before and
+        collectgarbage("step") == true and
+        collectgarbage("isrunning") == true and
+        collectgarbage("stop") == 0 and collectgarbage("isrunning") == false and
+        collectgarbage("restart") == 0 and collectgarbage("isrunning") == true and
+        collectgarbage("setpause", 100) == 200 and collectgarbage("setpause", 200) == 100 and
+        collectgarbage("setstepmul", 400) == 100 and collectgarbage("setstepmul") == 400 and
+        not pcall(collectgarbage, "bogus")
+end
+
+return
+    test_next() and
+    test_next_clear() and
+    test_pairs() and
+    test_ipairs() and
+    test_tostring() and
+    test_tonumber() and
+    test_assert() and
+    test_xpcall() and
+    test_select_count() and
+    test_rawlen_unpack() and
+    test_globals() and
+    test_collectgarbage()
@@ -0,0 +1,110 @@
+local function test1()
+    local functor = setmetatable({count = 0}, {
+        __call = function(self, n)
+            self.count = self.count + (n or 1)
+            return self.count
+        end
+    })
+    return functor() == 1 and functor(2) == 3 and functor.count == 3
+end
+
+local function test2()
+    local mt = {}
+    function mt.__call(self, ...)
+        return self, ...
+    end
+    local t = setmetatable({}, mt)
+    local s, a, b, c = t(1, nil, 3)
+    return s == t and a == 1 and b == nil and c == 3
+end
+
+local function test3()
+    local module = setmetatable({}, {
+        __call = function(_, x)
+            return x * 2
+        end
+    })
+    local function tail(x)
+        return module(x)
+    end
+    return tail(21) == 42
+end
+
+local function test4()
+    local items = {"a", "b", "c"}
+    local iter = setmetatable({i = 0}, {
+        __call = function(self)
+            self.i = self.i + 1
+            return items[self.i]
+        end
+    })
+    local s = ""
+    for v in iter do
+        s = s .. v
+    end
+    return s == "abc"
+end
+
+local function test5()
+    local co = coroutine.create(function()
+        local t = setmetatable({}, {
+            __call = function(_, a)
+                return coroutine.yield(a) + 1
+            end
+        })
+        return t(1)
+    end)
+    local _, a = coroutine.resume(co)
+    local _, b = coroutine.resume(co, 10)
+    return a == 1 and b == 11
+end
+
+local function test6()
+    local callable = setmetatable({}, {
+        __call = function(self, a, b)
+            return self, b
+        end
+    })
+    local v = setmetatable({}, {__add = callable})
+    return v + 1 == callable
+end
+
+local function test7()
+    local a = setmetatable({}, {})
+    local b = setmetatable({}, {__call = 1})
+    return
+        pcall(function() a() end) == false and
+        pcall(function() b() end) == false and
+        pcall(function() return (nil)() end) == false
+end
+
+local function test8()
+    local inner = setmetatable({}, {
+        __call = function(self, outer, a)
+            return self, outer, a
+        end
+    })
+    local outer = setmetatable({}, {__call = inner})
+    local s, o, a = outer(42)
+    local ps, po, pa = select(2, pcall(function() return outer(43) end))
+    local v = setmetatable({}, {__add = outer})
+
+    local loop = setmetatable({}, {})
+    getmetatable(loop).__call = loop
+    local ok, err = pcall(function() return loop() end)
+    return
+        s == inner and o == outer and a == 42 and
+        ps == inner and po == outer and pa == 43 and
+        v + 1 == inner and
+        ok == false and tostring(err):find("'__call' chain too long") ~= nil
+end
+
+return
+    test1() and
+    test2() and
+    test3() and
+    test4() and
+    test5() and
+    test6() and
+    test7() and
+    test8()
@@ -38,7 +38,7 @@ function test2()
 
     return
         e1 == true and r1 == 1 and s1 == "suspended" and
-        e2 == false and r2 == 'test error' and s2 == "dead"
+        e2 == false and r2:find(":29: test error$") ~= nil and s2 == "dead"
 end
 
 return
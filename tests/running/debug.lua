@@ -0,0 +1,141 @@
+local function test_getinfo()
+    local function f(a, b, ...)
+        local info = debug.getinfo(1)
+        return info
+    end
+    local info = f()
+    local caller = debug.getinfo(1, "Sl")
+    local main = debug.getinfo(2, "S")
+    local c = debug.getinfo(print)
+    local chunk = load("local info = debug.getinfo(1, 'S')\nreturn info", "@chunk.lua")()
+    return
+        info.currentline == 3 and info.linedefined == 2 and info.what == "Lua" and
+        info.nparams == 2 and info.isvararg == true and info.func == f and
+        info.name == "f" and info.namewhat == "local" and
+        chunk.source == "@chunk.lua" and chunk.short_src == "chunk.lua" and
+        chunk.what == "main" and chunk.linedefined == 0 and
+        caller.currentline == 7 and caller.func == nil and caller.nparams == nil and
+        main.what == "main" and
+        c.what == "C" and c.short_src == "[C]" and c.func == print and
+        debug.getinfo(100) == nil
+end
+
+local function test_getlocal()
+    local function f(a, b, ...)
+        local c = a + b
+        local n1, v1 = debug.getlocal(1, 1)
+        local n3, v3 = debug.getlocal(1, 3)
+        local nv, vv = debug.getlocal(1, -1)
+        local none = debug.getlocal(1, 100)
+        debug.setlocal(1, 3, 100)
+        return
+            n1 == "a" and v1 == 1 and n3 == "c" and v3 == 3 and
+            nv == "(vararg)" and vv == "x" and none == nil and c == 100
+    end
+    local caller_local = "value"
+    local function g()
+        local name, value = debug.getlocal(2, 2)
+        return name == "caller_local" and value == "value"
+    end
+    local co = coroutine.create(function(x)
+        local y = x + 1
+        coroutine.yield()
+    end)
+    coroutine.resume(co, 1)
+    local n1, v1 = debug.getlocal(co, 1, 1)
+    local n2, v2 = debug.getlocal(co, 1, 2)
+    return
+        f(1, 2, "x") and g() and
+        debug.getlocal(f, 1) == "a" and debug.getlocal(f, 2) == "b" and
+        debug.getlocal(f, 3) == nil and
+        n1 == "x" and v1 == 1 and n2 == "y" and v2 == 2 and
+        not pcall(debug.getlocal, 100, 1)
+end
+
+local function test_upvalues()
+    local up = 5
+    local function f() return up end
+    local function g() return up end
+    local other = 7
+    local function h() return other end
+
+    local name, value = debug.getupvalue(f, 1)
+    local set_name = debug.setupvalue(f, 1, 6)
+    local shared = debug.upvalueid(f, 1) == debug.upvalueid(g, 1)
+    local distinct = debug.upvalueid(f, 1) ~= debug.upvalueid(h, 1)
+    debug.upvaluejoin(h, 1, f, 1)
+
+    return
+        name == "up" and value == 5 and set_name == "up" and up == 6 and g() == 6 and
+        shared and distinct and h() == 6 and
+        debug.upvalueid(h, 1) == debug.upvalueid(f, 1) and
+        debug.getupvalue(f, 2) == nil and debug.getupvalue(print, 1) == nil
+end
+
+local function test_metatables()
+    local mt = {__metatable = "locked"}
+    local t = setmetatable({}, mt)
+    local ok = pcall(setmetatable, t, nil)
+    local same = debug.setmetatable(t, nil) == t
+    return
+        getmetatable(t) == nil and not ok and same and
+        debug.getmetatable({}) == nil and
+        debug.getmetatable("").__index == string and
+        not pcall(debug.setmetatable, 1, {})
+end
+
+local function test_hooks()
+    local function work(x)
+        local y = x * 2
+        return y
+    end
+
+    local events = {}
+    debug.sethook(function(event, line)
+        events[#events + 1] = event .. (line and (":" .. line) or "")
+    end, "crl")
+    work(1)
+    debug.sethook()
+    local hook_events = table.concat(events, " ")
+
+    local count = 0
+    debug.sethook(function() count = count + 1 end, "", 10)
+    local f, mask, n = debug.gethook()
+    for i = 1, 100 do end
+    debug.sethook()
+
+    local hooked = 0
+    local ok, err = pcall(function()
+        debug.sethook(function()
+            hooked = hooked + 1
+            if hooked == 3 then
+                error("stop")
+            end
+        end, "", 100)
+        while true do end
+    end)
+    debug.sethook()
+
+    local names = {}
+    debug.sethook(function()
+        local info = debug.getinfo(2, "nl")
+        local name = debug.getlocal(2, 1)
+        names[#names + 1] = (info.name or "?") .. ":" .. (name or "?")
+    end, "l")
+    work(1)
+    debug.sethook()
+
+    return
+        hook_events == "line:97 call line:89 line:90 return line:98" and
+        count >= 10 and type(f) == "function" and mask == "" and n == 10 and
+        debug.gethook() == nil and
+        not ok and err:find("stop") ~= nil and hooked == 3 and
+        names[2] == "work:x" and names[3] == "work:x"
+end
+
+return
+    test_getinfo() and
+    test_getlocal() and
+    test_upvalues() and
+    test_metatables() and
+    test_hooks()
@@ -0,0 +1,101 @@
+-- Finalizers are called by a later step of the main thread after a collection, so give them time
+-- to run
+local function collect()
+    collectgarbage()
+    for i = 1, 2000 do
+    end
+end
+
+local finalized = {}
+local gc_mt = {
+    __gc = function(o)
+        finalized[#finalized + 1] = o.name
+    end
+}
+
+local function make(name)
+    setmetatable({name = name}, gc_mt)
+end
+
+local function test_finalize()
+    finalized = {}
+    local kept = setmetatable({name = "kept"}, gc_mt)
+    make("a")
+    make("b")
+    collect()
+    local names = {}
+    for _, name in ipairs(finalized) do
+        names[name] = true
+    end
+    return #finalized == 2 and names.a and names.b and kept.name == "kept"
+end
+
+local function test_once()
+    finalized = {}
+    -- Registering twice does not call the finalizer twice
+    local function make_twice()
+        local t = setmetatable({name = "twice"}, gc_mt)
+        setmetatable(t, gc_mt)
+    end
+    make_twice()
+    collect()
+    collect()
+    local count = 0
+    for _, name in ipairs(finalized) do
+        if name == "twice" then
+            count = count + 1
+        end
+    end
+    return count == 1
+end
+
+local function test_resurrection()
+    local resurrected
+    local function make_resurrecting()
+        setmetatable({}, {__gc = function(o)
+            o.value = 42
+            resurrected = o
+        end})
+    end
+    make_resurrecting()
+    collect()
+    return resurrected ~= nil and resurrected.value == 42
+end
+
+local function test_errors()
+    local ran = false
+    local function make_erroring()
+        setmetatable({}, {__gc = function()
+            error("ignored")
+        end})
+        setmetatable({}, {__gc = function()
+            ran = true
+        end})
+    end
+    make_erroring()
+    collect()
+    collect()
+    return ran
+end
+
+local function test_missing_gc()
+    -- Only metatables which have a __gc field when they are set register a finalizer
+    local mt = {}
+    local called = false
+    local function make_late()
+        setmetatable({}, mt)
+    end
+    make_late()
+    mt.__gc = function()
+        called = true
+    end
+    collect()
+    return not called
+end
+
+return
+    test_finalize() and
+    test_once() and
+    test_resurrection() and
+    test_errors() and
+    test_missing_gc()
@@ -0,0 +1,61 @@
+local function test_switch()
+    return collectgarbage("generational") == "incremental"
+        and collectgarbage("generational") == "generational"
+end
+
+local function test_young_garbage_collected()
+    collectgarbage()
+    local before = collectgarbage("count")
+    for i = 1, 100000 do
+        local t = {i, {}}
+    end
+    return collectgarbage("count") < before + 1024
+end
+
+local function test_old_table_keeps_young_values()
+    local old = {}
+    collectgarbage()
+    for i = 1, 20000 do
+        old[i % 100 + 1] = {i}
+        local garbage = {i, i}
+    end
+    for i = 1, 100 do
+        local v = old[i]
+        if type(v) ~= "table" or v[1] % 100 + 1 ~= i then
+            return false
+        end
+    end
+    return true
+end
+
+local function test_weak_and_finalizers()
+    local t = setmetatable({}, {__mode = "k"})
+    local finalized = 0
+    local function make()
+        local kept = {}
+        t[kept] = true
+        t[{}] = true
+        setmetatable({}, {__gc = function() finalized = finalized + 1 end})
+        return kept
+    end
+    local kept = make()
+    collectgarbage()
+    for i = 1, 2000 do end
+    local n = 0
+    for _ in pairs(t) do
+        n = n + 1
+    end
+    return n == 1 and t[kept] and finalized == 1
+end
+
+local function test_switch_back()
+    return collectgarbage("incremental") == "generational"
+        and collectgarbage("incremental") == "incremental"
+end
+
+return
+    test_switch() and
+    test_young_garbage_collected() and
+    test_old_table_keeps_young_values() and
+    test_weak_and_finalizers() and
+    test_switch_back()
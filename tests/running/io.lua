@@ -0,0 +1,68 @@
+local function test_file_roundtrip()
+    local name = os.tmpname()
+    local f = io.open(name, "w")
+    local written = f:write("line one\n", 2, "\n", "0x1p4 rest") == f
+    f:close()
+
+    f = io.open(name, "r")
+    local l1 = f:read("l")
+    io.input(f)
+    local n1, n2 = io.read("n", "n")
+    io.input(io.stdin)
+    local rest = f:read(5)
+    local size = f:seek("end")
+    f:close()
+
+    local lines = {}
+    for a, b in io.lines(name, 1, "l") do
+        lines[#lines + 1] = a .. "|" .. (b or "")
+    end
+    os.remove(name)
+
+    return
+        written and
+        l1 == "line one" and n1 == 2 and n2 == 16.0 and rest == " rest" and size == 21 and
+        #lines == 3 and lines[1] == "l|ine one" and lines[2] == "2|" and
+        lines[3] == "0|x1p4 rest"
+end
+
+local function test_read_number_failure()
+    local name = os.tmpname()
+    local f = io.open(name, "w")
+    f:write("abc")
+    f:close()
+    f = io.open(name, "r")
+    local n1 = f:read("n")
+    local rest1 = f:read("a")
+    f:close()
+
+    f = io.open(name, "w")
+    f:write(" nan")
+    f:close()
+    f = io.open(name, "r")
+    local n2 = f:read("n")
+    local rest2 = f:read("a")
+    f:close()
+    os.remove(name)
+
+    return n1 == nil and rest1 == "abc" and n2 == nil and rest2 == "nan"
+end
+
+local function test_errors()
+    local ok1 = pcall(io.open, "x", "rw")
+    local ok2 = pcall(io.read, "x")
+    local ok3 = pcall(io.lines, "/nonexistent/file")
+    local closed, msg = io.close(io.stdout)
+    return
+        ok1 == false and ok2 == false and ok3 == false and
+        closed == nil and msg == "cannot close standard file" and
+        io.open("/nonexistent/file") == nil and
+        io.stdout:setvbuf("no") == true and
+        pcall(io.stdout.setvbuf, io.stdout, "bad") == false and
+        io.output() == io.stdout and io.input() == io.stdin
+end
+
+return
+    test_file_roundtrip() and
+    test_read_number_failure() and
+    test_errors()
@@ -0,0 +1,46 @@
+local function test_runtime_errors()
+    local f = load("local x = nil\n\nreturn x.y", "=t")
+    local ok, msg = pcall(f)
+
+    local g = load("local t = {}\nfunction t.f()\n    return 1 + {}\nend\nreturn t.f()", "=t")
+    local ok2, msg2 = pcall(g)
+
+    local h = load("local s = string.rep()\nreturn s", "@file.lua")
+    local ok3, msg3 = pcall(h)
+
+    return
+        not ok and msg:sub(1, 5) == "t:3: " and
+        not ok2 and msg2:sub(1, 5) == "t:3: " and
+        not ok3 and msg3:sub(1, 12) == "file.lua:1: "
+end
+
+local function test_error_levels()
+    local source = "local function f(level)\n    error('msg', level)\nend\nf(...)"
+    local f = load(source, "=t")
+    local ok1, msg1 = pcall(f, 1)
+    local ok2, msg2 = pcall(f, 2)
+    local ok0, msg0 = pcall(f, 0)
+    local ok9, msg9 = pcall(f, 9)
+    local okt, msgt = pcall(error, {})
+    local okn, msgn = pcall(load("error(42)", "=t"))
+    return
+        not ok1 and msg1 == "t:2: msg" and
+        not ok2 and msg2 == "t:4: msg" and
+        not ok0 and msg0 == "msg" and
+        not ok9 and msg9 == "msg" and
+        not okt and type(msgt) == "table" and
+        not okn and msgn == 42
+end
+
+local function test_syntax_errors()
+    local f, msg = load("x = 1\n\nx x", "=t")
+    local g, msg2 = load("x = [[\n\n]] +", "=t")
+    return
+        f == nil and msg:sub(1, 5) == "t:3: " and
+        g == nil and msg2:sub(1, 5) == "t:3: "
+end
+
+return
+    test_runtime_errors() and
+    test_error_levels() and
+    test_syntax_errors()
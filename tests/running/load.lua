@@ -0,0 +1,70 @@
+local function test_load_string()
+    local f = load("return 1 + 2")
+    local g = loadstring("local a, b = ... return a * b")
+    local bad, msg = load("return +", "=chunk")
+    local bad2, msg2 = load("x x", "local x = 1\nreturn x")
+    return
+        f() == 3 and g(6, 7) == 42 and
+        bad == nil and msg:sub(1, 9) == "chunk:1: " and
+        bad2 == nil and msg2:sub(1, 26) == '[string "local x = 1..."]:'
+end
+
+local function test_load_env()
+    local env = {y = 5}
+    local f = load("x = y * 2 return x", "chunk", "t", env)
+    local r = f()
+    return
+        r == 10 and env.x == 10 and x == nil and
+        load("return y")() == nil and
+        load("return string", "chunk", "t", nil)() == string and
+        pcall(load, "return 1", "chunk", "t", 1) == false
+end
+
+local function test_load_reader()
+    local pieces = {"return ", "'a'", " .. ", "'b'"}
+    local i = 0
+    local f = load(function()
+        i = i + 1
+        return pieces[i]
+    end)
+    local bad, msg = load(function() return 1 end)
+    local bad2, msg2 = load(function() error("oops") end)
+    return
+        f() == "ab" and
+        bad == nil and msg == "reader function must return a string" and
+        bad2 == nil and msg2:find(":31: oops$") ~= nil
+end
+
+local function test_load_mode()
+    local bad, msg = load("return 1", "=c", "b")
+    local bad2, msg2 = load("\27Lua", "=c", "t")
+    return
+        bad == nil and msg == "c: attempt to load a text chunk (mode is 'b')" and
+        bad2 == nil and msg2 == "c: attempt to load a binary chunk (mode is 't')" and
+        load("return 1", "=c", "bt")() == 1
+end
+
+local function test_loadfile()
+    local name = os.tmpname()
+    local f = io.open(name, "w")
+    f:write("#!/usr/bin/env lua\nlocal a = ...\nreturn (a or 1) + 1, 'second'")
+    f:close()
+
+    local chunk = loadfile(name)
+    local explicit = loadfile(name, "t", nil)
+    local a, b = dofile(name)
+    os.remove(name)
+
+    local missing, msg = loadfile(name)
+    return
+        chunk(41) == 42 and explicit(1) == 2 and a == 2 and b == "second" and
+        missing == nil and msg:sub(1, 12) == "cannot open " and
+        pcall(dofile, name) == false
+end
+
+return
+    test_load_string() and
+    test_load_env() and
+    test_load_reader() and
+    test_load_mode() and
+    test_loadfile()
@@ -0,0 +1,144 @@
+local Vec = {}
+Vec.__index = Vec
+
+local function vec(x, y)
+    return setmetatable({x = x, y = y}, Vec)
+end
+
+Vec.__add = function(a, b) return vec(a.x + b.x, a.y + b.y) end
+Vec.__sub = function(a, b) return vec(a.x - b.x, a.y - b.y) end
+Vec.__mul = function(a, b)
+    if type(a) == "number" then
+        return vec(a * b.x, a * b.y)
+    elseif type(b) == "number" then
+        return vec(a.x * b, a.y * b)
+    else
+        return a.x * b.x + a.y * b.y
+    end
+end
+Vec.__unm = function(a) return vec(-a.x, -a.y) end
+Vec.__eq = function(a, b) return a.x == b.x and a.y == b.y end
+Vec.__lt = function(a, b) return a.x * a.x + a.y * a.y < b.x * b.x + b.y * b.y end
+Vec.__len = function(a) return 2 end
+Vec.__concat = function(a, b)
+    local function str(v)
+        if type(v) == "table" then
+            return "(" .. v.x .. "," .. v.y .. ")"
+        else
+            return v
+        end
+    end
+    return str(a) .. str(b)
+end
+
+local function test_arithmetic()
+    local a = vec(1, 2)
+    local b = vec(3, 4)
+    local c = a + b
+    local d = b - a
+    local e = 2 * a
+    local f = a * 3
+    local g = -a
+    return
+        c.x == 4 and c.y == 6 and
+        d.x == 2 and d.y == 2 and
+        e.x == 2 and e.y == 4 and
+        f.x == 3 and f.y == 6 and
+        g.x == -1 and g.y == -2 and
+        a * b == 11
+end
+
+local function test_comparison()
+    local a = vec(1, 2)
+    local b = vec(3, 4)
+    return
+        a == vec(1, 2) and
+        a ~= b and
+        a ~= 1 and
+        a < b and
+        not (b < a) and
+        a <= b and
+        b > a and
+        b >= a and
+        not (a > b)
+end
+
+local function test_len_concat()
+    local a = vec(1, 2)
+    return
+        #a == 2 and
+        #"hello" == 5 and
+        a .. "!" == "(1,2)!" and
+        "v = " .. a == "v = (1,2)" and
+        "a" .. 1 .. a .. 2 .. "b" == "a1(1,2)2b"
+end
+
+local function test_all_operators()
+    local mt = {}
+    local names = {
+        "__add", "__sub", "__mul", "__div", "__mod", "__pow", "__idiv",
+        "__band", "__bor", "__bxor", "__shl", "__shr"
+    }
+    for i = 1, 12 do
+        local name = names[i]
+        mt[name] = function(a, b) return name end
+    end
+    mt.__unm = function() return "__unm" end
+    mt.__bnot = function() return "__bnot" end
+    local t = setmetatable({}, mt)
+    return
+        t + 1 == "__add" and 1 - t == "__sub" and t * t == "__mul" and
+        t / 1 == "__div" and t % 1 == "__mod" and t ^ 1 == "__pow" and
+        t // 1 == "__idiv" and t & 1 == "__band" and t | 1 == "__bor" and
+        t ~ 1 == "__bxor" and t << 1 == "__shl" and t >> 1 == "__shr" and
+        -t == "__unm" and ~t == "__bnot"
+end
+
+local function test_le_fallback()
+    local mt = {__lt = function(a, b) return a.v < b.v end}
+    local a = setmetatable({v = 1}, mt)
+    local b = setmetatable({v = 2}, mt)
+    return a <= b and not (b <= a) and a <= a
+end
+
+local function test_errors()
+    local t = {}
+    return
+        pcall(function() return t + 1 end) == false and
+        pcall(function() return t < t end) == false and
+        pcall(function() return t .. "" end) == false and
+        pcall(function() return -t end) == false and
+        pcall(function() return #nil end) == false and
+        (t == {}) == false
+end
+
+local function test_yield()
+    local mt = {
+        __add = function(a, b) return coroutine.yield("add") end,
+        __lt = function(a, b) return coroutine.yield("lt") end,
+        __concat = function(a, b) return coroutine.yield("concat") end,
+    }
+    local t = setmetatable({}, mt)
+    local co = coroutine.create(function()
+        local s = t + 1
+        local l = t < t
+        local c = "a" .. t .. "b"
+        return s, l, c
+    end)
+    local _, y1 = coroutine.resume(co)
+    local _, y2 = coroutine.resume(co, 10)
+    local _, y3 = coroutine.resume(co, false)
+    local _, s, l, c = coroutine.resume(co, "x")
+    return
+        y1 == "add" and y2 == "lt" and y3 == "concat" and
+        s == 10 and l == false and c == "ax"
+end
+
+return
+    test_arithmetic() and
+    test_comparison() and
+    test_len_concat() and
+    test_all_operators() and
+    test_le_fallback() and
+    test_errors() and
+    test_yield()
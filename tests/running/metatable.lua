@@ -0,0 +1,110 @@
+local function test1()
+    local t = {}
+    local mt = {}
+    return
+        getmetatable(t) == nil and
+        setmetatable(t, mt) == t and
+        getmetatable(t) == mt and
+        setmetatable(t, nil) == t and
+        getmetatable(t) == nil
+end
+
+local function test2()
+    local t = setmetatable({}, {__metatable = "protected"})
+    return
+        getmetatable(t) == "protected" and
+        pcall(setmetatable, t, {}) == false
+end
+
+local function test3()
+    local Class = {}
+    Class.__index = Class
+
+    function Class.new(v)
+        return setmetatable({value = v}, Class)
+    end
+
+    function Class:get()
+        return self.value
+    end
+
+    local Derived = setmetatable({}, {__index = Class})
+    Derived.__index = Derived
+
+    function Derived:double()
+        return self:get() * 2
+    end
+
+    local o = setmetatable(Class.new(21), Derived)
+    return o:get() == 21 and o:double() == 42 and o.missing == nil
+end
+
+local function test4()
+    local calls = 0
+    local t = setmetatable({present = 1}, {
+        __index = function(t, k)
+            calls = calls + 1
+            return k .. "!"
+        end
+    })
+    return t.present == 1 and t.foo == "foo!" and t[1] == "1!" and calls == 2
+end
+
+local function test5()
+    local store = {}
+    local t = setmetatable({}, {
+        __newindex = function(t, k, v)
+            rawset(t, k, v * 2)
+        end
+    })
+    t.a = 1
+    t.a = 5
+    local proxy = setmetatable({}, {__newindex = store})
+    proxy.b = 3
+    return t.a == 5 and rawget(proxy, "b") == nil and store.b == 3
+end
+
+local function test6()
+    local t = setmetatable({}, {__index = function() return 1 end})
+    local u = setmetatable({}, {__index = t})
+    local v = setmetatable({}, {__index = u})
+    return v.x == 1 and rawget(v, "x") == nil
+end
+
+local function test7()
+    local t = setmetatable({}, {__index = function(t, k)
+        return coroutine.yield(k)
+    end})
+    local co = coroutine.create(function()
+        return t.foo
+    end)
+    local _, k = coroutine.resume(co)
+    local _, v = coroutine.resume(co, "bar")
+    return k == "foo" and v == "bar"
+end
+
+local function test8()
+    local t = {1, 2, 3}
+    return
+        rawlen(t) == 3 and
+        rawlen("abcd") == 4 and
+        rawequal(t, t) and
+        not rawequal(t, {})
+end
+
+local function test9()
+    local t = {}
+    setmetatable(t, {__index = t, __newindex = t})
+    return pcall(function() return t.x end) == false
+end
+
+return
+    test1() and
+    test2() and
+    test3() and
+    test4() and
+    test5() and
+    test6() and
+    test7() and
+    test8() and
+    test9()
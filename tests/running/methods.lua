@@ -23,6 +23,31 @@ function test2()
     return t:method(42) == 42
 end
 
+function test3()
+    local t = {}
+    function t:multi()
+        return 1, 2, 3
+    end
+
+    local a, b, c = t:multi()
+    local function args(...)
+        return ...
+    end
+    local d, e, f = args(t:multi())
+    local g, h, i, j = args(0, t:multi())
+    local function ret()
+        return t:multi()
+    end
+    local k, l, m = ret()
+
+    return
+        a == 1 and b == 2 and c == 3 and
+        d == 1 and e == 2 and f == 3 and
+        g == 0 and h == 1 and i == 2 and j == 3 and
+        k == 1 and l == 2 and m == 3
+end
+
 return
     test1() and
-    test2()
+    test2() and
+    test3()
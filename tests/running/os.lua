@@ -0,0 +1,58 @@
+local function test_date()
+    return
+        os.date("!%Y-%m-%d %H:%M:%S", 0) == "1970-01-01 00:00:00" and
+        os.date("!%a %A %b %B %p", 0) == "Thu Thursday Jan January AM" and
+        os.date("!%j %U %W %V %G %u %w", 0) == "001 00 00 01 1970 4 4" and
+        os.date("!%D %F %R %T %e %I %y %C %%", 1234567890) ==
+            "02/13/09 2009-02-13 23:31 23:31:30 13 11 09 20 %" and
+        os.date("!%V %G %g", 1230768000) == "01 2009 09" and
+        os.date("!%V %G", 1262304000) == "53 2009" and
+        os.date("!%Ey %OH", 0) == "70 00" and
+        pcall(os.date, "%Q") == false and
+        pcall(os.date, "%Ea") == false and
+        pcall(os.date, "%") == false
+end
+
+local function test_date_table()
+    local t = os.date("!*t", 86400 * 59)
+    return
+        t.year == 1970 and t.month == 3 and t.day == 1 and
+        t.hour == 0 and t.min == 0 and t.sec == 0 and
+        t.wday == 1 and t.yday == 60 and t.isdst == false
+end
+
+local function out_of_bound(name, t)
+    local ok, msg = pcall(os.time, t)
+    return not ok and msg:find("field '" .. name .. "' is out-of-bound", 1, true) ~= nil
+end
+
+local function test_time()
+    local t = {year = 2000, month = 14, day = 0, hour = 25}
+    local time = os.time(t)
+    return
+        math.type(os.time()) == "integer" and
+        os.time({year = 1970, month = 1, day = 1, hour = 0}) == 0 and
+        os.time({year = 2009, month = 2, day = 13, hour = 23, min = 31, sec = 30}) == 1234567890 and
+        time == os.time({year = 2001, month = 2, day = 1, hour = 1}) and
+        t.year == 2001 and t.month == 2 and t.day == 1 and t.hour == 1 and
+        pcall(os.time, {year = 2000}) == false and
+        pcall(os.time, {year = 2000, month = 1, day = 1.5}) == false and
+        out_of_bound("year", {year = math.mininteger, month = 1, day = 1}) and
+        out_of_bound("day", {year = 2000, month = 1, day = 2^31})
+end
+
+local function test_misc()
+    local c = os.clock()
+    return
+        os.difftime(10, 4) == 6.0 and
+        math.type(os.difftime(10)) == "float" and
+        math.type(c) == "float" and c >= 0 and
+        os.getenv("LUSTER_SURELY_NOT_SET") == nil and
+        os.remove("/luster/surely/does/not/exist") == nil
+end
+
+return
+    test_date() and
+    test_date_table() and
+    test_time() and
+    test_misc()
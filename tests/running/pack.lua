@@ -0,0 +1,98 @@
+local pack, unpack, packsize = string.pack, string.unpack, string.packsize
+
+local function test_integers()
+    local a, b, c, d, n = unpack("<bBhH", pack("<bBhH", -1, 255, -2, 65535))
+    local i3, u3, i16, n2 = unpack("<i3I3i16", pack("<i3I3i16", -3, 0xffffff, -4))
+    return
+        pack("<i4", 1) == "\1\0\0\0" and
+        pack(">i4", 1) == "\0\0\0\1" and
+        pack("<I2", 0x1234) == "\x34\x12" and
+        pack(">I2", 0x1234) == "\x12\x34" and
+        pack("<i3", -2) == "\xfe\xff\xff" and
+        pack("<i12", -1) == string.rep("\xff", 12) and
+        pack("<I12", 1) == "\1" .. string.rep("\0", 11) and
+        pack("b", 127) == "\x7f" and
+        a == -1 and b == 255 and c == -2 and d == 65535 and n == 7 and
+        i3 == -3 and u3 == 0xffffff and i16 == -4 and n2 == 23 and
+        unpack("<j", pack("<j", math.maxinteger)) == math.maxinteger and
+        unpack("<J", pack("<J", -1)) == -1 and
+        unpack(">l", pack(">l", math.mininteger)) == math.mininteger and
+        unpack("<i9", "\1\0\0\0\0\0\0\0\0") == 1 and
+        unpack("<i9", string.rep("\xff", 9)) == -1
+end
+
+local function test_floats()
+    local f, d, nn, n = unpack("<fdn", pack("<fdn", 0.5, -1.25, 3.0))
+    return
+        pack("<d", 1.0) == "\0\0\0\0\0\0\xf0\x3f" and
+        pack(">f", 1.0) == "\x3f\x80\0\0" and
+        f == 0.5 and d == -1.25 and nn == 3.0 and n == 21 and
+        math.type(f) == "float"
+end
+
+local function test_strings()
+    local a, b, c, n = unpack("z s1 c3", pack("z s1 c3", "hello", "world", "ab"))
+    return
+        pack("z", "abc") == "abc\0" and
+        pack("<s2", "abc") == "\3\0abc" and
+        pack("c5", "abc") == "abc\0\0" and
+        a == "hello" and b == "world" and c == "ab\0" and n == 16 and
+        unpack("s", pack("s", "")) == ""
+end
+
+local function test_alignment()
+    return
+        packsize("!8 b i8") == 16 and
+        packsize("!4 b i8") == 12 and
+        packsize("b i8") == 9 and
+        packsize("!8 b Xi4") == 4 and
+        packsize("!2 b h b i4") == 10 and
+        packsize("c3 x") == 4 and
+        pack("!4 b i4", 1, 2) == "\1\0\0\0\2\0\0\0" and
+        unpack("!4 b i4", "\1\0\0\0\2\0\0\0", 1) == 1 and
+        select(2, unpack("!4 b i4", "\1\0\0\0\2\0\0\0")) == 2
+end
+
+local function test_positions()
+    local s = pack("i4i4", 10, 20)
+    local a, n = unpack("i4", s, 5)
+    local b, m = unpack("i4", s, -4)
+    return
+        a == 20 and n == 9 and
+        b == 20 and m == 9 and
+        unpack("", s, 9) == 9
+end
+
+local function test_errors()
+    return
+        pcall(pack, "y", 1) == false and
+        pcall(pack, "i17", 1) == false and
+        pcall(pack, "i0", 1) == false and
+        pcall(pack, "c", "a") == false and
+        pcall(pack, "b", 128) == false and
+        pcall(pack, "B", -1) == false and
+        pcall(pack, "i2", 40000) == false and
+        pcall(pack, "s1", string.rep("a", 256)) == false and
+        pcall(pack, "c2", "abc") == false and
+        pcall(pack, "z", "a\0b") == false and
+        pcall(pack, "i4", "x") == false and
+        pcall(pack, "i4", 1.5) == false and
+        pcall(pack, "!3 i4", 1) == false and
+        pcall(pack, "X") == false and
+        pcall(pack, "Xc1") == false and
+        pcall(packsize, "s") == false and
+        pcall(packsize, "z") == false and
+        pcall(unpack, "i4", "abc") == false and
+        pcall(unpack, "z", "abc") == false and
+        pcall(unpack, "s1", "\5abc") == false and
+        pcall(unpack, "i4", "abcd", 6) == false and
+        pcall(unpack, "<i9", "\0\0\0\0\0\0\0\0\1") == false
+end
+
+return
+    test_integers() and
+    test_floats() and
+    test_strings() and
+    test_alignment() and
+    test_positions() and
+    test_errors()
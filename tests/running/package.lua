@@ -0,0 +1,59 @@
+local function test_loaded()
+    return
+        package.loaded.string == string and package.loaded._G.package == package and
+        package.loaded.package == package and require("table") == table and
+        type(package.path) == "string" and package.config:sub(1, 1) == "/" and
+        #package.searchers == 2
+end
+
+local function test_preload()
+    local calls = 0
+    package.preload.mymod = function(name, extra)
+        calls = calls + 1
+        return {name = name, extra = extra}
+    end
+    package.preload.empty = function() end
+
+    local m = require("mymod")
+    local m2 = require("mymod")
+    return
+        m == m2 and calls == 1 and m.name == "mymod" and m.extra == ":preload:" and
+        package.loaded.mymod == m and
+        require("empty") == true
+end
+
+local function test_not_found()
+    local ok, msg = pcall(require, "surely.not.a.module")
+    return
+        ok == false and
+        msg:find("module 'surely.not.a.module' not found:", 1, true) == 1 and
+        msg:find("no field package.preload['surely.not.a.module']", 1, true) ~= nil and
+        msg:find("no file './surely/not/a/module.lua'", 1, true) ~= nil
+end
+
+local function test_file_module()
+    local name = os.tmpname()
+    local f = io.open(name .. ".lua", "w")
+    f:write("local name, path = ... return {name = name, path = path}")
+    f:close()
+
+    local dir, base = string.match(name, "^(.*)/([^/]*)$")
+    local old_path = package.path
+    package.path = dir .. "/?.lua"
+    local found = package.searchpath(base, package.path)
+    local m = require(base)
+    package.path = old_path
+    os.remove(name .. ".lua")
+
+    local missing, msg = package.searchpath("a.b", "x/?.lua;y/?.lua")
+    return
+        found == name .. ".lua" and
+        m.name == base and m.path == name .. ".lua" and
+        missing == nil and msg == "\n\tno file 'x/a/b.lua'\n\tno file 'y/a/b.lua'"
+end
+
+return
+    test_loaded() and
+    test_preload() and
+    test_not_found() and
+    test_file_module()
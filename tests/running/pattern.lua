@@ -0,0 +1,105 @@
+local function test_find()
+    local a, b = string.find("hello world", "wor")
+    local c, d, e, f = string.find("hello world", "(o)(r)")
+    return
+        a == 7 and b == 9 and
+        c == 8 and d == 9 and e == "o" and f == "r" and
+        string.find("hello world", "o", 6) == 8 and
+        string.find("hello world", "o", -3) == nil and
+        string.find("a.b", ".", 1, true) == 2 and
+        string.find("abc", "^b") == nil and
+        string.find("abc", "^a") == 1 and
+        string.find("abc", "", 10) == nil and
+        select(2, string.find("abc", "", 4)) == 3
+end
+
+local function test_match()
+    local k, v = string.match("key = value", "(%w+)%s*=%s*(%w+)")
+    local p1, p2 = string.match("hello", "()ll()")
+    local y, m, d = string.match("2024-01-02", "(%d+)-(%d+)-(%d+)")
+    return
+        k == "key" and v == "value" and
+        p1 == 3 and p2 == 5 and
+        y == "2024" and m == "01" and d == "02" and
+        string.match("  trim  ", "^%s*(.-)%s*$") == "trim" and
+        string.match("THE (quick) fox", "%((%a+)%)") == "quick" and
+        string.match("f(a(b)c)d", "%b()") == "(a(b)c)" and
+        string.match("THE (quick) fox", "%f[%a]%a+", 5) == "quick" and
+        string.match("abcabc", "(abc)%1") == "abc" and
+        string.match("x1 = 10", "[%a_][%w_]*") == "x1" and
+        string.match("[]", "[]]") == "]" and
+        string.match("a-b", "[%-]") == "-" and
+        string.match("aaab", "a-b") == "aaab" and
+        string.match("aaa", "a-$") == "aaa" and
+        string.match("abc", "%u") == nil and
+        string.match("abc", "[^%l]") == nil
+end
+
+local function test_gmatch()
+    local s = ""
+    for k, v in string.gmatch("a=1, b=2, c=3", "(%w+)=(%w+)") do
+        s = s .. k .. v
+    end
+    local words = {}
+    for w in ("one two three"):gmatch("%a+") do
+        words[#words + 1] = w
+    end
+    local count = 0
+    for _ in string.gmatch("abc", "") do
+        count = count + 1
+    end
+    return s == "a1b2c3" and #words == 3 and words[3] == "three" and count == 4
+end
+
+local function test_gsub()
+    local function check(es, en, s, n)
+        return s == es and n == en
+    end
+    return
+        check("hell0 w0rld", 2, string.gsub("hello world", "o", "0")) and
+        check("hell[oo] world", 1, string.gsub("hello world", "(o)", "[%1%1]", 1)) and
+        check("<hello> <world>", 2, string.gsub("hello world", "%w+", "<%0>")) and
+        check("bob is 42", 2, string.gsub("$name is $age", "%$(%w+)", {name = "bob", age = 42})) and
+        check("A.B.C.", 3, string.gsub("abc", "%w", function(c) return c:upper() .. "." end)) and
+        check("abc", 1, string.gsub("abc", "b", function() return false end)) and
+        check("-a-b-c-", 4, string.gsub("abc", "", "-")) and
+        check("Hello", 1, string.gsub("hello", "^h", "H")) and
+        check("a;b;;c", 3, string.gsub("a,b,,c", ",", ";")) and
+        check("a%c", 1, string.gsub("abc", "b", "%%")) and
+        check("a5c", 1, string.gsub("abc", "b", 5))
+end
+
+local function test_gsub_metamethods()
+    local t = setmetatable({}, {__index = function(_, k) return k .. k end})
+    local co = coroutine.create(function()
+        return string.gsub("ab", "%w", function(c) return coroutine.yield(c) end)
+    end)
+    local _, a = coroutine.resume(co)
+    local _, b = coroutine.resume(co, "x")
+    local _, s, n = coroutine.resume(co, "y")
+    return
+        string.gsub("ab", "%w", t) == "aabb" and
+        a == "a" and b == "b" and s == "xy" and n == 2
+end
+
+local function test_errors()
+    return
+        pcall(string.find, "abc", "[a") == false and
+        pcall(string.find, "abc", "%") == false and
+        pcall(string.gsub, "abc", "a", "%2") == false and
+        pcall(string.gsub, "abc", "a", "%x") == false and
+        pcall(string.gsub, "abc", "a", {a = {}}) == false and
+        pcall(string.match, "abc", "%1") == false and
+        pcall(string.match, "abc", "%f") == false and
+        pcall(string.match, "abc", "a)") == false and
+        pcall(string.gsub, "abc", "a", true) == false and
+        pcall(string.match, string.rep("a", 1000), string.rep("a?", 1000) .. string.rep("a", 1000)) == false
+end
+
+return
+    test_find() and
+    test_match() and
+    test_gmatch() and
+    test_gsub() and
+    test_gsub_metamethods() and
+    test_errors()
@@ -11,8 +11,8 @@ function test1()
     local r3, e3 = pcall(good_func)
 
     return
-        r1 == false and e1 == "test error" and
-        r2 == false and e2 == "test error 2" and
+        r1 == false and e1:find(":3: test error$") ~= nil and
+        r2 == false and e2:find(":3: test error 2$") ~= nil and
         r3 == true and e3 == "good"
 end
 
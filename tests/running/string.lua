@@ -5,4 +5,88 @@ function test_concat()
         1 .. 2 .. 3 == "123"
 end
 
-return test_concat()
+function test_basic()
+    return
+        string.len("abc") == 3 and
+        string.len("") == 0 and
+        string.upper("aBc1") == "ABC1" and
+        string.lower("AbC1") == "abc1" and
+        string.reverse("abc") == "cba" and
+        string.rep("ab", 3) == "ababab" and
+        string.rep("ab", 3, ",") == "ab,ab,ab" and
+        string.rep("ab", 0) == ""
+end
+
+function test_sub()
+    local s = "hello world"
+    return
+        string.sub(s, 1, 5) == "hello" and
+        string.sub(s, 7) == "world" and
+        string.sub(s, -5) == "world" and
+        string.sub(s, -5, -2) == "worl" and
+        string.sub(s, 0) == s and
+        string.sub(s, 5, 2) == "" and
+        string.sub(s, 100) == "" and
+        string.sub(s, -100, 2) == "he"
+end
+
+function test_byte_char()
+    local a, b, c = string.byte("ABC", 1, -1)
+    return
+        string.byte("A") == 65 and
+        a == 65 and b == 66 and c == 67 and
+        string.byte("ABC", -1) == 67 and
+        string.byte("", 1) == nil and
+        string.char(72, 105) == "Hi" and
+        string.char() == "" and
+        pcall(string.char, 256) == false
+end
+
+function test_format()
+    local point = setmetatable({}, {__tostring = function() return "(1,2)" end})
+    local named = setmetatable({}, {__name = "Named"})
+    local bad = setmetatable({}, {__tostring = function() return {} end})
+    return
+        string.format("%d %i", 42, -7) == "42 -7" and
+        string.format("%5d|%-5d|%05d|%+d|%.3d", 42, 42, 42, 42, 7) ==
+            "   42|42   |00042|+42|007" and
+        string.format("%x %X %#x %o", 255, 255, 255, 8) == "ff FF 0xff 10" and
+        string.format("%c%c", 72, 105) == "Hi" and
+        string.format("%s|%5s|%-5s|%.2s", "a", "hi", "hi", "hello") == "a|   hi|hi   |he" and
+        string.format("%g %g %g %g", 1.5, 1e20, 0.0001, 100) == "1.5 1e+20 0.0001 100" and
+        string.format("%.3g %10.2f %e", 3.14159, 3.14159, 12345.678) ==
+            "3.14       3.14 1.234568e+04" and
+        string.format("%a %a %.2a %A", 1.0, 0.5, 1.9, 3.0) == "0x1p+0 0x1p-1 0x1.e6p+0 0X1.8P+1" and
+        string.format("%5.1f%%", 99.44) == " 99.4%" and
+        string.format("%q", "a\n\"b\\\0" .. "1\1x") == "\"a\\\n\\\"b\\\\\\0001\\1x\"" and
+        string.format("%q %q %q", 42, 0.5, 1/0) == "42 0x1p-1 1e9999" and
+        pcall(string.format, "%y", 1) == false and
+        pcall(string.format, "%d") == false and
+        pcall(string.format, "%d", 1.5) == false and
+        not pcall(string.format, "%99999999999999999999999d", 1) and
+        select(2, pcall(string.format, "%1.99999999999999999999f", 1)):find("too long") ~= nil and
+        string.format("%s|%-6s|%.3s|%d", point, point, point, 7) == "(1,2)|(1,2) |(1,|7" and
+        string.format("%s %5.1f %s", nil, 1.25, true) == "nil   1.2 true" and
+        string.format("%s", named):sub(1, 7) == "Named: " and
+        pcall(string.format, "%s", bad) == false
+end
+
+function test_methods()
+    local s = "hello"
+    return
+        ("x"):upper() == "X" and
+        s:len() == 5 and
+        s:sub(2, 3) == "el" and
+        s:byte(-1) == 111 and
+        ("%d-%d"):format(1, 2) == "1-2" and
+        getmetatable("").__index == string and
+        pcall(function() return s.x.y end) == false
+end
+
+return
+    test_concat() and
+    test_basic() and
+    test_sub() and
+    test_byte_char() and
+    test_format() and
+    test_methods()
@@ -94,9 +94,141 @@ function test5()
     return t[1] == 1 and t[2] == 2 and t[3] == 3 and t.a == "a"
 end
 
+function test_insert_remove()
+    local t = {}
+    table.insert(t, "a")
+    table.insert(t, "c")
+    table.insert(t, 2, "b")
+    table.insert(t, 1, "z")
+    local passed = #t == 4 and t[1] == "z" and t[2] == "a" and t[3] == "b" and t[4] == "c"
+
+    passed = passed and table.remove(t, 1) == "z" and #t == 3 and t[1] == "a"
+    passed = passed and table.remove(t) == "c" and #t == 2 and t[3] == nil
+    passed = passed and table.remove({}) == nil
+    passed = passed and table.remove(t, 3) == nil and #t == 2
+
+    return
+        passed and
+        pcall(table.insert, t, 5, "x") == false and
+        pcall(table.insert, t, 0, "x") == false and
+        pcall(table.insert, t) == false and
+        pcall(table.remove, t, 5) == false
+end
+
+function test_concat()
+    return
+        table.concat({}) == "" and
+        table.concat({1, 2, 3}) == "123" and
+        table.concat({"a", "b", "c"}, ", ") == "a, b, c" and
+        table.concat({"a", "b", "c", "d"}, "-", 2, 3) == "b-c" and
+        table.concat({"a"}, "-", 2, 1) == "" and
+        pcall(table.concat, {"a", {}}) == false
+end
+
+function test_pack_unpack()
+    local t = table.pack(1, nil, 3)
+    local a, b, c = table.unpack({1, 2, 3})
+    local d, e = table.unpack({1, 2, 3, 4}, 2, 3)
+    return
+        t.n == 3 and t[1] == 1 and t[2] == nil and t[3] == 3 and
+        table.pack().n == 0 and
+        a == 1 and b == 2 and c == 3 and
+        d == 2 and e == 3 and
+        table.unpack({}, 1, 0) == nil and
+        pcall(table.unpack, {}, 1, math.maxinteger) == false
+end
+
+function test_move()
+    local t = table.move({1, 2, 3}, 1, 3, 2)
+    local u = table.move({1, 2, 3, 4}, 2, 4, 1)
+    local v = {}
+    local w = table.move({1, 2}, 1, 2, 3, v)
+    return
+        t[1] == 1 and t[2] == 1 and t[3] == 2 and t[4] == 3 and
+        u[1] == 2 and u[2] == 3 and u[3] == 4 and u[4] == 4 and
+        w == v and v[3] == 1 and v[4] == 2 and v[1] == nil
+end
+
+function test_sort()
+    local t = {5, 2, 8, 1, 9, 3, 7, 4, 6}
+    table.sort(t)
+    local passed = true
+    for i = 1, 9 do
+        passed = passed and t[i] == i
+    end
+
+    table.sort(t, function(a, b) return a > b end)
+    for i = 1, 9 do
+        passed = passed and t[i] == 10 - i
+    end
+
+    local s = {"banana", "apple", "cherry"}
+    table.sort(s)
+    passed = passed and s[1] == "apple" and s[2] == "banana" and s[3] == "cherry"
+
+    local r = {{k = 2, v = "a"}, {k = 1, v = "b"}, {k = 2, v = "c"}, {k = 1, v = "d"}}
+    table.sort(r, function(a, b) return a.k < b.k end)
+    passed = passed and r[1].v == "b" and r[2].v == "d" and r[3].v == "a" and r[4].v == "c"
+
+    local mt = {__lt = function(a, b) return a.n < b.n end}
+    local m = {}
+    for i = 1, 5 do
+        m[i] = setmetatable({n = 6 - i}, mt)
+    end
+    table.sort(m)
+    for i = 1, 5 do
+        passed = passed and m[i].n == i
+    end
+
+    local descending = setmetatable({}, {__call = function(self, a, b) return a > b end})
+    table.sort(t)
+    table.sort(t, descending)
+    for i = 1, 9 do
+        passed = passed and t[i] == 10 - i
+    end
+
+    local empty = {}
+    table.sort(empty)
+
+    return
+        passed and
+        #empty == 0 and
+        pcall(table.sort, {1, "a"}) == false and
+        pcall(table.sort, {3, 2, 1}, function(a, b) error("fail") end) == false and
+        pcall(table.sort, {3, 2, 1}, {}) == false and
+        select(2, pcall(table.sort, {1, 2, 3}, 5)):find(
+            "bad argument #2 to 'sort' (function expected, got number)", 1, true) ~= nil
+end
+
+function test_sort_yield()
+    local co = coroutine.create(function()
+        local t = {3, 1, 2}
+        table.sort(t, function(a, b)
+            coroutine.yield()
+            return a < b
+        end)
+        return t[1] == 1 and t[2] == 2 and t[3] == 3
+    end)
+
+    local yields = 0
+    while true do
+        local ok, res = coroutine.resume(co)
+        if coroutine.status(co) == "dead" then
+            return ok and res and yields > 0
+        end
+        yields = yields + 1
+    end
+end
+
 return
     test1() and
     test2() and
     test3() and
     test4() and
-    test5()
+    test5() and
+    test_insert_remove() and
+    test_concat() and
+    test_pack_unpack() and
+    test_move() and
+    test_sort() and
+    test_sort_yield()
@@ -0,0 +1,58 @@
+local function test_traceback()
+    local function f()
+        local tb = debug.traceback("message")
+        return tb
+    end
+    local tb = f()
+    local lines = {}
+    for line in tb:gmatch("[^\n]+") do
+        lines[#lines + 1] = line
+    end
+    return
+        lines[1] == "message" and
+        lines[2] == "stack traceback:" and
+        lines[3]:find(":3: in local 'f'$") ~= nil and
+        debug.traceback({}) ~= nil and type(debug.traceback({})) == "table" and
+        debug.traceback():sub(1, 16) == "stack traceback:"
+end
+
+local function test_traceback_level()
+    local function f(level)
+        local tb = debug.traceback("m", level)
+        return tb
+    end
+    local count = function(s)
+        local n = 0
+        for _ in s:gmatch("\n") do
+            n = n + 1
+        end
+        return n
+    end
+    return count(f(1)) == count(f(2)) + 1 and count(f(100)) == 1
+end
+
+local function test_coroutine_traceback()
+    local co = coroutine.create(function()
+        local t = {}
+        t.value = 1 + nil
+    end)
+    local ok = coroutine.resume(co)
+    local tb = debug.traceback(co, "dead")
+
+    local waiting = coroutine.create(function()
+        coroutine.yield()
+    end)
+    coroutine.resume(waiting)
+    local tb2 = debug.traceback(waiting)
+
+    return
+        not ok and
+        tb:find("^dead\nstack traceback:\n\t.*:37: in function <.*:35>") ~= nil and
+        tb:find("coroutine boundary") ~= nil and
+        tb2:find("\n\t.*:43: in function <.*:42>") ~= nil
+end
+
+return
+    test_traceback() and
+    test_traceback_level() and
+    test_coroutine_traceback()
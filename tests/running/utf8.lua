@@ -0,0 +1,105 @@
+local function test_char()
+    return
+        utf8.char() == "" and
+        utf8.char(72, 105) == "Hi" and
+        utf8.char(0xe9) == "\u{e9}" and
+        utf8.char(0x20ac, 0x10348) == "\u{20ac}\u{10348}" and
+        utf8.char(0x7fffffff) == "\xfd\xbf\xbf\xbf\xbf\xbf" and
+        pcall(utf8.char, -1) == false and
+        pcall(utf8.char, 0x80000000) == false
+end
+
+local function test_codepoint()
+    local a, b, c = utf8.codepoint("a\u{e9}\u{20ac}", 1, -1)
+    return
+        utf8.codepoint("abc") == 97 and
+        utf8.codepoint("abc", 3) == 99 and
+        a == 97 and b == 0xe9 and c == 0x20ac and
+        utf8.codepoint("\u{10348}", -4) == 0x10348 and
+        utf8.codepoint("abc", 4, 3) == nil and
+        pcall(utf8.codepoint, "abc", 0) == false and
+        pcall(utf8.codepoint, "abc", 1, 4) == false and
+        pcall(utf8.codepoint, "\xff") == false and
+        pcall(utf8.codepoint, "\xc0\x80") == false and
+        pcall(utf8.codepoint, "\xed\xa0\x80") == false and
+        utf8.codepoint("\xed\xa0\x80", 1, 1, true) == 0xd800
+end
+
+local function test_len()
+    local n, pos = utf8.len("ab\xffcd")
+    return
+        utf8.len("") == 0 and
+        utf8.len("abc") == 3 and
+        utf8.len("a\u{e9}\u{20ac}\u{10348}") == 4 and
+        utf8.len("a\u{e9}\u{20ac}", 2) == 2 and
+        utf8.len("a\u{e9}\u{20ac}", -3) == 1 and
+        utf8.len("abc", 4) == 0 and
+        n == nil and pos == 3 and
+        utf8.len("\xed\xa0\x80") == nil and
+        utf8.len("\xed\xa0\x80", 1, -1, true) == 1 and
+        pcall(utf8.len, "abc", 5) == false and
+        pcall(utf8.len, "abc", 1, 4) == false
+end
+
+local function test_offset()
+    local s = "a\u{e9}\u{20ac}b"
+    return
+        utf8.offset(s, 1) == 1 and
+        utf8.offset(s, 2) == 2 and
+        utf8.offset(s, 3) == 4 and
+        utf8.offset(s, 4) == 7 and
+        utf8.offset(s, 5) == 8 and
+        utf8.offset(s, 6) == nil and
+        utf8.offset(s, -1) == 7 and
+        utf8.offset(s, -2) == 4 and
+        utf8.offset(s, -4) == 1 and
+        utf8.offset(s, -5) == nil and
+        utf8.offset(s, 0, 3) == 2 and
+        utf8.offset(s, 0, 6) == 4 and
+        utf8.offset(s, 2, 4) == 7 and
+        pcall(utf8.offset, s, 1, 3) == false and
+        pcall(utf8.offset, s, 1, 10) == false
+end
+
+local function test_codes()
+    local positions, codes = {}, {}
+    for p, c in utf8.codes("a\u{e9}\u{20ac}") do
+        positions[#positions + 1] = p
+        codes[#codes + 1] = c
+    end
+
+    local count = 0
+    for p, c in utf8.codes("") do
+        count = count + 1
+    end
+
+    local lax = 0
+    for p, c in utf8.codes("\xed\xa0\x80", true) do
+        lax = c
+    end
+
+    return
+        #positions == 3 and
+        positions[1] == 1 and positions[2] == 2 and positions[3] == 4 and
+        codes[1] == 97 and codes[2] == 0xe9 and codes[3] == 0x20ac and
+        count == 0 and
+        lax == 0xd800 and
+        pcall(function() for p, c in utf8.codes("a\xffb") do end end) == false and
+        pcall(function() for p, c in utf8.codes("\xed\xa0\x80") do end end) == false
+end
+
+local function test_charpattern()
+    local chars = {}
+    for c in string.gmatch("a\u{e9}\u{20ac}", utf8.charpattern) do
+        chars[#chars + 1] = c
+    end
+    return #chars == 3 and chars[2] == "\u{e9}" and chars[3] == "\u{20ac}"
+end
+
+return
+    test_char() and
+    test_codepoint() and
+    test_len() and
+    test_offset() and
+    test_codes() and
+    test_charpattern()
@@ -0,0 +1,100 @@
+local function count(t)
+    local n = 0
+    for _ in pairs(t) do
+        n = n + 1
+    end
+    return n
+end
+
+local function test_weak_keys()
+    local t = setmetatable({}, {__mode = "k"})
+    local kept = {}
+    t[kept] = 1
+    for i = 1, 10 do
+        t[{}] = i
+    end
+    t.name = "string keys are never removed"
+    collectgarbage()
+    return count(t) == 2 and t[kept] == 1 and t.name ~= nil
+end
+
+local function test_weak_values()
+    local t = setmetatable({}, {__mode = "v"})
+    local kept = {}
+    t[1] = kept
+    for i = 2, 10 do
+        t[i] = {}
+    end
+    t.f = function() end
+    t.s = "strings are never removed"
+    t.n = 5
+    collectgarbage()
+    return count(t) == 3 and t[1] == kept and t.s ~= nil and t.n == 5
+end
+
+local function test_weak_keys_and_values()
+    local t = setmetatable({}, {__mode = "kv"})
+    local k, v = {}, {}
+    t[k] = {}
+    t[{}] = v
+    t[k] = v
+    t[{}] = {}
+    collectgarbage()
+    return count(t) == 1 and t[k] == v
+end
+
+local function test_ephemerons()
+    local t = setmetatable({}, {__mode = "k"})
+    local kept = {}
+    -- Values refer to their own keys, and chain to the key of the next entry
+    local a, b, c = {}, {}, {}
+    t[a] = {a, b}
+    t[b] = {b, c}
+    t[c] = {c}
+    t[kept] = {kept}
+    a, b, c = nil, nil, nil
+    collectgarbage()
+    return count(t) == 1 and t[kept][1] == kept
+end
+
+local function test_ephemeron_chain()
+    local t = setmetatable({}, {__mode = "k"})
+    local root = {}
+    local key = root
+    for i = 1, 10 do
+        local next_key = {}
+        t[key] = next_key
+        key = next_key
+    end
+    key = nil
+    collectgarbage()
+    local n = count(t)
+    root = nil
+    collectgarbage()
+    return n == 10 and count(t) == 0
+end
+
+local function test_strong_references()
+    local t = setmetatable({}, {__mode = "v"})
+    local holder = {}
+    for i = 1, 10 do
+        local v = {}
+        holder[i] = v
+        t[i] = v
+    end
+    collectgarbage()
+    for i = 1, 10 do
+        if t[i] ~= holder[i] then
+            return false
+        end
+    end
+    return true
+end
+
+return
+    test_weak_keys() and
+    test_weak_values() and
+    test_weak_keys_and_values() and
+    test_ephemerons() and
+    test_ephemeron_chain() and
+    test_strong_references()
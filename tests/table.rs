@@ -0,0 +1,68 @@
+use luster::{Lua, String, Table, Value};
+
+#[test]
+fn table_next() {
+    let mut lua = Lua::new();
+    lua.mutate(|mc, _| {
+        let table = Table::new(mc);
+        assert_eq!(table.next(Value::Nil).unwrap(), None);
+
+        table.set(mc, 1, 10).unwrap();
+        table.set(mc, 2, 20).unwrap();
+        table.set(mc, String::new_static(b"a"), 30).unwrap();
+
+        let mut count = 0;
+        let mut key = Value::Nil;
+        while let Some((k, v)) = table.next(key).unwrap() {
+            assert_eq!(table.get(k), v);
+            count += 1;
+            key = k;
+        }
+        assert_eq!(count, 3);
+
+        assert!(table.next(String::new_static(b"missing")).is_err());
+        assert!(table.next(100).is_err());
+    });
+}
+
+#[test]
+fn table_iter() {
+    let mut lua = Lua::new();
+    lua.mutate(|mc, _| {
+        let table = Table::new(mc);
+        for i in 1..=10 {
+            table.set(mc, i, i * 2).unwrap();
+            table
+                .set(mc, String::new(mc, format!("k{}", i).as_bytes()), i)
+                .unwrap();
+        }
+
+        let mut sum = 0;
+        for (_, v) in table.iter() {
+            if let Value::Integer(i) = v {
+                sum += i;
+            }
+        }
+        assert_eq!(sum, 110 + 55);
+
+        // Clearing every key during iteration must still visit every key exactly once
+        let mut count = 0;
+        for (k, _) in table.iter() {
+            table.set(mc, k, Value::Nil).unwrap();
+            count += 1;
+        }
+        assert_eq!(count, 20);
+        assert_eq!(table.iter().next(), None);
+
+        // Assigning to existing keys during iteration is also allowed
+        for i in 1..=10 {
+            table.set(mc, i, i).unwrap();
+        }
+        for (k, v) in table.iter() {
+            if let Value::Integer(i) = v {
+                table.set(mc, k, i + 1).unwrap();
+            }
+        }
+        assert_eq!(table.get(10), Value::Integer(11));
+    });
+}
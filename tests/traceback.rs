@@ -0,0 +1,78 @@
+use gc_sequence::{self as sequence, SequenceExt, SequenceResultExt};
+use luster::{
+    compile_named, Closure, Error, Function, FunctionName, LineNumber, Lua, StaticError,
+    ThreadSequence, TracebackFrame,
+};
+
+#[test]
+fn error_traceback() -> Result<(), Box<StaticError>> {
+    let mut lua = Lua::new();
+    let res = lua.sequence(|root| {
+        sequence::from_fn_with(root, |mc, root| {
+            Ok(Closure::new(
+                mc,
+                compile_named(
+                    mc,
+                    root.interned_strings,
+                    b"=test",
+                    &br#"
+                        function do_error()
+                            error('test error')
+                        end
+                        local t = {f = function() do_error() end}
+                        t.f()
+                    "#[..],
+                )?,
+                Some(root.globals),
+            )?)
+        })
+        .and_chain_with(root, |mc, root, closure| {
+            Ok(ThreadSequence::call_function(
+                mc,
+                root.main_thread,
+                Function::Closure(closure),
+                &[],
+            )?)
+        })
+        .map_ok(|_| ())
+        .map_err(Error::to_static)
+        .boxed()
+    });
+    assert!(res.is_err());
+
+    let traceback = lua
+        .mutate(|_, root| root.main_thread.error_traceback())
+        .expect("no traceback for error");
+    assert_eq!(
+        traceback.frames,
+        vec![
+            TracebackFrame::Lua {
+                chunk: "test".to_owned(),
+                line_defined: LineNumber(2),
+                current_line: Some(LineNumber(3)),
+                name: FunctionName::Global("do_error".to_owned()),
+            },
+            TracebackFrame::Lua {
+                chunk: "test".to_owned(),
+                line_defined: LineNumber(5),
+                current_line: Some(LineNumber(5)),
+                name: FunctionName::Field("f".to_owned()),
+            },
+            TracebackFrame::Lua {
+                chunk: "test".to_owned(),
+                line_defined: LineNumber(0),
+                current_line: Some(LineNumber(6)),
+                name: FunctionName::MainChunk,
+            },
+        ]
+    );
+    assert_eq!(
+        traceback.to_string(),
+        "stack traceback:\n\
+         \ttest:3: in function 'do_error'\n\
+         \ttest:5: in field 'f'\n\
+         \ttest:6: in main chunk"
+    );
+
+    Ok(())
+}
@@ -0,0 +1,130 @@
+use gc_arena::Collect;
+use gc_sequence::{self as sequence, SequenceExt, SequenceResultExt};
+use luster::{
+    compile, Callback, CallbackResult, Closure, Error, Function, Lua, StaticError, String, Table,
+    ThreadSequence, UserData, UserDataRoot, Value,
+};
+
+#[derive(Collect)]
+#[collect(empty_drop)]
+struct Node<'gc> {
+    value: Value<'gc>,
+}
+
+struct NodeRoot;
+
+impl<'gc> UserDataRoot<'gc> for NodeRoot {
+    type Data = Node<'gc>;
+}
+
+#[test]
+fn userdata_downcast() -> Result<(), Box<StaticError>> {
+    let mut lua = Lua::new();
+    lua.sequence(|_| {
+        sequence::from_fn(|mc| {
+            let counter = UserData::new_static(mc, 17i32);
+            assert!(counter.is_static::<i32>());
+            assert!(!counter.is_static::<u32>());
+            assert!(!counter.is::<NodeRoot>());
+            assert_eq!(*counter.read_static::<i32>().unwrap(), 17);
+            *counter.write_static::<i32>(mc).unwrap() += 1;
+            assert_eq!(*counter.read_static::<i32>().unwrap(), 18);
+            assert!(counter.read_static::<u32>().is_none());
+
+            let node = UserData::new::<NodeRoot>(
+                mc,
+                Node {
+                    value: Value::String(String::new(mc, b"hello")),
+                },
+            );
+            assert!(node.is::<NodeRoot>());
+            assert!(node.read_static::<i32>().is_none());
+            assert_eq!(
+                node.read::<NodeRoot>().unwrap().value,
+                Value::String(String::new_static(b"hello"))
+            );
+
+            assert_eq!(Value::UserData(node), Value::UserData(node));
+            assert_ne!(Value::UserData(node), Value::UserData(counter));
+            assert_eq!(Value::UserData(node).type_name(), "userdata");
+
+            let table = Table::new(mc);
+            table.set(mc, node, 1)?;
+            table.set(mc, counter, 2)?;
+            assert_eq!(table.get(node), Value::Integer(1));
+            assert_eq!(table.get(counter), Value::Integer(2));
+
+            Ok(())
+        })
+        .map_err(Error::to_static)
+        .boxed()
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn userdata_metatable() -> Result<(), Box<StaticError>> {
+    let mut lua = Lua::new();
+    lua.sequence(|root| {
+        sequence::from_fn_with(root, |mc, root| {
+            let new_counter = Callback::new_sequence(mc, |args| {
+                let start = args.get(0).cloned().unwrap_or(Value::Nil);
+                Ok(sequence::from_fn_with(start, |mc, start| {
+                    let counter = UserData::new_static(mc, start.to_integer().unwrap_or(0));
+                    let get = Callback::new_immediate(mc, |args| match args.get(0) {
+                        Some(Value::UserData(counter)) => {
+                            Ok(CallbackResult::Return(vec![Value::Integer(
+                                *counter.read_static::<i64>().unwrap(),
+                            )]))
+                        }
+                        _ => Ok(CallbackResult::Return(vec![])),
+                    });
+                    let methods = Table::new(mc);
+                    methods.set(mc, String::new_static(b"get"), get)?;
+                    let metatable = Table::new(mc);
+                    metatable.set(mc, String::new_static(b"__index"), methods)?;
+                    counter.set_metatable(mc, Some(metatable));
+                    Ok(CallbackResult::Return(vec![Value::UserData(counter)]))
+                }))
+            });
+            root.globals
+                .set(mc, String::new_static(b"new_counter"), new_counter)?;
+            Ok(())
+        })
+        .and_then_with(root, |mc, root, _| {
+            Ok(Closure::new(
+                mc,
+                compile(
+                    mc,
+                    root.interned_strings,
+                    &br#"
+                        local c = new_counter(5)
+                        local d = new_counter(5)
+                        return
+                            type(c) == "userdata" and
+                            c:get() == 5 and
+                            c == c and
+                            c ~= d and
+                            getmetatable(c).__index.get == c.get and
+                            pcall(function() c.x = 1 end) == false
+                    "#[..],
+                )?,
+                Some(root.globals),
+            )?)
+        })
+        .and_chain_with(root, |mc, root, closure| {
+            Ok(ThreadSequence::call_function(
+                mc,
+                root.main_thread,
+                Function::Closure(closure),
+                &[],
+            )?)
+        })
+        .map_ok(|b| assert_eq!(b, vec![Value::Boolean(true)]))
+        .map_err(Error::to_static)
+        .boxed()
+    })?;
+
+    Ok(())
+}